@@ -6,7 +6,7 @@ use rand::prelude::*;
 use rand::rngs::OsRng;
 use safe_transmute::{
     guard::{PermissiveGuard, SingleManyGuard, SingleValueGuard},
-    to_bytes::{transmute_one_to_bytes, transmute_to_bytes},
+    to_bytes::transmute_to_bytes,
     transmute_many, transmute_many_pedantic, transmute_many_permissive, transmute_one,
     transmute_one_pedantic, try_copy,
 };
@@ -14,12 +14,13 @@ use serum_common::client::rpc::{
     create_and_init_mint, create_token_account, mint_to_new_account, send_txn,
 };
 use serum_common::client::Cluster;
+use serum_dex::client::MarketKeys;
+use serum_dex::fees::FeeSchedule;
 use serum_dex::instruction::{MarketInstruction, NewOrderInstructionV1};
 use serum_dex::matching::{OrderType, Side};
 use serum_dex::state::gen_vault_signer_key;
 use serum_dex::state::Event;
 use serum_dex::state::EventQueueHeader;
-use serum_dex::state::MarketState;
 use serum_dex::state::QueueHeader;
 use serum_dex::state::Request;
 use serum_dex::state::RequestQueueHeader;
@@ -146,6 +147,19 @@ enum Command {
         #[clap(long, short)]
         pc_wallet: Pubkey,
     },
+    PruneExpiredOrders {
+        #[clap(long, short)]
+        dex_program_id: Pubkey,
+
+        #[clap(long)]
+        payer: String,
+
+        #[clap(long, short)]
+        market: Pubkey,
+
+        #[clap(long, short)]
+        limit: u16,
+    },
     MonitorQueue {
         #[clap(long, short)]
         dex_program_id: Pubkey,
@@ -261,6 +275,19 @@ fn main() -> Result<()> {
                 pc_wallet,
             )?;
         }
+        Command::PruneExpiredOrders {
+            ref dex_program_id,
+            ref payer,
+            ref market,
+            limit,
+        } => {
+            let payer = read_keypair_file(&payer)?;
+
+            debug_println!("Getting market keys ...");
+            let market_keys = get_keys_for_market(&client, dex_program_id, &market)?;
+            debug_println!("{:#?}", market_keys);
+            prune_expired_orders(&client, dex_program_id, &payer, &market_keys, limit)?;
+        }
         Command::ConsumeEvents {
             ref dex_program_id,
             ref payer,
@@ -416,28 +443,16 @@ fn get_keys_for_market<'a>(
     market: &'a Pubkey,
 ) -> Result<MarketPubkeys> {
     let account_data: Vec<u8> = client.get_account_data(&market)?;
-    let words: Cow<[u64]> = remove_dex_account_padding(&account_data)?;
-    let market_state: MarketState =
-        transmute_one_pedantic::<MarketState>(transmute_to_bytes(&words))
-            .map_err(|e| e.without_src())?;
-    market_state.check_flags()?;
-    let vault_signer_key =
-        gen_vault_signer_key(market_state.vault_signer_nonce, market, program_id)?;
-    assert_eq!(
-        transmute_to_bytes(&market_state.own_address),
-        market.as_ref()
-    );
+    let keys = MarketKeys::load(program_id, market, &account_data)?;
     Ok(MarketPubkeys {
-        market: Box::new(*market),
-        req_q: Box::new(Pubkey::new(transmute_one_to_bytes(&market_state.req_q))),
-        event_q: Box::new(Pubkey::new(transmute_one_to_bytes(&market_state.event_q))),
-        bids: Box::new(Pubkey::new(transmute_one_to_bytes(&market_state.bids))),
-        asks: Box::new(Pubkey::new(transmute_one_to_bytes(&market_state.asks))),
-        coin_vault: Box::new(Pubkey::new(transmute_one_to_bytes(
-            &market_state.coin_vault,
-        ))),
-        pc_vault: Box::new(Pubkey::new(transmute_one_to_bytes(&market_state.pc_vault))),
-        vault_signer_key: Box::new(vault_signer_key),
+        market: Box::new(keys.market),
+        req_q: Box::new(keys.req_q),
+        event_q: Box::new(keys.event_q),
+        bids: Box::new(keys.bids),
+        asks: Box::new(keys.asks),
+        coin_vault: Box::new(keys.coin_vault),
+        pc_vault: Box::new(keys.pc_vault),
+        vault_signer_key: Box::new(keys.vault_signer),
     })
 }
 
@@ -879,23 +894,24 @@ fn place_order(
         }
     };
     *orders = Some(orders_pubkey);
-    let side = new_order.side;
-    let data = MarketInstruction::NewOrder(new_order).pack();
-    let instruction = Instruction {
-        program_id: *program_id,
-        data,
-        accounts: vec![
-            AccountMeta::new(*state.market, false),
-            AccountMeta::new(orders_pubkey, false),
-            AccountMeta::new(*state.req_q, false),
-            AccountMeta::new(*wallet, false),
-            AccountMeta::new(payer.pubkey(), true),
-            AccountMeta::new(*state.coin_vault, false),
-            AccountMeta::new(*state.pc_vault, false),
-            AccountMeta::new(spl_token::ID, false),
-            AccountMeta::new_readonly(solana_sdk::sysvar::rent::ID, false),
-        ],
-    };
+    let instruction = serum_dex::instruction::new_order_v1(
+        &state.market,
+        &orders_pubkey,
+        &state.req_q,
+        wallet,
+        &payer.pubkey(),
+        &state.coin_vault,
+        &state.pc_vault,
+        &spl_token::ID,
+        &solana_sdk::sysvar::rent::ID,
+        None,
+        program_id,
+        new_order.side,
+        new_order.limit_price,
+        new_order.max_qty,
+        new_order.order_type,
+        new_order.client_id,
+    )?;
     instructions.push(instruction);
     signers.push(payer);
 
@@ -920,22 +936,19 @@ fn settle_funds(
     coin_wallet: &Pubkey,
     pc_wallet: &Pubkey,
 ) -> Result<()> {
-    let data = MarketInstruction::SettleFunds.pack();
-    let instruction = Instruction {
-        program_id: *program_id,
-        data,
-        accounts: vec![
-            AccountMeta::new(*state.market, false),
-            AccountMeta::new(*orders, false),
-            AccountMeta::new_readonly(signer.unwrap_or(payer).pubkey(), true),
-            AccountMeta::new(*state.coin_vault, false),
-            AccountMeta::new(*state.pc_vault, false),
-            AccountMeta::new(*coin_wallet, false),
-            AccountMeta::new(*pc_wallet, false),
-            AccountMeta::new_readonly(*state.vault_signer_key, false),
-            AccountMeta::new_readonly(spl_token::ID, false),
-        ],
-    };
+    let instruction = serum_dex::instruction::settle_funds(
+        program_id,
+        &state.market,
+        orders,
+        &signer.unwrap_or(payer).pubkey(),
+        &state.coin_vault,
+        &state.pc_vault,
+        coin_wallet,
+        pc_wallet,
+        &state.vault_signer_key,
+        &spl_token::ID,
+        None,
+    )?;
     let (recent_hash, _fee_calc) = client.get_recent_blockhash()?;
     let mut signers = vec![payer];
     if let Some(s) = signer {
@@ -1005,6 +1018,8 @@ fn list_market(
         pc_lot_size,
         vault_signer_nonce,
         100,
+        FeeSchedule::default(),
+        Some(&payer.pubkey()),
     )?;
     debug_println!(
         "initialize_market_instruction: {:#?}",
@@ -1126,21 +1141,19 @@ fn match_orders(
     coin_wallet: &Pubkey,
     pc_wallet: &Pubkey,
 ) -> Result<()> {
-    let instruction_data: Vec<u8> = MarketInstruction::MatchOrders(2).pack();
-
-    let instruction = Instruction {
-        program_id: *program_id,
-        accounts: vec![
-            AccountMeta::new(*state.market, false),
-            AccountMeta::new(*state.req_q, false),
-            AccountMeta::new(*state.event_q, false),
-            AccountMeta::new(*state.bids, false),
-            AccountMeta::new(*state.asks, false),
-            AccountMeta::new(*coin_wallet, false),
-            AccountMeta::new(*pc_wallet, false),
-        ],
-        data: instruction_data,
-    };
+    let instruction = serum_dex::instruction::match_orders(
+        program_id,
+        &state.market,
+        &state.req_q,
+        &state.event_q,
+        &state.bids,
+        &state.asks,
+        coin_wallet,
+        pc_wallet,
+        &solana_sdk::sysvar::clock::ID,
+        Some(&payer.pubkey()),
+        2,
+    )?;
 
     let (recent_hash, _fee_calc) = client.get_recent_blockhash()?;
     let txn = Transaction::new_signed_with_payer(
@@ -1160,6 +1173,36 @@ fn match_orders(
     Ok(())
 }
 
+fn prune_expired_orders(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    payer: &Keypair,
+    state: &MarketPubkeys,
+    limit: u16,
+) -> Result<()> {
+    let instruction = serum_dex::instruction::prune_expired_orders(
+        program_id,
+        &state.market,
+        &state.event_q,
+        &state.bids,
+        &state.asks,
+        &solana_sdk::sysvar::clock::ID,
+        limit,
+    )?;
+
+    let (recent_hash, _fee_calc) = client.get_recent_blockhash()?;
+    let txn = Transaction::new_signed_with_payer(
+        std::slice::from_ref(&instruction),
+        Some(&payer.pubkey()),
+        &[payer],
+        recent_hash,
+    );
+
+    debug_println!("Pruning expired orders ...");
+    send_txn(client, &txn, false)?;
+    Ok(())
+}
+
 fn create_account(
     client: &RpcClient,
     mint_pubkey: &Pubkey,
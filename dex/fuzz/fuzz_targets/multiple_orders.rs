@@ -364,6 +364,7 @@ fn run_action<'bump>(
             .map_err(|e| match e {
                 DexError::ErrorCode(DexErrorCode::InsufficientFunds) => {}
                 DexError::ErrorCode(DexErrorCode::RequestQueueFull) => {}
+                DexError::ErrorCode(DexErrorCode::AbortOrderNotMatched) => {}
                 e => Err(e).unwrap(),
             })
             .ok();
@@ -384,16 +385,22 @@ fn run_action<'bump>(
                 }
             };
             let (side, order_id, client_order_id) = {
-                if let Some(orders) = owner.open_orders() {
-                    if let Some(side) = orders.slot_side(slot) {
-                        (
-                            side,
-                            orders.orders[slot as usize],
-                            orders.client_order_ids[slot as usize],
-                        )
-                    } else {
+                let market_state =
+                    MarketState::load(&market_accounts.market, market_accounts.market.owner)
+                        .unwrap();
+                let orders = match market_state.load_orders_mut(
+                    &owner.orders_account,
+                    None,
+                    market_accounts.market.owner,
+                    None,
+                ) {
+                    Ok(orders) => orders,
+                    Err(_) => {
                         return;
                     }
+                };
+                if let Some(side) = orders.slot_side(slot) {
+                    (side, orders.order_id(slot), orders.client_order_id(slot))
                 } else {
                     return;
                 }
@@ -450,6 +457,7 @@ fn run_action<'bump>(
                 market_accounts.asks.clone(),
                 market_accounts.coin_vault.clone(),
                 market_accounts.pc_vault.clone(),
+                market_accounts.clock_sysvar.clone(),
             ],
             &MarketInstruction::MatchOrders(limit).pack(),
         )
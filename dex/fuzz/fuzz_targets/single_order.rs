@@ -105,6 +105,7 @@ fn fuzz_place_order(data: SingleOrder) {
             market_accounts.asks.clone(),
             coin_account.clone(),
             pc_account.clone(),
+            market_accounts.clock_sysvar.clone(),
         ],
         &MarketInstruction::MatchOrders(5).pack(),
     )
@@ -181,6 +182,7 @@ fn fuzz_place_order(data: SingleOrder) {
             market_accounts.asks.clone(),
             coin_account.clone(),
             pc_account.clone(),
+            market_accounts.clock_sysvar.clone(),
         ],
         &MarketInstruction::MatchOrders(5).pack(),
     )
@@ -4,7 +4,7 @@ use bumpalo::Bump;
 use safe_transmute::to_bytes::{transmute_to_bytes, transmute_to_bytes_mut};
 use solana_sdk::account_info::AccountInfo;
 use solana_sdk::bpf_loader;
-use solana_sdk::clock::Epoch;
+use solana_sdk::clock::{Clock, Epoch};
 use solana_sdk::pubkey::Pubkey;
 use solana_sdk::rent::Rent;
 use solana_sdk::system_program;
@@ -15,9 +15,10 @@ use spl_token::state::Account as SplAccount;
 use spl_token::state::Mint;
 
 use serum_dex::error::DexResult;
+use serum_dex::fees::FeeSchedule;
 use serum_dex::instruction::{fee_sweeper, initialize_market};
 use serum_dex::state::{
-    gen_vault_signer_key, strip_header, EventQueue, MarketState, Queue, RequestQueue, State,
+    gen_vault_signer_key, strip_header, EventQueue, MarketStateV2, Queue, RequestQueue, State,
 };
 
 fn random_pubkey(bump: &Bump) -> &Pubkey {
@@ -149,6 +150,22 @@ fn new_rent_sysvar_account(lamports: u64, rent: Rent, bump: &Bump) -> AccountInf
     account_info
 }
 
+fn new_clock_sysvar_account(clock: Clock, bump: &Bump) -> AccountInfo {
+    let data = bump.alloc_slice_fill_copy(size_of::<Clock>(), 0u8);
+    let mut account_info = AccountInfo::new(
+        &sysvar::clock::ID,
+        false,
+        false,
+        bump.alloc(0),
+        data,
+        &sysvar::ID,
+        false,
+        Epoch::default(),
+    );
+    clock.to_account_info(&mut account_info).unwrap();
+    account_info
+}
+
 fn new_vault_signer_account<'bump>(
     market: &AccountInfo,
     program_id: &Pubkey,
@@ -187,6 +204,7 @@ pub struct MarketAccounts<'bump> {
     pub vault_signer: AccountInfo<'bump>,
     pub spl_token_program: AccountInfo<'bump>,
     pub rent_sysvar: AccountInfo<'bump>,
+    pub clock_sysvar: AccountInfo<'bump>,
     pub sweep_authority: AccountInfo<'bump>,
     pub fee_receiver: AccountInfo<'bump>,
 }
@@ -197,7 +215,7 @@ pub const PC_DUST_THRESHOLD: u64 = 500;
 
 pub fn setup_market(bump: &Bump) -> MarketAccounts {
     let program_id = random_pubkey(bump);
-    let market = new_dex_owned_account(size_of::<MarketState>(), program_id, bump);
+    let market = new_dex_owned_account(size_of::<MarketStateV2>(), program_id, bump);
     let bids = new_dex_owned_account(1 << 16, program_id, bump);
     let asks = new_dex_owned_account(1 << 16, program_id, bump);
     let req_q = new_dex_owned_account(640, program_id, bump);
@@ -207,6 +225,7 @@ pub fn setup_market(bump: &Bump) -> MarketAccounts {
     let pc_mint = new_token_mint(bump);
 
     let rent_sysvar = new_rent_sysvar_account(100000, Rent::default(), bump);
+    let clock_sysvar = new_clock_sysvar_account(Clock::default(), bump);
 
     let (vault_signer_nonce, vault_signer) = new_vault_signer_account(&market, program_id, bump);
 
@@ -237,6 +256,8 @@ pub fn setup_market(bump: &Bump) -> MarketAccounts {
         pc_lot_size,
         vault_signer_nonce,
         pc_dust_threshold,
+        FeeSchedule::default(),
+        None,
     )
     .unwrap();
 
@@ -270,6 +291,7 @@ pub fn setup_market(bump: &Bump) -> MarketAccounts {
         vault_signer,
         spl_token_program,
         rent_sysvar,
+        clock_sysvar,
         fee_receiver,
         sweep_authority,
     }
@@ -0,0 +1,986 @@
+use std::{mem::size_of, num::NonZeroU64};
+
+use bytemuck::{bytes_of, bytes_of_mut, cast_slice, cast_slice_mut, from_bytes, Zeroable};
+use enumflags2::BitFlags;
+use solana_sdk::{program_error::ProgramError, pubkey::Pubkey};
+use spl_token::pack::Pack;
+
+use crate::{
+    critbit::{LeafNode, Slab, SLAB_HEADER_LEN},
+    error::{DexErrorCode, DexResult},
+    fees::FeeSchedule,
+    matching::Side,
+    state::{
+        gen_vault_signer_key, AccountFlag, Event, EventQueueHeader, EventView, MarketState,
+        MarketStateV2, OrderBookStateHeader, QueueHeader, ACCOUNT_HEAD_PADDING,
+        ACCOUNT_TAIL_PADDING, EVENT_VERSION, ORIGINAL_EVENT_LEN,
+    },
+};
+
+/// Checks the head and tail padding of a dex-owned account and copies the
+/// data in between into an aligned buffer.
+pub fn strip_dex_padding(data: &[u8]) -> DexResult<Vec<u64>> {
+    let head_len = ACCOUNT_HEAD_PADDING.len();
+    let tail_len = ACCOUNT_TAIL_PADDING.len();
+    if data.len() < head_len + tail_len {
+        Err(DexErrorCode::WrongAccountDataPaddingLength)?
+    }
+    let (head, rest) = data.split_at(head_len);
+    let (inner, tail) = rest.split_at(rest.len() - tail_len);
+    if head != ACCOUNT_HEAD_PADDING {
+        Err(DexErrorCode::WrongAccountHeadPadding)?
+    }
+    if tail != ACCOUNT_TAIL_PADDING {
+        Err(DexErrorCode::WrongAccountTailPadding)?
+    }
+    if inner.len() % size_of::<u64>() != 0 {
+        Err(DexErrorCode::WrongAccountDataAlignment)?
+    }
+    let mut words = vec![0u64; inner.len() / size_of::<u64>()];
+    let word_bytes: &mut [u8] = cast_slice_mut(words.as_mut_slice());
+    word_bytes.copy_from_slice(inner);
+    Ok(words)
+}
+
+/// Decodes the fields every market has out of a raw market account of either
+/// version, and checks its flags.
+pub fn load_market_state(data: &[u8]) -> DexResult<MarketState> {
+    let words = strip_dex_padding(data)?;
+    let bytes: &[u8] = cast_slice(words.as_slice());
+    if bytes.len() != size_of::<MarketState>() && bytes.len() != size_of::<MarketStateV2>() {
+        Err(ProgramError::InvalidAccountData)?
+    }
+    let state: MarketState = *from_bytes(&bytes[..size_of::<MarketState>()]);
+    state.check_flags()?;
+    Ok(state)
+}
+
+/// Decodes a raw market account created by this version of the program and
+/// checks its flags.
+pub fn load_market_state_v2(data: &[u8]) -> DexResult<MarketStateV2> {
+    let words = strip_dex_padding(data)?;
+    let bytes: &[u8] = cast_slice(words.as_slice());
+    if bytes.len() == size_of::<MarketState>() {
+        Err(DexErrorCode::UnsupportedMarketVersion)?
+    }
+    if bytes.len() != size_of::<MarketStateV2>() {
+        Err(ProgramError::InvalidAccountData)?
+    }
+    let state: MarketStateV2 = *from_bytes(bytes);
+    state.check_flags()?;
+    Ok(state)
+}
+
+/// Reads the decimals out of a raw spl-token mint account.
+pub fn load_mint_decimals(data: &[u8]) -> DexResult<u8> {
+    Ok(spl_token::state::Mint::unpack(data)?.decimals)
+}
+
+#[inline]
+fn aligned_to_pubkey(key: &[u64; 4]) -> Pubkey {
+    Pubkey::new(bytes_of(key))
+}
+
+/// The accounts a client needs to interact with a market.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MarketKeys {
+    pub market: Pubkey,
+    pub coin_mint: Pubkey,
+    pub pc_mint: Pubkey,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+    pub req_q: Pubkey,
+    pub event_q: Pubkey,
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub vault_signer: Pubkey,
+}
+
+impl MarketKeys {
+    pub fn from_state(state: &MarketState, program_id: &Pubkey) -> DexResult<Self> {
+        let market = aligned_to_pubkey(&state.own_address);
+        let vault_signer = gen_vault_signer_key(state.vault_signer_nonce, &market, program_id)?;
+        Ok(MarketKeys {
+            market,
+            coin_mint: aligned_to_pubkey(&state.coin_mint),
+            pc_mint: aligned_to_pubkey(&state.pc_mint),
+            coin_vault: aligned_to_pubkey(&state.coin_vault),
+            pc_vault: aligned_to_pubkey(&state.pc_vault),
+            req_q: aligned_to_pubkey(&state.req_q),
+            event_q: aligned_to_pubkey(&state.event_q),
+            bids: aligned_to_pubkey(&state.bids),
+            asks: aligned_to_pubkey(&state.asks),
+            vault_signer,
+        })
+    }
+
+    /// Decodes the market account stored at `market`.
+    pub fn load(program_id: &Pubkey, market: &Pubkey, data: &[u8]) -> DexResult<Self> {
+        let keys = Self::from_state(&load_market_state(data)?, program_id)?;
+        if keys.market != *market {
+            Err(ProgramError::InvalidAccountData)?
+        }
+        Ok(keys)
+    }
+}
+
+/// A market together with the decimals of its mints, for converting between
+/// lots and human-readable amounts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MarketView {
+    pub keys: MarketKeys,
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
+    pub pc_dust_threshold: u64,
+    pub fee_rate_bps: u64,
+    pub fee_schedule: FeeSchedule,
+    pub authority: Option<Pubkey>,
+    pub paused: bool,
+    pub last_trade_price: u64,
+    pub price_band_bps: u64,
+    pub price_band_halt_slots: u64,
+    pub coin_volume_total: u64,
+    pub pc_volume_total: u64,
+    pub fill_count: u64,
+    pub crank_fee_lamports: u64,
+    pub match_rewards_accrued: u64,
+    pub consume_rewards_accrued: u64,
+    pub coin_decimals: u8,
+    pub pc_decimals: u8,
+}
+
+impl MarketView {
+    pub fn new(
+        state: &MarketStateV2,
+        program_id: &Pubkey,
+        coin_decimals: u8,
+        pc_decimals: u8,
+    ) -> DexResult<Self> {
+        Ok(MarketView {
+            keys: MarketKeys::from_state(state, program_id)?,
+            coin_lot_size: state.coin_lot_size,
+            pc_lot_size: state.pc_lot_size,
+            pc_dust_threshold: state.pc_dust_threshold,
+            fee_rate_bps: state.fee_rate_bps,
+            fee_schedule: state.fee_schedule,
+            authority: match state.authority {
+                [0, 0, 0, 0] => None,
+                ref authority => Some(aligned_to_pubkey(authority)),
+            },
+            paused: state.is_paused(),
+            last_trade_price: state.last_trade_price,
+            price_band_bps: state.price_band_bps,
+            price_band_halt_slots: state.price_band_halt_slots,
+            coin_volume_total: state.coin_volume_total,
+            pc_volume_total: state.pc_volume_total,
+            fill_count: state.fill_count,
+            crank_fee_lamports: state.crank_fee_lamports,
+            match_rewards_accrued: state.match_rewards_accrued,
+            consume_rewards_accrued: state.consume_rewards_accrued,
+            coin_decimals,
+            pc_decimals,
+        })
+    }
+
+    /// Decodes a market from the raw market and mint accounts.
+    pub fn load(
+        program_id: &Pubkey,
+        market: &Pubkey,
+        market_data: &[u8],
+        coin_mint_data: &[u8],
+        pc_mint_data: &[u8],
+    ) -> DexResult<Self> {
+        let state = load_market_state_v2(market_data)?;
+        let view = Self::new(
+            &state,
+            program_id,
+            load_mint_decimals(coin_mint_data)?,
+            load_mint_decimals(pc_mint_data)?,
+        )?;
+        if view.keys.market != *market {
+            Err(ProgramError::InvalidAccountData)?
+        }
+        Ok(view)
+    }
+
+    /// The smallest price increment, in whole price currency per whole coin.
+    #[inline]
+    pub fn tick_size(&self) -> f64 {
+        self.price_lots_to_number(1)
+    }
+
+    /// The smallest order quantity, in whole coins.
+    #[inline]
+    pub fn min_order_size(&self) -> f64 {
+        self.quantity_lots_to_number(1)
+    }
+
+    /// The price of the most recent fill, or `None` if nothing has traded yet.
+    pub fn last_price(&self) -> Option<f64> {
+        match self.last_trade_price {
+            0 => None,
+            price_lots => Some(self.price_lots_to_number(price_lots)),
+        }
+    }
+
+    /// Total coin traded since the market was created, in whole coins.
+    pub fn coin_volume(&self) -> f64 {
+        self.coin_volume_total as f64 / 10f64.powi(self.coin_decimals as i32)
+    }
+
+    /// Total price currency traded since the market was created, in whole units.
+    pub fn pc_volume(&self) -> f64 {
+        self.pc_volume_total as f64 / 10f64.powi(self.pc_decimals as i32)
+    }
+
+    pub fn price_lots_to_number(&self, price_lots: u64) -> f64 {
+        (price_lots as f64 * self.pc_lot_size as f64 * 10f64.powi(self.coin_decimals as i32))
+            / (self.coin_lot_size as f64 * 10f64.powi(self.pc_decimals as i32))
+    }
+
+    pub fn quantity_lots_to_number(&self, quantity_lots: u64) -> f64 {
+        quantity_lots as f64 * self.coin_lot_size as f64 / 10f64.powi(self.coin_decimals as i32)
+    }
+}
+
+/// A resting order, with its lot quantities converted using the market's lot sizes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OrderView {
+    leaf: LeafNode,
+    coin_lot_size: u64,
+    pc_lot_size: u64,
+}
+
+impl OrderView {
+    #[inline]
+    pub fn leaf(&self) -> &LeafNode {
+        &self.leaf
+    }
+
+    #[inline]
+    pub fn order_id(&self) -> u128 {
+        *self.leaf.order_id()
+    }
+
+    #[inline]
+    pub fn owner(&self) -> Pubkey {
+        Pubkey::new(bytes_of(self.leaf.owner()))
+    }
+
+    #[inline]
+    pub fn price_lots(&self) -> u64 {
+        self.leaf.price().get()
+    }
+
+    #[inline]
+    pub fn quantity_lots(&self) -> u64 {
+        self.leaf.quantity()
+    }
+
+    /// Native price currency units paid for one coin lot.
+    #[inline]
+    pub fn native_pc_per_coin_lot(&self) -> u64 {
+        self.price_lots() * self.pc_lot_size
+    }
+
+    #[inline]
+    pub fn native_coin_qty(&self) -> u64 {
+        self.quantity_lots() * self.coin_lot_size
+    }
+
+    #[inline]
+    pub fn native_pc_qty(&self) -> u64 {
+        self.quantity_lots() * self.native_pc_per_coin_lot()
+    }
+}
+
+/// All resting orders at a single price.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PriceLevel {
+    pub price_lots: u64,
+    pub quantity_lots: u64,
+    pub order_count: usize,
+    pub native_pc_per_coin_lot: u64,
+    pub native_coin_qty: u64,
+}
+
+/// One side of the order book, decoded from the raw bids or asks account.
+pub struct OrderBookSide {
+    side: Side,
+    orders: Vec<OrderView>,
+}
+
+impl OrderBookSide {
+    pub fn decode(
+        data: &[u8],
+        side: Side,
+        coin_lot_size: u64,
+        pc_lot_size: u64,
+    ) -> DexResult<Self> {
+        let (expected_flag, flags_error) = match side {
+            Side::Bid => (AccountFlag::Bids, DexErrorCode::InvalidBidFlags),
+            Side::Ask => (AccountFlag::Asks, DexErrorCode::InvalidAskFlags),
+        };
+        const HEADER_WORDS: usize = size_of::<OrderBookStateHeader>() / size_of::<u64>();
+
+        let mut words = strip_dex_padding(data)?;
+        if words.len() * size_of::<u64>() < (HEADER_WORDS * size_of::<u64>()) + SLAB_HEADER_LEN {
+            Err(DexErrorCode::SlabTooSmall)?
+        }
+        let (header_words, slab_words) = words.split_at_mut(HEADER_WORDS);
+        let header: &OrderBookStateHeader = from_bytes(cast_slice(header_words));
+        let flags: BitFlags<AccountFlag> =
+            BitFlags::from_bits(header.account_flags).map_err(|_| flags_error)?;
+        if flags != AccountFlag::Initialized | expected_flag {
+            Err(flags_error)?
+        }
+
+        let slab = Slab::new(cast_slice_mut(slab_words));
+        let orders = slab
+            .leaves(side == Side::Bid)
+            .map(|leaf| OrderView {
+                leaf: *leaf,
+                coin_lot_size,
+                pc_lot_size,
+            })
+            .collect();
+        Ok(OrderBookSide { side, orders })
+    }
+
+    #[inline]
+    pub fn side(&self) -> Side {
+        self.side
+    }
+
+    /// Orders sorted from the most to the least aggressive price, and by time
+    /// priority within a price.
+    #[inline]
+    pub fn orders(&self) -> &[OrderView] {
+        &self.orders
+    }
+
+    #[inline]
+    pub fn best(&self) -> Option<&OrderView> {
+        self.orders.first()
+    }
+
+    /// Aggregates the orders by price level, most aggressive price first.
+    pub fn levels(&self) -> Vec<PriceLevel> {
+        let mut levels: Vec<PriceLevel> = Vec::new();
+        for order in &self.orders {
+            match levels.last_mut() {
+                Some(level) if level.price_lots == order.price_lots() => {
+                    level.quantity_lots += order.quantity_lots();
+                    level.native_coin_qty += order.native_coin_qty();
+                    level.order_count += 1;
+                }
+                _ => levels.push(PriceLevel {
+                    price_lots: order.price_lots(),
+                    quantity_lots: order.quantity_lots(),
+                    order_count: 1,
+                    native_pc_per_coin_lot: order.native_pc_per_coin_lot(),
+                    native_coin_qty: order.native_coin_qty(),
+                }),
+            }
+        }
+        levels
+    }
+}
+
+/// A fill read off the event queue.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FillView {
+    pub seq_num: u64,
+    pub slot: u64,
+    pub side: Side,
+    pub maker: bool,
+    /// In pc lots per coin lot: the price of the maker order filled against.
+    pub price_lots: u64,
+    pub native_qty_paid: u64,
+    pub native_qty_received: u64,
+    pub native_fee_or_rebate: u64,
+    pub order_id: u128,
+    pub owner: Pubkey,
+    pub owner_slot: u8,
+    pub client_order_id: Option<NonZeroU64>,
+}
+
+/// The events a market's event queue holds that have not been consumed yet,
+/// decoded from the raw event queue account.
+pub struct EventQueueView {
+    next_seq_num: u64,
+    events: Vec<Event>,
+}
+
+impl EventQueueView {
+    pub fn decode(data: &[u8]) -> DexResult<Self> {
+        Self::decode_events(data, size_of::<Event>(), EVENT_VERSION)
+    }
+
+    /// Decodes the event queue of a market of the original size, whose
+    /// events are version 0. They have no sequence number, slot or price,
+    /// which read as 0.
+    pub fn decode_original(data: &[u8]) -> DexResult<Self> {
+        Self::decode_events(data, ORIGINAL_EVENT_LEN, 0)
+    }
+
+    fn decode_events(data: &[u8], event_len: usize, version: u8) -> DexResult<Self> {
+        const HEADER_WORDS: usize = size_of::<EventQueueHeader>() / size_of::<u64>();
+
+        let words = strip_dex_padding(data)?;
+        if words.len() < HEADER_WORDS {
+            Err(DexErrorCode::EventQueueTooSmall)?
+        }
+        let (header_words, event_words) = words.split_at(HEADER_WORDS);
+        let header: &EventQueueHeader = from_bytes(cast_slice(header_words));
+        let flags: BitFlags<AccountFlag> = BitFlags::from_bits(header.account_flags)
+            .map_err(|_| DexErrorCode::InvalidEventQueueFlags)?;
+        if flags != AccountFlag::Initialized | AccountFlag::EventQueue {
+            Err(DexErrorCode::InvalidEventQueueFlags)?
+        }
+
+        // events may need a stricter alignment than the words they were copied into
+        let buf: Vec<Event> = cast_slice::<u64, u8>(event_words)
+            .chunks_exact(event_len)
+            .map(|bytes| {
+                let mut event = Event::zeroed();
+                bytes_of_mut(&mut event)[..event_len].copy_from_slice(bytes);
+                event
+            })
+            .collect();
+        let (head, count) = (header.head(), header.count());
+        if buf.is_empty() || head >= buf.len() as u64 || count > buf.len() as u64 {
+            Err(DexErrorCode::InvalidQueueLength)?
+        }
+
+        let mut events = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let event = buf[((head + i) % buf.len() as u64) as usize];
+            if event.version() != version {
+                Err(DexErrorCode::UnsupportedEventVersion)?
+            }
+            event.as_view()?;
+            events.push(event);
+        }
+        Ok(EventQueueView {
+            next_seq_num: header.seq_num,
+            events,
+        })
+    }
+
+    /// The sequence number the next event pushed onto the queue will get.
+    #[inline]
+    pub fn next_seq_num(&self) -> u64 {
+        self.next_seq_num
+    }
+
+    /// Oldest event first.
+    #[inline]
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// The fills among the events, oldest first.
+    pub fn fills(&self) -> Vec<FillView> {
+        self.events
+            .iter()
+            .filter_map(|event| match event.as_view() {
+                Ok(EventView::Fill {
+                    side,
+                    maker,
+                    native_qty_paid,
+                    native_qty_received,
+                    native_fee_or_rebate,
+                    order_id,
+                    owner,
+                    owner_slot,
+                    fee_tier: _,
+                    client_order_id,
+                    price,
+                }) => Some(FillView {
+                    seq_num: event.seq_num(),
+                    slot: event.slot(),
+                    side,
+                    maker,
+                    price_lots: price,
+                    native_qty_paid,
+                    native_qty_received,
+                    native_fee_or_rebate,
+                    order_id: *order_id,
+                    owner: Pubkey::new(bytes_of(owner)),
+                    owner_slot,
+                    client_order_id,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        fees::FeeTier,
+        state::{EventQueue, ToAlignedBytes},
+    };
+    use bytemuck::Pod;
+    use std::cell::{RefCell, RefMut};
+
+    fn random_pubkey() -> Pubkey {
+        Pubkey::new(&rand::random::<[u8; 32]>())
+    }
+
+    fn market_account_data<T: Pod>(state: &T) -> Vec<u8> {
+        let mut data = ACCOUNT_HEAD_PADDING.to_vec();
+        data.extend_from_slice(bytes_of(state));
+        data.extend_from_slice(ACCOUNT_TAIL_PADDING);
+        data
+    }
+
+    fn mint_account_data(decimals: u8) -> Vec<u8> {
+        let mut data = vec![0u8; spl_token::state::Mint::LEN];
+        data[0x2c] = decimals;
+        data[0x2d] = 1;
+        data
+    }
+
+    fn leaf(side: Side, price: u64, seq_num: u64, quantity: u64) -> LeafNode {
+        let lower = match side {
+            Side::Bid => !seq_num,
+            Side::Ask => seq_num,
+        };
+        let key = ((price as u128) << 64) | (lower as u128);
+        LeafNode::new(
+            0,
+            &key,
+            &[seq_num; 4],
+            quantity,
+            FeeTier::Base,
+            seq_num,
+            None,
+        )
+    }
+
+    fn book_account_data(flag: AccountFlag, leaves: &[LeafNode]) -> Vec<u8> {
+        let mut slab_words = vec![0u64; 1_000];
+        {
+            let slab = Slab::new(cast_slice_mut(slab_words.as_mut_slice()));
+            for leaf in leaves {
+                slab.insert_leaf(leaf).unwrap();
+            }
+        }
+        let flags = (AccountFlag::Initialized | flag).bits();
+        let mut data = ACCOUNT_HEAD_PADDING.to_vec();
+        data.extend_from_slice(&flags.to_le_bytes());
+        data.extend_from_slice(cast_slice(slab_words.as_slice()));
+        data.extend_from_slice(ACCOUNT_TAIL_PADDING);
+        data
+    }
+
+    #[test]
+    fn test_decode_bids() {
+        let data = book_account_data(
+            AccountFlag::Bids,
+            &[
+                leaf(Side::Bid, 100, 1, 3),
+                leaf(Side::Bid, 99, 2, 4),
+                leaf(Side::Bid, 105, 3, 5),
+                leaf(Side::Bid, 100, 4, 6),
+            ],
+        );
+        let bids = OrderBookSide::decode(&data, Side::Bid, 10, 2).unwrap();
+        let prices_and_seq: Vec<(u64, u64)> = bids
+            .orders()
+            .iter()
+            .map(|o| (o.price_lots(), o.leaf().client_order_id()))
+            .collect();
+        assert_eq!(prices_and_seq, vec![(105, 3), (100, 1), (100, 4), (99, 2)]);
+
+        let best = bids.best().unwrap();
+        assert_eq!(best.native_pc_per_coin_lot(), 210);
+        assert_eq!(best.native_coin_qty(), 50);
+        assert_eq!(best.native_pc_qty(), 1050);
+        assert_eq!(best.owner(), Pubkey::new(bytes_of(&[3u64; 4])));
+
+        assert_eq!(
+            bids.levels(),
+            vec![
+                PriceLevel {
+                    price_lots: 105,
+                    quantity_lots: 5,
+                    order_count: 1,
+                    native_pc_per_coin_lot: 210,
+                    native_coin_qty: 50,
+                },
+                PriceLevel {
+                    price_lots: 100,
+                    quantity_lots: 9,
+                    order_count: 2,
+                    native_pc_per_coin_lot: 200,
+                    native_coin_qty: 90,
+                },
+                PriceLevel {
+                    price_lots: 99,
+                    quantity_lots: 4,
+                    order_count: 1,
+                    native_pc_per_coin_lot: 198,
+                    native_coin_qty: 40,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_asks() {
+        let data = book_account_data(
+            AccountFlag::Asks,
+            &[
+                leaf(Side::Ask, 100, 1, 3),
+                leaf(Side::Ask, 99, 2, 4),
+                leaf(Side::Ask, 105, 3, 5),
+                leaf(Side::Ask, 100, 4, 6),
+            ],
+        );
+        let asks = OrderBookSide::decode(&data, Side::Ask, 10, 2).unwrap();
+        let prices_and_seq: Vec<(u64, u64)> = asks
+            .orders()
+            .iter()
+            .map(|o| (o.price_lots(), o.leaf().client_order_id()))
+            .collect();
+        assert_eq!(prices_and_seq, vec![(99, 2), (100, 1), (100, 4), (105, 3)]);
+        let level_sizes: Vec<(u64, u64)> = asks
+            .levels()
+            .iter()
+            .map(|l| (l.price_lots, l.quantity_lots))
+            .collect();
+        assert_eq!(level_sizes, vec![(99, 4), (100, 9), (105, 5)]);
+    }
+
+    #[test]
+    fn test_decode_empty_book() {
+        let data = book_account_data(AccountFlag::Asks, &[]);
+        let asks = OrderBookSide::decode(&data, Side::Ask, 10, 2).unwrap();
+        assert!(asks.best().is_none());
+        assert!(asks.levels().is_empty());
+    }
+
+    #[test]
+    fn test_decode_rejects_bad_accounts() {
+        let mut data = book_account_data(AccountFlag::Bids, &[leaf(Side::Bid, 1, 1, 1)]);
+        assert_eq!(
+            OrderBookSide::decode(&data, Side::Ask, 1, 1).err(),
+            Some(DexErrorCode::InvalidAskFlags.into())
+        );
+
+        data[0] = b'x';
+        assert_eq!(
+            OrderBookSide::decode(&data, Side::Bid, 1, 1).err(),
+            Some(DexErrorCode::WrongAccountHeadPadding.into())
+        );
+
+        let len = data.len();
+        data[0] = ACCOUNT_HEAD_PADDING[0];
+        data[len - 1] = b'x';
+        assert_eq!(
+            OrderBookSide::decode(&data, Side::Bid, 1, 1).err(),
+            Some(DexErrorCode::WrongAccountTailPadding.into())
+        );
+    }
+
+    fn fill(order_id: &u128, maker: bool, price: u64, slot: u64) -> Event {
+        Event::new(
+            EventView::Fill {
+                side: Side::Bid,
+                maker,
+                native_qty_paid: price * 10,
+                native_qty_received: 10,
+                native_fee_or_rebate: 1,
+                order_id,
+                owner: &[7; 4],
+                owner_slot: 3,
+                fee_tier: FeeTier::Base,
+                client_order_id: NonZeroU64::new(42),
+                price,
+            },
+            slot,
+        )
+    }
+
+    fn event_queue_account_data(header: &EventQueueHeader, events: &[Event]) -> Vec<u8> {
+        let mut data = ACCOUNT_HEAD_PADDING.to_vec();
+        data.extend_from_slice(bytes_of(header));
+        data.extend_from_slice(cast_slice(events));
+        data.extend_from_slice(ACCOUNT_TAIL_PADDING);
+        data
+    }
+
+    #[test]
+    fn test_decode_event_queue() {
+        let mut header = EventQueueHeader::zeroed();
+        header.account_flags = (AccountFlag::Initialized | AccountFlag::EventQueue).bits();
+        let header = RefCell::new(header);
+        let events = RefCell::new(vec![Event::zeroed(); 4]);
+        {
+            let mut event_q = EventQueue::new(
+                header.borrow_mut(),
+                RefMut::map(events.borrow_mut(), Vec::as_mut_slice),
+            );
+            let out = Event::new(
+                EventView::Out {
+                    side: Side::Ask,
+                    native_qty_unlocked: 5,
+                    native_qty_still_locked: 0,
+                    order_id: &1,
+                    owner: &[7; 4],
+                    owner_slot: 4,
+                    client_order_id: None,
+                },
+                10,
+            );
+            for event in &[out, fill(&1, true, 0, 0), out, fill(&2, true, 99, 11)] {
+                event_q.push_back(*event).unwrap();
+            }
+            event_q.pop_front().unwrap();
+            event_q.pop_front().unwrap();
+            event_q.pop_front().unwrap();
+            // wraps around the end of the buffer
+            event_q.push_back(fill(&3, false, 98, 12)).unwrap();
+        }
+
+        let data = event_queue_account_data(&header.borrow(), &events.borrow());
+        let event_q = EventQueueView::decode(&data).unwrap();
+        assert_eq!(event_q.next_seq_num(), 5);
+        assert_eq!(event_q.events().len(), 2);
+        assert_eq!(
+            event_q.fills(),
+            vec![
+                FillView {
+                    seq_num: 3,
+                    slot: 11,
+                    side: Side::Bid,
+                    maker: true,
+                    price_lots: 99,
+                    native_qty_paid: 990,
+                    native_qty_received: 10,
+                    native_fee_or_rebate: 1,
+                    order_id: 2,
+                    owner: Pubkey::new(bytes_of(&[7u64; 4])),
+                    owner_slot: 3,
+                    client_order_id: NonZeroU64::new(42),
+                },
+                FillView {
+                    seq_num: 4,
+                    slot: 12,
+                    side: Side::Bid,
+                    maker: false,
+                    price_lots: 98,
+                    native_qty_paid: 980,
+                    native_qty_received: 10,
+                    native_fee_or_rebate: 1,
+                    order_id: 3,
+                    owner: Pubkey::new(bytes_of(&[7u64; 4])),
+                    owner_slot: 3,
+                    client_order_id: NonZeroU64::new(42),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_original_event_queue() {
+        let mut header = EventQueueHeader::zeroed();
+        header.account_flags = (AccountFlag::Initialized | AccountFlag::EventQueue).bits();
+        let header = RefCell::new(header);
+        let events = RefCell::new(vec![0u8; 3 * ORIGINAL_EVENT_LEN]);
+        {
+            let mut event_q = EventQueue::original(
+                header.borrow_mut(),
+                RefMut::map(events.borrow_mut(), Vec::as_mut_slice),
+            );
+            assert_eq!(event_q.event_version(), 0);
+            event_q.push_back(fill(&1, true, 100, 9)).unwrap();
+            event_q.push_back(fill(&1, false, 100, 9)).unwrap();
+        }
+
+        let mut data = ACCOUNT_HEAD_PADDING.to_vec();
+        data.extend_from_slice(bytes_of(&*header.borrow()));
+        data.extend_from_slice(&events.borrow());
+        data.extend_from_slice(ACCOUNT_TAIL_PADDING);
+        let event_q = EventQueueView::decode_original(&data).unwrap();
+        assert_eq!(event_q.next_seq_num(), 2);
+        assert!(event_q.events().iter().all(|event| event.version() == 0));
+        // only the fields of the original layout are kept
+        let fills = event_q.fills();
+        assert_eq!(fills.len(), 2);
+        assert_eq!(
+            fills[1],
+            FillView {
+                seq_num: 0,
+                slot: 0,
+                side: Side::Bid,
+                maker: false,
+                price_lots: 0,
+                native_qty_paid: 1000,
+                native_qty_received: 10,
+                native_fee_or_rebate: 1,
+                order_id: 1,
+                owner: Pubkey::new(bytes_of(&[7u64; 4])),
+                owner_slot: 3,
+                client_order_id: NonZeroU64::new(42),
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_event_queue_rejects_bad_accounts() {
+        let mut header = EventQueueHeader::zeroed();
+        header.account_flags = (AccountFlag::Initialized | AccountFlag::RequestQueue).bits();
+        let events = vec![Event::zeroed(); 4];
+        assert_eq!(
+            EventQueueView::decode(&event_queue_account_data(&header, &events)).err(),
+            Some(DexErrorCode::InvalidEventQueueFlags.into())
+        );
+
+        header.account_flags = (AccountFlag::Initialized | AccountFlag::EventQueue).bits();
+        let header = RefCell::new(header);
+        let events = RefCell::new(events);
+        EventQueue::new(
+            header.borrow_mut(),
+            RefMut::map(events.borrow_mut(), Vec::as_mut_slice),
+        )
+        .push_back(fill(&1, true, 100, 1))
+        .unwrap();
+        let mut data = event_queue_account_data(&header.borrow(), &events.borrow());
+        assert!(EventQueueView::decode(&data).is_ok());
+
+        // an event written before the layout was versioned
+        let version_offset = ACCOUNT_HEAD_PADDING.len() + size_of::<EventQueueHeader>() + 3;
+        data[version_offset] = 0;
+        assert_eq!(
+            EventQueueView::decode(&data).err(),
+            Some(DexErrorCode::UnsupportedEventVersion.into())
+        );
+    }
+
+    #[test]
+    fn test_load_market() {
+        let program_id = random_pubkey();
+        let market = random_pubkey();
+        let keys: Vec<Pubkey> = (0..8).map(|_| random_pubkey()).collect();
+
+        let mut state = MarketStateV2::zeroed();
+        state.account_flags = (AccountFlag::Initialized | AccountFlag::Market).bits();
+        state.own_address = market.to_aligned_bytes();
+        state.coin_mint = keys[0].to_aligned_bytes();
+        state.pc_mint = keys[1].to_aligned_bytes();
+        state.coin_vault = keys[2].to_aligned_bytes();
+        state.pc_vault = keys[3].to_aligned_bytes();
+        state.req_q = keys[4].to_aligned_bytes();
+        state.event_q = keys[5].to_aligned_bytes();
+        state.bids = keys[6].to_aligned_bytes();
+        state.asks = keys[7].to_aligned_bytes();
+        state.coin_lot_size = 1_000_000;
+        state.pc_lot_size = 100;
+        state.fee_rate_bps = 22;
+        state.fee_schedule = FeeSchedule::default();
+        let data = market_account_data(&state);
+
+        let view = MarketView::load(
+            &program_id,
+            &market,
+            &data,
+            &mint_account_data(9),
+            &mint_account_data(6),
+        )
+        .unwrap();
+        assert_eq!(
+            view.keys,
+            MarketKeys {
+                market,
+                coin_mint: keys[0],
+                pc_mint: keys[1],
+                coin_vault: keys[2],
+                pc_vault: keys[3],
+                req_q: keys[4],
+                event_q: keys[5],
+                bids: keys[6],
+                asks: keys[7],
+                vault_signer: gen_vault_signer_key(0, &market, &program_id).unwrap(),
+            }
+        );
+        assert_eq!(view.coin_lot_size, 1_000_000);
+        assert_eq!(view.pc_lot_size, 100);
+        assert_eq!(view.fee_rate_bps, 22);
+        assert_eq!(view.fee_schedule, FeeSchedule::default());
+        assert_eq!(view.authority, None);
+        assert!(!view.paused);
+        assert_eq!(view.last_trade_price, 0);
+        assert_eq!(view.price_band_bps, 0);
+        assert_eq!(view.last_price(), None);
+        assert_eq!((view.coin_volume_total, view.pc_volume_total), (0, 0));
+        assert_eq!(view.fill_count, 0);
+        assert_eq!(view.crank_fee_lamports, 0);
+        assert_eq!((view.coin_decimals, view.pc_decimals), (9, 6));
+        assert_eq!(view.tick_size(), 0.1);
+        assert_eq!(view.min_order_size(), 0.001);
+        assert_eq!(view.price_lots_to_number(25), 2.5);
+        assert_eq!(view.quantity_lots_to_number(3_000), 3.0);
+
+        assert_eq!(
+            MarketKeys::load(&program_id, &random_pubkey(), &data).err(),
+            Some(ProgramError::InvalidAccountData.into())
+        );
+        assert_eq!(
+            MarketKeys::load(&program_id, &market, &data[..data.len() - 8]).err(),
+            Some(DexErrorCode::WrongAccountTailPadding.into())
+        );
+
+        let authority = random_pubkey();
+        state.authority = authority.to_aligned_bytes();
+        state.account_flags |= BitFlags::from_flag(AccountFlag::Paused).bits();
+        let view = MarketView::load(
+            &program_id,
+            &market,
+            &market_account_data(&state),
+            &mint_account_data(9),
+            &mint_account_data(6),
+        )
+        .unwrap();
+        assert_eq!(view.authority, Some(authority));
+        assert!(view.paused);
+
+        state.account_flags |= BitFlags::from_flag(AccountFlag::Disabled).bits();
+        assert_eq!(
+            load_market_state(&market_account_data(&state)).err(),
+            Some(DexErrorCode::InvalidMarketFlags.into())
+        );
+    }
+
+    #[test]
+    fn test_load_original_market() {
+        let program_id = random_pubkey();
+        let market = random_pubkey();
+
+        let mut state = MarketState::zeroed();
+        state.account_flags = (AccountFlag::Initialized | AccountFlag::Market).bits();
+        state.own_address = market.to_aligned_bytes();
+        state.coin_lot_size = 1_000_000;
+        let data = market_account_data(&state);
+
+        let keys = MarketKeys::load(&program_id, &market, &data).unwrap();
+        assert_eq!(keys.market, market);
+        assert_eq!(load_market_state(&data).unwrap().coin_lot_size, 1_000_000);
+        assert_eq!(
+            MarketView::load(
+                &program_id,
+                &market,
+                &data,
+                &mint_account_data(9),
+                &mint_account_data(6),
+            )
+            .err(),
+            Some(DexErrorCode::UnsupportedMarketVersion.into())
+        );
+    }
+}
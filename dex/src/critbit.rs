@@ -1,6 +1,7 @@
 use crate::{
-    error::{DexErrorCode, DexResult},
+    error::{DexErrorCode, DexResult, SourceFileId},
     fees::FeeTier,
+    instruction::OrderExpiry,
 };
 use arrayref::{array_refs, mut_array_refs};
 use bytemuck::{cast, cast_mut, cast_ref, cast_slice, cast_slice_mut, Pod, Zeroable};
@@ -9,10 +10,14 @@ use num_enum::{IntoPrimitive, TryFromPrimitive};
 use static_assertions::const_assert_eq;
 use std::{
     convert::TryFrom,
+    fmt::Debug,
+    marker::PhantomData,
     mem::{align_of, size_of},
     num::NonZeroU64,
 };
 
+declare_check_assert_macros!(SourceFileId::Critbit);
+
 pub type NodeHandle = u32;
 
 #[derive(IntoPrimitive, TryFromPrimitive)]
@@ -25,19 +30,88 @@ enum NodeTag {
     LastFreeNode = 4,
 }
 
+/// What every node has past the 72 bytes of the original layout, which only
+/// leaves use: the order's expiry and iceberg quantities. The books of markets
+/// of the original size use `()`, and their orders have neither.
+pub trait NodeTail: Pod + Debug + PartialEq + Eq {
+    fn new(expiry: u64, display_quantity: u64, reserve_quantity: u64) -> Self;
+    fn expiry(&self) -> u64;
+    fn display_quantity(&self) -> Option<u64>;
+    fn reserve_quantity(&self) -> u64;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(C)]
+pub struct LeafTail {
+    expiry: u64,
+    display_quantity: u64,
+    reserve_quantity: u64,
+}
+unsafe impl Zeroable for LeafTail {}
+unsafe impl Pod for LeafTail {}
+
+impl NodeTail for LeafTail {
+    #[inline]
+    fn new(expiry: u64, display_quantity: u64, reserve_quantity: u64) -> Self {
+        LeafTail {
+            expiry,
+            display_quantity,
+            reserve_quantity,
+        }
+    }
+
+    #[inline]
+    fn expiry(&self) -> u64 {
+        self.expiry
+    }
+
+    #[inline]
+    fn display_quantity(&self) -> Option<u64> {
+        Some(self.display_quantity)
+    }
+
+    #[inline]
+    fn reserve_quantity(&self) -> u64 {
+        self.reserve_quantity
+    }
+}
+
+impl NodeTail for () {
+    #[inline]
+    fn new(expiry: u64, _display_quantity: u64, reserve_quantity: u64) -> Self {
+        debug_assert!(expiry == 0 && reserve_quantity == 0);
+    }
+
+    #[inline]
+    fn expiry(&self) -> u64 {
+        0
+    }
+
+    #[inline]
+    fn display_quantity(&self) -> Option<u64> {
+        None
+    }
+
+    #[inline]
+    fn reserve_quantity(&self) -> u64 {
+        0
+    }
+}
+
 #[derive(Copy, Clone)]
 #[repr(C, align(8))]
-struct InnerNode {
+struct InnerNode<X = LeafTail> {
     tag: u32,
     prefix_len: u32,
     key: u128,
     children: [u32; 2],
     _padding: [u64; 5],
+    _tail: X,
 }
-unsafe impl Zeroable for InnerNode {}
-unsafe impl Pod for InnerNode {}
+unsafe impl<X: NodeTail> Zeroable for InnerNode<X> {}
+unsafe impl<X: NodeTail> Pod for InnerNode<X> {}
 
-impl InnerNode {
+impl<X> InnerNode<X> {
     fn walk_down(&self, search_key: &u128) -> (NodeHandle, bool) {
         let crit_bit_mask = (1u128 << 127) >> self.prefix_len;
         let crit_bit = (*search_key & crit_bit_mask) != 0;
@@ -47,20 +121,22 @@ impl InnerNode {
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(C, align(8))]
-pub struct LeafNode {
+pub struct LeafNode<X = LeafTail> {
     tag: u32,
     owner_slot: u8,
     fee_tier: u8,
-    padding: [u8; 2],
+    expiry_kind: u8,
+    padding: [u8; 1],
     key: u128,
     owner: [u64; 4],
     quantity: u64,
     client_order_id: u64,
+    tail: X,
 }
-unsafe impl Zeroable for LeafNode {}
-unsafe impl Pod for LeafNode {}
+unsafe impl<X: NodeTail> Zeroable for LeafNode<X> {}
+unsafe impl<X: NodeTail> Pod for LeafNode<X> {}
 
-impl LeafNode {
+impl<X: NodeTail> LeafNode<X> {
     #[inline]
     pub fn new(
         owner_slot: u8,
@@ -69,16 +145,50 @@ impl LeafNode {
         quantity: u64,
         fee_tier: FeeTier,
         client_order_id: u64,
+        expiry: Option<OrderExpiry>,
     ) -> Self {
+        let (expiry_kind, expiry) = OrderExpiry::to_parts(expiry);
         LeafNode {
             tag: NodeTag::LeafNode.into(),
             owner_slot,
             fee_tier: fee_tier.into(),
-            padding: [0; 2],
+            expiry_kind,
+            padding: [0; 1],
             key: *key,
             owner: *owner,
             quantity,
             client_order_id,
+            tail: X::new(expiry, quantity, 0),
+        }
+    }
+
+    /// Turns the order into an iceberg: `quantity` stays the size shown on
+    /// the book, and `reserve_quantity` more is posted in slices of that size
+    /// as each one fills. The reserve is only hidden from book views; it is
+    /// still stored in the account data.
+    #[inline]
+    pub fn with_reserve(self, reserve_quantity: u64) -> Self {
+        LeafNode {
+            tail: X::new(self.tail.expiry(), self.quantity, reserve_quantity),
+            ..self
+        }
+    }
+
+    /// The next slice of an iceberg order, to be posted under `key` once the
+    /// visible quantity has been filled.
+    #[inline]
+    pub fn replenished(&self, key: &u128) -> Self {
+        let display_quantity = self.display_quantity();
+        let quantity = display_quantity.min(self.reserve_quantity());
+        LeafNode {
+            key: *key,
+            quantity,
+            tail: X::new(
+                self.tail.expiry(),
+                display_quantity,
+                self.reserve_quantity() - quantity,
+            ),
+            ..*self
         }
     }
 
@@ -107,6 +217,23 @@ impl LeafNode {
         &mut self.quantity
     }
 
+    #[inline]
+    pub fn reserve_quantity(&self) -> u64 {
+        self.tail.reserve_quantity()
+    }
+
+    /// The size of each slice an iceberg order reposts from its reserve.
+    #[inline]
+    pub fn display_quantity(&self) -> u64 {
+        self.tail.display_quantity().unwrap_or(self.quantity)
+    }
+
+    /// The visible and the reserve quantity together.
+    #[inline]
+    pub fn total_quantity(&self) -> u64 {
+        self.quantity + self.reserve_quantity()
+    }
+
     #[inline]
     pub fn owner(&self) -> &[u64; 4] {
         &self.owner
@@ -121,17 +248,27 @@ impl LeafNode {
     pub fn client_order_id(&self) -> u64 {
         self.client_order_id
     }
+
+    /// Fails if the stored expiry kind is not one this program writes.
+    #[inline]
+    pub fn expiry(&self) -> DexResult<Option<OrderExpiry>> {
+        Ok(
+            OrderExpiry::from_parts(self.expiry_kind, self.tail.expiry())
+                .ok_or(assertion_error!())?,
+        )
+    }
 }
 
 #[derive(Copy, Clone)]
 #[repr(C, align(8))]
-struct FreeNode {
+struct FreeNode<X = LeafTail> {
     tag: u32,
     next: u32,
     _padding: [u64; 8],
+    _tail: X,
 }
-unsafe impl Zeroable for FreeNode {}
-unsafe impl Pod for FreeNode {}
+unsafe impl<X: NodeTail> Zeroable for FreeNode<X> {}
+unsafe impl<X: NodeTail> Pod for FreeNode<X> {}
 
 const fn _const_max(a: usize, b: usize) -> usize {
     let gt = (a > b) as usize;
@@ -141,7 +278,8 @@ const fn _const_max(a: usize, b: usize) -> usize {
 const _INNER_NODE_SIZE: usize = size_of::<InnerNode>();
 const _LEAF_NODE_SIZE: usize = size_of::<LeafNode>();
 const _FREE_NODE_SIZE: usize = size_of::<FreeNode>();
-const _NODE_SIZE: usize = 72;
+const _NODE_SIZE: usize = 96;
+const _ORIGINAL_NODE_SIZE: usize = 72;
 
 const _INNER_NODE_ALIGN: usize = align_of::<InnerNode>();
 const _LEAF_NODE_ALIGN: usize = align_of::<LeafNode>();
@@ -152,30 +290,35 @@ const_assert_eq!(_NODE_SIZE, _INNER_NODE_SIZE);
 const_assert_eq!(_NODE_SIZE, _LEAF_NODE_SIZE);
 const_assert_eq!(_NODE_SIZE, _FREE_NODE_SIZE);
 
+const_assert_eq!(_ORIGINAL_NODE_SIZE, size_of::<InnerNode<()>>());
+const_assert_eq!(_ORIGINAL_NODE_SIZE, size_of::<LeafNode<()>>());
+const_assert_eq!(_ORIGINAL_NODE_SIZE, size_of::<FreeNode<()>>());
+
 const_assert_eq!(_NODE_ALIGN, _INNER_NODE_ALIGN);
 const_assert_eq!(_NODE_ALIGN, _LEAF_NODE_ALIGN);
 const_assert_eq!(_NODE_ALIGN, _FREE_NODE_ALIGN);
 
 #[derive(Copy, Clone)]
 #[repr(C, align(8))]
-pub struct AnyNode {
+pub struct AnyNode<X = LeafTail> {
     tag: u32,
     padding: [u32; 17],
+    tail: X,
 }
-unsafe impl Zeroable for AnyNode {}
-unsafe impl Pod for AnyNode {}
+unsafe impl<X: NodeTail> Zeroable for AnyNode<X> {}
+unsafe impl<X: NodeTail> Pod for AnyNode<X> {}
 
-enum NodeRef<'a> {
-    Inner(&'a InnerNode),
-    Leaf(&'a LeafNode),
+enum NodeRef<'a, X> {
+    Inner(&'a InnerNode<X>),
+    Leaf(&'a LeafNode<X>),
 }
 
-enum NodeRefMut<'a> {
-    Inner(&'a mut InnerNode),
-    Leaf(&'a mut LeafNode),
+enum NodeRefMut<'a, X> {
+    Inner(&'a mut InnerNode<X>),
+    Leaf(&'a mut LeafNode<X>),
 }
 
-impl AnyNode {
+impl<X: NodeTail> AnyNode<X> {
     fn key(&self) -> Option<u128> {
         match self.case()? {
             NodeRef::Inner(inner) => Some(inner.key),
@@ -183,7 +326,6 @@ impl AnyNode {
         }
     }
 
-    #[cfg(test)]
     fn prefix_len(&self) -> u32 {
         match self.case().unwrap() {
             NodeRef::Inner(&InnerNode { prefix_len, .. }) => prefix_len,
@@ -198,7 +340,7 @@ impl AnyNode {
         }
     }
 
-    fn case(&self) -> Option<NodeRef> {
+    fn case(&self) -> Option<NodeRef<X>> {
         match NodeTag::try_from(self.tag) {
             Ok(NodeTag::InnerNode) => Some(NodeRef::Inner(cast_ref(self))),
             Ok(NodeTag::LeafNode) => Some(NodeRef::Leaf(cast_ref(self))),
@@ -206,7 +348,7 @@ impl AnyNode {
         }
     }
 
-    fn case_mut(&mut self) -> Option<NodeRefMut> {
+    fn case_mut(&mut self) -> Option<NodeRefMut<X>> {
         match NodeTag::try_from(self.tag) {
             Ok(NodeTag::InnerNode) => Some(NodeRefMut::Inner(cast_mut(self))),
             Ok(NodeTag::LeafNode) => Some(NodeRefMut::Leaf(cast_mut(self))),
@@ -215,7 +357,15 @@ impl AnyNode {
     }
 
     #[inline]
-    pub fn as_leaf_mut(&mut self) -> Option<&mut LeafNode> {
+    pub fn as_leaf(&self) -> Option<&LeafNode<X>> {
+        match self.case() {
+            Some(NodeRef::Leaf(leaf_ref)) => Some(leaf_ref),
+            _ => None,
+        }
+    }
+
+    #[inline]
+    pub fn as_leaf_mut(&mut self) -> Option<&mut LeafNode<X>> {
         match self.case_mut() {
             Some(NodeRefMut::Leaf(leaf_ref)) => Some(leaf_ref),
             _ => None,
@@ -223,21 +373,23 @@ impl AnyNode {
     }
 }
 
-impl AsRef<AnyNode> for InnerNode {
-    fn as_ref(&self) -> &AnyNode {
+impl<X: NodeTail> AsRef<AnyNode<X>> for InnerNode<X> {
+    fn as_ref(&self) -> &AnyNode<X> {
         cast_ref(self)
     }
 }
 
-impl AsRef<AnyNode> for LeafNode {
+impl<X: NodeTail> AsRef<AnyNode<X>> for LeafNode<X> {
     #[inline]
-    fn as_ref(&self) -> &AnyNode {
+    fn as_ref(&self) -> &AnyNode<X> {
         cast_ref(self)
     }
 }
 
 const_assert_eq!(_NODE_SIZE, size_of::<AnyNode>());
 const_assert_eq!(_NODE_ALIGN, align_of::<AnyNode>());
+const_assert_eq!(_ORIGINAL_NODE_SIZE, size_of::<AnyNode<()>>());
+const_assert_eq!(_NODE_ALIGN, align_of::<AnyNode<()>>());
 
 #[derive(Copy, Clone)]
 #[repr(C)]
@@ -252,7 +404,7 @@ struct SlabHeader {
 unsafe impl Zeroable for SlabHeader {}
 unsafe impl Pod for SlabHeader {}
 
-const SLAB_HEADER_LEN: usize = size_of::<SlabHeader>();
+pub(crate) const SLAB_HEADER_LEN: usize = size_of::<SlabHeader>();
 
 #[cfg(debug_assertions)]
 unsafe fn invariant(check: bool) {
@@ -269,14 +421,16 @@ unsafe fn invariant(check: bool) {
     }
 }
 
+/// A critbit tree of orders, over nodes that end in `X`. The books of
+/// markets of the original size are `Slab<()>`.
 #[repr(transparent)]
-pub struct Slab([u8]);
+pub struct Slab<X = LeafTail>(PhantomData<X>, [u8]);
 
-impl Slab {
+impl<X: NodeTail> Slab<X> {
     /// Creates a slab that holds and references the bytes
     ///
     /// ```compile_fail
-    /// let slab = {
+    /// let slab: &mut serum_dex::critbit::Slab = {
     ///     let mut bytes = [10; 100];
     ///     serum_dex::critbit::Slab::new(&mut bytes)
     /// };
@@ -284,10 +438,10 @@ impl Slab {
     #[inline]
     pub fn new(bytes: &mut [u8]) -> &mut Self {
         let len_without_header = bytes.len().checked_sub(SLAB_HEADER_LEN).unwrap();
-        let slop = len_without_header % size_of::<AnyNode>();
+        let slop = len_without_header % size_of::<AnyNode<X>>();
         let truncated_len = bytes.len() - slop;
         let bytes = &mut bytes[..truncated_len];
-        let slab: &mut Self = unsafe { &mut *(bytes as *mut [u8] as *mut Slab) };
+        let slab: &mut Self = unsafe { &mut *(bytes as *mut [u8] as *mut Slab<X>) };
         slab.check_size_align(); // check alignment
         slab
     }
@@ -301,36 +455,38 @@ impl Slab {
     }
 
     fn check_size_align(&self) {
-        let (header_bytes, nodes_bytes) = array_refs![&self.0, SLAB_HEADER_LEN; .. ;];
+        let (header_bytes, nodes_bytes) = array_refs![&self.1, SLAB_HEADER_LEN; .. ;];
         let _header: &SlabHeader = cast_ref(header_bytes);
-        let _nodes: &[AnyNode] = cast_slice(nodes_bytes);
+        let _nodes: &[AnyNode<X>] = cast_slice(nodes_bytes);
     }
 
-    fn parts(&self) -> (&SlabHeader, &[AnyNode]) {
+    fn parts(&self) -> (&SlabHeader, &[AnyNode<X>]) {
         unsafe {
-            invariant(self.0.len() < size_of::<SlabHeader>());
-            invariant((self.0.as_ptr() as usize) % align_of::<SlabHeader>() != 0);
+            invariant(self.1.len() < size_of::<SlabHeader>());
+            invariant((self.1.as_ptr() as usize) % align_of::<SlabHeader>() != 0);
             invariant(
-                ((self.0.as_ptr() as usize) + size_of::<SlabHeader>()) % align_of::<AnyNode>() != 0,
+                ((self.1.as_ptr() as usize) + size_of::<SlabHeader>()) % align_of::<AnyNode<X>>()
+                    != 0,
             );
         }
 
-        let (header_bytes, nodes_bytes) = array_refs![&self.0, SLAB_HEADER_LEN; .. ;];
+        let (header_bytes, nodes_bytes) = array_refs![&self.1, SLAB_HEADER_LEN; .. ;];
         let header = cast_ref(header_bytes);
         let nodes = cast_slice(nodes_bytes);
         (header, nodes)
     }
 
-    fn parts_mut(&mut self) -> (&mut SlabHeader, &mut [AnyNode]) {
+    fn parts_mut(&mut self) -> (&mut SlabHeader, &mut [AnyNode<X>]) {
         unsafe {
-            invariant(self.0.len() < size_of::<SlabHeader>());
-            invariant((self.0.as_ptr() as usize) % align_of::<SlabHeader>() != 0);
+            invariant(self.1.len() < size_of::<SlabHeader>());
+            invariant((self.1.as_ptr() as usize) % align_of::<SlabHeader>() != 0);
             invariant(
-                ((self.0.as_ptr() as usize) + size_of::<SlabHeader>()) % align_of::<AnyNode>() != 0,
+                ((self.1.as_ptr() as usize) + size_of::<SlabHeader>()) % align_of::<AnyNode<X>>()
+                    != 0,
             );
         }
 
-        let (header_bytes, nodes_bytes) = mut_array_refs![&mut self.0, SLAB_HEADER_LEN; .. ;];
+        let (header_bytes, nodes_bytes) = mut_array_refs![&mut self.1, SLAB_HEADER_LEN; .. ;];
         let header = cast_mut(header_bytes);
         let nodes = cast_slice_mut(nodes_bytes);
         (header, nodes)
@@ -344,11 +500,11 @@ impl Slab {
         self.parts_mut().0
     }
 
-    fn nodes(&self) -> &[AnyNode] {
+    fn nodes(&self) -> &[AnyNode<X>] {
         self.parts().1
     }
 
-    fn nodes_mut(&mut self) -> &mut [AnyNode] {
+    fn nodes_mut(&mut self) -> &mut [AnyNode<X>] {
         self.parts_mut().1
     }
 }
@@ -364,7 +520,7 @@ pub trait SlabView<T> {
     fn contains(&self, h: NodeHandle) -> bool;
 }
 
-impl SlabView<AnyNode> for Slab {
+impl<X: NodeTail> SlabView<AnyNode<X>> for Slab<X> {
     fn capacity(&self) -> u64 {
         self.nodes().len() as u64
     }
@@ -390,7 +546,7 @@ impl SlabView<AnyNode> for Slab {
         bump_index == free_list_len
     }
 
-    fn get(&self, key: u32) -> Option<&AnyNode> {
+    fn get(&self, key: u32) -> Option<&AnyNode<X>> {
         let node = self.nodes().get(key as usize)?;
         let tag = NodeTag::try_from(node.tag);
         match tag {
@@ -399,7 +555,7 @@ impl SlabView<AnyNode> for Slab {
         }
     }
 
-    fn get_mut(&mut self, key: u32) -> Option<&mut AnyNode> {
+    fn get_mut(&mut self, key: u32) -> Option<&mut AnyNode<X>> {
         let node = self.nodes_mut().get_mut(key as usize)?;
         let tag = NodeTag::try_from(node.tag);
         match tag {
@@ -408,7 +564,7 @@ impl SlabView<AnyNode> for Slab {
         }
     }
 
-    fn insert(&mut self, val: &AnyNode) -> Result<u32, ()> {
+    fn insert(&mut self, val: &AnyNode<X>) -> Result<u32, ()> {
         match NodeTag::try_from(val.tag) {
             Ok(NodeTag::InnerNode) | Ok(NodeTag::LeafNode) => (),
             _ => unreachable!(),
@@ -442,7 +598,7 @@ impl SlabView<AnyNode> for Slab {
 
         let next_free_list_head: u32;
         {
-            let free_list_item: &FreeNode = cast_ref(node);
+            let free_list_item: &FreeNode<X> = cast_ref(node);
             next_free_list_head = free_list_item.next;
         }
         header.free_list_head = next_free_list_head;
@@ -451,11 +607,11 @@ impl SlabView<AnyNode> for Slab {
         Ok(key)
     }
 
-    fn remove(&mut self, key: u32) -> Option<AnyNode> {
+    fn remove(&mut self, key: u32) -> Option<AnyNode<X>> {
         let val = *self.get(key)?;
         let (header, nodes) = self.parts_mut();
         let any_node_ref = &mut nodes[key as usize];
-        let free_node_ref: &mut FreeNode = cast_mut(any_node_ref);
+        let free_node_ref: &mut FreeNode<X> = cast_mut(any_node_ref);
         *free_node_ref = FreeNode {
             tag: if header.free_list_len == 0 {
                 NodeTag::LastFreeNode.into()
@@ -464,6 +620,7 @@ impl SlabView<AnyNode> for Slab {
             },
             next: header.free_list_head,
             _padding: Zeroable::zeroed(),
+            _tail: Zeroable::zeroed(),
         };
         header.free_list_len += 1;
         header.free_list_head = key;
@@ -480,7 +637,7 @@ pub enum SlabTreeError {
     OutOfSpace,
 }
 
-impl Slab {
+impl<X: NodeTail> Slab<X> {
     fn root(&self) -> Option<NodeHandle> {
         if self.header().leaf_count == 0 {
             return None;
@@ -516,8 +673,8 @@ impl Slab {
     #[inline]
     pub fn insert_leaf(
         &mut self,
-        new_leaf: &LeafNode,
-    ) -> Result<(NodeHandle, Option<LeafNode>), SlabTreeError> {
+        new_leaf: &LeafNode<X>,
+    ) -> Result<(NodeHandle, Option<LeafNode<X>>), SlabTreeError> {
         let mut root: NodeHandle = match self.root() {
             Some(h) => h,
             None => {
@@ -572,13 +729,14 @@ impl Slab {
                 }
             };
 
-            let new_root: &mut InnerNode = cast_mut(self.get_mut(root).unwrap());
+            let new_root: &mut InnerNode<X> = cast_mut(self.get_mut(root).unwrap());
             *new_root = InnerNode {
                 tag: NodeTag::InnerNode.into(),
                 prefix_len: shared_prefix_len,
                 key: new_leaf.key,
                 children: [0; 2],
                 _padding: Zeroable::zeroed(),
+                _tail: Zeroable::zeroed(),
             };
 
             new_root.children[new_leaf_crit_bit as usize] = new_leaf_handle;
@@ -588,8 +746,7 @@ impl Slab {
         }
     }
 
-    #[cfg(test)]
-    fn find_by_key(&self, search_key: &u128) -> Option<NodeHandle> {
+    pub fn find_by_key(&self, search_key: &u128) -> Option<NodeHandle> {
         let mut node_handle: NodeHandle = self.root()?;
         loop {
             let node_ref = self.get(node_handle).unwrap();
@@ -612,7 +769,7 @@ impl Slab {
     }
 
     #[inline]
-    pub fn remove_by_key(&mut self, search_key: &u128) -> Option<LeafNode> {
+    pub fn remove_by_key(&mut self, search_key: &u128) -> Option<LeafNode<X>> {
         let mut parent_h = self.root()?;
         let mut child_h;
         let mut crit_bit;
@@ -659,19 +816,33 @@ impl Slab {
         Some(cast(self.remove(child_h).unwrap()))
     }
 
+    /// Iterates over the leaves in key order, starting from the largest key
+    /// if `descending` is set.
+    pub fn leaves(&self, descending: bool) -> SlabLeafIterator<X> {
+        SlabLeafIterator {
+            slab: self,
+            stack: self.root().into_iter().collect(),
+            descending,
+        }
+    }
+
     #[inline]
-    pub fn remove_min(&mut self) -> Option<LeafNode> {
+    pub fn remove_min(&mut self) -> Option<LeafNode<X>> {
         self.remove_by_key(&self.get(self.find_min()?)?.key()?)
     }
 
     #[inline]
-    pub fn remove_max(&mut self) -> Option<LeafNode> {
+    pub fn remove_max(&mut self) -> Option<LeafNode<X>> {
         self.remove_by_key(&self.get(self.find_max()?)?.key()?)
     }
 
     #[cfg(test)]
-    fn traverse(&self) -> Vec<&LeafNode> {
-        fn walk_rec<'a>(slab: &'a Slab, sub_root: NodeHandle, buf: &mut Vec<&'a LeafNode>) {
+    fn traverse(&self) -> Vec<&LeafNode<X>> {
+        fn walk_rec<'a, X: NodeTail>(
+            slab: &'a Slab<X>,
+            sub_root: NodeHandle,
+            buf: &mut Vec<&'a LeafNode<X>>,
+        ) {
             match slab.get(sub_root).unwrap().case().unwrap() {
                 NodeRef::Leaf(leaf) => {
                     buf.push(leaf);
@@ -706,8 +877,8 @@ impl Slab {
     fn check_invariants(&self) {
         // first check the live tree contents
         let mut count = 0;
-        fn check_rec(
-            slab: &Slab,
+        fn check_rec<X: NodeTail>(
+            slab: &Slab<X>,
             key: NodeHandle,
             last_prefix_len: u32,
             last_prefix: u128,
@@ -758,13 +929,41 @@ impl Slab {
                     assert_eq!(contents.tag, u32::from(NodeTag::FreeNode));
                 }
             };
-            let typed_ref: &FreeNode = cast_ref(contents);
+            let typed_ref: &FreeNode<X> = cast_ref(contents);
             next_free_node = typed_ref.next;
             free_nodes_remaining -= 1;
         }
     }
 }
 
+pub struct SlabLeafIterator<'a, X = LeafTail> {
+    slab: &'a Slab<X>,
+    stack: Vec<NodeHandle>,
+    descending: bool,
+}
+
+impl<'a, X: NodeTail> Iterator for SlabLeafIterator<'a, X> {
+    type Item = &'a LeafNode<X>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(handle) = self.stack.pop() {
+            match self.slab.get(handle)?.case()? {
+                NodeRef::Leaf(leaf) => return Some(leaf),
+                NodeRef::Inner(&InnerNode { children, .. }) => {
+                    let [first, second] = if self.descending {
+                        [children[1], children[0]]
+                    } else {
+                        children
+                    };
+                    self.stack.push(second);
+                    self.stack.push(first);
+                }
+            }
+        }
+        None
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -794,7 +993,7 @@ mod tests {
                 let key = rng.gen();
                 let owner = rng.gen();
                 let qty = rng.gen();
-                let leaf = LeafNode::new(offset, &key, &owner, qty, FeeTier::Base, 0);
+                let leaf = LeafNode::new(offset, &key, &owner, qty, FeeTier::Base, 0, None);
 
                 println!("{:x}", key);
                 println!("{}", i);
@@ -878,6 +1077,11 @@ mod tests {
                 let model_state = model.values().collect::<Vec<_>>();
                 let slab_state = slab.traverse();
                 assert_eq!(model_state, slab_state);
+                if i % 1_000 == 0 {
+                    assert_eq!(model_state, slab.leaves(false).collect::<Vec<_>>());
+                    let model_state_desc = model.values().rev().collect::<Vec<_>>();
+                    assert_eq!(model_state_desc, slab.leaves(true).collect::<Vec<_>>());
+                }
 
                 match weights[dist.sample(&mut rng)].0 {
                     op @ Op::InsertNew | op @ Op::InsertDup => {
@@ -889,7 +1093,7 @@ mod tests {
                         };
                         let owner = rng.gen();
                         let qty = rng.gen();
-                        let leaf = LeafNode::new(offset, &key, &owner, qty, FeeTier::SRM5, 5);
+                        let leaf = LeafNode::new(offset, &key, &owner, qty, FeeTier::SRM5, 5, None);
 
                         println!("Insert {:x}", key);
 
@@ -941,12 +1145,30 @@ mod tests {
         }
     }
 
+    #[test]
+    fn replenish_iceberg_leaf() {
+        let owner = [1, 2, 3, 4];
+        let leaf: LeafNode =
+            LeafNode::new(0, &7, &owner, 2, FeeTier::Base, 0, None).with_reserve(3);
+        assert_eq!(leaf.quantity(), 2);
+        assert_eq!(leaf.total_quantity(), 5);
+
+        let leaf = leaf.replenished(&8);
+        assert_eq!(leaf.order_id(), &8);
+        assert_eq!(leaf.quantity(), 2);
+        assert_eq!(leaf.reserve_quantity(), 1);
+
+        let leaf = leaf.replenished(&9);
+        assert_eq!(leaf.quantity(), 1);
+        assert_eq!(leaf.reserve_quantity(), 0);
+    }
+
     #[test]
     #[should_panic]
     fn panics_unaligned() {
         let mut aligned_buf = vec![0u64; 10_000];
         let bytes: &mut [u8] = cast_slice_mut(aligned_buf.as_mut_slice());
 
-        Slab::new(&mut bytes[1..]);
+        Slab::<LeafTail>::new(&mut bytes[1..]);
     }
 }
@@ -106,6 +106,26 @@ pub enum DexErrorCode {
     WrongRentSysvarAccount,
     RentNotProvided,
     OrdersNotRentExempt,
+    OpenOrdersNotEmpty,
+
+    InvalidFeeSchedule = 60,
+    MarketIsPaused,
+    WrongMarketAuthority,
+    WouldSelfTrade,
+    AbortOrderNotMatched,
+    InsufficientCrankFee,
+    WrongSettlementWallet,
+    InvalidOpenOrdersCapacity,
+    MarketNotDisabled,
+    MarketNotEmpty,
+    InvalidEventQueueFlags,
+    UnsupportedEventVersion,
+    UnsupportedMarketVersion,
+    ClockNotProvided,
+    NewOrderNotMatched,
+    UnsupportedOpenOrdersVersion,
+    InvalidPriceBand,
+    RequestQueueNotEmpty,
 
     Unknown = 1000,
 
@@ -1,4 +1,7 @@
+use crate::error::{DexErrorCode, DexResult};
+use bytemuck::{Pod, Zeroable};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
+use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
 
 #[cfg(test)]
@@ -79,50 +82,89 @@ impl FeeTier {
             () => FeeTier::Base,
         }
     }
+}
+
+/// The highest taker fee a market may charge, so that fee arithmetic on any
+/// u64 quantity cannot overflow.
+pub const MAX_TAKER_FEE_BPS: u16 = 1_000;
+
+/// The fee rates of a market, indexed by `FeeTier`.
+///
+/// Taker fees and maker rebates are in basis points of the quote quantity
+/// traded; the referrer rebate is in basis points of the taker fee.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+#[repr(C)]
+pub struct FeeSchedule {
+    pub taker_fee_bps: [u16; 7],
+    pub maker_rebate_bps: [u16; 7],
+    pub referrer_rebate_bps: u16,
+}
+unsafe impl Zeroable for FeeSchedule {}
+unsafe impl Pod for FeeSchedule {}
+
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        FeeSchedule {
+            taker_fee_bps: [22, 20, 18, 16, 14, 12, 10],
+            maker_rebate_bps: [3, 3, 3, 3, 3, 3, 5],
+            referrer_rebate_bps: 2_000,
+        }
+    }
+}
+
+impl FeeSchedule {
+    /// Checks that every taker fee, net of the referrer rebate, exceeds every
+    /// maker rebate by at least one basis point, so matching never pays out
+    /// more than it collects.
+    pub fn validate(&self) -> DexResult {
+        if self.referrer_rebate_bps > 10_000 {
+            Err(DexErrorCode::InvalidFeeSchedule)?
+        }
+        let max_maker_rebate_bps = *self.maker_rebate_bps.iter().max().unwrap() as u64;
+        for &taker_fee_bps in self.taker_fee_bps.iter() {
+            if taker_fee_bps > MAX_TAKER_FEE_BPS {
+                Err(DexErrorCode::InvalidFeeSchedule)?
+            }
+            let net_taker_fee = taker_fee_bps as u64 * (10_000 - self.referrer_rebate_bps as u64);
+            if net_taker_fee < (max_maker_rebate_bps + 1) * 10_000 {
+                Err(DexErrorCode::InvalidFeeSchedule)?
+            }
+        }
+        Ok(())
+    }
 
     #[inline]
-    pub fn maker_rebate(self, pc_qty: u64) -> u64 {
-        use FeeTier::*;
-        let rate: U64F64 = match self {
-            MSRM => rebate_bps(5),
-            Base | SRM2 | SRM3 | SRM4 | SRM5 | SRM6 => rebate_bps(3),
-        };
+    pub fn maker_rebate(&self, tier: FeeTier, pc_qty: u64) -> u64 {
+        let rate = rebate_bps(self.maker_rebate_bps[tier as usize] as u64);
         rate.mul_u64(pc_qty).floor()
     }
 
-    fn taker_rate(self) -> U64F64 {
-        use FeeTier::*;
-        match self {
-            Base => fee_bps(22),
-            SRM2 => fee_bps(20),
-            SRM3 => fee_bps(18),
-            SRM4 => fee_bps(16),
-            SRM5 => fee_bps(14),
-            SRM6 => fee_bps(12),
-            MSRM => fee_bps(10),
-        }
+    fn taker_rate(&self, tier: FeeTier) -> U64F64 {
+        fee_bps(self.taker_fee_bps[tier as usize] as u64)
     }
 
     #[inline]
-    pub fn taker_fee(self, pc_qty: u64) -> u64 {
-        let rate = self.taker_rate();
+    pub fn taker_fee(&self, tier: FeeTier, pc_qty: u64) -> u64 {
+        let rate = self.taker_rate(tier);
         let exact_fee: U64F64 = rate.mul_u64(pc_qty);
         exact_fee.floor() + ((exact_fee.frac_part() != 0) as u64)
     }
 
     #[inline]
-    pub fn remove_taker_fee(self, pc_qty_incl_fee: u64) -> u64 {
-        let rate = self.taker_rate();
+    pub fn remove_taker_fee(&self, tier: FeeTier, pc_qty_incl_fee: u64) -> u64 {
+        let rate = self.taker_rate(tier);
         U64F64::from_int(pc_qty_incl_fee)
             .div(U64F64::ONE.add(rate))
             .try_into()
             .unwrap()
     }
-}
 
-#[inline]
-pub fn referrer_rebate(amount: u64) -> u64 {
-    amount / 5
+    #[inline]
+    pub fn referrer_rebate(&self, amount: u64) -> u64 {
+        ((amount as u128 * self.referrer_rebate_bps as u128) / 10_000) as u64
+    }
 }
 
 #[cfg(test)]
@@ -130,11 +172,51 @@ mod tests {
     use super::*;
     use proptest::prelude::*;
 
+    fn fee_schedule_strategy() -> impl Strategy<Value = FeeSchedule> {
+        (
+            prop::array::uniform7(0..=MAX_TAKER_FEE_BPS),
+            prop::array::uniform7(0..100u16),
+            0..=10_000u16,
+        )
+            .prop_map(
+                |(taker_fee_bps, maker_rebate_bps, referrer_rebate_bps)| FeeSchedule {
+                    taker_fee_bps,
+                    maker_rebate_bps,
+                    referrer_rebate_bps,
+                },
+            )
+            .prop_filter("invalid fee schedule", |s| s.validate().is_ok())
+    }
+
+    #[test]
+    fn default_schedule_is_valid() {
+        assert!(FeeSchedule::default().validate().is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_unprofitable_schedules() {
+        let mut schedule = FeeSchedule::default();
+        schedule.maker_rebate_bps[FeeTier::MSRM as usize] = 8;
+        assert_eq!(
+            schedule.validate(),
+            Err(DexErrorCode::InvalidFeeSchedule.into())
+        );
+
+        let mut schedule = FeeSchedule::default();
+        schedule.referrer_rebate_bps = 10_001;
+        assert!(schedule.validate().is_err());
+
+        let mut schedule = FeeSchedule::default();
+        schedule.taker_fee_bps[FeeTier::Base as usize] = MAX_TAKER_FEE_BPS + 1;
+        assert!(schedule.validate().is_err());
+    }
+
     proptest! {
         #[test]
         fn positive_net_fees(tt: FeeTier, mt: FeeTier, qty in 1..=std::u64::MAX) {
-            let fee = tt.taker_fee(qty);
-            let rebate = mt.maker_rebate(qty) + referrer_rebate(fee);
+            let schedule = FeeSchedule::default();
+            let fee = schedule.taker_fee(tt, qty);
+            let rebate = schedule.maker_rebate(mt, qty) + schedule.referrer_rebate(fee);
             assert!(fee > rebate);
             let net_bps_u64f64 = (fee - rebate) as u128 * 10_000;
             let three_bps = (qty as u128) * 3;
@@ -142,6 +224,18 @@ mod tests {
             assert!(net_bps_u64f64 + dust_qty_u64f64 > three_bps, "{:x}, {:x}, {:x}", qty, net_bps_u64f64, three_bps);
         }
 
+        #[test]
+        fn valid_schedules_have_positive_net_fees(
+            schedule in fee_schedule_strategy(),
+            tt: FeeTier,
+            mt: FeeTier,
+            qty in 1..=std::u64::MAX,
+        ) {
+            let fee = schedule.taker_fee(tt, qty);
+            let rebate = schedule.maker_rebate(mt, qty) + schedule.referrer_rebate(fee);
+            assert!(fee > rebate);
+        }
+
         #[test]
         fn fee_bps_approx(bps in 1..100u64) {
             let rate = fee_bps(bps);
@@ -154,8 +248,9 @@ mod tests {
 
         #[test]
         fn market_order_cannot_cheat(tier: FeeTier, qty: u64) {
-            let qty_without_fees = tier.remove_taker_fee(qty);
-            let required_fee = tier.taker_fee(qty_without_fees) as i128;
+            let schedule = FeeSchedule::default();
+            let qty_without_fees = schedule.remove_taker_fee(tier, qty);
+            let required_fee = schedule.taker_fee(tier, qty_without_fees) as i128;
             let actual_fee = qty as i128 - qty_without_fees as i128;
             assert!([required_fee + 1, required_fee].contains(&actual_fee),
                     "actual_fee = {}, required_fee = {}",
@@ -164,8 +259,9 @@ mod tests {
 
         #[test]
         fn test_add_remove_fees(tier: FeeTier, qty in 1..=(std::u64::MAX >> 1)) {
-            let qty_with_fees = qty + tier.taker_fee(qty);
-            let qty2 = tier.remove_taker_fee(qty_with_fees);
+            let schedule = FeeSchedule::default();
+            let qty_with_fees = qty + schedule.taker_fee(tier, qty);
+            let qty2 = schedule.remove_taker_fee(tier, qty_with_fees);
             assert!([-1, 0, 1].contains(&(qty as i128 - qty2 as i128)))
         }
     }
@@ -1,9 +1,12 @@
 #![cfg_attr(not(feature = "program"), allow(unused))]
 use crate::error::DexError;
+use crate::fees::FeeSchedule;
 use crate::matching::{OrderType, Side};
+use crate::state::ToAlignedBytes;
 use bytemuck::cast;
 use serde::{Deserialize, Serialize};
 use solana_sdk::{
+    clock::Clock,
     instruction::{AccountMeta, Instruction},
     pubkey::Pubkey,
 };
@@ -58,6 +61,66 @@ pub struct InitializeMarketInstruction {
     pub pc_dust_threshold: u64,
 }
 
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+#[cfg_attr(test, proptest(no_params))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub struct InitializeMarketInstructionV2 {
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
+    pub fee_rate_bps: u16,
+    pub vault_signer_nonce: u64,
+    pub pc_dust_threshold: u64,
+    pub fee_schedule: FeeSchedule,
+}
+
+impl InitializeMarketInstruction {
+    pub fn add_fee_schedule(self, fee_schedule: FeeSchedule) -> InitializeMarketInstructionV2 {
+        let InitializeMarketInstruction {
+            coin_lot_size,
+            pc_lot_size,
+            fee_rate_bps,
+            vault_signer_nonce,
+            pc_dust_threshold,
+        } = self;
+        InitializeMarketInstructionV2 {
+            coin_lot_size,
+            pc_lot_size,
+            fee_rate_bps,
+            vault_signer_nonce,
+            pc_dust_threshold,
+            fee_schedule,
+        }
+    }
+
+    fn unpack(data: &[u8; 34]) -> Self {
+        let fields = array_refs![data, 8, 8, 2, 8, 8];
+        InitializeMarketInstruction {
+            coin_lot_size: u64::from_le_bytes(*fields.0),
+            pc_lot_size: u64::from_le_bytes(*fields.1),
+            fee_rate_bps: u16::from_le_bytes(*fields.2),
+            vault_signer_nonce: u64::from_le_bytes(*fields.3),
+            pc_dust_threshold: u64::from_le_bytes(*fields.4),
+        }
+    }
+}
+
+impl InitializeMarketInstructionV2 {
+    fn unpack(data: &[u8; 64]) -> Self {
+        let (v1_arr, taker_arr, maker_arr, referrer_arr) = array_refs![data, 34, 14, 14, 2];
+        let mut fee_schedule = FeeSchedule {
+            taker_fee_bps: [0; 7],
+            maker_rebate_bps: [0; 7],
+            referrer_rebate_bps: u16::from_le_bytes(*referrer_arr),
+        };
+        for i in 0..7 {
+            fee_schedule.taker_fee_bps[i] = u16::from_le_bytes(*array_ref![taker_arr, 2 * i, 2]);
+            fee_schedule.maker_rebate_bps[i] = u16::from_le_bytes(*array_ref![maker_arr, 2 * i, 2]);
+        }
+        InitializeMarketInstruction::unpack(v1_arr).add_fee_schedule(fee_schedule)
+    }
+}
+
 #[derive(
     PartialEq, Eq, Copy, Clone, Debug, TryFromPrimitive, IntoPrimitive, Serialize, Deserialize,
 )]
@@ -67,6 +130,321 @@ pub struct InitializeMarketInstruction {
 pub enum SelfTradeBehavior {
     DecrementTake = 0,
     CancelProvide = 1,
+    /// Fails the whole instruction instead of trading against an order with
+    /// the same owner. Only accepted where the order is matched right away.
+    AbortTransaction = 2,
+}
+
+/// The point from which a resting order may no longer be matched.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub enum OrderExpiry {
+    Slot(u64),
+    UnixTimestamp(i64),
+}
+
+impl OrderExpiry {
+    #[inline]
+    pub fn has_passed(&self, clock: &Clock) -> bool {
+        match *self {
+            OrderExpiry::Slot(slot) => clock.slot >= slot,
+            OrderExpiry::UnixTimestamp(unix_timestamp) => clock.unix_timestamp >= unix_timestamp,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn to_parts(expiry: Option<Self>) -> (u8, u64) {
+        match expiry {
+            None => (0, 0),
+            Some(OrderExpiry::Slot(slot)) => (1, slot),
+            Some(OrderExpiry::UnixTimestamp(unix_timestamp)) => (2, unix_timestamp as u64),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn from_parts(kind: u8, value: u64) -> Option<Option<Self>> {
+        match kind {
+            0 => Some(None),
+            1 => Some(Some(OrderExpiry::Slot(value))),
+            2 => Some(Some(OrderExpiry::UnixTimestamp(value as i64))),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct NewOrderInstructionV3 {
+    pub side: Side,
+    #[cfg_attr(
+        test,
+        proptest(strategy = "(1u64..=std::u64::MAX).prop_map(|x| NonZeroU64::new(x).unwrap())")
+    )]
+    pub limit_price: NonZeroU64,
+    #[cfg_attr(
+        test,
+        proptest(strategy = "(1u64..=std::u64::MAX).prop_map(|x| NonZeroU64::new(x).unwrap())")
+    )]
+    pub max_qty: NonZeroU64,
+    pub order_type: OrderType,
+    pub client_id: u64,
+    pub self_trade_behavior: SelfTradeBehavior,
+    pub expiry: Option<OrderExpiry>,
+}
+
+impl NewOrderInstructionV3 {
+    fn unpack(data: &[u8]) -> Option<Self> {
+        let v2_data_arr = array_ref![data, 0, 36];
+        let v2_instr = NewOrderInstructionV2::unpack(v2_data_arr)?;
+        let expiry = match (data.len(), data[36]) {
+            (37, 0) => None,
+            (49, 1) => {
+                let expiry_arr = array_ref![data, 37, 12];
+                let (&kind_arr, &value_arr) = array_refs![expiry_arr, 4, 8];
+                Some(match u32::from_le_bytes(kind_arr) {
+                    0 => OrderExpiry::Slot(u64::from_le_bytes(value_arr)),
+                    1 => OrderExpiry::UnixTimestamp(i64::from_le_bytes(value_arr)),
+                    _ => return None,
+                })
+            }
+            _ => return None,
+        };
+        Some(v2_instr.add_expiry(expiry))
+    }
+
+    pub fn add_max_native_pc_qty(
+        self,
+        max_native_pc_qty_including_fees: NonZeroU64,
+    ) -> NewOrderInstructionV5 {
+        let NewOrderInstructionV3 {
+            side,
+            limit_price,
+            max_qty,
+            order_type,
+            client_id,
+            self_trade_behavior,
+            expiry,
+        } = self;
+        NewOrderInstructionV5 {
+            side,
+            limit_price,
+            max_qty,
+            order_type,
+            client_id,
+            self_trade_behavior,
+            expiry,
+            max_native_pc_qty_including_fees,
+        }
+    }
+
+    /// Upgrades an order whose size is only bounded by `max_qty`.
+    pub fn without_max_native_pc_qty(self) -> NewOrderInstructionV5 {
+        self.add_max_native_pc_qty(NonZeroU64::new(std::u64::MAX).unwrap())
+    }
+
+    pub fn add_match_limit(self, limit: u16) -> NewOrderInstructionV4 {
+        let NewOrderInstructionV3 {
+            side,
+            limit_price,
+            max_qty,
+            order_type,
+            client_id,
+            self_trade_behavior,
+            expiry,
+        } = self;
+        NewOrderInstructionV4 {
+            side,
+            limit_price,
+            max_qty,
+            order_type,
+            client_id,
+            self_trade_behavior,
+            expiry,
+            limit,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct NewOrderInstructionV4 {
+    pub side: Side,
+    #[cfg_attr(
+        test,
+        proptest(strategy = "(1u64..=std::u64::MAX).prop_map(|x| NonZeroU64::new(x).unwrap())")
+    )]
+    pub limit_price: NonZeroU64,
+    #[cfg_attr(
+        test,
+        proptest(strategy = "(1u64..=std::u64::MAX).prop_map(|x| NonZeroU64::new(x).unwrap())")
+    )]
+    pub max_qty: NonZeroU64,
+    pub order_type: OrderType,
+    pub client_id: u64,
+    pub self_trade_behavior: SelfTradeBehavior,
+    pub expiry: Option<OrderExpiry>,
+    /// The maximum number of requests to match before the instruction returns.
+    pub limit: u16,
+}
+
+impl NewOrderInstructionV4 {
+    fn unpack(data: &[u8]) -> Option<Self> {
+        let (v3_data, limit_data) = data.split_at(data.len() - 2);
+        let v3_instr = NewOrderInstructionV3::unpack(v3_data)?;
+        let limit = u16::from_le_bytes(*array_ref![limit_data, 0, 2]);
+        Some(v3_instr.add_match_limit(limit))
+    }
+
+    pub fn remove_match_limit(self) -> (NewOrderInstructionV3, u16) {
+        let NewOrderInstructionV4 {
+            side,
+            limit_price,
+            max_qty,
+            order_type,
+            client_id,
+            self_trade_behavior,
+            expiry,
+            limit,
+        } = self;
+        let v3_instr = NewOrderInstructionV3 {
+            side,
+            limit_price,
+            max_qty,
+            order_type,
+            client_id,
+            self_trade_behavior,
+            expiry,
+        };
+        (v3_instr, limit)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct NewOrderInstructionV5 {
+    pub side: Side,
+    #[cfg_attr(
+        test,
+        proptest(strategy = "(1u64..=std::u64::MAX).prop_map(|x| NonZeroU64::new(x).unwrap())")
+    )]
+    pub limit_price: NonZeroU64,
+    #[cfg_attr(
+        test,
+        proptest(strategy = "(1u64..=std::u64::MAX).prop_map(|x| NonZeroU64::new(x).unwrap())")
+    )]
+    pub max_qty: NonZeroU64,
+    pub order_type: OrderType,
+    pub client_id: u64,
+    pub self_trade_behavior: SelfTradeBehavior,
+    pub expiry: Option<OrderExpiry>,
+    /// For bids, the most price currency the order may lock, fees included.
+    /// Matching stops once this budget is spent, even if less than `max_qty`
+    /// was bought. Ignored for asks.
+    #[cfg_attr(
+        test,
+        proptest(strategy = "(1u64..=std::u64::MAX).prop_map(|x| NonZeroU64::new(x).unwrap())")
+    )]
+    pub max_native_pc_qty_including_fees: NonZeroU64,
+}
+
+impl NewOrderInstructionV5 {
+    fn unpack(data: &[u8]) -> Option<Self> {
+        let (v3_data, max_native_pc_qty_data) = data.split_at(data.len() - 8);
+        let v3_instr = NewOrderInstructionV3::unpack(v3_data)?;
+        let max_native_pc_qty_including_fees = NonZeroU64::new(u64::from_le_bytes(*array_ref![
+            max_native_pc_qty_data,
+            0,
+            8
+        ]))?;
+        Some(v3_instr.add_max_native_pc_qty(max_native_pc_qty_including_fees))
+    }
+
+    pub fn add_display_qty(self, display_qty: Option<NonZeroU64>) -> NewOrderInstructionV6 {
+        let NewOrderInstructionV5 {
+            side,
+            limit_price,
+            max_qty,
+            order_type,
+            client_id,
+            self_trade_behavior,
+            expiry,
+            max_native_pc_qty_including_fees,
+        } = self;
+        NewOrderInstructionV6 {
+            side,
+            limit_price,
+            max_qty,
+            order_type,
+            client_id,
+            self_trade_behavior,
+            expiry,
+            max_native_pc_qty_including_fees,
+            display_qty,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+pub struct NewOrderInstructionV6 {
+    pub side: Side,
+    #[cfg_attr(
+        test,
+        proptest(strategy = "(1u64..=std::u64::MAX).prop_map(|x| NonZeroU64::new(x).unwrap())")
+    )]
+    pub limit_price: NonZeroU64,
+    #[cfg_attr(
+        test,
+        proptest(strategy = "(1u64..=std::u64::MAX).prop_map(|x| NonZeroU64::new(x).unwrap())")
+    )]
+    pub max_qty: NonZeroU64,
+    pub order_type: OrderType,
+    pub client_id: u64,
+    pub self_trade_behavior: SelfTradeBehavior,
+    pub expiry: Option<OrderExpiry>,
+    #[cfg_attr(
+        test,
+        proptest(strategy = "(1u64..=std::u64::MAX).prop_map(|x| NonZeroU64::new(x).unwrap())")
+    )]
+    pub max_native_pc_qty_including_fees: NonZeroU64,
+    /// The most coin lots shown on the book at once. Any part of the order
+    /// that gets posted beyond this is held in reserve and shown a slice at a
+    /// time, each slice joining the back of its price level.
+    #[cfg_attr(
+        test,
+        proptest(
+            strategy = "proptest::option::of((1u64..=std::u64::MAX).prop_map(|x| NonZeroU64::new(x).unwrap()))"
+        )
+    )]
+    pub display_qty: Option<NonZeroU64>,
+}
+
+impl NewOrderInstructionV6 {
+    fn unpack(data: &[u8]) -> Option<Self> {
+        let (v5_data, display_qty) = match data.len() {
+            46 | 58 => {
+                let (v5_data, display_data) = data.split_at(data.len() - 1);
+                if display_data != [0] {
+                    return None;
+                }
+                (v5_data, None)
+            }
+            54 | 66 => {
+                let (v5_data, display_data) = data.split_at(data.len() - 9);
+                let display_data = array_ref![display_data, 0, 9];
+                let (&[tag], &display_qty_arr) = array_refs![display_data, 1, 8];
+                if tag != 1 {
+                    return None;
+                }
+                let display_qty = NonZeroU64::new(u64::from_le_bytes(display_qty_arr))?;
+                (v5_data, Some(display_qty))
+            }
+            _ => return None,
+        };
+        let v5_instr = NewOrderInstructionV5::unpack(v5_data)?;
+        Some(v5_instr.add_display_qty(display_qty))
+    }
 }
 
 #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
@@ -88,6 +466,38 @@ pub struct NewOrderInstructionV2 {
     pub self_trade_behavior: SelfTradeBehavior,
 }
 
+impl NewOrderInstructionV2 {
+    fn unpack(data: &[u8; 36]) -> Option<Self> {
+        let (v1_data_arr, v2_data_arr) = array_refs![data, 32, 4];
+        let v1_instr = NewOrderInstructionV1::unpack(v1_data_arr)?;
+        let self_trade_behavior = SelfTradeBehavior::try_from_primitive(
+            u32::from_le_bytes(*v2_data_arr).try_into().ok()?,
+        )
+        .ok()?;
+        Some(v1_instr.add_self_trade_behavior(self_trade_behavior))
+    }
+
+    pub fn add_expiry(self, expiry: Option<OrderExpiry>) -> NewOrderInstructionV3 {
+        let NewOrderInstructionV2 {
+            side,
+            limit_price,
+            max_qty,
+            order_type,
+            client_id,
+            self_trade_behavior,
+        } = self;
+        NewOrderInstructionV3 {
+            side,
+            limit_price,
+            max_qty,
+            order_type,
+            client_id,
+            self_trade_behavior,
+            expiry,
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(test, derive(Arbitrary))]
 pub struct NewOrderInstructionV1 {
@@ -145,6 +555,8 @@ impl NewOrderInstructionV1 {
             0 => OrderType::Limit,
             1 => OrderType::ImmediateOrCancel,
             2 => OrderType::PostOnly,
+            3 => OrderType::FillOrKill,
+            4 => OrderType::PostOnlySlide,
             _ => return None,
         };
         Some(NewOrderInstructionV1 {
@@ -159,27 +571,110 @@ impl NewOrderInstructionV1 {
 
 #[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 #[cfg_attr(test, derive(Arbitrary))]
-#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
-pub struct CancelOrderInstruction {
+pub struct SendTakeInstruction {
     pub side: Side,
-    pub order_id: u128,
-    pub owner: [u64; 4], // Unused
-    pub owner_slot: u8,
+    #[cfg_attr(
+        test,
+        proptest(strategy = "(1u64..=std::u64::MAX).prop_map(|x| NonZeroU64::new(x).unwrap())")
+    )]
+    pub limit_price: NonZeroU64,
+    #[cfg_attr(
+        test,
+        proptest(strategy = "(1u64..=std::u64::MAX).prop_map(|x| NonZeroU64::new(x).unwrap())")
+    )]
+    pub max_qty: NonZeroU64,
+    /// The maximum number of resting orders to match against, including
+    /// those matched by requests still waiting in the request queue.
+    pub limit: u16,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
-#[cfg_attr(test, derive(Arbitrary))]
-#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
-pub enum MarketInstruction {
-    /// 0. `[writable]` the market to initialize
-    /// 1. `[writable]` zeroed out request queue
-    /// 2. `[writable]` zeroed out event queue
-    /// 3. `[writable]` zeroed out bids
-    /// 4. `[writable]` zeroed out asks
+impl SendTakeInstruction {
+    fn unpack(data: &[u8; 22]) -> Option<Self> {
+        let (&side_arr, &price_arr, &max_qty_arr, &limit_arr) = array_refs![data, 4, 8, 8, 2];
+        let side = match u32::from_le_bytes(side_arr) {
+            0 => Side::Bid,
+            1 => Side::Ask,
+            _ => return None,
+        };
+        let limit_price = NonZeroU64::new(u64::from_le_bytes(price_arr))?;
+        let max_qty = NonZeroU64::new(u64::from_le_bytes(max_qty_arr))?;
+        let limit = u16::from_le_bytes(limit_arr);
+        Some(SendTakeInstruction {
+            side,
+            limit_price,
+            max_qty,
+            limit,
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub struct PriceBandInstruction {
+    /// How far, in basis points, a trade may be priced away from the last
+    /// trade, below 10_000. Zero turns the band off and then `halt_slots`
+    /// must be zero too.
+    pub max_deviation_bps: u64,
+    /// For how many slots matching stops after an order runs into the band.
+    pub halt_slots: u64,
+}
+
+impl PriceBandInstruction {
+    fn unpack(data: &[u8; 16]) -> Self {
+        let (&max_deviation_bps_arr, &halt_slots_arr) = array_refs![data, 8, 8];
+        PriceBandInstruction {
+            max_deviation_bps: u64::from_le_bytes(max_deviation_bps_arr),
+            halt_slots: u64::from_le_bytes(halt_slots_arr),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub struct InitOpenOrdersInstruction {
+    pub index: u64,
+    /// How many orders the account can hold at once, see
+    /// `state::OpenOrders::account_size`.
+    pub capacity: u16,
+}
+
+impl InitOpenOrdersInstruction {
+    fn unpack(data: &[u8; 10]) -> Self {
+        let (&index_arr, &capacity_arr) = array_refs![data, 8, 2];
+        InitOpenOrdersInstruction {
+            index: u64::from_le_bytes(index_arr),
+            capacity: u16::from_le_bytes(capacity_arr),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub struct CancelOrderInstruction {
+    pub side: Side,
+    pub order_id: u128,
+    pub owner: [u64; 4], // Unused
+    pub owner_slot: u8,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Arbitrary))]
+#[cfg_attr(test, proptest(no_params))]
+#[cfg_attr(feature = "fuzz", derive(arbitrary::Arbitrary))]
+pub enum MarketInstruction {
+    /// 0. `[writable]` the market to initialize
+    /// 1. `[writable]` zeroed out request queue
+    /// 2. `[writable]` zeroed out event queue
+    /// 3. `[writable]` zeroed out bids
+    /// 4. `[writable]` zeroed out asks
     /// 5. `[writable]` spl-token account for the coin currency
     /// 6. `[writable]` spl-token account for the price currency
     /// 7. `[]` coin currency Mint
     /// 8. `[]` price currency Mint
+    /// 9. `[]` (optional) the market admin authority
     InitializeMarket(InitializeMarketInstruction),
     /// 0. `[writable]` the market
     /// 1. `[writable]` the OpenOrders account to use
@@ -199,6 +694,11 @@ pub enum MarketInstruction {
     /// 4. `[writable]` asks
     /// 5. `[writable]` coin fee receivable account
     /// 6. `[writable]` pc fee receivable account
+    /// 7. `[]` (optional) the clock sysvar. Without it, matching fails with
+    ///    `ClockNotProvided` once it needs the time: when the market has a
+    ///    price band, or when it meets an order with an expiry.
+    /// 8. `[writable, signer]` (optional) the account paid a share of the
+    ///    matching rewards in lamports. Requires the clock sysvar.
     MatchOrders(u16),
     /// ... `[writable]` OpenOrders
     /// accounts.len() - 4 `[writable]` market
@@ -248,6 +748,243 @@ pub enum MarketInstruction {
     /// 8. `[]` the rent sysvar
     /// 9. `[writable]` (optional) the (M)SRM account used for fee discounts
     NewOrderV2(NewOrderInstructionV2),
+    /// Cancels every open order of the OpenOrders account, or only those on
+    /// the given side.
+    ///
+    /// 0. `[]` market
+    /// 1. `[writable]` OpenOrders
+    /// 2. `[writable]` the request queue
+    /// 3. `[signer]` the OpenOrders owner
+    CancelAllOrders(Option<Side>),
+    /// Cancels the open order with the given client order id and places the
+    /// new order in its slot, in the same instruction. Fails if there is no
+    /// such order. Takes the same accounts as `NewOrderV2`.
+    ///
+    /// 0. `[writable]` the market
+    /// 1. `[writable]` the OpenOrders account to use
+    /// 2. `[writable]` the request queue
+    /// 3. `[writable]` the (coin or price currency) account paying for the order
+    /// 4. `[signer]` owner of the OpenOrders account
+    /// 5. `[writable]` coin vault
+    /// 6. `[writable]` pc vault
+    /// 7. `[]` spl token program
+    /// 8. `[]` the rent sysvar
+    /// 9. `[writable]` (optional) the (M)SRM account used for fee discounts
+    ReplaceOrderByClientId(NewOrderInstructionV2),
+    /// Like `NewOrderV2`, with an optional expiry after which the order is
+    /// no longer matched and is cancelled instead.
+    ///
+    /// 0. `[writable]` the market
+    /// 1. `[writable]` the OpenOrders account to use
+    /// 2. `[writable]` the request queue
+    /// 3. `[writable]` the (coin or price currency) account paying for the order
+    /// 4. `[signer]` owner of the OpenOrders account
+    /// 5. `[writable]` coin vault
+    /// 6. `[writable]` pc vault
+    /// 7. `[]` spl token program
+    /// 8. `[]` the rent sysvar
+    /// 9. `[writable]` (optional) the (M)SRM account used for fee discounts
+    NewOrderV3(NewOrderInstructionV3),
+    /// Removes up to `limit` expired orders from the book, emitting an `Out`
+    /// event for each.
+    ///
+    /// 0. `[writable]` market
+    /// 1. `[writable]` event_q
+    /// 2. `[writable]` bids
+    /// 3. `[writable]` asks
+    /// 4. `[]` the clock sysvar
+    PruneExpiredOrders(u16),
+    /// Like `NewOrderV3`, but also matches up to `limit` requests from the
+    /// request queue, including the new order, within the same instruction.
+    /// Requests already in the queue are matched first, and the instruction
+    /// fails with `NewOrderNotMatched` if `limit` runs out before the new
+    /// order has been matched. The matching rewards for the requests it
+    /// processes are paid back to the OpenOrders account.
+    ///
+    /// 0. `[writable]` the market
+    /// 1. `[writable]` the OpenOrders account to use
+    /// 2. `[writable]` the request queue
+    /// 3. `[writable]` the (coin or price currency) account paying for the order
+    /// 4. `[signer]` owner of the OpenOrders account
+    /// 5. `[writable]` coin vault
+    /// 6. `[writable]` pc vault
+    /// 7. `[]` spl token program
+    /// 8. `[]` the rent sysvar
+    /// 9. `[writable]` event_q
+    /// 10. `[writable]` bids
+    /// 11. `[writable]` asks
+    /// 12. `[]` the clock sysvar
+    /// 13. `[writable]` (optional) the (M)SRM account used for fee discounts
+    NewOrderV4(NewOrderInstructionV4),
+    /// Closes an OpenOrders account with no open orders and no funds,
+    /// sending its lamports to the destination account. This also works
+    /// after the market itself has been closed.
+    ///
+    /// 0. `[writable]` OpenOrders
+    /// 1. `[signer]` the OpenOrders owner
+    /// 2. `[writable]` the destination account to send rent exemption SOL to
+    /// 3. `[]` market
+    CloseOpenOrders,
+    /// Same accounts as `InitializeMarket`. The fee schedule must leave the
+    /// market a positive net fee on every trade.
+    ///
+    /// 0. `[writable]` the market to initialize
+    /// 1. `[writable]` zeroed out request queue
+    /// 2. `[writable]` zeroed out event queue
+    /// 3. `[writable]` zeroed out bids
+    /// 4. `[writable]` zeroed out asks
+    /// 5. `[writable]` spl-token account for the coin currency
+    /// 6. `[writable]` spl-token account for the price currency
+    /// 7. `[]` coin currency Mint
+    /// 8. `[]` price currency Mint
+    /// 9. `[]` (optional) the market admin authority
+    InitializeMarketV2(InitializeMarketInstructionV2),
+    /// Stops the market from accepting new orders. Cancels, matching of
+    /// already queued requests, and settlement are unaffected.
+    ///
+    /// 0. `[writable]` market
+    /// 1. `[signer]` the market admin authority
+    PauseMarket,
+    /// 0. `[writable]` market
+    /// 1. `[signer]` the market admin authority
+    ResumeMarket,
+    /// Hands the market admin authority to a new key. Passing the system
+    /// program as the new authority leaves the market without one.
+    ///
+    /// 0. `[writable]` market
+    /// 1. `[signer]` the current market admin authority
+    /// 2. `[]` the new market admin authority
+    SetMarketAuthority,
+    /// Crosses the book as an immediate-or-cancel taker without an OpenOrders
+    /// account. The amount paid is pulled from the owner's wallet and the
+    /// proceeds are sent straight back to the owner's other wallet; only the
+    /// makers' events are added to the event queue. The crank fee is paid
+    /// from the owner's lamports and goes entirely to consuming events.
+    /// Requests still waiting in the request queue are matched first, and the
+    /// instruction fails if they can't all be matched within the limit; the
+    /// owner collects the matching rewards for them.
+    ///
+    /// 0. `[writable]` market
+    /// 1. `[writable]` request queue
+    /// 2. `[writable]` event queue
+    /// 3. `[writable]` bids
+    /// 4. `[writable]` asks
+    /// 5. `[writable]` coin wallet
+    /// 6. `[writable]` pc wallet
+    /// 7. `[writable, signer]` owner of the paying wallet, pays the crank fee
+    /// 8. `[writable]` coin vault
+    /// 9. `[writable]` pc vault
+    /// 10. `[]` vault signer
+    /// 11. `[]` spl token program
+    /// 12. `[]` the clock sysvar
+    /// 13. `[]` the system program
+    /// 14. `[writable]` (optional) the (M)SRM account used for fee discounts
+    SendTake(SendTakeInstruction),
+    /// Like `NewOrderV3`, but bids also stop once they have spent
+    /// `max_native_pc_qty_including_fees`. Setting `max_qty` and
+    /// `limit_price` high turns a bid into a market order for exactly that
+    /// much price currency.
+    ///
+    /// 0. `[writable]` the market
+    /// 1. `[writable]` the OpenOrders account to use
+    /// 2. `[writable]` the request queue
+    /// 3. `[writable]` the (coin or price currency) account paying for the order
+    /// 4. `[signer]` owner of the OpenOrders account
+    /// 5. `[writable]` coin vault
+    /// 6. `[writable]` pc vault
+    /// 7. `[]` spl token program
+    /// 8. `[]` the rent sysvar
+    /// 9. `[writable]` (optional) the (M)SRM account used for fee discounts
+    NewOrderV5(NewOrderInstructionV5),
+    /// Like `NewOrderV5`, but whatever part of the order rests on the book
+    /// can be shown only `display_qty` lots at a time.
+    ///
+    /// 0. `[writable]` the market
+    /// 1. `[writable]` the OpenOrders account to use
+    /// 2. `[writable]` the request queue
+    /// 3. `[writable]` the (coin or price currency) account paying for the order
+    /// 4. `[signer]` owner of the OpenOrders account
+    /// 5. `[writable]` coin vault
+    /// 6. `[writable]` pc vault
+    /// 7. `[]` spl token program
+    /// 8. `[]` the rent sysvar
+    /// 9. `[writable]` (optional) the (M)SRM account used for fee discounts
+    NewOrderV6(NewOrderInstructionV6),
+    /// Sets how far trades may stray from the last trade price. A taker
+    /// reaching a resting order outside the band stops matching there, and
+    /// if `halt_slots` is set, nothing on the market matches for that many
+    /// slots afterwards.
+    ///
+    /// 0. `[writable]` market
+    /// 1. `[signer]` the market admin authority
+    SetPriceBand(PriceBandInstruction),
+    /// Sets the fee, in lamports, charged for every queued new order and
+    /// every `SendTake` to pay for cranking it. The fee is taken from the
+    /// lamports the OpenOrders account holds beyond its rent exempt balance,
+    /// or from the owner for `SendTake`. Half of a new order's fee goes to a
+    /// pool paid out to whoever matches requests, in proportion to the
+    /// requests they process, and the rest to a pool paid out to
+    /// `ConsumeEventsWithReward` callers in proportion to the events they
+    /// process. Whoever drains a queue collects what is left in its pool.
+    ///
+    /// 0. `[writable]` market
+    /// 1. `[signer]` the market admin authority
+    SetCrankFee(u64),
+    /// Lets a second key place and cancel orders for an OpenOrders account.
+    /// The delegate can also settle, but only into wallets owned by the
+    /// OpenOrders owner, and can't close the account. OpenOrders accounts of
+    /// the original size have no room for a delegate; the account needs to be
+    /// sized with `OpenOrders::account_size`.
+    ///
+    /// 0. `[]` market
+    /// 1. `[writable]` OpenOrders
+    /// 2. `[signer]` the OpenOrders owner
+    /// 3. `[]` the delegate
+    SetDelegate,
+    /// 0. `[]` market
+    /// 1. `[writable]` OpenOrders
+    /// 2. `[signer]` the OpenOrders owner
+    RevokeDelegate,
+    /// Creates the owner's `index`th OpenOrders account for the market at
+    /// the program derived address given by `state::find_open_orders_key`,
+    /// so that clients can find it without scanning the program's accounts.
+    /// The account is sized to hold `capacity` orders. If lamports were
+    /// already sent to the address, the owner only pays the rest of the
+    /// rent-exempt balance.
+    ///
+    /// 0. `[]` market
+    /// 1. `[writable]` the OpenOrders account to create
+    /// 2. `[writable, signer]` the OpenOrders owner, paying for the account
+    /// 3. `[]` the system program
+    /// 4. `[]` the rent sysvar
+    InitOpenOrders(InitOpenOrdersInstruction),
+    /// Closes a disabled market whose book and queues are empty and which
+    /// holds no deposits, unswept fees, unpaid referrer rebates or unpaid crank
+    /// rewards, returning the lamports of the market, both queues and both
+    /// sides of the book to the destination. Disabled markets can still be
+    /// settled and swept, and their leftover crank rewards claimed with
+    /// `ConsumeEventsWithReward`.
+    ///
+    /// 0. `[writable]` market
+    /// 1. `[writable]` request queue
+    /// 2. `[writable]` event queue
+    /// 3. `[writable]` bids
+    /// 4. `[writable]` asks
+    /// 5. `[signer]` market authority, or the disable authority for markets
+    ///    without one
+    /// 6. `[writable]` destination for the lamports
+    CloseMarket,
+    /// Like `ConsumeEvents`, but pays the caller a share of the event
+    /// rewards in lamports. Works on disabled markets too, so that what is
+    /// left of the rewards can be claimed before the market is closed.
+    ///
+    /// ... `[writable]` OpenOrders
+    /// accounts.len() - 5 `[writable]` market
+    /// accounts.len() - 4 `[writable]` event queue
+    /// accounts.len() - 3 `[writable]` coin fee receivable account
+    /// accounts.len() - 2 `[writable]` pc fee receivable account
+    /// accounts.len() - 1 `[writable, signer]` the account the reward is paid to
+    ConsumeEventsWithReward(u16),
 }
 
 impl MarketInstruction {
@@ -256,7 +993,7 @@ impl MarketInstruction {
     }
 
     pub fn unpack(versioned_bytes: &[u8]) -> Option<Self> {
-        if versioned_bytes.len() < 5 || versioned_bytes.len() > 58 {
+        if versioned_bytes.len() < 5 || versioned_bytes.len() > 71 {
             return None;
         }
         let (&[version], &discrim, data) = array_refs![versioned_bytes, 1, 4; ..;];
@@ -267,14 +1004,7 @@ impl MarketInstruction {
         Some(match (discrim, data.len()) {
             (0, 34) => MarketInstruction::InitializeMarket({
                 let data_array = array_ref![data, 0, 34];
-                let fields = array_refs![data_array, 8, 8, 2, 8, 8];
-                InitializeMarketInstruction {
-                    coin_lot_size: u64::from_le_bytes(*fields.0),
-                    pc_lot_size: u64::from_le_bytes(*fields.1),
-                    fee_rate_bps: u16::from_le_bytes(*fields.2),
-                    vault_signer_nonce: u64::from_le_bytes(*fields.3),
-                    pc_dust_threshold: u64::from_le_bytes(*fields.4),
-                }
+                InitializeMarketInstruction::unpack(data_array)
             }),
             (1, 32) => MarketInstruction::NewOrder({
                 let data_arr = array_ref![data, 0, 32];
@@ -315,82 +1045,994 @@ impl MarketInstruction {
             (8, 0) => MarketInstruction::SweepFees,
             (9, 36) => MarketInstruction::NewOrderV2({
                 let data_arr = array_ref![data, 0, 36];
-                let (v1_data_arr, v2_data_arr) = array_refs![data_arr, 32, 4];
-                let v1_instr = NewOrderInstructionV1::unpack(v1_data_arr)?;
-                let self_trade_behavior = SelfTradeBehavior::try_from_primitive(
-                    u32::from_le_bytes(*v2_data_arr).try_into().ok()?,
-                )
-                .ok()?;
-                v1_instr.add_self_trade_behavior(self_trade_behavior)
+                NewOrderInstructionV2::unpack(data_arr)?
+            }),
+            (10, 1) if data[0] == 0 => MarketInstruction::CancelAllOrders(None),
+            (10, 5) if data[0] == 1 => {
+                let side_arr = array_ref![data, 1, 4];
+                let side = match u32::from_le_bytes(*side_arr) {
+                    0 => Side::Bid,
+                    1 => Side::Ask,
+                    _ => return None,
+                };
+                MarketInstruction::CancelAllOrders(Some(side))
+            }
+            (11, 36) => MarketInstruction::ReplaceOrderByClientId({
+                let data_arr = array_ref![data, 0, 36];
+                NewOrderInstructionV2::unpack(data_arr)?
             }),
+            (12, 37) | (12, 49) => {
+                MarketInstruction::NewOrderV3(NewOrderInstructionV3::unpack(data)?)
+            }
+            (13, 2) => {
+                let limit = array_ref![data, 0, 2];
+                MarketInstruction::PruneExpiredOrders(u16::from_le_bytes(*limit))
+            }
+            (14, 39) | (14, 51) => {
+                MarketInstruction::NewOrderV4(NewOrderInstructionV4::unpack(data)?)
+            }
+            (15, 0) => MarketInstruction::CloseOpenOrders,
+            (16, 64) => MarketInstruction::InitializeMarketV2({
+                let data_array = array_ref![data, 0, 64];
+                InitializeMarketInstructionV2::unpack(data_array)
+            }),
+            (17, 0) => MarketInstruction::PauseMarket,
+            (18, 0) => MarketInstruction::ResumeMarket,
+            (19, 0) => MarketInstruction::SetMarketAuthority,
+            (20, 22) => MarketInstruction::SendTake({
+                let data_arr = array_ref![data, 0, 22];
+                SendTakeInstruction::unpack(data_arr)?
+            }),
+            (21, 45) | (21, 57) => {
+                MarketInstruction::NewOrderV5(NewOrderInstructionV5::unpack(data)?)
+            }
+            (22, 46) | (22, 54) | (22, 58) | (22, 66) => {
+                MarketInstruction::NewOrderV6(NewOrderInstructionV6::unpack(data)?)
+            }
+            (23, 16) => MarketInstruction::SetPriceBand({
+                let data_arr = array_ref![data, 0, 16];
+                PriceBandInstruction::unpack(data_arr)
+            }),
+            (24, 8) => {
+                let crank_fee_lamports = array_ref![data, 0, 8];
+                MarketInstruction::SetCrankFee(u64::from_le_bytes(*crank_fee_lamports))
+            }
+            (25, 0) => MarketInstruction::SetDelegate,
+            (26, 0) => MarketInstruction::RevokeDelegate,
+            (27, 10) => MarketInstruction::InitOpenOrders({
+                let data_arr = array_ref![data, 0, 10];
+                InitOpenOrdersInstruction::unpack(data_arr)
+            }),
+            (28, 0) => MarketInstruction::CloseMarket,
+            (29, 2) => {
+                let limit = array_ref![data, 0, 2];
+                MarketInstruction::ConsumeEventsWithReward(u16::from_le_bytes(*limit))
+            }
             _ => return None,
         })
     }
 
-    #[cfg(test)]
-    #[inline]
-    pub fn unpack_serde(data: &[u8]) -> Result<Self, ()> {
-        match data.split_first() {
-            None => Err(()),
-            Some((&0u8, rest)) => bincode::deserialize(rest).map_err(|_| ()),
-            Some((_, _rest)) => Err(()),
-        }
+    #[cfg(test)]
+    #[inline]
+    pub fn unpack_serde(data: &[u8]) -> Result<Self, ()> {
+        match data.split_first() {
+            None => Err(()),
+            Some((&0u8, rest)) => bincode::deserialize(rest).map_err(|_| ()),
+            Some((_, _rest)) => Err(()),
+        }
+    }
+}
+
+pub fn initialize_market(
+    market: &Pubkey,
+    program_id: &Pubkey,
+    coin_mint_pk: &Pubkey,
+    pc_mint_pk: &Pubkey,
+    coin_vault_pk: &Pubkey,
+    pc_vault_pk: &Pubkey,
+    // srm_vault_pk: &Pubkey,
+    bids_pk: &Pubkey,
+    asks_pk: &Pubkey,
+    req_q_pk: &Pubkey,
+    event_q_pk: &Pubkey,
+    coin_lot_size: u64,
+    pc_lot_size: u64,
+    vault_signer_nonce: u64,
+    pc_dust_threshold: u64,
+    fee_schedule: FeeSchedule,
+    authority: Option<&Pubkey>,
+) -> Result<solana_sdk::instruction::Instruction, DexError> {
+    let data = MarketInstruction::InitializeMarketV2(InitializeMarketInstructionV2 {
+        coin_lot_size,
+        pc_lot_size,
+        fee_rate_bps: 0,
+        vault_signer_nonce,
+        pc_dust_threshold,
+        fee_schedule,
+    })
+    .pack();
+
+    let market_account = AccountMeta::new(*market, false);
+
+    let bids = AccountMeta::new(*bids_pk, false);
+    let asks = AccountMeta::new(*asks_pk, false);
+    let req_q = AccountMeta::new(*req_q_pk, false);
+    let event_q = AccountMeta::new(*event_q_pk, false);
+
+    let coin_vault = AccountMeta::new(*coin_vault_pk, false);
+    let pc_vault = AccountMeta::new(*pc_vault_pk, false);
+
+    let coin_mint = AccountMeta::new_readonly(*coin_mint_pk, false);
+    let pc_mint = AccountMeta::new_readonly(*pc_mint_pk, false);
+
+    let mut accounts = vec![
+        market_account,
+        req_q,
+        event_q,
+        bids,
+        asks,
+        coin_vault,
+        pc_vault,
+        //srm_vault,
+        coin_mint,
+        pc_mint,
+        //srm_mint,
+    ];
+    if let Some(key) = authority {
+        accounts.push(AccountMeta::new_readonly(*key, false));
+    }
+
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+fn new_order_accounts(
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    request_queue: &Pubkey,
+    order_payer: &Pubkey,
+    open_orders_account_owner: &Pubkey,
+    coin_vault: &Pubkey,
+    pc_vault: &Pubkey,
+    spl_token_program_id: &Pubkey,
+    rent_sysvar_id: &Pubkey,
+    srm_account_referral: Option<&Pubkey>,
+) -> Vec<AccountMeta> {
+    let mut accounts = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new(*open_orders_account, false),
+        AccountMeta::new(*request_queue, false),
+        AccountMeta::new(*order_payer, false),
+        AccountMeta::new_readonly(*open_orders_account_owner, true),
+        AccountMeta::new(*coin_vault, false),
+        AccountMeta::new(*pc_vault, false),
+        AccountMeta::new_readonly(*spl_token_program_id, false),
+        AccountMeta::new_readonly(*rent_sysvar_id, false),
+    ];
+    if let Some(key) = srm_account_referral {
+        accounts.push(AccountMeta::new(*key, false))
+    }
+    accounts
+}
+
+/// Builds the original `NewOrder` instruction, for programs that still
+/// expect it. New clients should prefer `new_order`.
+pub fn new_order_v1(
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    request_queue: &Pubkey,
+    order_payer: &Pubkey,
+    open_orders_account_owner: &Pubkey,
+    coin_vault: &Pubkey,
+    pc_vault: &Pubkey,
+    spl_token_program_id: &Pubkey,
+    rent_sysvar_id: &Pubkey,
+    srm_account_referral: Option<&Pubkey>,
+    program_id: &Pubkey,
+    side: Side,
+    limit_price: NonZeroU64,
+    max_qty: NonZeroU64,
+    order_type: OrderType,
+    client_order_id: u64,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::NewOrder(NewOrderInstructionV1 {
+        side,
+        limit_price,
+        max_qty,
+        order_type,
+        client_id: client_order_id,
+    })
+    .pack();
+    let accounts = new_order_accounts(
+        market,
+        open_orders_account,
+        request_queue,
+        order_payer,
+        open_orders_account_owner,
+        coin_vault,
+        pc_vault,
+        spl_token_program_id,
+        rent_sysvar_id,
+        srm_account_referral,
+    );
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn new_order(
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    request_queue: &Pubkey,
+    order_payer: &Pubkey,
+    open_orders_account_owner: &Pubkey,
+    coin_vault: &Pubkey,
+    pc_vault: &Pubkey,
+    spl_token_program_id: &Pubkey,
+    rent_sysvar_id: &Pubkey,
+    srm_account_referral: Option<&Pubkey>,
+    program_id: &Pubkey,
+    side: Side,
+    limit_price: NonZeroU64,
+    max_qty: NonZeroU64,
+    order_type: OrderType,
+    client_order_id: u64,
+    self_trade_behavior: SelfTradeBehavior,
+    expiry: Option<OrderExpiry>,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+        side,
+        limit_price,
+        max_qty,
+        order_type,
+        client_id: client_order_id,
+        self_trade_behavior,
+        expiry,
+    })
+    .pack();
+    let accounts = new_order_accounts(
+        market,
+        open_orders_account,
+        request_queue,
+        order_payer,
+        open_orders_account_owner,
+        coin_vault,
+        pc_vault,
+        spl_token_program_id,
+        rent_sysvar_id,
+        srm_account_referral,
+    );
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn new_order_with_max_native_pc_qty(
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    request_queue: &Pubkey,
+    order_payer: &Pubkey,
+    open_orders_account_owner: &Pubkey,
+    coin_vault: &Pubkey,
+    pc_vault: &Pubkey,
+    spl_token_program_id: &Pubkey,
+    rent_sysvar_id: &Pubkey,
+    srm_account_referral: Option<&Pubkey>,
+    program_id: &Pubkey,
+    side: Side,
+    limit_price: NonZeroU64,
+    max_qty: NonZeroU64,
+    max_native_pc_qty_including_fees: NonZeroU64,
+    order_type: OrderType,
+    client_order_id: u64,
+    self_trade_behavior: SelfTradeBehavior,
+    expiry: Option<OrderExpiry>,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::NewOrderV5(NewOrderInstructionV5 {
+        side,
+        limit_price,
+        max_qty,
+        order_type,
+        client_id: client_order_id,
+        self_trade_behavior,
+        expiry,
+        max_native_pc_qty_including_fees,
+    })
+    .pack();
+    let accounts = new_order_accounts(
+        market,
+        open_orders_account,
+        request_queue,
+        order_payer,
+        open_orders_account_owner,
+        coin_vault,
+        pc_vault,
+        spl_token_program_id,
+        rent_sysvar_id,
+        srm_account_referral,
+    );
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn new_iceberg_order(
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    request_queue: &Pubkey,
+    order_payer: &Pubkey,
+    open_orders_account_owner: &Pubkey,
+    coin_vault: &Pubkey,
+    pc_vault: &Pubkey,
+    spl_token_program_id: &Pubkey,
+    rent_sysvar_id: &Pubkey,
+    srm_account_referral: Option<&Pubkey>,
+    program_id: &Pubkey,
+    side: Side,
+    limit_price: NonZeroU64,
+    max_qty: NonZeroU64,
+    display_qty: NonZeroU64,
+    order_type: OrderType,
+    client_order_id: u64,
+    self_trade_behavior: SelfTradeBehavior,
+    expiry: Option<OrderExpiry>,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::NewOrderV6(NewOrderInstructionV6 {
+        side,
+        limit_price,
+        max_qty,
+        order_type,
+        client_id: client_order_id,
+        self_trade_behavior,
+        expiry,
+        max_native_pc_qty_including_fees: NonZeroU64::new(std::u64::MAX).unwrap(),
+        display_qty: Some(display_qty),
+    })
+    .pack();
+    let accounts = new_order_accounts(
+        market,
+        open_orders_account,
+        request_queue,
+        order_payer,
+        open_orders_account_owner,
+        coin_vault,
+        pc_vault,
+        spl_token_program_id,
+        rent_sysvar_id,
+        srm_account_referral,
+    );
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn new_order_and_match(
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    request_queue: &Pubkey,
+    event_queue: &Pubkey,
+    bids: &Pubkey,
+    asks: &Pubkey,
+    order_payer: &Pubkey,
+    open_orders_account_owner: &Pubkey,
+    coin_vault: &Pubkey,
+    pc_vault: &Pubkey,
+    spl_token_program_id: &Pubkey,
+    rent_sysvar_id: &Pubkey,
+    clock_sysvar_id: &Pubkey,
+    srm_account_referral: Option<&Pubkey>,
+    program_id: &Pubkey,
+    side: Side,
+    limit_price: NonZeroU64,
+    max_qty: NonZeroU64,
+    order_type: OrderType,
+    client_order_id: u64,
+    self_trade_behavior: SelfTradeBehavior,
+    expiry: Option<OrderExpiry>,
+    limit: u16,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::NewOrderV4(NewOrderInstructionV4 {
+        side,
+        limit_price,
+        max_qty,
+        order_type,
+        client_id: client_order_id,
+        self_trade_behavior,
+        expiry,
+        limit,
+    })
+    .pack();
+    let mut accounts = new_order_accounts(
+        market,
+        open_orders_account,
+        request_queue,
+        order_payer,
+        open_orders_account_owner,
+        coin_vault,
+        pc_vault,
+        spl_token_program_id,
+        rent_sysvar_id,
+        None,
+    );
+    accounts.extend(vec![
+        AccountMeta::new(*event_queue, false),
+        AccountMeta::new(*bids, false),
+        AccountMeta::new(*asks, false),
+        AccountMeta::new_readonly(*clock_sysvar_id, false),
+    ]);
+    if let Some(key) = srm_account_referral {
+        accounts.push(AccountMeta::new(*key, false))
+    }
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn replace_order_by_client_id(
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    request_queue: &Pubkey,
+    order_payer: &Pubkey,
+    open_orders_account_owner: &Pubkey,
+    coin_vault: &Pubkey,
+    pc_vault: &Pubkey,
+    spl_token_program_id: &Pubkey,
+    rent_sysvar_id: &Pubkey,
+    srm_account_referral: Option<&Pubkey>,
+    program_id: &Pubkey,
+    side: Side,
+    limit_price: NonZeroU64,
+    max_qty: NonZeroU64,
+    order_type: OrderType,
+    client_order_id: u64,
+    self_trade_behavior: SelfTradeBehavior,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::ReplaceOrderByClientId(NewOrderInstructionV2 {
+        side,
+        limit_price,
+        max_qty,
+        order_type,
+        client_id: client_order_id,
+        self_trade_behavior,
+    })
+    .pack();
+    let accounts = new_order_accounts(
+        market,
+        open_orders_account,
+        request_queue,
+        order_payer,
+        open_orders_account_owner,
+        coin_vault,
+        pc_vault,
+        spl_token_program_id,
+        rent_sysvar_id,
+        srm_account_referral,
+    );
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn match_orders(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    request_queue: &Pubkey,
+    event_queue: &Pubkey,
+    bids: &Pubkey,
+    asks: &Pubkey,
+    coin_fee_receivable_account: &Pubkey,
+    pc_fee_receivable_account: &Pubkey,
+    clock_sysvar_id: &Pubkey,
+    crank_reward_recipient: Option<&Pubkey>,
+    limit: u16,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::MatchOrders(limit).pack();
+    let mut accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new(*request_queue, false),
+        AccountMeta::new(*event_queue, false),
+        AccountMeta::new(*bids, false),
+        AccountMeta::new(*asks, false),
+        AccountMeta::new(*coin_fee_receivable_account, false),
+        AccountMeta::new(*pc_fee_receivable_account, false),
+        AccountMeta::new_readonly(*clock_sysvar_id, false),
+    ];
+    if let Some(recipient) = crank_reward_recipient {
+        accounts.push(AccountMeta::new(*recipient, true));
+    }
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn prune_expired_orders(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    event_queue: &Pubkey,
+    bids: &Pubkey,
+    asks: &Pubkey,
+    clock_sysvar_id: &Pubkey,
+    limit: u16,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::PruneExpiredOrders(limit).pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new(*event_queue, false),
+        AccountMeta::new(*bids, false),
+        AccountMeta::new(*asks, false),
+        AccountMeta::new_readonly(*clock_sysvar_id, false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+/// The program looks up OpenOrders accounts by binary search, so the
+/// given accounts are sorted by their aligned key before being attached.
+/// With a `crank_reward_recipient`, this is a `ConsumeEventsWithReward`.
+pub fn consume_events(
+    program_id: &Pubkey,
+    open_orders_accounts: Vec<&Pubkey>,
+    market: &Pubkey,
+    event_queue: &Pubkey,
+    coin_fee_receivable_account: &Pubkey,
+    pc_fee_receivable_account: &Pubkey,
+    crank_reward_recipient: Option<&Pubkey>,
+    limit: u16,
+) -> Result<Instruction, DexError> {
+    let data = match crank_reward_recipient {
+        Some(_) => MarketInstruction::ConsumeEventsWithReward(limit),
+        None => MarketInstruction::ConsumeEvents(limit),
+    }
+    .pack();
+    let mut open_orders_accounts = open_orders_accounts;
+    open_orders_accounts.sort_by_key(|key| key.to_aligned_bytes());
+    let mut accounts: Vec<AccountMeta> = open_orders_accounts
+        .into_iter()
+        .map(|key| AccountMeta::new(*key, false))
+        .collect();
+    accounts.append(&mut vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new(*event_queue, false),
+        AccountMeta::new(*coin_fee_receivable_account, false),
+        AccountMeta::new(*pc_fee_receivable_account, false),
+    ]);
+    if let Some(recipient) = crank_reward_recipient {
+        accounts.push(AccountMeta::new(*recipient, true));
+    }
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn cancel_order(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    request_queue: &Pubkey,
+    open_orders_account_owner: &Pubkey,
+    side: Side,
+    order_id: u128,
+    owner_slot: u8,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::CancelOrder(CancelOrderInstruction {
+        side,
+        order_id,
+        owner: open_orders_account.to_aligned_bytes(),
+        owner_slot,
+    })
+    .pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new_readonly(*market, false),
+        AccountMeta::new(*open_orders_account, false),
+        AccountMeta::new(*request_queue, false),
+        AccountMeta::new_readonly(*open_orders_account_owner, true),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn cancel_order_by_client_order_id(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    request_queue: &Pubkey,
+    open_orders_account_owner: &Pubkey,
+    client_order_id: u64,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::CancelOrderByClientId(client_order_id).pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new_readonly(*market, false),
+        AccountMeta::new(*open_orders_account, false),
+        AccountMeta::new(*request_queue, false),
+        AccountMeta::new_readonly(*open_orders_account_owner, true),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn cancel_all_orders(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    request_queue: &Pubkey,
+    open_orders_account_owner: &Pubkey,
+    side: Option<Side>,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::CancelAllOrders(side).pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new_readonly(*market, false),
+        AccountMeta::new(*open_orders_account, false),
+        AccountMeta::new(*request_queue, false),
+        AccountMeta::new_readonly(*open_orders_account_owner, true),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn close_open_orders(
+    program_id: &Pubkey,
+    open_orders: &Pubkey,
+    owner: &Pubkey,
+    destination: &Pubkey,
+    market: &Pubkey,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::CloseOpenOrders.pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*open_orders, false),
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new(*destination, false),
+        AccountMeta::new_readonly(*market, false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn settle_funds(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    open_orders_account_owner: &Pubkey,
+    coin_vault: &Pubkey,
+    pc_vault: &Pubkey,
+    coin_wallet: &Pubkey,
+    pc_wallet: &Pubkey,
+    vault_signer: &Pubkey,
+    spl_token_program_id: &Pubkey,
+    referrer_pc_wallet: Option<&Pubkey>,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::SettleFunds.pack();
+    let mut accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new(*open_orders_account, false),
+        AccountMeta::new_readonly(*open_orders_account_owner, true),
+        AccountMeta::new(*coin_vault, false),
+        AccountMeta::new(*pc_vault, false),
+        AccountMeta::new(*coin_wallet, false),
+        AccountMeta::new(*pc_wallet, false),
+        AccountMeta::new_readonly(*vault_signer, false),
+        AccountMeta::new_readonly(*spl_token_program_id, false),
+    ];
+    if let Some(key) = referrer_pc_wallet {
+        accounts.push(AccountMeta::new(*key, false))
     }
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
 }
 
-pub fn initialize_market(
+pub fn disable_market(
+    program_id: &Pubkey,
     market: &Pubkey,
+    disable_authority_key: &Pubkey,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::DisableMarket.pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new_readonly(*disable_authority_key, true),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn pause_market(
     program_id: &Pubkey,
-    coin_mint_pk: &Pubkey,
-    pc_mint_pk: &Pubkey,
-    coin_vault_pk: &Pubkey,
-    pc_vault_pk: &Pubkey,
-    // srm_vault_pk: &Pubkey,
-    bids_pk: &Pubkey,
-    asks_pk: &Pubkey,
-    req_q_pk: &Pubkey,
-    event_q_pk: &Pubkey,
-    coin_lot_size: u64,
-    pc_lot_size: u64,
-    vault_signer_nonce: u64,
-    pc_dust_threshold: u64,
-) -> Result<solana_sdk::instruction::Instruction, DexError> {
-    let data = MarketInstruction::InitializeMarket(InitializeMarketInstruction {
-        coin_lot_size,
-        pc_lot_size,
-        fee_rate_bps: 0,
-        vault_signer_nonce,
-        pc_dust_threshold,
+    market: &Pubkey,
+    authority: &Pubkey,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::PauseMarket.pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new_readonly(*authority, true),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn resume_market(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    authority: &Pubkey,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::ResumeMarket.pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new_readonly(*authority, true),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn set_market_authority(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    authority: &Pubkey,
+    new_authority: &Pubkey,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::SetMarketAuthority.pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new_readonly(*new_authority, false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn set_price_band(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    authority: &Pubkey,
+    max_deviation_bps: u64,
+    halt_slots: u64,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::SetPriceBand(PriceBandInstruction {
+        max_deviation_bps,
+        halt_slots,
     })
     .pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new_readonly(*authority, true),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
 
-    let market_account = AccountMeta::new(*market, false);
+pub fn set_crank_fee(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    authority: &Pubkey,
+    crank_fee_lamports: u64,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::SetCrankFee(crank_fee_lamports).pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new_readonly(*authority, true),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
 
-    let bids = AccountMeta::new(*bids_pk, false);
-    let asks = AccountMeta::new(*asks_pk, false);
-    let req_q = AccountMeta::new(*req_q_pk, false);
-    let event_q = AccountMeta::new(*event_q_pk, false);
+pub fn init_open_orders(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    owner: &Pubkey,
+    system_program_id: &Pubkey,
+    rent_sysvar_id: &Pubkey,
+    index: u64,
+    capacity: u16,
+) -> Result<Instruction, DexError> {
+    let data =
+        MarketInstruction::InitOpenOrders(InitOpenOrdersInstruction { index, capacity }).pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new_readonly(*market, false),
+        AccountMeta::new(*open_orders_account, false),
+        AccountMeta::new(*owner, true),
+        AccountMeta::new_readonly(*system_program_id, false),
+        AccountMeta::new_readonly(*rent_sysvar_id, false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
 
-    let coin_vault = AccountMeta::new(*coin_vault_pk, false);
-    let pc_vault = AccountMeta::new(*pc_vault_pk, false);
+pub fn set_delegate(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    owner: &Pubkey,
+    delegate: &Pubkey,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::SetDelegate.pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new_readonly(*market, false),
+        AccountMeta::new(*open_orders_account, false),
+        AccountMeta::new_readonly(*owner, true),
+        AccountMeta::new_readonly(*delegate, false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
 
-    let coin_mint = AccountMeta::new_readonly(*coin_mint_pk, false);
-    let pc_mint = AccountMeta::new_readonly(*pc_mint_pk, false);
+pub fn revoke_delegate(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    open_orders_account: &Pubkey,
+    owner: &Pubkey,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::RevokeDelegate.pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new_readonly(*market, false),
+        AccountMeta::new(*open_orders_account, false),
+        AccountMeta::new_readonly(*owner, true),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
 
-    let accounts = vec![
-        market_account,
-        req_q,
-        event_q,
-        bids,
-        asks,
-        coin_vault,
-        pc_vault,
-        //srm_vault,
-        coin_mint,
-        pc_mint,
-        //srm_mint,
+pub fn close_market(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    request_queue: &Pubkey,
+    event_queue: &Pubkey,
+    bids: &Pubkey,
+    asks: &Pubkey,
+    authority: &Pubkey,
+    destination: &Pubkey,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::CloseMarket.pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new(*request_queue, false),
+        AccountMeta::new(*event_queue, false),
+        AccountMeta::new(*bids, false),
+        AccountMeta::new(*asks, false),
+        AccountMeta::new_readonly(*authority, true),
+        AccountMeta::new(*destination, false),
+    ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
+
+pub fn sweep_fees(
+    program_id: &Pubkey,
+    market: &Pubkey,
+    pc_vault: &Pubkey,
+    fee_sweeping_authority: &Pubkey,
+    fee_receivable_account: &Pubkey,
+    vault_signer: &Pubkey,
+    spl_token_program_id: &Pubkey,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::SweepFees.pack();
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new(*pc_vault, false),
+        AccountMeta::new_readonly(*fee_sweeping_authority, true),
+        AccountMeta::new(*fee_receivable_account, false),
+        AccountMeta::new_readonly(*vault_signer, false),
+        AccountMeta::new_readonly(*spl_token_program_id, false),
     ];
+    Ok(Instruction {
+        program_id: *program_id,
+        data,
+        accounts,
+    })
+}
 
+pub fn send_take(
+    market: &Pubkey,
+    request_queue: &Pubkey,
+    event_queue: &Pubkey,
+    bids: &Pubkey,
+    asks: &Pubkey,
+    coin_wallet: &Pubkey,
+    pc_wallet: &Pubkey,
+    wallet_owner: &Pubkey,
+    coin_vault: &Pubkey,
+    pc_vault: &Pubkey,
+    vault_signer: &Pubkey,
+    spl_token_program_id: &Pubkey,
+    clock_sysvar_id: &Pubkey,
+    system_program_id: &Pubkey,
+    srm_account_referral: Option<&Pubkey>,
+    program_id: &Pubkey,
+    side: Side,
+    limit_price: NonZeroU64,
+    max_qty: NonZeroU64,
+    limit: u16,
+) -> Result<Instruction, DexError> {
+    let data = MarketInstruction::SendTake(SendTakeInstruction {
+        side,
+        limit_price,
+        max_qty,
+        limit,
+    })
+    .pack();
+    let mut accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*market, false),
+        AccountMeta::new(*request_queue, false),
+        AccountMeta::new(*event_queue, false),
+        AccountMeta::new(*bids, false),
+        AccountMeta::new(*asks, false),
+        AccountMeta::new(*coin_wallet, false),
+        AccountMeta::new(*pc_wallet, false),
+        AccountMeta::new(*wallet_owner, true),
+        AccountMeta::new(*coin_vault, false),
+        AccountMeta::new(*pc_vault, false),
+        AccountMeta::new_readonly(*vault_signer, false),
+        AccountMeta::new_readonly(*spl_token_program_id, false),
+        AccountMeta::new_readonly(*clock_sysvar_id, false),
+        AccountMeta::new_readonly(*system_program_id, false),
+    ];
+    if let Some(key) = srm_account_referral {
+        accounts.push(AccountMeta::new(*key, false))
+    }
     Ok(Instruction {
         program_id: *program_id,
         data,
@@ -415,6 +2057,684 @@ mod tests {
             );
         }
     }
+
+    fn random_pubkey() -> Pubkey {
+        Pubkey::new(&rand::random::<[u8; 32]>())
+    }
+
+    fn account_keys(instruction: &Instruction) -> Vec<Pubkey> {
+        instruction
+            .accounts
+            .iter()
+            .map(|meta| meta.pubkey)
+            .collect()
+    }
+
+    #[test]
+    fn test_new_order_builder() {
+        let keys: Vec<Pubkey> = (0..11).map(|_| random_pubkey()).collect();
+        let limit_price = NonZeroU64::new(100).unwrap();
+        let max_qty = NonZeroU64::new(5).unwrap();
+        for srm_account_referral in &[None, Some(&keys[9])] {
+            let instruction = new_order(
+                &keys[0],
+                &keys[1],
+                &keys[2],
+                &keys[3],
+                &keys[4],
+                &keys[5],
+                &keys[6],
+                &keys[7],
+                &keys[8],
+                *srm_account_referral,
+                &keys[10],
+                Side::Bid,
+                limit_price,
+                max_qty,
+                OrderType::PostOnly,
+                0xabcd,
+                SelfTradeBehavior::CancelProvide,
+                Some(OrderExpiry::Slot(1_000)),
+            )
+            .unwrap();
+            assert_eq!(instruction.program_id, keys[10]);
+            assert_eq!(
+                MarketInstruction::unpack(&instruction.data),
+                Some(MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+                    side: Side::Bid,
+                    limit_price,
+                    max_qty,
+                    order_type: OrderType::PostOnly,
+                    client_id: 0xabcd,
+                    self_trade_behavior: SelfTradeBehavior::CancelProvide,
+                    expiry: Some(OrderExpiry::Slot(1_000)),
+                }))
+            );
+            let expected_len = 9 + srm_account_referral.is_some() as usize;
+            assert_eq!(account_keys(&instruction), keys[..expected_len].to_vec());
+            let signers: Vec<bool> = instruction.accounts.iter().map(|m| m.is_signer).collect();
+            assert_eq!(signers.iter().filter(|&&s| s).count(), 1);
+            assert!(signers[4]);
+        }
+    }
+
+    #[test]
+    fn test_new_order_v1_builder() {
+        let keys: Vec<Pubkey> = (0..11).map(|_| random_pubkey()).collect();
+        let limit_price = NonZeroU64::new(100).unwrap();
+        let max_qty = NonZeroU64::new(5).unwrap();
+        let instruction = new_order_v1(
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            &keys[4],
+            &keys[5],
+            &keys[6],
+            &keys[7],
+            &keys[8],
+            Some(&keys[9]),
+            &keys[10],
+            Side::Ask,
+            limit_price,
+            max_qty,
+            OrderType::ImmediateOrCancel,
+            7,
+        )
+        .unwrap();
+        assert_eq!(instruction.program_id, keys[10]);
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::NewOrder(NewOrderInstructionV1 {
+                side: Side::Ask,
+                limit_price,
+                max_qty,
+                order_type: OrderType::ImmediateOrCancel,
+                client_id: 7,
+            }))
+        );
+        assert_eq!(account_keys(&instruction), keys[..10].to_vec());
+        assert!(instruction.accounts[4].is_signer);
+    }
+
+    #[test]
+    fn test_new_order_with_max_native_pc_qty_builder() {
+        let keys: Vec<Pubkey> = (0..11).map(|_| random_pubkey()).collect();
+        let limit_price = NonZeroU64::new(std::u64::MAX).unwrap();
+        let max_qty = NonZeroU64::new(std::u64::MAX).unwrap();
+        let max_native_pc_qty_including_fees = NonZeroU64::new(1_000_000).unwrap();
+        let instruction = new_order_with_max_native_pc_qty(
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            &keys[4],
+            &keys[5],
+            &keys[6],
+            &keys[7],
+            &keys[8],
+            None,
+            &keys[10],
+            Side::Bid,
+            limit_price,
+            max_qty,
+            max_native_pc_qty_including_fees,
+            OrderType::ImmediateOrCancel,
+            1,
+            SelfTradeBehavior::DecrementTake,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::NewOrderV5(NewOrderInstructionV5 {
+                side: Side::Bid,
+                limit_price,
+                max_qty,
+                order_type: OrderType::ImmediateOrCancel,
+                client_id: 1,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                expiry: None,
+                max_native_pc_qty_including_fees,
+            }))
+        );
+        assert_eq!(account_keys(&instruction), keys[..9].to_vec());
+        assert!(instruction.accounts[4].is_signer);
+    }
+
+    #[test]
+    fn test_new_iceberg_order_builder() {
+        let keys: Vec<Pubkey> = (0..11).map(|_| random_pubkey()).collect();
+        let limit_price = NonZeroU64::new(100).unwrap();
+        let max_qty = NonZeroU64::new(50).unwrap();
+        let display_qty = NonZeroU64::new(5).unwrap();
+        let instruction = new_iceberg_order(
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            &keys[4],
+            &keys[5],
+            &keys[6],
+            &keys[7],
+            &keys[8],
+            Some(&keys[9]),
+            &keys[10],
+            Side::Ask,
+            limit_price,
+            max_qty,
+            display_qty,
+            OrderType::PostOnly,
+            2,
+            SelfTradeBehavior::CancelProvide,
+            Some(OrderExpiry::UnixTimestamp(1_600_000_000)),
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::NewOrderV6(NewOrderInstructionV6 {
+                side: Side::Ask,
+                limit_price,
+                max_qty,
+                order_type: OrderType::PostOnly,
+                client_id: 2,
+                self_trade_behavior: SelfTradeBehavior::CancelProvide,
+                expiry: Some(OrderExpiry::UnixTimestamp(1_600_000_000)),
+                max_native_pc_qty_including_fees: NonZeroU64::new(std::u64::MAX).unwrap(),
+                display_qty: Some(display_qty),
+            }))
+        );
+        assert_eq!(account_keys(&instruction), keys[..10].to_vec());
+        assert!(instruction.accounts[4].is_signer);
+    }
+
+    #[test]
+    fn test_new_order_and_match_builder() {
+        let keys: Vec<Pubkey> = (0..15).map(|_| random_pubkey()).collect();
+        let limit_price = NonZeroU64::new(100).unwrap();
+        let max_qty = NonZeroU64::new(5).unwrap();
+        let instruction = new_order_and_match(
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &keys[9],
+            &keys[10],
+            &keys[11],
+            &keys[3],
+            &keys[4],
+            &keys[5],
+            &keys[6],
+            &keys[7],
+            &keys[8],
+            &keys[12],
+            Some(&keys[13]),
+            &keys[14],
+            Side::Ask,
+            limit_price,
+            max_qty,
+            OrderType::ImmediateOrCancel,
+            3,
+            SelfTradeBehavior::DecrementTake,
+            None,
+            20,
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::NewOrderV4(NewOrderInstructionV4 {
+                side: Side::Ask,
+                limit_price,
+                max_qty,
+                order_type: OrderType::ImmediateOrCancel,
+                client_id: 3,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                expiry: None,
+                limit: 20,
+            }))
+        );
+        assert_eq!(account_keys(&instruction), keys[..14].to_vec());
+        assert!(instruction.accounts[4].is_signer);
+        assert!(!instruction.accounts[12].is_writable);
+    }
+
+    #[test]
+    fn test_send_take_builder() {
+        let keys: Vec<Pubkey> = (0..16).map(|_| random_pubkey()).collect();
+        let limit_price = NonZeroU64::new(100).unwrap();
+        let max_qty = NonZeroU64::new(5).unwrap();
+        let instruction = send_take(
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            &keys[4],
+            &keys[5],
+            &keys[6],
+            &keys[7],
+            &keys[8],
+            &keys[9],
+            &keys[10],
+            &keys[11],
+            &keys[12],
+            &keys[13],
+            Some(&keys[14]),
+            &keys[15],
+            Side::Bid,
+            limit_price,
+            max_qty,
+            7,
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::SendTake(SendTakeInstruction {
+                side: Side::Bid,
+                limit_price,
+                max_qty,
+                limit: 7,
+            }))
+        );
+        assert_eq!(account_keys(&instruction), keys[..15].to_vec());
+        let signers: Vec<bool> = instruction.accounts.iter().map(|m| m.is_signer).collect();
+        assert_eq!(signers.iter().filter(|&&s| s).count(), 1);
+        assert!(signers[7]);
+        assert!(instruction.accounts[7].is_writable);
+        assert!(!instruction.accounts[10].is_writable);
+        assert!(!instruction.accounts[13].is_writable);
+        assert!(instruction.accounts[14].is_writable);
+    }
+
+    #[test]
+    fn test_replace_order_builder() {
+        let keys: Vec<Pubkey> = (0..11).map(|_| random_pubkey()).collect();
+        let limit_price = NonZeroU64::new(101).unwrap();
+        let max_qty = NonZeroU64::new(2).unwrap();
+        let instruction = replace_order_by_client_id(
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            &keys[4],
+            &keys[5],
+            &keys[6],
+            &keys[7],
+            &keys[8],
+            Some(&keys[9]),
+            &keys[10],
+            Side::Ask,
+            limit_price,
+            max_qty,
+            OrderType::Limit,
+            7,
+            SelfTradeBehavior::DecrementTake,
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::ReplaceOrderByClientId(
+                NewOrderInstructionV2 {
+                    side: Side::Ask,
+                    limit_price,
+                    max_qty,
+                    order_type: OrderType::Limit,
+                    client_id: 7,
+                    self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                }
+            ))
+        );
+        assert_eq!(account_keys(&instruction), keys[..10].to_vec());
+        assert!(instruction.accounts[4].is_signer);
+    }
+
+    #[test]
+    fn test_crank_builders() {
+        let keys: Vec<Pubkey> = (0..10).map(|_| random_pubkey()).collect();
+        let instruction = match_orders(
+            &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &keys[5], &keys[6], &keys[7],
+            &keys[8], None, 7,
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::MatchOrders(7))
+        );
+        assert_eq!(account_keys(&instruction), keys[1..9].to_vec());
+        assert!(instruction.accounts[..7].iter().all(|m| m.is_writable));
+        assert!(!instruction.accounts[7].is_writable);
+
+        let instruction = match_orders(
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            &keys[4],
+            &keys[5],
+            &keys[6],
+            &keys[7],
+            &keys[8],
+            Some(&keys[9]),
+            7,
+        )
+        .unwrap();
+        assert_eq!(account_keys(&instruction), keys[1..].to_vec());
+        assert!(instruction.accounts[8].is_signer && instruction.accounts[8].is_writable);
+
+        let instruction = prune_expired_orders(
+            &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &keys[5], 3,
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::PruneExpiredOrders(3))
+        );
+        assert_eq!(account_keys(&instruction), keys[1..6].to_vec());
+
+        let open_orders: Vec<Pubkey> = (0..5).map(|_| random_pubkey()).collect();
+        let instruction = consume_events(
+            &keys[0],
+            open_orders.iter().collect(),
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            &keys[4],
+            None,
+            9,
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::ConsumeEvents(9))
+        );
+        let keys_used = account_keys(&instruction);
+        let (orders_keys, fixed_keys) = keys_used.split_at(open_orders.len());
+        assert_eq!(fixed_keys, &keys[1..5]);
+        let mut sorted_open_orders = open_orders.clone();
+        sorted_open_orders.sort_by_key(|k| k.to_aligned_bytes());
+        assert_eq!(orders_keys, &sorted_open_orders[..]);
+
+        let instruction = consume_events(
+            &keys[0],
+            open_orders.iter().collect(),
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            &keys[4],
+            Some(&keys[5]),
+            9,
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::ConsumeEventsWithReward(9))
+        );
+        let keys_used = account_keys(&instruction);
+        assert_eq!(&keys_used[open_orders.len()..], &keys[1..6]);
+        assert!(instruction.accounts.last().unwrap().is_signer);
+    }
+
+    #[test]
+    fn test_cancel_builders() {
+        let keys: Vec<Pubkey> = (0..5).map(|_| random_pubkey()).collect();
+        let instruction = cancel_order(
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            &keys[4],
+            Side::Ask,
+            0x1234,
+            3,
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::CancelOrder(CancelOrderInstruction {
+                side: Side::Ask,
+                order_id: 0x1234,
+                owner: keys[2].to_aligned_bytes(),
+                owner_slot: 3,
+            }))
+        );
+        assert_eq!(account_keys(&instruction), keys[1..].to_vec());
+        assert!(instruction.accounts[3].is_signer);
+
+        let instruction =
+            cancel_order_by_client_order_id(&keys[0], &keys[1], &keys[2], &keys[3], &keys[4], 42)
+                .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::CancelOrderByClientId(42))
+        );
+        assert_eq!(account_keys(&instruction), keys[1..].to_vec());
+        assert!(instruction.accounts[3].is_signer);
+
+        for side in &[None, Some(Side::Bid), Some(Side::Ask)] {
+            let instruction =
+                cancel_all_orders(&keys[0], &keys[1], &keys[2], &keys[3], &keys[4], *side).unwrap();
+            assert_eq!(
+                MarketInstruction::unpack(&instruction.data),
+                Some(MarketInstruction::CancelAllOrders(*side))
+            );
+            assert_eq!(account_keys(&instruction), keys[1..].to_vec());
+            assert!(instruction.accounts[3].is_signer);
+        }
+
+        let instruction =
+            close_open_orders(&keys[0], &keys[1], &keys[2], &keys[3], &keys[4]).unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::CloseOpenOrders)
+        );
+        assert_eq!(account_keys(&instruction), keys[1..].to_vec());
+        assert!(instruction.accounts[1].is_signer);
+        assert!(!instruction.accounts[3].is_writable);
+    }
+
+    #[test]
+    fn test_settle_and_admin_builders() {
+        let keys: Vec<Pubkey> = (0..11).map(|_| random_pubkey()).collect();
+        let instruction = settle_funds(
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            &keys[4],
+            &keys[5],
+            &keys[6],
+            &keys[7],
+            &keys[8],
+            &keys[9],
+            Some(&keys[10]),
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::SettleFunds)
+        );
+        assert_eq!(account_keys(&instruction), keys[1..].to_vec());
+        assert!(instruction.accounts[2].is_signer);
+
+        let instruction = disable_market(&keys[0], &keys[1], &disable_authority::ID).unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::DisableMarket)
+        );
+        assert_eq!(
+            account_keys(&instruction),
+            vec![keys[1], disable_authority::ID]
+        );
+        assert!(instruction.accounts[1].is_signer);
+
+        let instruction = sweep_fees(
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &fee_sweeper::ID,
+            &keys[3],
+            &keys[4],
+            &keys[5],
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::SweepFees)
+        );
+        assert_eq!(
+            account_keys(&instruction),
+            vec![keys[1], keys[2], fee_sweeper::ID, keys[3], keys[4], keys[5]]
+        );
+        assert!(instruction.accounts[2].is_signer);
+
+        let instruction = pause_market(&keys[0], &keys[1], &keys[2]).unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::PauseMarket)
+        );
+        assert_eq!(account_keys(&instruction), vec![keys[1], keys[2]]);
+        assert!(instruction.accounts[0].is_writable);
+        assert!(instruction.accounts[1].is_signer);
+
+        let instruction = resume_market(&keys[0], &keys[1], &keys[2]).unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::ResumeMarket)
+        );
+        assert_eq!(account_keys(&instruction), vec![keys[1], keys[2]]);
+        assert!(instruction.accounts[1].is_signer);
+
+        let instruction = set_market_authority(&keys[0], &keys[1], &keys[2], &keys[3]).unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::SetMarketAuthority)
+        );
+        assert_eq!(account_keys(&instruction), vec![keys[1], keys[2], keys[3]]);
+        assert!(instruction.accounts[1].is_signer);
+        assert!(!instruction.accounts[2].is_signer);
+
+        let instruction = set_price_band(&keys[0], &keys[1], &keys[2], 500, 10).unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::SetPriceBand(PriceBandInstruction {
+                max_deviation_bps: 500,
+                halt_slots: 10,
+            }))
+        );
+        assert_eq!(account_keys(&instruction), vec![keys[1], keys[2]]);
+        assert!(instruction.accounts[0].is_writable);
+        assert!(instruction.accounts[1].is_signer);
+
+        let instruction = set_crank_fee(&keys[0], &keys[1], &keys[2], 5_000).unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::SetCrankFee(5_000))
+        );
+        assert_eq!(account_keys(&instruction), vec![keys[1], keys[2]]);
+        assert!(instruction.accounts[0].is_writable);
+        assert!(instruction.accounts[1].is_signer);
+
+        let instruction = set_delegate(&keys[0], &keys[1], &keys[2], &keys[3], &keys[4]).unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::SetDelegate)
+        );
+        assert_eq!(
+            account_keys(&instruction),
+            vec![keys[1], keys[2], keys[3], keys[4]]
+        );
+        assert!(instruction.accounts[1].is_writable);
+        assert!(instruction.accounts[2].is_signer);
+        assert!(!instruction.accounts[3].is_signer);
+
+        let instruction = init_open_orders(
+            &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &keys[5], 7, 256,
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::InitOpenOrders(
+                InitOpenOrdersInstruction {
+                    index: 7,
+                    capacity: 256,
+                }
+            ))
+        );
+        assert_eq!(
+            account_keys(&instruction),
+            vec![keys[1], keys[2], keys[3], keys[4], keys[5]]
+        );
+        assert!(instruction.accounts[1].is_writable);
+        assert!(instruction.accounts[2].is_signer);
+
+        let instruction = revoke_delegate(&keys[0], &keys[1], &keys[2], &keys[3]).unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::RevokeDelegate)
+        );
+        assert_eq!(account_keys(&instruction), vec![keys[1], keys[2], keys[3]]);
+        assert!(instruction.accounts[2].is_signer);
+
+        let instruction = close_market(
+            &keys[0], &keys[1], &keys[2], &keys[3], &keys[4], &keys[5], &keys[6], &keys[7],
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::CloseMarket)
+        );
+        assert_eq!(account_keys(&instruction), keys[1..8].to_vec());
+        assert!(instruction.accounts[..5]
+            .iter()
+            .all(|meta| meta.is_writable));
+        assert!(instruction.accounts[5].is_signer);
+        assert!(instruction.accounts[6].is_writable);
+    }
+
+    #[test]
+    fn test_initialize_market_builder() {
+        let keys: Vec<Pubkey> = (0..10).map(|_| random_pubkey()).collect();
+        let mut fee_schedule = FeeSchedule::default();
+        fee_schedule.taker_fee_bps[0] = 30;
+        let instruction = initialize_market(
+            &keys[0],
+            &keys[1],
+            &keys[2],
+            &keys[3],
+            &keys[4],
+            &keys[5],
+            &keys[6],
+            &keys[7],
+            &keys[8],
+            &keys[9],
+            1_000,
+            10,
+            3,
+            5,
+            fee_schedule,
+            Some(&keys[1]),
+        )
+        .unwrap();
+        assert_eq!(
+            MarketInstruction::unpack(&instruction.data),
+            Some(MarketInstruction::InitializeMarketV2(
+                InitializeMarketInstructionV2 {
+                    coin_lot_size: 1_000,
+                    pc_lot_size: 10,
+                    fee_rate_bps: 0,
+                    vault_signer_nonce: 3,
+                    pc_dust_threshold: 5,
+                    fee_schedule,
+                }
+            ))
+        );
+        assert_eq!(
+            account_keys(&instruction),
+            vec![
+                keys[0], keys[8], keys[9], keys[6], keys[7], keys[4], keys[5], keys[2], keys[3],
+                keys[1]
+            ]
+        );
+        assert!(!instruction.accounts[9].is_signer);
+    }
 }
 
 #[cfg(feature = "fuzz")]
@@ -530,4 +2850,54 @@ mod fuzzing {
             )
         }
     }
+
+    impl arbitrary::Arbitrary for NewOrderInstructionV3 {
+        fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self, arbitrary::Error> {
+            let v2_instr = <NewOrderInstructionV2 as arbitrary::Arbitrary>::arbitrary(u)?;
+            let expiry = <Option<OrderExpiry> as arbitrary::Arbitrary>::arbitrary(u)?;
+            Ok(v2_instr.add_expiry(expiry))
+        }
+    }
+
+    impl arbitrary::Arbitrary for NewOrderInstructionV4 {
+        fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self, arbitrary::Error> {
+            let v3_instr = <NewOrderInstructionV3 as arbitrary::Arbitrary>::arbitrary(u)?;
+            let limit = <u16 as arbitrary::Arbitrary>::arbitrary(u)?;
+            Ok(v3_instr.add_match_limit(limit))
+        }
+    }
+
+    fn arbitrary_non_zero(u: &mut Unstructured<'_>) -> Result<NonZeroU64, arbitrary::Error> {
+        NonZeroU64::new(<u64 as arbitrary::Arbitrary>::arbitrary(u)?)
+            .ok_or(arbitrary::Error::IncorrectFormat)
+    }
+
+    impl arbitrary::Arbitrary for SendTakeInstruction {
+        fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self, arbitrary::Error> {
+            Ok(SendTakeInstruction {
+                side: <Side as arbitrary::Arbitrary>::arbitrary(u)?,
+                limit_price: arbitrary_non_zero(u)?,
+                max_qty: arbitrary_non_zero(u)?,
+                limit: <u16 as arbitrary::Arbitrary>::arbitrary(u)?,
+            })
+        }
+    }
+
+    impl arbitrary::Arbitrary for NewOrderInstructionV5 {
+        fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self, arbitrary::Error> {
+            let v3_instr = <NewOrderInstructionV3 as arbitrary::Arbitrary>::arbitrary(u)?;
+            Ok(v3_instr.add_max_native_pc_qty(arbitrary_non_zero(u)?))
+        }
+    }
+
+    impl arbitrary::Arbitrary for NewOrderInstructionV6 {
+        fn arbitrary(u: &mut Unstructured<'_>) -> Result<Self, arbitrary::Error> {
+            let v5_instr = <NewOrderInstructionV5 as arbitrary::Arbitrary>::arbitrary(u)?;
+            let display_qty = match <bool as arbitrary::Arbitrary>::arbitrary(u)? {
+                true => Some(arbitrary_non_zero(u)?),
+                false => None,
+            };
+            Ok(v5_instr.add_display_qty(display_qty))
+        }
+    }
 }
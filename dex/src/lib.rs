@@ -6,8 +6,10 @@ pub mod error;
 #[cfg(test)]
 mod tests;
 
+#[cfg(any(test, feature = "client"))]
+pub mod client;
 pub mod critbit;
-mod fees;
+pub mod fees;
 pub mod instruction;
 pub mod matching;
 pub mod state;
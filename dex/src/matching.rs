@@ -1,20 +1,21 @@
 use std::num::NonZeroU64;
 
-use crate::instruction::SelfTradeBehavior;
+use crate::instruction::{OrderExpiry, SelfTradeBehavior};
 use num_enum::{IntoPrimitive, TryFromPrimitive};
 #[cfg(test)]
 use proptest_derive::Arbitrary;
 use serde::{Deserialize, Serialize};
+use solana_sdk::clock::Clock;
 #[cfg(feature = "program")]
 use solana_sdk::info;
 
 use crate::critbit::SlabTreeError;
 use crate::error::{DexErrorCode, DexResult, SourceFileId};
 use crate::{
-    critbit::{LeafNode, NodeHandle, Slab, SlabView},
+    critbit::{LeafNode, LeafTail, NodeHandle, NodeTail, Slab, SlabView},
     error::DexError,
-    fees::{self, FeeTier},
-    state::{Event, EventQueue, EventView, MarketState, Request, RequestQueue, RequestView},
+    fees::FeeTier,
+    state::{Event, EventQueue, EventView, MarketStateV2, Request, RequestQueue, RequestView},
 };
 
 #[cfg(not(feature = "program"))]
@@ -44,21 +45,85 @@ pub enum OrderType {
     Limit = 0,
     ImmediateOrCancel = 1,
     PostOnly = 2,
+    FillOrKill = 3,
+    /// Like `PostOnly`, but an order that would cross is repriced to one tick
+    /// behind the best opposite order and rests there.
+    PostOnlySlide = 4,
 }
 
 fn extract_price_from_order_id(order_id: &u128) -> u64 {
     (order_id >> 64) as u64
 }
 
-pub struct OrderBookState<'a> {
+/// Shows at most `display_qty` of a resting order on the book and keeps the
+/// rest of it in reserve.
+fn with_display_qty<X: NodeTail>(
+    mut leaf: LeafNode<X>,
+    display_qty: Option<NonZeroU64>,
+) -> LeafNode<X> {
+    match display_qty {
+        Some(display_qty) if display_qty.get() < leaf.quantity() => {
+            let reserve_quantity = leaf.quantity() - display_qty.get();
+            *leaf.quantity_mut() = display_qty.get();
+            leaf.with_reserve(reserve_quantity)
+        }
+        _ => leaf,
+    }
+}
+
+/// The prices a taker may trade at. It is fixed when the taker starts
+/// matching, so that walking the book one level at a time cannot drag the
+/// band along with it.
+#[derive(Copy, Clone, Debug)]
+struct PriceBand {
+    min_price: u64,
+    max_price: u64,
+}
+
+impl PriceBand {
+    const UNBOUNDED: PriceBand = PriceBand {
+        min_price: 0,
+        max_price: std::u64::MAX,
+    };
+    const EMPTY: PriceBand = PriceBand {
+        min_price: std::u64::MAX,
+        max_price: 0,
+    };
+
+    fn contains(&self, price: NonZeroU64) -> bool {
+        self.min_price <= price.get() && price.get() <= self.max_price
+    }
+
+    /// Narrows the band to the prices a taker on `side` accepts.
+    fn with_limit(self, side: Side, limit_price: Option<NonZeroU64>) -> Self {
+        match (side, limit_price) {
+            (_, None) => self,
+            (Side::Bid, Some(limit_price)) => PriceBand {
+                max_price: self.max_price.min(limit_price.get()),
+                ..self
+            },
+            (Side::Ask, Some(limit_price)) => PriceBand {
+                min_price: self.min_price.max(limit_price.get()),
+                ..self
+            },
+        }
+    }
+}
+
+/// The book and the market it belongs to. The books of markets of the
+/// original size have `X = ()`.
+pub struct OrderBookState<'a, X = LeafTail> {
     // first byte of a key is 0xaa or 0xbb, disambiguating bids and asks
-    pub bids: &'a mut Slab,
-    pub asks: &'a mut Slab,
-    pub market_state: &'a mut MarketState,
+    pub bids: &'a mut Slab<X>,
+    pub asks: &'a mut Slab<X>,
+    pub market_state: &'a mut MarketStateV2,
+    /// `None` if the instruction was not given the clock sysvar, in which case
+    /// matching fails once it needs the time.
+    pub clock: Option<Clock>,
 }
 
-impl<'ob> OrderBookState<'ob> {
-    fn orders_mut(&mut self, side: Side) -> &mut Slab {
+impl<'ob, X: NodeTail> OrderBookState<'ob, X> {
+    fn orders_mut(&mut self, side: Side) -> &mut Slab<X> {
         match side {
             Side::Bid => self.bids,
             Side::Ask => self.asks,
@@ -72,6 +137,194 @@ impl<'ob> OrderBookState<'ob> {
         }
     }
 
+    fn clock(&self) -> DexResult<&Clock> {
+        Ok(self.clock.as_ref().ok_or(DexErrorCode::ClockNotProvided)?)
+    }
+
+    /// The slot events are stamped with, or 0 without the clock.
+    fn current_slot(&self) -> u64 {
+        self.clock.as_ref().map_or(0, |clock| clock.slot)
+    }
+
+    fn has_expired(&self, expiry: Option<OrderExpiry>) -> DexResult<bool> {
+        match expiry {
+            Some(expiry) => Ok(expiry.has_passed(self.clock()?)),
+            None => Ok(false),
+        }
+    }
+
+    /// The band around the last trade price that trades must stay within.
+    /// Nothing may trade while matching is halted.
+    fn price_band(&self) -> DexResult<PriceBand> {
+        let market = &*self.market_state;
+        if market.price_band_bps == 0 && market.halted_until_slot == 0 {
+            return Ok(PriceBand::UNBOUNDED);
+        }
+        if self.clock()?.slot < market.halted_until_slot {
+            return Ok(PriceBand::EMPTY);
+        }
+        if market.price_band_bps == 0 || market.last_trade_price == 0 {
+            return Ok(PriceBand::UNBOUNDED);
+        }
+        let max_deviation = (market.last_trade_price as u128 * market.price_band_bps as u128
+            / 10_000)
+            .min(std::u64::MAX as u128) as u64;
+        Ok(PriceBand {
+            min_price: market.last_trade_price.saturating_sub(max_deviation),
+            max_price: market.last_trade_price.saturating_add(max_deviation),
+        })
+    }
+
+    /// Called when a taker runs into the price band: stops all matching for
+    /// the market's configured number of slots, unless it is already halted.
+    fn halt_matching(&mut self) -> DexResult {
+        let current_slot = self.clock()?.slot;
+        let market = &mut *self.market_state;
+        if current_slot >= market.halted_until_slot {
+            market.halted_until_slot = current_slot.saturating_add(market.price_band_halt_slots);
+        }
+        Ok(())
+    }
+
+    /// Updates the market's trading statistics for a single maker fill.
+    fn record_fill(&mut self, trade_price: NonZeroU64, trade_qty: u64) {
+        let market = &mut *self.market_state;
+        market.last_trade_price = trade_price.get();
+        market.coin_volume_total = market
+            .coin_volume_total
+            .wrapping_add(trade_qty * market.coin_lot_size);
+        market.pc_volume_total = market
+            .pc_volume_total
+            .wrapping_add(trade_qty * trade_price.get() * market.pc_lot_size);
+        market.fill_count = market.fill_count.wrapping_add(1);
+    }
+
+    /// Removes the order if its expiry has passed, emitting an `Out` event
+    /// that unlocks all of its funds.
+    fn remove_if_expired(
+        &mut self,
+        side: Side,
+        handle: NodeHandle,
+        event_q: &mut EventQueue,
+    ) -> DexResult<bool> {
+        let leaf = *self
+            .orders_mut(side)
+            .get(handle)
+            .unwrap()
+            .as_leaf()
+            .unwrap();
+        if !self.has_expired(leaf.expiry()?)? {
+            return Ok(false);
+        }
+        self.remove_expired(side, &leaf, event_q)?;
+        Ok(true)
+    }
+
+    fn remove_expired(
+        &mut self,
+        side: Side,
+        leaf: &LeafNode<X>,
+        event_q: &mut EventQueue,
+    ) -> DexResult {
+        let native_qty_unlocked = match side {
+            Side::Bid => leaf.total_quantity() * leaf.price().get() * self.market_state.pc_lot_size,
+            Side::Ask => leaf.total_quantity() * self.market_state.coin_lot_size,
+        };
+        event_q
+            .push_back(Event::new(
+                EventView::Out {
+                    side,
+                    native_qty_unlocked,
+                    native_qty_still_locked: 0,
+                    order_id: leaf.order_id(),
+                    owner: leaf.owner(),
+                    owner_slot: leaf.owner_slot(),
+                    client_order_id: NonZeroU64::new(leaf.client_order_id()),
+                },
+                self.current_slot(),
+            ))
+            .map_err(|_| DexErrorCode::EventQueueFull)?;
+        self.orders_mut(side)
+            .remove_by_key(leaf.order_id())
+            .unwrap();
+        Ok(())
+    }
+
+    /// Reposts the next slice of a fully filled iceberg order from its
+    /// reserve. The slice goes to the back of the price level under a new
+    /// order id, which the owner picks up from the `Repost` event.
+    fn replenish(
+        &mut self,
+        side: Side,
+        order_id: &u128,
+        req_q: &mut RequestQueue,
+        event_q: &mut EventQueue,
+    ) -> DexResult {
+        let leaf = self.orders_mut(side).remove_by_key(order_id).unwrap();
+        let new_order_id = req_q.gen_order_id(leaf.price().get(), side);
+        event_q
+            .push_back(Event::new(
+                EventView::Repost {
+                    side,
+                    order_id,
+                    new_order_id,
+                    owner: leaf.owner(),
+                    owner_slot: leaf.owner_slot(),
+                    client_order_id: NonZeroU64::new(leaf.client_order_id()),
+                },
+                self.current_slot(),
+            ))
+            .map_err(|_| DexErrorCode::EventQueueFull)?;
+        self.orders_mut(side)
+            .insert_leaf(&leaf.replenished(&new_order_id))
+            .unwrap();
+        Ok(())
+    }
+
+    /// The order id a crossing post-only-slide order on `side` rests under:
+    /// one tick behind the best opposite order, keeping its sequence number.
+    /// Returns `None` if there is no such price.
+    fn slide_order_id(&self, side: Side, order_id: &u128) -> Option<u128> {
+        let best_opposite_price = match side {
+            Side::Bid => self.asks.get(self.find_bbo(Side::Ask)?),
+            Side::Ask => self.bids.get(self.find_bbo(Side::Bid)?),
+        }?
+        .as_leaf()?
+        .price()
+        .get();
+        let slid_price = match side {
+            Side::Bid => NonZeroU64::new(best_opposite_price - 1)?,
+            Side::Ask => NonZeroU64::new(best_opposite_price.checked_add(1)?)?,
+        };
+        Some(((slid_price.get() as u128) << 64) | (order_id & std::u64::MAX as u128))
+    }
+
+    /// Removes up to `limit` expired orders from anywhere in the book, so that
+    /// their funds are released without waiting for a taker to reach them.
+    pub fn prune_expired_orders(&mut self, event_q: &mut EventQueue, limit: u16) -> DexResult {
+        let mut limit_remaining = limit as usize;
+        for &side in &[Side::Bid, Side::Ask] {
+            let orders = match side {
+                Side::Bid => &*self.bids,
+                Side::Ask => &*self.asks,
+            };
+            let mut expired: Vec<LeafNode<X>> = Vec::new();
+            for leaf in orders.leaves(false) {
+                if expired.len() == limit_remaining {
+                    break;
+                }
+                if self.has_expired(leaf.expiry()?)? {
+                    expired.push(*leaf);
+                }
+            }
+            limit_remaining -= expired.len();
+            for leaf in expired.iter() {
+                self.remove_expired(side, leaf, event_q)?;
+            }
+        }
+        Ok(())
+    }
+
     pub fn process_requests(
         &mut self,
         req_q: &mut RequestQueue,
@@ -79,14 +332,23 @@ impl<'ob> OrderBookState<'ob> {
         limit: u16,
     ) -> Result<(), DexError> {
         let mut limit_remaining = limit;
-        while limit_remaining > 0 {
-            let request = match req_q.peek_front_mut() {
+        self.process_requests_within(req_q, event_q, &mut limit_remaining)
+    }
+
+    fn process_requests_within(
+        &mut self,
+        req_q: &mut RequestQueue,
+        event_q: &mut EventQueue,
+        limit_remaining: &mut u16,
+    ) -> DexResult {
+        while *limit_remaining > 0 {
+            let request = match req_q.peek_front() {
                 Some(r) => r,
                 None => break,
             };
-            match self.process_orderbook_request(request, event_q, &mut limit_remaining)? {
+            match self.process_orderbook_request(&request, req_q, event_q, limit_remaining)? {
                 Some(remaining_request) => {
-                    *request = remaining_request;
+                    req_q.replace_front(remaining_request).unwrap();
                 }
                 None => {
                     req_q.pop_front().unwrap();
@@ -100,6 +362,7 @@ impl<'ob> OrderBookState<'ob> {
     fn process_orderbook_request(
         &mut self,
         request: &Request,
+        req_q: &mut RequestQueue,
         event_q: &mut EventQueue,
         limit: &mut u16,
     ) -> DexResult<Option<Request>> {
@@ -115,6 +378,8 @@ impl<'ob> OrderBookState<'ob> {
                 native_pc_qty_locked,
                 client_order_id,
                 self_trade_behavior,
+                expiry,
+                display_qty,
             } => self
                 .new_order(
                     NewOrderParams {
@@ -128,7 +393,10 @@ impl<'ob> OrderBookState<'ob> {
                         native_pc_qty_locked,
                         client_order_id: client_order_id.map_or(0, NonZeroU64::get),
                         self_trade_behavior,
+                        expiry,
+                        display_qty,
                     },
+                    req_q,
                     event_q,
                     limit,
                 )?
@@ -144,6 +412,8 @@ impl<'ob> OrderBookState<'ob> {
                         native_pc_qty_locked: remaining.native_pc_qty_remaining,
                         client_order_id,
                         self_trade_behavior,
+                        expiry,
+                        display_qty,
                     })
                 }),
             RequestView::CancelOrder {
@@ -180,6 +450,8 @@ struct NewOrderParams<'a> {
     native_pc_qty_locked: Option<NonZeroU64>,
     client_order_id: u64,
     self_trade_behavior: SelfTradeBehavior,
+    expiry: Option<OrderExpiry>,
+    display_qty: Option<NonZeroU64>,
 }
 
 struct OrderRemaining {
@@ -187,12 +459,13 @@ struct OrderRemaining {
     native_pc_qty_remaining: Option<NonZeroU64>,
 }
 
-impl<'ob> OrderBookState<'ob> {
+impl<'ob, X: NodeTail> OrderBookState<'ob, X> {
     fn new_order(
         &mut self,
 
         params: NewOrderParams,
 
+        req_q: &mut RequestQueue,
         event_q: &mut EventQueue,
         limit: &mut u16,
     ) -> DexResult<Option<OrderRemaining>> {
@@ -207,15 +480,28 @@ impl<'ob> OrderBookState<'ob> {
             mut native_pc_qty_locked,
             client_order_id,
             self_trade_behavior,
+            expiry,
+            display_qty,
         } = params;
-        let (post_only, post_allowed) = match order_type {
+        let (post_only, mut post_allowed) = match order_type {
             OrderType::Limit => (false, true),
-            OrderType::ImmediateOrCancel => (false, false),
-            OrderType::PostOnly => (true, true),
+            OrderType::ImmediateOrCancel | OrderType::FillOrKill => (false, false),
+            OrderType::PostOnly | OrderType::PostOnlySlide => (true, true),
         };
+        if self.has_expired(expiry)? {
+            post_allowed = false;
+        }
+        let fill_or_kill = order_type == OrderType::FillOrKill;
+        let post_only_slide = order_type == OrderType::PostOnlySlide;
         let limit_price = extract_price_from_order_id(order_id);
-        while *limit > 0 {
-            *limit -= 1;
+        let price_band = self.price_band()?;
+        // A fill-or-kill order is checked against the book once, up front, and
+        // only goes ahead if it fills within the limit. It then matches to
+        // completion, so it is never left partially filled and re-queued.
+        let mut first_match = true;
+        while *limit > 0 || (fill_or_kill && !first_match) {
+            let matches_left = *limit;
+            *limit = limit.saturating_sub(1);
             let remaining_order = match side {
                 Side::Bid => self.new_bid(
                     NewBidParams {
@@ -227,10 +513,17 @@ impl<'ob> OrderBookState<'ob> {
                         owner_slot,
                         fee_tier,
                         post_only,
+                        post_only_slide,
                         post_allowed,
+                        fill_or_kill: fill_or_kill && first_match,
+                        matches_left,
                         client_order_id,
                         self_trade_behavior,
+                        expiry,
+                        display_qty,
+                        price_band,
                     },
+                    req_q,
                     event_q,
                 ),
                 Side::Ask => {
@@ -244,15 +537,23 @@ impl<'ob> OrderBookState<'ob> {
                             owner_slot,
                             fee_tier,
                             post_only,
+                            post_only_slide,
                             post_allowed,
+                            fill_or_kill: fill_or_kill && first_match,
+                            matches_left,
                             client_order_id,
                             self_trade_behavior,
+                            expiry,
+                            display_qty,
+                            price_band,
                         },
+                        req_q,
                         event_q,
                     )
                 }
             }?;
-            if *limit == 0 {
+            first_match = false;
+            if *limit == 0 && !fill_or_kill {
                 return Ok(remaining_order);
             }
             match remaining_order {
@@ -267,6 +568,104 @@ impl<'ob> OrderBookState<'ob> {
     }
 }
 
+pub struct SendTakeParams<'a> {
+    pub side: Side,
+    pub order_id: &'a u128,
+    pub owner: &'a [u64; 4],
+    pub fee_tier: FeeTier,
+    pub max_coin_qty: NonZeroU64,
+    pub native_pc_qty_locked: Option<NonZeroU64>,
+}
+
+/// What a taker matched through `send_take` owes and is owed. Quantities are
+/// in the native units of the currency paid and received respectively.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SendTakeResult {
+    pub native_qty_paid: u64,
+    pub native_qty_received: u64,
+    pub native_referrer_rebate: u64,
+}
+
+impl<'ob, X: NodeTail> OrderBookState<'ob, X> {
+    /// Matches an immediate-or-cancel order against up to `limit` resting
+    /// orders, once the request queue has been drained within the same
+    /// limit. The taker has no OpenOrders account to settle against, so its
+    /// own events are kept off the event queue and summed up for the caller.
+    pub fn send_take(
+        &mut self,
+        params: SendTakeParams,
+        req_q: &mut RequestQueue,
+        event_q: &mut EventQueue,
+        mut limit: u16,
+    ) -> DexResult<SendTakeResult> {
+        let SendTakeParams {
+            side,
+            order_id,
+            owner,
+            fee_tier,
+            max_coin_qty,
+            native_pc_qty_locked,
+        } = params;
+        // Requests queued ahead of the taker, cancels included, have to be
+        // matched first, or it could take orders their owners have already
+        // asked to cancel.
+        self.process_requests_within(req_q, event_q, &mut limit)?;
+        if !req_q.empty() {
+            Err(DexErrorCode::RequestQueueNotEmpty)?
+        }
+        let events_before = event_q.len();
+        self.new_order(
+            NewOrderParams {
+                side,
+                order_type: OrderType::ImmediateOrCancel,
+                order_id,
+                owner,
+                owner_slot: 0,
+                fee_tier,
+                max_coin_qty,
+                native_pc_qty_locked,
+                client_order_id: 0,
+                self_trade_behavior: SelfTradeBehavior::DecrementTake,
+                expiry: None,
+                display_qty: None,
+            },
+            req_q,
+            event_q,
+            &mut limit,
+        )?;
+
+        let new_events: Vec<Event> = event_q.iter().skip(events_before as usize).collect();
+        event_q.revert_pushes(events_before)?;
+
+        let fee_schedule = self.market_state.fee_schedule;
+        let mut result = SendTakeResult::default();
+        for event in new_events {
+            match event.as_view()? {
+                EventView::Fill {
+                    maker: false,
+                    native_qty_paid,
+                    native_qty_received,
+                    native_fee_or_rebate,
+                    ..
+                } => {
+                    result.native_qty_paid += native_qty_paid;
+                    result.native_qty_received += native_qty_received;
+                    result.native_referrer_rebate +=
+                        fee_schedule.referrer_rebate(native_fee_or_rebate);
+                }
+                EventView::Out {
+                    order_id: out_order_id,
+                    ..
+                } if out_order_id == order_id => {}
+                _ => event_q
+                    .push_back(event)
+                    .map_err(|_| DexErrorCode::EventQueueFull)?,
+            }
+        }
+        Ok(result)
+    }
+}
+
 struct NewAskParams<'a> {
     max_qty: NonZeroU64,
     limit_price: NonZeroU64,
@@ -275,15 +674,22 @@ struct NewAskParams<'a> {
     owner_slot: u8,
     fee_tier: FeeTier,
     post_only: bool,
+    post_only_slide: bool,
     post_allowed: bool,
+    fill_or_kill: bool,
+    matches_left: u16,
     client_order_id: u64,
     self_trade_behavior: SelfTradeBehavior,
+    expiry: Option<OrderExpiry>,
+    display_qty: Option<NonZeroU64>,
+    price_band: PriceBand,
 }
 
-impl<'ob> OrderBookState<'ob> {
+impl<'ob, X: NodeTail> OrderBookState<'ob, X> {
     fn new_ask(
         &mut self,
         params: NewAskParams,
+        req_q: &mut RequestQueue,
         event_q: &mut EventQueue,
     ) -> DexResult<Option<OrderRemaining>> {
         let NewAskParams {
@@ -294,17 +700,55 @@ impl<'ob> OrderBookState<'ob> {
             owner_slot,
             fee_tier,
             post_only,
+            post_only_slide,
             post_allowed,
+            fill_or_kill,
+            matches_left,
             client_order_id,
             self_trade_behavior,
+            expiry,
+            display_qty,
+            price_band,
         } = params;
         let mut unfilled_qty = max_qty.get();
         let mut accum_fill_price = 0;
 
         let pc_lot_size = self.market_state.pc_lot_size;
         let coin_lot_size = self.market_state.coin_lot_size;
+        let fee_schedule = self.market_state.fee_schedule;
+        let current_slot = self.current_slot();
+
+        if fill_or_kill
+            && !self.can_fill_completely(
+                Side::Ask,
+                price_band.with_limit(Side::Ask, Some(limit_price)),
+                max_qty.get(),
+                None,
+                owner,
+                self_trade_behavior,
+                matches_left,
+            )?
+        {
+            event_q
+                .push_back(Event::new(
+                    EventView::Out {
+                        side: Side::Ask,
+                        native_qty_unlocked: max_qty.get() * coin_lot_size,
+                        native_qty_still_locked: 0,
+                        order_id,
+                        owner,
+                        owner_slot,
+                        client_order_id: NonZeroU64::new(client_order_id),
+                    },
+                    current_slot,
+                ))
+                .map_err(|_| DexErrorCode::EventQueueFull)?;
+            return Ok(None);
+        }
 
         let mut accum_maker_rebates = 0;
+        let mut outside_price_band = false;
+        let mut fill_price = 0;
         let crossed;
         let done = loop {
             let best_bid_h = match self.find_bbo(Side::Bid) {
@@ -315,6 +759,13 @@ impl<'ob> OrderBookState<'ob> {
                 Some(h) => h,
             };
 
+            if self.remove_if_expired(Side::Bid, best_bid_h, event_q)? {
+                return Ok(Some(OrderRemaining {
+                    coin_qty_remaining: max_qty,
+                    native_pc_qty_remaining: None,
+                }));
+            }
+
             let best_bid_ref = self
                 .orders_mut(Side::Bid)
                 .get_mut(best_bid_h)
@@ -329,6 +780,11 @@ impl<'ob> OrderBookState<'ob> {
                 break true;
             }
 
+            if !price_band.contains(trade_price) {
+                outside_price_band = true;
+                break true;
+            }
+
             let bid_size = best_bid_ref.quantity();
             let trade_qty = bid_size.min(unfilled_qty);
 
@@ -348,23 +804,31 @@ impl<'ob> OrderBookState<'ob> {
                         cancelled_take_qty = trade_qty;
                     }
                     SelfTradeBehavior::CancelProvide => {
-                        cancelled_provide_qty = best_bid_ref.quantity();
+                        cancelled_provide_qty = best_bid_ref.total_quantity();
                         cancelled_take_qty = 0;
                     }
+                    SelfTradeBehavior::AbortTransaction => {
+                        return Err(DexErrorCode::WouldSelfTrade.into())
+                    }
                 };
 
-                let remaining_provide_size = bid_size - cancelled_provide_qty;
-                let provide_out = Event::new(EventView::Out {
-                    side: Side::Bid,
-                    native_qty_unlocked: cancelled_provide_qty * trade_price.get() * pc_lot_size,
-                    native_qty_still_locked: remaining_provide_size
-                        * trade_price.get()
-                        * pc_lot_size,
-                    order_id: &best_bid_id,
-                    owner: best_bid_ref.owner(),
-                    owner_slot: best_bid_ref.owner_slot(),
-                    client_order_id: NonZeroU64::new(best_bid_ref.client_order_id()),
-                });
+                let remaining_provide_size = best_bid_ref.total_quantity() - cancelled_provide_qty;
+                let provide_out = Event::new(
+                    EventView::Out {
+                        side: Side::Bid,
+                        native_qty_unlocked: cancelled_provide_qty
+                            * trade_price.get()
+                            * pc_lot_size,
+                        native_qty_still_locked: remaining_provide_size
+                            * trade_price.get()
+                            * pc_lot_size,
+                        order_id: &best_bid_id,
+                        owner: best_bid_ref.owner(),
+                        owner_slot: best_bid_ref.owner_slot(),
+                        client_order_id: NonZeroU64::new(best_bid_ref.client_order_id()),
+                    },
+                    current_slot,
+                );
                 event_q
                     .push_back(provide_out)
                     .map_err(|_| DexErrorCode::EventQueueFull)?;
@@ -372,20 +836,25 @@ impl<'ob> OrderBookState<'ob> {
                     self.orders_mut(Side::Bid)
                         .remove_by_key(&best_bid_id)
                         .unwrap();
+                } else if cancelled_provide_qty == bid_size {
+                    self.replenish(Side::Bid, &best_bid_id, req_q, event_q)?;
                 } else {
-                    *best_bid_ref.quantity_mut() = remaining_provide_size;
+                    *best_bid_ref.quantity_mut() -= cancelled_provide_qty;
                 }
 
                 unfilled_qty -= cancelled_take_qty;
-                let take_out = Event::new(EventView::Out {
-                    side: Side::Ask,
-                    native_qty_unlocked: cancelled_take_qty * coin_lot_size,
-                    native_qty_still_locked: unfilled_qty,
-                    order_id,
-                    owner,
-                    owner_slot,
-                    client_order_id: NonZeroU64::new(client_order_id),
-                });
+                let take_out = Event::new(
+                    EventView::Out {
+                        side: Side::Ask,
+                        native_qty_unlocked: cancelled_take_qty * coin_lot_size,
+                        native_qty_still_locked: unfilled_qty,
+                        order_id,
+                        owner,
+                        owner_slot,
+                        client_order_id: NonZeroU64::new(client_order_id),
+                    },
+                    current_slot,
+                );
                 event_q
                     .push_back(take_out)
                     .map_err(|_| DexErrorCode::EventQueueFull)?;
@@ -400,21 +869,26 @@ impl<'ob> OrderBookState<'ob> {
 
             let maker_fee_tier = best_bid_ref.fee_tier();
             let native_maker_pc_qty = trade_qty * trade_price.get() * pc_lot_size;
-            let native_maker_rebate = maker_fee_tier.maker_rebate(native_maker_pc_qty);
+            let native_maker_rebate =
+                fee_schedule.maker_rebate(maker_fee_tier, native_maker_pc_qty);
             accum_maker_rebates += native_maker_rebate;
 
-            let maker_fill = Event::new(EventView::Fill {
-                side: Side::Bid,
-                maker: true,
-                native_qty_paid: native_maker_pc_qty - native_maker_rebate,
-                native_qty_received: trade_qty * coin_lot_size,
-                native_fee_or_rebate: native_maker_rebate,
-                order_id: best_bid_ref.order_id(),
-                owner: best_bid_ref.owner(),
-                owner_slot: best_bid_ref.owner_slot(),
-                fee_tier: maker_fee_tier,
-                client_order_id: NonZeroU64::new(best_bid_ref.client_order_id()),
-            });
+            let maker_fill = Event::new(
+                EventView::Fill {
+                    side: Side::Bid,
+                    maker: true,
+                    native_qty_paid: native_maker_pc_qty - native_maker_rebate,
+                    native_qty_received: trade_qty * coin_lot_size,
+                    native_fee_or_rebate: native_maker_rebate,
+                    order_id: best_bid_ref.order_id(),
+                    owner: best_bid_ref.owner(),
+                    owner_slot: best_bid_ref.owner_slot(),
+                    fee_tier: maker_fee_tier,
+                    client_order_id: NonZeroU64::new(best_bid_ref.client_order_id()),
+                    price: trade_price.get(),
+                },
+                current_slot,
+            );
             event_q
                 .push_back(maker_fill)
                 .map_err(|_| DexErrorCode::EventQueueFull)?;
@@ -425,47 +899,63 @@ impl<'ob> OrderBookState<'ob> {
 
             if best_bid_ref.quantity() == 0 {
                 let best_bid_id = *best_bid_ref.order_id();
-                event_q
-                    .push_back(Event::new(EventView::Out {
-                        side: Side::Bid,
-                        native_qty_unlocked: 0,
-                        native_qty_still_locked: 0,
-                        order_id: &best_bid_id,
-                        owner: best_bid_ref.owner(),
-                        owner_slot: best_bid_ref.owner_slot(),
-                        client_order_id: NonZeroU64::new(best_bid_ref.client_order_id()),
-                    }))
-                    .map_err(|_| DexErrorCode::EventQueueFull)?;
-                self.orders_mut(Side::Bid)
-                    .remove_by_key(&best_bid_id)
-                    .unwrap();
+                if best_bid_ref.reserve_quantity() > 0 {
+                    self.replenish(Side::Bid, &best_bid_id, req_q, event_q)?;
+                } else {
+                    event_q
+                        .push_back(Event::new(
+                            EventView::Out {
+                                side: Side::Bid,
+                                native_qty_unlocked: 0,
+                                native_qty_still_locked: 0,
+                                order_id: &best_bid_id,
+                                owner: best_bid_ref.owner(),
+                                owner_slot: best_bid_ref.owner_slot(),
+                                client_order_id: NonZeroU64::new(best_bid_ref.client_order_id()),
+                            },
+                            current_slot,
+                        ))
+                        .map_err(|_| DexErrorCode::EventQueueFull)?;
+                    self.orders_mut(Side::Bid)
+                        .remove_by_key(&best_bid_id)
+                        .unwrap();
+                }
             }
 
+            fill_price = trade_price.get();
+            self.record_fill(trade_price, trade_qty);
             break false;
         };
+        if outside_price_band {
+            self.halt_matching()?;
+        }
 
         let native_taker_pc_qty = accum_fill_price * pc_lot_size;
-        let native_taker_fee = fee_tier.taker_fee(native_taker_pc_qty);
+        let native_taker_fee = fee_schedule.taker_fee(fee_tier, native_taker_pc_qty);
         if native_taker_pc_qty > 0 {
-            let taker_fill = Event::new(EventView::Fill {
-                side: Side::Ask,
-                maker: false,
-                native_qty_paid: (max_qty.get() - unfilled_qty) * coin_lot_size,
-                native_qty_received: native_taker_pc_qty - native_taker_fee,
-                native_fee_or_rebate: native_taker_fee,
-                order_id,
-                owner,
-                owner_slot,
-                fee_tier,
-                client_order_id: NonZeroU64::new(client_order_id),
-            });
+            let taker_fill = Event::new(
+                EventView::Fill {
+                    side: Side::Ask,
+                    maker: false,
+                    native_qty_paid: (max_qty.get() - unfilled_qty) * coin_lot_size,
+                    native_qty_received: native_taker_pc_qty - native_taker_fee,
+                    native_fee_or_rebate: native_taker_fee,
+                    order_id,
+                    owner,
+                    owner_slot,
+                    fee_tier,
+                    client_order_id: NonZeroU64::new(client_order_id),
+                    price: fill_price,
+                },
+                current_slot,
+            );
             event_q
                 .push_back(taker_fill)
                 .map_err(|_| DexErrorCode::EventQueueFull)?;
         }
 
         let net_fees_before_referrer_rebate = native_taker_fee - accum_maker_rebates;
-        let referrer_rebate = fees::referrer_rebate(native_taker_fee);
+        let referrer_rebate = fee_schedule.referrer_rebate(native_taker_fee);
         let net_fees = net_fees_before_referrer_rebate - referrer_rebate;
 
         self.market_state.referrer_rebates_accrued += referrer_rebate;
@@ -481,30 +971,42 @@ impl<'ob> OrderBookState<'ob> {
             }
         }
 
-        if post_allowed && !crossed && unfilled_qty > 0 {
+        let slid_order_id = if post_only_slide && crossed {
+            self.slide_order_id(Side::Ask, order_id)
+        } else {
+            None
+        };
+        if post_allowed && (!crossed || slid_order_id.is_some()) && unfilled_qty > 0 {
             let offers = self.orders_mut(Side::Ask);
-            let new_order = LeafNode::new(
-                owner_slot,
-                order_id,
-                owner,
-                unfilled_qty,
-                fee_tier,
-                client_order_id,
+            let new_order = with_display_qty(
+                LeafNode::new(
+                    owner_slot,
+                    slid_order_id.as_ref().unwrap_or(order_id),
+                    owner,
+                    unfilled_qty,
+                    fee_tier,
+                    client_order_id,
+                    expiry,
+                ),
+                display_qty,
             );
             let insert_result = offers.insert_leaf(&new_order);
             if let Err(SlabTreeError::OutOfSpace) = insert_result {
                 // boot out the least aggressive offer
                 info!("offers full! booting...");
                 let order = offers.remove_max().unwrap();
-                let out = Event::new(EventView::Out {
-                    side: Side::Ask,
-                    native_qty_unlocked: order.quantity() * coin_lot_size,
-                    native_qty_still_locked: 0,
-                    order_id: order.order_id(),
-                    owner: order.owner(),
-                    owner_slot: order.owner_slot(),
-                    client_order_id: NonZeroU64::new(order.client_order_id()),
-                });
+                let out = Event::new(
+                    EventView::Out {
+                        side: Side::Ask,
+                        native_qty_unlocked: order.total_quantity() * coin_lot_size,
+                        native_qty_still_locked: 0,
+                        order_id: order.order_id(),
+                        owner: order.owner(),
+                        owner_slot: order.owner_slot(),
+                        client_order_id: NonZeroU64::new(order.client_order_id()),
+                    },
+                    current_slot,
+                );
                 event_q
                     .push_back(out)
                     .map_err(|_| DexErrorCode::EventQueueFull)?;
@@ -512,16 +1014,34 @@ impl<'ob> OrderBookState<'ob> {
             } else {
                 insert_result.unwrap();
             }
+            if let Some(new_order_id) = slid_order_id {
+                event_q
+                    .push_back(Event::new(
+                        EventView::Repost {
+                            side: Side::Ask,
+                            order_id,
+                            new_order_id,
+                            owner,
+                            owner_slot,
+                            client_order_id: NonZeroU64::new(client_order_id),
+                        },
+                        current_slot,
+                    ))
+                    .map_err(|_| DexErrorCode::EventQueueFull)?;
+            }
         } else {
-            let out = Event::new(EventView::Out {
-                side: Side::Ask,
-                native_qty_unlocked: unfilled_qty * coin_lot_size,
-                native_qty_still_locked: 0,
-                order_id,
-                owner,
-                owner_slot,
-                client_order_id: NonZeroU64::new(client_order_id),
-            });
+            let out = Event::new(
+                EventView::Out {
+                    side: Side::Ask,
+                    native_qty_unlocked: unfilled_qty * coin_lot_size,
+                    native_qty_still_locked: 0,
+                    order_id,
+                    owner,
+                    owner_slot,
+                    client_order_id: NonZeroU64::new(client_order_id),
+                },
+                current_slot,
+            );
             event_q
                 .push_back(out)
                 .map_err(|_| DexErrorCode::EventQueueFull)?;
@@ -540,15 +1060,22 @@ struct NewBidParams<'a> {
     owner_slot: u8,
     fee_tier: FeeTier,
     post_only: bool,
+    post_only_slide: bool,
     post_allowed: bool,
+    fill_or_kill: bool,
+    matches_left: u16,
     client_order_id: u64,
     self_trade_behavior: SelfTradeBehavior,
+    expiry: Option<OrderExpiry>,
+    display_qty: Option<NonZeroU64>,
+    price_band: PriceBand,
 }
 
-impl<'ob> OrderBookState<'ob> {
+impl<'ob, X: NodeTail> OrderBookState<'ob, X> {
     fn new_bid(
         &mut self,
         params: NewBidParams,
+        req_q: &mut RequestQueue,
         event_q: &mut EventQueue,
     ) -> DexResult<Option<OrderRemaining>> {
         let NewBidParams {
@@ -560,9 +1087,15 @@ impl<'ob> OrderBookState<'ob> {
             owner_slot,
             fee_tier,
             post_only,
+            post_only_slide,
             post_allowed,
+            fill_or_kill,
+            matches_left,
             client_order_id,
             self_trade_behavior,
+            expiry,
+            display_qty,
+            price_band,
         } = params;
         if post_allowed {
             check_assert!(limit_price.is_some())?;
@@ -570,13 +1103,46 @@ impl<'ob> OrderBookState<'ob> {
 
         let pc_lot_size = self.market_state.pc_lot_size;
         let coin_lot_size = self.market_state.coin_lot_size;
-
-        let max_pc_qty = fee_tier.remove_taker_fee(native_pc_qty_locked.get()) / pc_lot_size;
+        let fee_schedule = self.market_state.fee_schedule;
+        let current_slot = self.current_slot();
+
+        let max_pc_qty =
+            fee_schedule.remove_taker_fee(fee_tier, native_pc_qty_locked.get()) / pc_lot_size;
+
+        if fill_or_kill
+            && !self.can_fill_completely(
+                Side::Bid,
+                price_band.with_limit(Side::Bid, limit_price),
+                max_coin_qty.get(),
+                Some(max_pc_qty),
+                owner,
+                self_trade_behavior,
+                matches_left,
+            )?
+        {
+            event_q
+                .push_back(Event::new(
+                    EventView::Out {
+                        side: Side::Bid,
+                        native_qty_unlocked: native_pc_qty_locked.get(),
+                        native_qty_still_locked: 0,
+                        order_id,
+                        owner,
+                        owner_slot,
+                        client_order_id: NonZeroU64::new(client_order_id),
+                    },
+                    current_slot,
+                ))
+                .map_err(|_| DexErrorCode::EventQueueFull)?;
+            return Ok(None);
+        }
 
         let mut coin_qty_remaining = max_coin_qty.get();
         let mut pc_qty_remaining = max_pc_qty;
         let mut accum_maker_rebates = 0;
+        let mut outside_price_band = false;
 
+        let mut fill_price = 0;
         let crossed;
         let done = loop {
             let best_offer_h = match self.find_bbo(Side::Ask) {
@@ -587,6 +1153,13 @@ impl<'ob> OrderBookState<'ob> {
                 Some(h) => h,
             };
 
+            if self.remove_if_expired(Side::Ask, best_offer_h, event_q)? {
+                return Ok(Some(OrderRemaining {
+                    coin_qty_remaining: max_coin_qty,
+                    native_pc_qty_remaining: Some(native_pc_qty_locked),
+                }));
+            }
+
             let best_offer_ref = self
                 .orders_mut(Side::Ask)
                 .get_mut(best_offer_h)
@@ -602,6 +1175,11 @@ impl<'ob> OrderBookState<'ob> {
                 break true;
             }
 
+            if !price_band.contains(trade_price) {
+                outside_price_band = true;
+                break true;
+            }
+
             let offer_size = best_offer_ref.quantity();
             let trade_qty = offer_size
                 .min(coin_qty_remaining)
@@ -621,24 +1199,30 @@ impl<'ob> OrderBookState<'ob> {
                 match self_trade_behavior {
                     SelfTradeBehavior::CancelProvide => {
                         cancelled_take_qty = 0;
-                        cancelled_provide_qty = best_offer_ref.quantity();
+                        cancelled_provide_qty = best_offer_ref.total_quantity();
                     }
                     SelfTradeBehavior::DecrementTake => {
                         cancelled_take_qty = trade_qty;
                         cancelled_provide_qty = trade_qty;
                     }
+                    SelfTradeBehavior::AbortTransaction => {
+                        return Err(DexErrorCode::WouldSelfTrade.into())
+                    }
                 };
 
-                let remaining_provide_qty = best_offer_ref.quantity() - cancelled_provide_qty;
-                let provide_out = Event::new(EventView::Out {
-                    side: Side::Ask,
-                    native_qty_unlocked: cancelled_provide_qty * coin_lot_size,
-                    native_qty_still_locked: remaining_provide_qty * coin_lot_size,
-                    order_id: &best_offer_id,
-                    owner: best_offer_ref.owner(),
-                    owner_slot: best_offer_ref.owner_slot(),
-                    client_order_id: NonZeroU64::new(best_offer_ref.client_order_id()),
-                });
+                let remaining_provide_qty = best_offer_ref.total_quantity() - cancelled_provide_qty;
+                let provide_out = Event::new(
+                    EventView::Out {
+                        side: Side::Ask,
+                        native_qty_unlocked: cancelled_provide_qty * coin_lot_size,
+                        native_qty_still_locked: remaining_provide_qty * coin_lot_size,
+                        order_id: &best_offer_id,
+                        owner: best_offer_ref.owner(),
+                        owner_slot: best_offer_ref.owner_slot(),
+                        client_order_id: NonZeroU64::new(best_offer_ref.client_order_id()),
+                    },
+                    current_slot,
+                );
                 event_q
                     .push_back(provide_out)
                     .map_err(|_| DexErrorCode::EventQueueFull)?;
@@ -646,8 +1230,10 @@ impl<'ob> OrderBookState<'ob> {
                     self.orders_mut(Side::Ask)
                         .remove_by_key(&best_offer_id)
                         .unwrap();
+                } else if cancelled_provide_qty == offer_size {
+                    self.replenish(Side::Ask, &best_offer_id, req_q, event_q)?;
                 } else {
-                    *best_offer_ref.quantity_mut() = remaining_provide_qty;
+                    *best_offer_ref.quantity_mut() -= cancelled_provide_qty;
                 }
 
                 let native_taker_pc_unlocked = cancelled_take_qty * trade_price.get() * pc_lot_size;
@@ -678,15 +1264,18 @@ impl<'ob> OrderBookState<'ob> {
                             native_qty_still_locked = 0;
                         }
                     };
-                    Event::new(EventView::Out {
-                        side: Side::Bid,
-                        native_qty_unlocked,
-                        native_qty_still_locked,
-                        order_id,
-                        owner,
-                        owner_slot,
-                        client_order_id: NonZeroU64::new(client_order_id),
-                    })
+                    Event::new(
+                        EventView::Out {
+                            side: Side::Bid,
+                            native_qty_unlocked,
+                            native_qty_still_locked,
+                            order_id,
+                            owner,
+                            owner_slot,
+                            client_order_id: NonZeroU64::new(client_order_id),
+                        },
+                        current_slot,
+                    )
                 };
                 event_q
                     .push_back(take_out)
@@ -696,21 +1285,26 @@ impl<'ob> OrderBookState<'ob> {
             }
             let maker_fee_tier = best_offer_ref.fee_tier();
             let native_maker_pc_qty = trade_qty * trade_price.get() * pc_lot_size;
-            let native_maker_rebate = maker_fee_tier.maker_rebate(native_maker_pc_qty);
+            let native_maker_rebate =
+                fee_schedule.maker_rebate(maker_fee_tier, native_maker_pc_qty);
             accum_maker_rebates += native_maker_rebate;
 
-            let maker_fill = Event::new(EventView::Fill {
-                side: Side::Ask,
-                maker: true,
-                native_qty_paid: trade_qty * coin_lot_size,
-                native_qty_received: native_maker_pc_qty + native_maker_rebate,
-                native_fee_or_rebate: native_maker_rebate,
-                order_id: best_offer_ref.order_id(),
-                owner: best_offer_ref.owner(),
-                owner_slot: best_offer_ref.owner_slot(),
-                fee_tier: maker_fee_tier,
-                client_order_id: NonZeroU64::new(best_offer_ref.client_order_id()),
-            });
+            let maker_fill = Event::new(
+                EventView::Fill {
+                    side: Side::Ask,
+                    maker: true,
+                    native_qty_paid: trade_qty * coin_lot_size,
+                    native_qty_received: native_maker_pc_qty + native_maker_rebate,
+                    native_fee_or_rebate: native_maker_rebate,
+                    order_id: best_offer_ref.order_id(),
+                    owner: best_offer_ref.owner(),
+                    owner_slot: best_offer_ref.owner_slot(),
+                    fee_tier: maker_fee_tier,
+                    client_order_id: NonZeroU64::new(best_offer_ref.client_order_id()),
+                    price: trade_price.get(),
+                },
+                current_slot,
+            );
             event_q
                 .push_back(maker_fill)
                 .map_err(|_| DexErrorCode::EventQueueFull)?;
@@ -721,50 +1315,66 @@ impl<'ob> OrderBookState<'ob> {
 
             if best_offer_ref.quantity() == 0 {
                 let best_offer_id = *best_offer_ref.order_id();
-                event_q
-                    .push_back(Event::new(EventView::Out {
-                        side: Side::Ask,
-                        native_qty_unlocked: 0,
-                        native_qty_still_locked: 0,
-                        order_id: &best_offer_id,
-                        owner: best_offer_ref.owner(),
-                        owner_slot: best_offer_ref.owner_slot(),
-                        client_order_id: NonZeroU64::new(best_offer_ref.client_order_id()),
-                    }))
-                    .map_err(|_| DexErrorCode::EventQueueFull)?;
-                self.orders_mut(Side::Ask)
-                    .remove_by_key(&best_offer_id)
-                    .unwrap();
+                if best_offer_ref.reserve_quantity() > 0 {
+                    self.replenish(Side::Ask, &best_offer_id, req_q, event_q)?;
+                } else {
+                    event_q
+                        .push_back(Event::new(
+                            EventView::Out {
+                                side: Side::Ask,
+                                native_qty_unlocked: 0,
+                                native_qty_still_locked: 0,
+                                order_id: &best_offer_id,
+                                owner: best_offer_ref.owner(),
+                                owner_slot: best_offer_ref.owner_slot(),
+                                client_order_id: NonZeroU64::new(best_offer_ref.client_order_id()),
+                            },
+                            current_slot,
+                        ))
+                        .map_err(|_| DexErrorCode::EventQueueFull)?;
+                    self.orders_mut(Side::Ask)
+                        .remove_by_key(&best_offer_id)
+                        .unwrap();
+                }
             }
 
+            fill_price = trade_price.get();
+            self.record_fill(trade_price, trade_qty);
             break false;
         };
+        if outside_price_band {
+            self.halt_matching()?;
+        }
 
         let native_accum_fill_price = (max_pc_qty - pc_qty_remaining) * pc_lot_size;
-        let native_taker_fee = fee_tier.taker_fee(native_accum_fill_price);
+        let native_taker_fee = fee_schedule.taker_fee(fee_tier, native_accum_fill_price);
         let native_pc_qty_remaining =
             native_pc_qty_locked.get() - native_accum_fill_price - native_taker_fee;
 
         if native_accum_fill_price > 0 {
-            let taker_fill = Event::new(EventView::Fill {
-                side: Side::Bid,
-                maker: false,
-                native_qty_paid: native_accum_fill_price + native_taker_fee,
-                native_qty_received: (max_coin_qty.get() - coin_qty_remaining) * coin_lot_size,
-                native_fee_or_rebate: native_taker_fee,
-                order_id,
-                owner,
-                owner_slot,
-                fee_tier,
-                client_order_id: NonZeroU64::new(client_order_id),
-            });
+            let taker_fill = Event::new(
+                EventView::Fill {
+                    side: Side::Bid,
+                    maker: false,
+                    native_qty_paid: native_accum_fill_price + native_taker_fee,
+                    native_qty_received: (max_coin_qty.get() - coin_qty_remaining) * coin_lot_size,
+                    native_fee_or_rebate: native_taker_fee,
+                    order_id,
+                    owner,
+                    owner_slot,
+                    fee_tier,
+                    client_order_id: NonZeroU64::new(client_order_id),
+                    price: fill_price,
+                },
+                current_slot,
+            );
             event_q
                 .push_back(taker_fill)
                 .map_err(|_| DexErrorCode::EventQueueFull)?;
         }
 
         let net_fees_before_referrer_rebate = native_taker_fee - accum_maker_rebates;
-        let referrer_rebate = fees::referrer_rebate(native_taker_fee);
+        let referrer_rebate = fee_schedule.referrer_rebate(native_taker_fee);
         let net_fees = net_fees_before_referrer_rebate - referrer_rebate;
 
         self.market_state.referrer_rebates_accrued += referrer_rebate;
@@ -782,8 +1392,18 @@ impl<'ob> OrderBookState<'ob> {
             }
         }
 
-        let (coin_qty_to_post, pc_qty_to_keep_locked) = match limit_price {
-            Some(price) if post_allowed && !crossed => {
+        let slid_order_id = if post_only_slide && crossed {
+            self.slide_order_id(Side::Bid, order_id)
+        } else {
+            None
+        };
+        let post_price = match slid_order_id {
+            Some(ref slid_order_id) => NonZeroU64::new(extract_price_from_order_id(slid_order_id)),
+            None if !crossed => limit_price,
+            None => None,
+        };
+        let (coin_qty_to_post, pc_qty_to_keep_locked) = match post_price {
+            Some(price) if post_allowed => {
                 let coin_qty_to_post =
                     coin_qty_remaining.min(native_pc_qty_remaining / pc_lot_size / price.get());
                 (coin_qty_to_post, coin_qty_to_post * price.get())
@@ -794,15 +1414,18 @@ impl<'ob> OrderBookState<'ob> {
         let out = {
             let native_qty_still_locked = pc_qty_to_keep_locked * pc_lot_size;
             let native_qty_unlocked = native_pc_qty_remaining - native_qty_still_locked;
-            Event::new(EventView::Out {
-                side: Side::Bid,
-                native_qty_unlocked,
-                native_qty_still_locked,
-                order_id,
-                owner,
-                owner_slot,
-                client_order_id: NonZeroU64::new(client_order_id),
-            })
+            Event::new(
+                EventView::Out {
+                    side: Side::Bid,
+                    native_qty_unlocked,
+                    native_qty_still_locked,
+                    order_id,
+                    owner,
+                    owner_slot,
+                    client_order_id: NonZeroU64::new(client_order_id),
+                },
+                current_slot,
+            )
         };
         event_q
             .push_back(out)
@@ -810,28 +1433,37 @@ impl<'ob> OrderBookState<'ob> {
 
         if pc_qty_to_keep_locked > 0 {
             let bids = self.orders_mut(Side::Bid);
-            let new_leaf = LeafNode::new(
-                owner_slot,
-                order_id,
-                owner,
-                coin_qty_to_post,
-                fee_tier,
-                client_order_id,
+            let new_leaf = with_display_qty(
+                LeafNode::new(
+                    owner_slot,
+                    slid_order_id.as_ref().unwrap_or(order_id),
+                    owner,
+                    coin_qty_to_post,
+                    fee_tier,
+                    client_order_id,
+                    expiry,
+                ),
+                display_qty,
             );
             let insert_result = bids.insert_leaf(&new_leaf);
             if let Err(SlabTreeError::OutOfSpace) = insert_result {
                 // boot out the least aggressive bid
                 info!("bids full! booting...");
                 let order = bids.remove_min().unwrap();
-                let out = Event::new(EventView::Out {
-                    side: Side::Bid,
-                    native_qty_unlocked: order.quantity() * order.price().get() * pc_lot_size,
-                    native_qty_still_locked: 0,
-                    order_id: order.order_id(),
-                    owner: order.owner(),
-                    owner_slot: order.owner_slot(),
-                    client_order_id: NonZeroU64::new(order.client_order_id()),
-                });
+                let out = Event::new(
+                    EventView::Out {
+                        side: Side::Bid,
+                        native_qty_unlocked: order.total_quantity()
+                            * order.price().get()
+                            * pc_lot_size,
+                        native_qty_still_locked: 0,
+                        order_id: order.order_id(),
+                        owner: order.owner(),
+                        owner_slot: order.owner_slot(),
+                        client_order_id: NonZeroU64::new(order.client_order_id()),
+                    },
+                    current_slot,
+                );
                 event_q
                     .push_back(out)
                     .map_err(|_| DexErrorCode::EventQueueFull)?;
@@ -839,11 +1471,139 @@ impl<'ob> OrderBookState<'ob> {
             } else {
                 insert_result.unwrap();
             }
+            if let Some(new_order_id) = slid_order_id {
+                event_q
+                    .push_back(Event::new(
+                        EventView::Repost {
+                            side: Side::Bid,
+                            order_id,
+                            new_order_id,
+                            owner,
+                            owner_slot,
+                            client_order_id: NonZeroU64::new(client_order_id),
+                        },
+                        current_slot,
+                    ))
+                    .map_err(|_| DexErrorCode::EventQueueFull)?;
+            }
         }
 
         Ok(None)
     }
 
+    /// Walks the resting orders a taker on `side` would match against and checks
+    /// that all of `max_coin_qty` can be filled within `max_matches` matching
+    /// steps, mirroring the limits applied by `new_bid` and `new_ask`.
+    fn can_fill_completely(
+        &self,
+        side: Side,
+        price_band: PriceBand,
+        max_coin_qty: u64,
+        max_pc_qty: Option<u64>,
+        owner: &[u64; 4],
+        self_trade_behavior: SelfTradeBehavior,
+        max_matches: u16,
+    ) -> DexResult<bool> {
+        let makers = match side {
+            Side::Bid => self.asks.leaves(false),
+            Side::Ask => self.bids.leaves(true),
+        };
+        let mut coin_qty_remaining = max_coin_qty;
+        let mut pc_qty_remaining = max_pc_qty;
+        let mut matches = 0u64;
+        for maker in makers {
+            // every maker costs at least one step, even if it is only removed
+            matches += 1;
+            if matches > max_matches.into() {
+                return Ok(false);
+            }
+            if self.has_expired(maker.expiry()?)? {
+                continue;
+            }
+            let trade_price = maker.price();
+            if !price_band.contains(trade_price) {
+                return Ok(false);
+            }
+            if maker.owner() == owner {
+                match self_trade_behavior {
+                    SelfTradeBehavior::DecrementTake | SelfTradeBehavior::AbortTransaction => {
+                        return Ok(false)
+                    }
+                    SelfTradeBehavior::CancelProvide => continue,
+                }
+            }
+            let mut trade_qty = maker.total_quantity().min(coin_qty_remaining);
+            if let Some(ref mut pc_qty_remaining) = pc_qty_remaining {
+                trade_qty = trade_qty.min(*pc_qty_remaining / trade_price.get());
+                *pc_qty_remaining -= trade_qty * trade_price.get();
+            }
+            if trade_qty == 0 {
+                return Ok(false);
+            }
+            // past the visible quantity, an iceberg is taken one slice at a time
+            if trade_qty > maker.quantity() {
+                let hidden_qty = trade_qty - maker.quantity();
+                let display_qty = maker.display_quantity();
+                matches += (hidden_qty + display_qty - 1) / display_qty;
+                if matches > max_matches.into() {
+                    return Ok(false);
+                }
+            }
+            coin_qty_remaining -= trade_qty;
+            if coin_qty_remaining == 0 {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The id the order placed as `order_id` rests under on `side`. This is
+    /// `order_id` unless the order was reposted as an iceberg slice or slid,
+    /// in which case the owner's open orders account only learns the new id
+    /// once the `Repost` event is consumed. Until then the new id is found by
+    /// following the `Repost` events still in the event queue. Once they have
+    /// been consumed, the owner has to cancel by the new id.
+    fn resting_order_id(
+        &self,
+        side: Side,
+        order_id: &u128,
+        owner: &[u64; 4],
+        owner_slot: u8,
+        event_q: &EventQueue,
+    ) -> DexResult<Option<u128>> {
+        let orders: &Slab<X> = match side {
+            Side::Bid => &*self.bids,
+            Side::Ask => &*self.asks,
+        };
+        if orders.find_by_key(order_id).is_some() {
+            return Ok(Some(*order_id));
+        }
+        let mut resting_order_id = *order_id;
+        for event in event_q.iter() {
+            if let EventView::Repost {
+                side: repost_side,
+                order_id: reposted_order_id,
+                new_order_id,
+                owner: repost_owner,
+                owner_slot: repost_owner_slot,
+                ..
+            } = event.as_view()?
+            {
+                if repost_side == side
+                    && *reposted_order_id == resting_order_id
+                    && repost_owner == owner
+                    && repost_owner_slot == owner_slot
+                {
+                    resting_order_id = new_order_id;
+                }
+            }
+        }
+        if resting_order_id == *order_id || orders.find_by_key(&resting_order_id).is_none() {
+            return Ok(None);
+        }
+        Ok(Some(resting_order_id))
+    }
+
     fn cancel_order(
         &mut self,
         side: Side,
@@ -854,6 +1614,16 @@ impl<'ob> OrderBookState<'ob> {
 
         event_q: &mut EventQueue,
     ) -> DexResult<()> {
+        let order_id = &match self.resting_order_id(
+            side,
+            order_id,
+            expected_owner,
+            expected_owner_slot,
+            event_q,
+        )? {
+            Some(resting_order_id) => resting_order_id,
+            None => return Ok(()),
+        };
         if let Some(leaf_node) = self.orders_mut(side).remove_by_key(order_id) {
             if leaf_node.owner() == expected_owner && leaf_node.owner_slot() == expected_owner_slot
             {
@@ -862,22 +1632,25 @@ impl<'ob> OrderBookState<'ob> {
                 }
                 let native_qty_unlocked = match side {
                     Side::Bid => {
-                        leaf_node.quantity()
+                        leaf_node.total_quantity()
                             * leaf_node.price().get()
                             * self.market_state.pc_lot_size
                     }
-                    Side::Ask => leaf_node.quantity() * self.market_state.coin_lot_size,
+                    Side::Ask => leaf_node.total_quantity() * self.market_state.coin_lot_size,
                 };
                 event_q
-                    .push_back(Event::new(EventView::Out {
-                        side,
-                        native_qty_unlocked,
-                        native_qty_still_locked: 0,
-                        order_id,
-                        owner: expected_owner,
-                        owner_slot: expected_owner_slot,
-                        client_order_id: NonZeroU64::new(leaf_node.client_order_id()),
-                    }))
+                    .push_back(Event::new(
+                        EventView::Out {
+                            side,
+                            native_qty_unlocked,
+                            native_qty_still_locked: 0,
+                            order_id,
+                            owner: expected_owner,
+                            owner_slot: expected_owner_slot,
+                            client_order_id: NonZeroU64::new(leaf_node.client_order_id()),
+                        },
+                        self.current_slot(),
+                    ))
                     .map_err(|_| DexErrorCode::EventQueueFull)?;
             } else {
                 self.orders_mut(side).insert_leaf(&leaf_node).unwrap();
@@ -1,12 +1,18 @@
 #![cfg_attr(not(feature = "program"), allow(unused))]
 use num_enum::TryFromPrimitive;
-use std::{cell::RefMut, convert::TryInto, mem::size_of, num::NonZeroU64, ops::DerefMut};
+use std::{
+    cell::RefMut,
+    convert::TryInto,
+    mem::size_of,
+    num::NonZeroU64,
+    ops::{Deref, DerefMut},
+};
 
 use arrayref::{array_ref, array_refs, mut_array_refs};
 
 use bytemuck::{
-    bytes_of, bytes_of_mut, cast, cast_slice, cast_slice_mut, from_bytes_mut, try_cast_mut,
-    try_cast_slice_mut, try_from_bytes_mut, Pod, Zeroable,
+    bytes_of, bytes_of_mut, cast, cast_mut, cast_slice, cast_slice_mut, from_bytes_mut,
+    try_cast_mut, try_cast_slice_mut, try_from_bytes_mut, Pod, Zeroable,
 };
 use enumflags2::BitFlags;
 use num_traits::FromPrimitive;
@@ -22,23 +28,27 @@ macro_rules! info {
 
 use solana_sdk::{
     account_info::AccountInfo,
+    clock::Clock,
     program_error::ProgramError,
     pubkey::Pubkey,
     rent::Rent,
+    system_instruction, system_program,
     sysvar::{Sysvar, SysvarId},
 };
 use spl_token::error::TokenError;
 use spl_token::pack::Pack;
 
 use crate::{
-    critbit::Slab,
+    critbit::{LeafTail, NodeTail, Slab},
     error::{DexErrorCode, DexResult, SourceFileId},
-    fees::{self, FeeTier},
+    fees::{FeeSchedule, FeeTier},
     instruction::{
         disable_authority, fee_sweeper, msrm_token, srm_token, CancelOrderInstruction,
-        InitializeMarketInstruction, MarketInstruction, NewOrderInstructionV2, SelfTradeBehavior,
+        InitOpenOrdersInstruction, InitializeMarketInstructionV2, MarketInstruction,
+        NewOrderInstructionV4, NewOrderInstructionV6, OrderExpiry, PriceBandInstruction,
+        SelfTradeBehavior, SendTakeInstruction,
     },
-    matching::{OrderBookState, OrderType, Side},
+    matching::{OrderBookState, OrderType, SendTakeParams, Side},
 };
 
 declare_check_assert_macros!(SourceFileId::State);
@@ -65,6 +75,7 @@ pub enum AccountFlag {
     Bids = 1u64 << 5,
     Asks = 1u64 << 6,
     Disabled = 1u64 << 7,
+    Paused = 1u64 << 8,
 }
 
 #[cfg_attr(target_endian = "little", derive(Debug))]
@@ -128,6 +139,69 @@ unsafe impl Pod for MarketState {}
 #[cfg(target_endian = "little")]
 unsafe impl TriviallyTransmutable for MarketState {}
 
+/// The layout of markets created by this version of the program: the original
+/// `MarketState` followed by the fee schedule, the market authority, the price
+/// band, the trade statistics and the crank reward pool. These markets also
+/// use the current request, event and order book layouts. Markets of the
+/// original size keep the original layouts and take no new orders, but their
+/// orders can still be cancelled, matched, consumed and settled.
+#[cfg_attr(target_endian = "little", derive(Debug))]
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct MarketStateV2 {
+    pub inner: MarketState,
+
+    // 47
+    pub fee_schedule: FeeSchedule,
+    pub fee_schedule_padding: [u8; 2],
+
+    // 51
+    pub authority: [u64; 4],
+
+    // 55
+    pub last_trade_price: u64,
+    // 56
+    pub price_band_bps: u64,
+    // 57
+    pub price_band_halt_slots: u64,
+    // 58
+    pub halted_until_slot: u64,
+
+    // 59
+    pub coin_volume_total: u64,
+    // 60
+    pub pc_volume_total: u64,
+    // 61
+    pub fill_count: u64,
+
+    // 62
+    pub crank_fee_lamports: u64,
+    // 63
+    pub match_rewards_accrued: u64,
+    // 64
+    pub consume_rewards_accrued: u64,
+}
+#[cfg(target_endian = "little")]
+unsafe impl Zeroable for MarketStateV2 {}
+#[cfg(target_endian = "little")]
+unsafe impl Pod for MarketStateV2 {}
+#[cfg(target_endian = "little")]
+unsafe impl TriviallyTransmutable for MarketStateV2 {}
+
+impl Deref for MarketStateV2 {
+    type Target = MarketState;
+
+    fn deref(&self) -> &MarketState {
+        &self.inner
+    }
+}
+
+impl DerefMut for MarketStateV2 {
+    fn deref_mut(&mut self) -> &mut MarketState {
+        &mut self.inner
+    }
+}
+
 pub const ACCOUNT_HEAD_PADDING: &[u8; 5] = b"serum";
 pub const ACCOUNT_TAIL_PADDING: &[u8; 7] = b"padding";
 
@@ -193,25 +267,57 @@ pub fn strip_header<'a, H: Pod, D: Pod>(
     Ok((header, inner))
 }
 
+/// The length of a market account's data between its head and tail padding.
+#[inline]
+fn market_data_len(market_account: &AccountInfo) -> usize {
+    market_account
+        .data_len()
+        .saturating_sub(ACCOUNT_HEAD_PADDING.len() + ACCOUNT_TAIL_PADDING.len())
+}
+
 impl MarketState {
+    /// Loads the fields every market has, from a market of either version.
     #[inline]
     pub fn load<'a>(
         market_account: &'a AccountInfo,
         program_id: &Pubkey,
+    ) -> DexResult<RefMut<'a, Self>> {
+        let state = Self::load_unchecked(market_account, program_id)?;
+        state.check_flags()?;
+        Ok(state)
+    }
+
+    #[inline]
+    fn load_unchecked<'a>(
+        market_account: &'a AccountInfo,
+        program_id: &Pubkey,
     ) -> DexResult<RefMut<'a, Self>> {
         check_assert_eq!(market_account.owner, program_id)?;
+        let data_len = market_data_len(market_account);
+        check_assert!(
+            data_len == size_of::<MarketState>() || data_len == size_of::<MarketStateV2>()
+        )?;
         let mut account_data: RefMut<'a, [u8]>;
         let state: RefMut<'a, Self>;
 
         account_data = RefMut::map(market_account.try_borrow_mut_data()?, |data| *data);
         check_account_padding(&mut account_data)?;
         state = RefMut::map(account_data, |data| {
-            from_bytes_mut(cast_slice_mut(
-                check_account_padding(data).unwrap_or_else(|_| unreachable!()),
-            ))
+            let data: &mut [u8] =
+                cast_slice_mut(check_account_padding(data).unwrap_or_else(|_| unreachable!()));
+            from_bytes_mut(&mut data[..size_of::<MarketState>()])
         });
+        Ok(state)
+    }
 
-        state.check_flags()?;
+    /// Like `load`, but also accepts markets that have been disabled, whose
+    /// funds can still be settled and swept before the market is closed.
+    pub fn load_allow_disabled<'a>(
+        market_account: &'a AccountInfo,
+        program_id: &Pubkey,
+    ) -> DexResult<RefMut<'a, Self>> {
+        let state = Self::load_unchecked(market_account, program_id)?;
+        state.check_flags_allow_disabled()?;
         Ok(state)
     }
 
@@ -220,88 +326,68 @@ impl MarketState {
         let flags = BitFlags::from_bits(self.account_flags)
             .map_err(|_| DexErrorCode::InvalidMarketFlags)?;
         let required_flags = AccountFlag::Initialized | AccountFlag::Market;
-        if flags != required_flags {
+        if flags != required_flags && flags != required_flags | AccountFlag::Paused {
+            Err(DexErrorCode::InvalidMarketFlags)?
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn check_flags_allow_disabled(&self) -> DexResult {
+        let flags = BitFlags::from_bits(self.account_flags)
+            .map_err(|_| DexErrorCode::InvalidMarketFlags)?;
+        let required_flags = AccountFlag::Initialized | AccountFlag::Market;
+        // the market may have been paused before it was disabled
+        let allowed_flags = required_flags | AccountFlag::Disabled | AccountFlag::Paused;
+        if !flags.contains(required_flags) || !allowed_flags.contains(flags) {
             Err(DexErrorCode::InvalidMarketFlags)?
         }
         Ok(())
     }
 
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.account_flags & (AccountFlag::Paused as u64) != 0
+    }
+
     pub fn load_orders_mut<'a>(
         &self,
         orders_account: &'a AccountInfo,
         owner_account: Option<&AccountInfo>,
         program_id: &Pubkey,
         rent: Option<Rent>,
-    ) -> DexResult<RefMut<'a, OpenOrders>> {
-        check_assert_eq!(orders_account.owner, program_id)?;
-        let mut open_orders: RefMut<'a, OpenOrders>;
-
-        let open_orders_data_len = orders_account.data_len();
-        let open_orders_lamports = orders_account.lamports();
-        let (_, data) = strip_header::<[u8; 0], u8>(orders_account, true)?;
-        open_orders = RefMut::map(data, |data| from_bytes_mut(data));
-
-        if open_orders.account_flags == 0 {
-            let rent = rent.ok_or(DexErrorCode::RentNotProvided)?;
-            let owner_account = owner_account.ok_or(DexErrorCode::OwnerAccountNotProvided)?;
-            if !rent.is_exempt(open_orders_lamports, open_orders_data_len) {
-                return Err(DexErrorCode::OrdersNotRentExempt)?;
-            }
-            open_orders.init(&self.own_address, &owner_account.key.to_aligned_bytes())?;
-        }
-        open_orders.check_flags()?;
-        check_assert_eq!(&open_orders.market, &self.own_address)
-            .map_err(|_| DexErrorCode::WrongOrdersAccount)?;
-        if let Some(owner) = owner_account {
-            check_assert_eq!(&open_orders.owner, &owner.key.to_aligned_bytes())
-                .map_err(|_| DexErrorCode::WrongOrdersAccount)?;
-        }
-
-        Ok(open_orders)
-    }
-
-    fn load_bids_mut<'a>(&self, bids: &'a AccountInfo) -> DexResult<RefMut<'a, Slab>> {
-        check_assert_eq!(&bids.key.to_aligned_bytes(), &self.bids)
-            .map_err(|_| DexErrorCode::WrongBidsAccount)?;
-        let (header, buf) = strip_header::<OrderBookStateHeader, u8>(bids, false)?;
-        let flags = BitFlags::from_bits(header.account_flags).unwrap();
-        check_assert_eq!(&flags, &(AccountFlag::Initialized | AccountFlag::Bids))?;
-        Ok(RefMut::map(buf, Slab::new))
-    }
-
-    fn load_asks_mut<'a>(&self, asks: &'a AccountInfo) -> DexResult<RefMut<'a, Slab>> {
-        check_assert_eq!(&asks.key.to_aligned_bytes(), &self.asks)
-            .map_err(|_| DexErrorCode::WrongAsksAccount)?;
-        let (header, buf) = strip_header::<OrderBookStateHeader, u8>(asks, false)?;
-        let flags = BitFlags::from_bits(header.account_flags).unwrap();
-        check_assert_eq!(&flags, &(AccountFlag::Initialized | AccountFlag::Asks))?;
-        Ok(RefMut::map(buf, Slab::new))
+    ) -> DexResult<OpenOrdersMut<'a>> {
+        self.load_orders_mut_as(orders_account, owner_account, false, program_id, rent)
     }
 
-    fn load_request_queue_mut<'a>(&self, queue: &'a AccountInfo) -> DexResult<RequestQueue<'a>> {
-        check_assert_eq!(&queue.key.to_aligned_bytes(), &self.req_q)
-            .map_err(|_| DexErrorCode::WrongRequestQueueAccount)?;
-
-        let (header, buf) = strip_header::<RequestQueueHeader, Request>(queue, false)?;
-        let flags = BitFlags::from_bits(header.account_flags).unwrap();
-        check_assert_eq!(
-            &flags,
-            &(AccountFlag::Initialized | AccountFlag::RequestQueue)
-        )?;
-        Ok(Queue { header, buf })
+    /// Like `load_orders_mut`, but also accepts the delegate the owner has
+    /// set, which may place and cancel orders on the owner's behalf.
+    pub fn load_orders_mut_for_trader<'a>(
+        &self,
+        orders_account: &'a AccountInfo,
+        trader_account: &AccountInfo,
+        program_id: &Pubkey,
+        rent: Option<Rent>,
+    ) -> DexResult<OpenOrdersMut<'a>> {
+        self.load_orders_mut_as(orders_account, Some(trader_account), true, program_id, rent)
     }
 
-    fn load_event_queue_mut<'a>(&self, queue: &'a AccountInfo) -> DexResult<EventQueue<'a>> {
-        check_assert_eq!(&queue.key.to_aligned_bytes(), &self.event_q)
-            .map_err(|_| DexErrorCode::WrongEventQueueAccount)?;
-        let (header, buf) = strip_header::<EventQueueHeader, Event>(queue, false)?;
-
-        let flags = BitFlags::from_bits(header.account_flags).unwrap();
-        check_assert_eq!(
-            &flags,
-            &(AccountFlag::Initialized | AccountFlag::EventQueue)
-        )?;
-        Ok(Queue { header, buf })
+    fn load_orders_mut_as<'a>(
+        &self,
+        orders_account: &'a AccountInfo,
+        owner_account: Option<&AccountInfo>,
+        allow_delegate: bool,
+        program_id: &Pubkey,
+        rent: Option<Rent>,
+    ) -> DexResult<OpenOrdersMut<'a>> {
+        load_orders_mut_for_market(
+            &self.own_address,
+            orders_account,
+            owner_account,
+            allow_delegate,
+            program_id,
+            rent,
+        )
     }
 
     #[inline]
@@ -369,6 +455,9 @@ impl MarketState {
         if flags.contains(AccountFlag::Disabled) {
             return Err(DexErrorCode::MarketIsDisabled.into());
         }
+        if flags.contains(AccountFlag::Paused) {
+            return Err(DexErrorCode::MarketIsPaused.into());
+        }
         Ok(())
     }
 
@@ -377,6 +466,249 @@ impl MarketState {
     }
 }
 
+/// Loads an OpenOrders account belonging to the market at `market_address`.
+/// Markets that are still open should use `MarketState::load_orders_mut`.
+fn load_orders_mut_for_market<'a>(
+    market_address: &[u64; 4],
+    orders_account: &'a AccountInfo,
+    owner_account: Option<&AccountInfo>,
+    allow_delegate: bool,
+    program_id: &Pubkey,
+    rent: Option<Rent>,
+) -> DexResult<OpenOrdersMut<'a>> {
+    check_assert_eq!(orders_account.owner, program_id)?;
+
+    let open_orders_data_len = orders_account.data_len();
+    let open_orders_lamports = orders_account.lamports();
+    OpenOrders::capacity_for_account_size(open_orders_data_len)
+        .ok_or(DexErrorCode::InvalidOpenOrdersCapacity)?;
+    let mut open_orders = if open_orders_data_len == OpenOrders::original_account_size() {
+        let (open_orders, extra_slots) =
+            strip_header::<OpenOrders, ExtraOrderSlots>(orders_account, true)?;
+        OpenOrdersMut {
+            data_len: open_orders_data_len,
+            open_orders,
+            delegate: None,
+            extra_slots,
+        }
+    } else {
+        let (header, extra_slots) =
+            strip_header::<OpenOrdersV2, ExtraOrderSlots>(orders_account, true)?;
+        let (open_orders, delegate) =
+            RefMut::map_split(header, |header| (&mut header.inner, &mut header.delegate));
+        OpenOrdersMut {
+            data_len: open_orders_data_len,
+            open_orders,
+            delegate: Some(delegate),
+            extra_slots,
+        }
+    };
+
+    if open_orders.account_flags == 0 {
+        let rent = rent.ok_or(DexErrorCode::RentNotProvided)?;
+        let owner_account = owner_account.ok_or(DexErrorCode::OwnerAccountNotProvided)?;
+        if !rent.is_exempt(open_orders_lamports, open_orders_data_len) {
+            Err(DexErrorCode::OrdersNotRentExempt)?
+        }
+        open_orders.init(market_address, &owner_account.key.to_aligned_bytes())?;
+    }
+    open_orders.check_flags()?;
+    check_assert_eq!(&open_orders.market, market_address)
+        .map_err(|_| DexErrorCode::WrongOrdersAccount)?;
+    if let Some(owner) = owner_account {
+        let signer = owner.key.to_aligned_bytes();
+        let is_delegate = allow_delegate && open_orders.delegate() == Some(&signer);
+        check_assert!(open_orders.owner == signer || is_delegate)
+            .map_err(|_| DexErrorCode::WrongOrdersAccount)?;
+    }
+
+    Ok(open_orders)
+}
+
+impl MarketStateV2 {
+    /// The market a market of the original size matches as: the original fee
+    /// schedule, no authority, no price band and no crank fee. Only the
+    /// `MarketState` part is written back to the account.
+    fn from_original(inner: MarketState) -> Self {
+        MarketStateV2 {
+            inner,
+            fee_schedule: FeeSchedule::default(),
+            ..MarketStateV2::zeroed()
+        }
+    }
+
+    /// Loads a market created by this version of the program. Markets of the
+    /// original size are refused with `UnsupportedMarketVersion`.
+    #[inline]
+    pub fn load<'a>(
+        market_account: &'a AccountInfo,
+        program_id: &Pubkey,
+    ) -> DexResult<RefMut<'a, Self>> {
+        let state = Self::load_unchecked(market_account, program_id)?;
+        state.check_flags()?;
+        Ok(state)
+    }
+
+    /// Like `load`, but also accepts markets that have been disabled, whose
+    /// queued events can still be consumed.
+    fn load_allow_disabled<'a>(
+        market_account: &'a AccountInfo,
+        program_id: &Pubkey,
+    ) -> DexResult<RefMut<'a, Self>> {
+        let state = Self::load_unchecked(market_account, program_id)?;
+        state.check_flags_allow_disabled()?;
+        Ok(state)
+    }
+
+    /// Like `load`, but for markets that have been disabled, which `load`
+    /// refuses.
+    fn load_disabled<'a>(
+        market_account: &'a AccountInfo,
+        program_id: &Pubkey,
+    ) -> DexResult<RefMut<'a, Self>> {
+        let state = Self::load_unchecked(market_account, program_id)?;
+        state.check_flags_allow_disabled()?;
+        if state.account_flags & (AccountFlag::Disabled as u64) == 0 {
+            Err(DexErrorCode::MarketNotDisabled)?
+        }
+        Ok(state)
+    }
+
+    #[inline]
+    fn load_unchecked<'a>(
+        market_account: &'a AccountInfo,
+        program_id: &Pubkey,
+    ) -> DexResult<RefMut<'a, Self>> {
+        check_assert_eq!(market_account.owner, program_id)?;
+        let data_len = market_data_len(market_account);
+        if data_len == size_of::<MarketState>() {
+            Err(DexErrorCode::UnsupportedMarketVersion)?
+        }
+        check_assert_eq!(data_len, size_of::<MarketStateV2>())?;
+        let mut account_data: RefMut<'a, [u8]>;
+        let state: RefMut<'a, Self>;
+
+        account_data = RefMut::map(market_account.try_borrow_mut_data()?, |data| *data);
+        check_account_padding(&mut account_data)?;
+        state = RefMut::map(account_data, |data| {
+            from_bytes_mut(cast_slice_mut(
+                check_account_padding(data).unwrap_or_else(|_| unreachable!()),
+            ))
+        });
+        Ok(state)
+    }
+
+    fn load_bids_mut<'a>(&self, bids: &'a AccountInfo) -> DexResult<RefMut<'a, Slab>> {
+        self.load_book_mut(bids, Side::Bid)
+    }
+
+    fn load_asks_mut<'a>(&self, asks: &'a AccountInfo) -> DexResult<RefMut<'a, Slab>> {
+        self.load_book_mut(asks, Side::Ask)
+    }
+
+    fn load_event_queue_mut<'a>(&self, queue: &'a AccountInfo) -> DexResult<EventQueue<'a>> {
+        let (header, buf) = self.load_event_queue_parts(queue)?;
+        Ok(Queue::new(header, RefMut::map(buf, remove_slop_mut)))
+    }
+}
+
+/// Loading the book and the queues. Those of markets of the original size keep
+/// the original node, request and event layouts.
+impl MarketState {
+    /// Whether the market account is of the original size.
+    #[inline]
+    fn is_original(market_account: &AccountInfo) -> bool {
+        market_data_len(market_account) == size_of::<MarketState>()
+    }
+
+    fn load_original_bids_mut<'a>(&self, bids: &'a AccountInfo) -> DexResult<RefMut<'a, Slab<()>>> {
+        self.load_book_mut(bids, Side::Bid)
+    }
+
+    fn load_original_asks_mut<'a>(&self, asks: &'a AccountInfo) -> DexResult<RefMut<'a, Slab<()>>> {
+        self.load_book_mut(asks, Side::Ask)
+    }
+
+    fn load_original_request_queue_mut<'a>(
+        &self,
+        queue: &'a AccountInfo,
+    ) -> DexResult<RequestQueue<'a>> {
+        let (header, buf) = self.load_request_queue_parts(queue)?;
+        Ok(Queue::original(header, buf))
+    }
+
+    /// The request queue in the current layout, which cancels also load for
+    /// markets that are only read as a `MarketState`.
+    fn load_request_queue_mut<'a>(&self, queue: &'a AccountInfo) -> DexResult<RequestQueue<'a>> {
+        let (header, buf) = self.load_request_queue_parts(queue)?;
+        Ok(Queue::new(header, RefMut::map(buf, remove_slop_mut)))
+    }
+
+    fn load_original_event_queue_mut<'a>(
+        &self,
+        queue: &'a AccountInfo,
+    ) -> DexResult<EventQueue<'a>> {
+        let (header, buf) = self.load_event_queue_parts(queue)?;
+        Ok(Queue::original(header, buf))
+    }
+
+    fn load_book_mut<'a, X: NodeTail>(
+        &self,
+        book: &'a AccountInfo,
+        side: Side,
+    ) -> DexResult<RefMut<'a, Slab<X>>> {
+        let (expected_key, flag, error) = match side {
+            Side::Bid => (
+                &self.bids,
+                AccountFlag::Bids,
+                DexErrorCode::WrongBidsAccount,
+            ),
+            Side::Ask => (
+                &self.asks,
+                AccountFlag::Asks,
+                DexErrorCode::WrongAsksAccount,
+            ),
+        };
+        check_assert_eq!(&book.key.to_aligned_bytes(), expected_key).map_err(|_| error)?;
+        let (header, buf) = strip_header::<OrderBookStateHeader, u8>(book, false)?;
+        let flags = BitFlags::from_bits(header.account_flags).unwrap();
+        check_assert_eq!(&flags, &(AccountFlag::Initialized | flag))?;
+        Ok(RefMut::map(buf, Slab::new))
+    }
+
+    fn load_request_queue_parts<'a>(
+        &self,
+        queue: &'a AccountInfo,
+    ) -> DexResult<(RefMut<'a, RequestQueueHeader>, RefMut<'a, [u8]>)> {
+        check_assert_eq!(&queue.key.to_aligned_bytes(), &self.req_q)
+            .map_err(|_| DexErrorCode::WrongRequestQueueAccount)?;
+
+        let (header, buf) = strip_header::<RequestQueueHeader, u8>(queue, false)?;
+        let flags = BitFlags::from_bits(header.account_flags).unwrap();
+        check_assert_eq!(
+            &flags,
+            &(AccountFlag::Initialized | AccountFlag::RequestQueue)
+        )?;
+        Ok((header, buf))
+    }
+
+    fn load_event_queue_parts<'a>(
+        &self,
+        queue: &'a AccountInfo,
+    ) -> DexResult<(RefMut<'a, EventQueueHeader>, RefMut<'a, [u8]>)> {
+        check_assert_eq!(&queue.key.to_aligned_bytes(), &self.event_q)
+            .map_err(|_| DexErrorCode::WrongEventQueueAccount)?;
+        let (header, buf) = strip_header::<EventQueueHeader, u8>(queue, false)?;
+
+        let flags = BitFlags::from_bits(header.account_flags).unwrap();
+        check_assert_eq!(
+            &flags,
+            &(AccountFlag::Initialized | AccountFlag::EventQueue)
+        )?;
+        Ok((header, buf))
+    }
+}
+
 #[cfg_attr(feature = "fuzz", derive(Debug))]
 #[repr(C)]
 #[derive(Copy, Clone)]
@@ -447,76 +779,365 @@ impl OpenOrders {
         self.native_pc_free = self.native_pc_free.checked_sub(native_pc_amount).unwrap();
     }
 
-    fn slot_is_free(&self, slot: u8) -> bool {
-        let slot_mask = 1u128 << slot;
-        self.free_slot_bits & slot_mask != 0
-    }
-
-    pub fn slot_side(&self, slot: u8) -> Option<Side> {
-        let slot_mask = 1u128 << slot;
-        if self.free_slot_bits & slot_mask != 0 {
-            None
-        } else if self.is_bid_bits & slot_mask != 0 {
-            Some(Side::Bid)
-        } else {
-            Some(Side::Ask)
+    /// The size of an OpenOrders account that holds `capacity` orders. The
+    /// capacity is 128 plus a multiple of 64, and at most 256.
+    pub fn account_size(capacity: usize) -> Option<usize> {
+        let extra_slots = capacity.checked_sub(BASE_ORDER_SLOTS)?;
+        if capacity > MAX_OPEN_ORDERS_CAPACITY || extra_slots % EXTRA_ORDER_SLOTS != 0 {
+            return None;
         }
+        let extra_blocks = extra_slots / EXTRA_ORDER_SLOTS;
+        Some(
+            ACCOUNT_HEAD_PADDING.len()
+                + size_of::<OpenOrdersV2>()
+                + extra_blocks * size_of::<ExtraOrderSlots>()
+                + ACCOUNT_TAIL_PADDING.len(),
+        )
     }
 
-    fn remove_order(&mut self, slot: u8) -> DexResult {
-        check_assert!(slot < 128)?;
-        check_assert!(!self.slot_is_free(slot))?;
-
-        let slot_mask = 1u128 << slot;
-        self.orders[slot as usize] = 0;
-        self.client_order_ids[slot as usize] = 0;
-        self.free_slot_bits |= slot_mask;
-        self.is_bid_bits &= !slot_mask;
-
-        Ok(())
+    /// The size of an OpenOrders account with the original layout, which
+    /// holds 128 orders and can't have a delegate.
+    pub fn original_account_size() -> usize {
+        ACCOUNT_HEAD_PADDING.len() + size_of::<OpenOrders>() + ACCOUNT_TAIL_PADDING.len()
     }
 
-    fn add_order(&mut self, id: u128, side: Side) -> DexResult<u8> {
-        if self.free_slot_bits == 0 {
-            Err(DexErrorCode::TooManyOpenOrders)?;
+    fn capacity_for_account_size(account_size: usize) -> Option<usize> {
+        if account_size == Self::original_account_size() {
+            return Some(BASE_ORDER_SLOTS);
         }
-        let slot = self.free_slot_bits.trailing_zeros();
-        check_assert!(self.slot_is_free(slot as u8))?;
-        let slot_mask = 1u128 << slot;
-        self.free_slot_bits &= !slot_mask;
-        match side {
-            Side::Bid => {
-                self.is_bid_bits |= slot_mask;
-            }
-            Side::Ask => {
-                self.is_bid_bits &= !slot_mask;
-            }
-        };
-        self.orders[slot as usize] = id;
-        Ok(slot as u8)
+        (BASE_ORDER_SLOTS..=MAX_OPEN_ORDERS_CAPACITY)
+            .step_by(EXTRA_ORDER_SLOTS)
+            .find(|&capacity| Self::account_size(capacity) == Some(account_size))
     }
 }
 
-pub trait QueueHeader: Pod {
-    type Item: Pod + Copy;
+/// The header of OpenOrders accounts sized with `OpenOrders::account_size`.
+/// Accounts of the original size hold only an `OpenOrders`.
+#[cfg_attr(feature = "fuzz", derive(Debug))]
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct OpenOrdersV2 {
+    pub inner: OpenOrders,
+    // all zeroes if the owner hasn't set a delegate
+    pub delegate: [u64; 4],
+}
+unsafe impl Pod for OpenOrdersV2 {}
+unsafe impl Zeroable for OpenOrdersV2 {}
 
-    fn head(&self) -> u64;
-    fn head_mut(&mut self) -> &mut u64;
-    fn count(&self) -> u64;
-    fn count_mut(&mut self) -> &mut u64;
+/// Order slots held past the first 128 of an OpenOrders account. Accounts
+/// created with a larger capacity have one of these laid out after the
+/// `OpenOrders` struct for every further 64 slots.
+#[cfg_attr(feature = "fuzz", derive(Debug))]
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct ExtraOrderSlots {
+    pub free_slot_bits: u64,
+    pub is_bid_bits: u64,
+    pub orders: [u128; 64],
+    pub client_order_ids: [u64; 64],
+}
+unsafe impl Pod for ExtraOrderSlots {}
+unsafe impl Zeroable for ExtraOrderSlots {}
+
+const BASE_ORDER_SLOTS: usize = 128;
+const EXTRA_ORDER_SLOTS: usize = 64;
+// Orders refer back to their slot with a u8, see `LeafNode::owner_slot`.
+pub const MAX_OPEN_ORDERS_CAPACITY: usize = 256;
+
+/// A loaded OpenOrders account. Derefs to the `OpenOrders` struct at the
+/// start of the account; the order slots should only be accessed through
+/// the methods here, since they may continue into `ExtraOrderSlots`.
+pub struct OpenOrdersMut<'a> {
+    data_len: usize,
+    open_orders: RefMut<'a, OpenOrders>,
+    delegate: Option<RefMut<'a, [u64; 4]>>,
+    extra_slots: RefMut<'a, [ExtraOrderSlots]>,
+}
 
-    fn incr_event_id(&mut self);
-    fn decr_event_id(&mut self, n: u64);
+impl<'a> Deref for OpenOrdersMut<'a> {
+    type Target = OpenOrders;
+
+    fn deref(&self) -> &OpenOrders {
+        &self.open_orders
+    }
 }
 
-pub struct Queue<'a, H: QueueHeader> {
-    header: RefMut<'a, H>,
-    buf: RefMut<'a, [H::Item]>,
+impl<'a> DerefMut for OpenOrdersMut<'a> {
+    fn deref_mut(&mut self) -> &mut OpenOrders {
+        &mut self.open_orders
+    }
+}
+
+impl<'a> OpenOrdersMut<'a> {
+    fn init(&mut self, market: &[u64; 4], owner: &[u64; 4]) -> DexResult<()> {
+        self.open_orders.init(market, owner)?;
+        if let Some(delegate) = self.delegate.as_mut() {
+            **delegate = [0; 4];
+        }
+        for block in self.extra_slots.iter_mut() {
+            block.free_slot_bits = !0;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        BASE_ORDER_SLOTS + self.extra_slots.len() * EXTRA_ORDER_SLOTS
+    }
+
+    /// The length of the account's data, which can't be read off the account
+    /// while it is loaded.
+    #[inline]
+    pub fn data_len(&self) -> usize {
+        self.data_len
+    }
+
+    /// The delegate the owner has set, if any. Accounts of the original size
+    /// never have one.
+    pub fn delegate(&self) -> Option<&[u64; 4]> {
+        self.delegate
+            .as_deref()
+            .filter(|&delegate| delegate != &[0; 4])
+    }
+
+    fn set_delegate(&mut self, delegate: &[u64; 4]) -> DexResult {
+        let stored_delegate = self
+            .delegate
+            .as_mut()
+            .ok_or(DexErrorCode::UnsupportedOpenOrdersVersion)?;
+        **stored_delegate = *delegate;
+        Ok(())
+    }
+
+    // The free and bid bitmaps for the 64 slots starting at `64 * word`.
+    fn slot_bit_words(&self, word: usize) -> (u64, u64) {
+        if word < 2 {
+            let free_slot_bits: [u64; 2] = cast(self.open_orders.free_slot_bits);
+            let is_bid_bits: [u64; 2] = cast(self.open_orders.is_bid_bits);
+            (free_slot_bits[word], is_bid_bits[word])
+        } else {
+            let block = &self.extra_slots[word - 2];
+            (block.free_slot_bits, block.is_bid_bits)
+        }
+    }
+
+    fn slot_bit_words_mut(&mut self, word: usize) -> (&mut u64, &mut u64) {
+        if word < 2 {
+            let open_orders: &mut OpenOrders = &mut self.open_orders;
+            let free_slot_bits: &mut [u64; 2] = cast_mut(&mut open_orders.free_slot_bits);
+            let is_bid_bits: &mut [u64; 2] = cast_mut(&mut open_orders.is_bid_bits);
+            (&mut free_slot_bits[word], &mut is_bid_bits[word])
+        } else {
+            let block = &mut self.extra_slots[word - 2];
+            (&mut block.free_slot_bits, &mut block.is_bid_bits)
+        }
+    }
+
+    fn slot_is_free(&self, slot: u8) -> bool {
+        let (free_slot_bits, _) = self.slot_bit_words(slot as usize / 64);
+        free_slot_bits & (1u64 << (slot % 64)) != 0
+    }
+
+    pub fn slot_side(&self, slot: u8) -> Option<Side> {
+        if slot as usize >= self.capacity() {
+            return None;
+        }
+        let (free_slot_bits, is_bid_bits) = self.slot_bit_words(slot as usize / 64);
+        let slot_mask = 1u64 << (slot % 64);
+        if free_slot_bits & slot_mask != 0 {
+            None
+        } else if is_bid_bits & slot_mask != 0 {
+            Some(Side::Bid)
+        } else {
+            Some(Side::Ask)
+        }
+    }
+
+    pub fn order_id(&self, slot: u8) -> u128 {
+        let slot = slot as usize;
+        if slot < BASE_ORDER_SLOTS {
+            self.open_orders.orders[slot]
+        } else {
+            let slot = slot - BASE_ORDER_SLOTS;
+            self.extra_slots[slot / EXTRA_ORDER_SLOTS].orders[slot % EXTRA_ORDER_SLOTS]
+        }
+    }
+
+    fn set_order_id(&mut self, slot: u8, order_id: u128) {
+        let slot = slot as usize;
+        if slot < BASE_ORDER_SLOTS {
+            self.open_orders.orders[slot] = order_id;
+        } else {
+            let slot = slot - BASE_ORDER_SLOTS;
+            self.extra_slots[slot / EXTRA_ORDER_SLOTS].orders[slot % EXTRA_ORDER_SLOTS] = order_id;
+        }
+    }
+
+    pub fn client_order_id(&self, slot: u8) -> u64 {
+        let slot = slot as usize;
+        if slot < BASE_ORDER_SLOTS {
+            self.open_orders.client_order_ids[slot]
+        } else {
+            let slot = slot - BASE_ORDER_SLOTS;
+            self.extra_slots[slot / EXTRA_ORDER_SLOTS].client_order_ids[slot % EXTRA_ORDER_SLOTS]
+        }
+    }
+
+    fn set_client_order_id(&mut self, slot: u8, client_order_id: u64) {
+        let slot = slot as usize;
+        if slot < BASE_ORDER_SLOTS {
+            self.open_orders.client_order_ids[slot] = client_order_id;
+        } else {
+            let slot = slot - BASE_ORDER_SLOTS;
+            let block = &mut self.extra_slots[slot / EXTRA_ORDER_SLOTS];
+            block.client_order_ids[slot % EXTRA_ORDER_SLOTS] = client_order_id;
+        }
+    }
+
+    pub fn iter_filled_slots(&self) -> impl Iterator<Item = u8> {
+        let mut filled_slot_bits = [0u64; MAX_OPEN_ORDERS_CAPACITY / 64];
+        for (word, bits) in filled_slot_bits[..self.capacity() / 64]
+            .iter_mut()
+            .enumerate()
+        {
+            *bits = !self.slot_bit_words(word).0;
+        }
+        let mut word = 0;
+        std::iter::from_fn(move || {
+            while word < filled_slot_bits.len() {
+                let bits = &mut filled_slot_bits[word];
+                if *bits != 0 {
+                    let bit = bits.trailing_zeros();
+                    *bits &= !(1u64 << bit);
+                    return Some((word * 64) as u8 + bit as u8);
+                }
+                word += 1;
+            }
+            None
+        })
+    }
+
+    fn remove_order(&mut self, slot: u8) -> DexResult {
+        check_assert!((slot as usize) < self.capacity())?;
+        check_assert!(!self.slot_is_free(slot))?;
+
+        self.set_order_id(slot, 0);
+        self.set_client_order_id(slot, 0);
+        let (free_slot_bits, is_bid_bits) = self.slot_bit_words_mut(slot as usize / 64);
+        let slot_mask = 1u64 << (slot % 64);
+        *free_slot_bits |= slot_mask;
+        *is_bid_bits &= !slot_mask;
+
+        Ok(())
+    }
+
+    fn set_slot_side(&mut self, slot: u8, side: Side) {
+        let (_, is_bid_bits) = self.slot_bit_words_mut(slot as usize / 64);
+        let slot_mask = 1u64 << (slot % 64);
+        match side {
+            Side::Bid => {
+                *is_bid_bits |= slot_mask;
+            }
+            Side::Ask => {
+                *is_bid_bits &= !slot_mask;
+            }
+        };
+    }
+
+    // Hands a filled slot over to a new order, see `ReplaceOrderByClientId`.
+    fn replace_order(&mut self, slot: u8, id: u128, side: Side) -> DexResult {
+        check_assert!((slot as usize) < self.capacity())?;
+        check_assert!(!self.slot_is_free(slot))?;
+        self.set_slot_side(slot, side);
+        self.set_order_id(slot, id);
+        Ok(())
+    }
+
+    fn add_order(&mut self, id: u128, side: Side) -> DexResult<u8> {
+        let word = (0..self.capacity() / 64)
+            .find(|&word| self.slot_bit_words(word).0 != 0)
+            .ok_or(DexErrorCode::TooManyOpenOrders)?;
+        let (free_slot_bits, _) = self.slot_bit_words_mut(word);
+        let bit = free_slot_bits.trailing_zeros();
+        *free_slot_bits &= !(1u64 << bit);
+        let slot = (word * 64) as u8 + bit as u8;
+        self.set_slot_side(slot, side);
+        check_assert!(!self.slot_is_free(slot))?;
+        self.set_order_id(slot, id);
+        Ok(slot)
+    }
+}
+
+pub trait QueueHeader: Pod {
+    type Item: Pod + Copy;
+    // queues of markets of the original size hold only the first this many
+    // bytes of each item, and read the rest as zero
+    const ORIGINAL_ITEM_LEN: usize;
+
+    fn head(&self) -> u64;
+    fn head_mut(&mut self) -> &mut u64;
+    fn count(&self) -> u64;
+    fn count_mut(&mut self) -> &mut u64;
+
+    fn incr_event_id(&mut self);
+    fn decr_event_id(&mut self, n: u64);
+    // fills in what an item records about the queue, before it is pushed
+    fn stamp_item(&self, item: &mut Self::Item);
+    // turns an item into the one a queue of the original layout holds, before
+    // all but its first `ORIGINAL_ITEM_LEN` bytes are dropped
+    fn to_original_item(item: &mut Self::Item);
+}
+
+pub struct Queue<'a, H: QueueHeader> {
+    header: RefMut<'a, H>,
+    buf: RefMut<'a, [u8]>,
+    item_len: usize,
 }
 
 impl<'a, H: QueueHeader> Queue<'a, H> {
     pub fn new(header: RefMut<'a, H>, buf: RefMut<'a, [H::Item]>) -> Self {
-        Self { header, buf }
+        let buf = RefMut::map(buf, |items| cast_slice_mut(items));
+        Self {
+            header,
+            buf,
+            item_len: size_of::<H::Item>(),
+        }
+    }
+
+    /// A queue of a market of the original size, whose items are only
+    /// `H::ORIGINAL_ITEM_LEN` bytes long. The header is the same for both.
+    pub fn original(header: RefMut<'a, H>, buf: RefMut<'a, [u8]>) -> Self {
+        let item_len = H::ORIGINAL_ITEM_LEN;
+        let len = buf.len() - buf.len() % item_len;
+        let buf = RefMut::map(buf, |bytes| &mut bytes[..len]);
+        Self {
+            header,
+            buf,
+            item_len,
+        }
+    }
+
+    #[inline]
+    fn capacity(&self) -> usize {
+        self.buf.len() / self.item_len
+    }
+
+    #[inline]
+    fn read(&self, index: usize) -> H::Item {
+        let mut item = H::Item::zeroed();
+        let start = index * self.item_len;
+        bytes_of_mut(&mut item)[..self.item_len]
+            .copy_from_slice(&self.buf[start..start + self.item_len]);
+        item
+    }
+
+    #[inline]
+    fn write(&mut self, index: usize, mut item: H::Item) {
+        if self.item_len < size_of::<H::Item>() {
+            H::to_original_item(&mut item);
+        }
+        let start = index * self.item_len;
+        self.buf[start..start + self.item_len].copy_from_slice(&bytes_of(&item)[..self.item_len]);
     }
 
     #[inline]
@@ -526,7 +1147,7 @@ impl<'a, H: QueueHeader> Queue<'a, H> {
 
     #[inline]
     pub fn full(&self) -> bool {
-        self.header.count() as usize == self.buf.len()
+        self.header.count() as usize == self.capacity()
     }
 
     #[inline]
@@ -535,31 +1156,35 @@ impl<'a, H: QueueHeader> Queue<'a, H> {
     }
 
     #[inline]
-    pub fn push_back(&mut self, value: H::Item) -> Result<(), H::Item> {
+    pub fn push_back(&mut self, mut value: H::Item) -> Result<(), H::Item> {
         if self.full() {
             return Err(value);
         }
-        let slot = ((self.header.head() + self.header.count()) as usize) % self.buf.len();
-        self.buf[slot] = value;
+        let slot = ((self.header.head() + self.header.count()) as usize) % self.capacity();
+        self.header.stamp_item(&mut value);
+        self.write(slot, value);
         *self.header.count_mut() += 1;
         self.header.incr_event_id();
         Ok(())
     }
 
     #[inline]
-    pub fn peek_front(&self) -> Option<&H::Item> {
+    pub fn peek_front(&self) -> Option<H::Item> {
         if self.empty() {
             return None;
         }
-        Some(&self.buf[self.header.head() as usize])
+        Some(self.read(self.header.head() as usize))
     }
 
+    /// Overwrites the item at the front of the queue, which must not be
+    /// empty.
     #[inline]
-    pub fn peek_front_mut(&mut self) -> Option<&mut H::Item> {
+    pub fn replace_front(&mut self, value: H::Item) -> Result<(), H::Item> {
         if self.empty() {
-            return None;
+            return Err(value);
         }
-        Some(&mut self.buf[self.header.head() as usize])
+        self.write(self.header.head() as usize, value);
+        Ok(())
     }
 
     #[inline]
@@ -567,10 +1192,11 @@ impl<'a, H: QueueHeader> Queue<'a, H> {
         if self.empty() {
             return Err(());
         }
-        let value = self.buf[self.header.head() as usize];
+        let value = self.read(self.header.head() as usize);
         *self.header.count_mut() -= 1;
+        let capacity = self.capacity() as u64;
         let head = self.header.head_mut();
-        *head = (*head + 1) % self.buf.len() as u64;
+        *head = (*head + 1) % capacity;
         Ok(value)
     }
 
@@ -583,7 +1209,7 @@ impl<'a, H: QueueHeader> Queue<'a, H> {
         Ok(())
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &H::Item> {
+    pub fn iter(&self) -> impl Iterator<Item = H::Item> + '_ {
         QueueIterator {
             queue: self,
             index: 0,
@@ -597,15 +1223,14 @@ struct QueueIterator<'a, 'b, H: QueueHeader> {
 }
 
 impl<'a, 'b, H: QueueHeader> Iterator for QueueIterator<'a, 'b, H> {
-    type Item = &'b H::Item;
+    type Item = H::Item;
     fn next(&mut self) -> Option<Self::Item> {
         if self.index == self.queue.len() {
             None
         } else {
-            let item = &self.queue.buf
-                [(self.queue.header.head() + self.index) as usize % self.queue.buf.len()];
+            let index = (self.queue.header.head() + self.index) as usize % self.queue.capacity();
             self.index += 1;
-            Some(item)
+            Some(self.queue.read(index))
         }
     }
 }
@@ -623,6 +1248,7 @@ unsafe impl Pod for RequestQueueHeader {}
 
 impl QueueHeader for RequestQueueHeader {
     type Item = Request;
+    const ORIGINAL_ITEM_LEN: usize = ORIGINAL_REQUEST_LEN;
 
     fn head(&self) -> u64 {
         self.head
@@ -640,12 +1266,16 @@ impl QueueHeader for RequestQueueHeader {
     fn incr_event_id(&mut self) {}
     #[inline(always)]
     fn decr_event_id(&mut self, _n: u64) {}
+    #[inline(always)]
+    fn stamp_item(&self, _item: &mut Request) {}
+    #[inline(always)]
+    fn to_original_item(_item: &mut Request) {}
 }
 
 pub type RequestQueue<'a> = Queue<'a, RequestQueueHeader>;
 
 impl RequestQueue<'_> {
-    fn gen_order_id(&mut self, limit_price: u64, side: Side) -> u128 {
+    pub fn gen_order_id(&mut self, limit_price: u64, side: Side) -> u128 {
         let seq_num = self.gen_seq_num();
         let upper = (limit_price as u128) << 64;
         let lower = match side {
@@ -671,8 +1301,14 @@ enum RequestFlag {
     PostOnly = 0x08,
     ImmediateOrCancel = 0x10,
     DecrementTakeOnSelfTrade = 0x20,
+    FillOrKill = 0x40,
+    PostOnlySlide = 0x80,
 }
 
+/// The length of requests in queues of markets of the original size. They
+/// had none of the fields from `expiry` on.
+pub const ORIGINAL_REQUEST_LEN: usize = 80;
+
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
 pub struct Request {
@@ -680,12 +1316,15 @@ pub struct Request {
     owner_slot: u8,
     fee_tier: u8,
     self_trade_behavior: u8,
-    padding: [u8; 4],
+    expiry_kind: u8,
+    padding: [u8; 3],
     max_coin_qty_or_cancel_id: u64,
     native_pc_qty_locked: u64,
     order_id: u128,
     owner: [u64; 4],
     client_order_id: u64,
+    expiry: u64,
+    display_qty: u64,
 }
 unsafe impl Zeroable for Request {}
 unsafe impl Pod for Request {}
@@ -703,6 +1342,8 @@ pub enum RequestView<'a> {
         owner: &'a [u64; 4],
         client_order_id: Option<NonZeroU64>,
         self_trade_behavior: SelfTradeBehavior,
+        expiry: Option<OrderExpiry>,
+        display_qty: Option<NonZeroU64>,
     },
     CancelOrder {
         side: Side,
@@ -729,6 +1370,8 @@ impl Request {
                 native_pc_qty_locked,
                 client_order_id,
                 self_trade_behavior,
+                expiry,
+                display_qty,
             } => {
                 let mut flags = BitFlags::from_flag(RequestFlag::NewOrder);
                 if side == Side::Bid {
@@ -737,20 +1380,26 @@ impl Request {
                 match order_type {
                     OrderType::PostOnly => flags |= RequestFlag::PostOnly,
                     OrderType::ImmediateOrCancel => flags |= RequestFlag::ImmediateOrCancel,
+                    OrderType::FillOrKill => flags |= RequestFlag::FillOrKill,
+                    OrderType::PostOnlySlide => flags |= RequestFlag::PostOnlySlide,
                     OrderType::Limit => (),
                 };
+                let (expiry_kind, expiry) = OrderExpiry::to_parts(expiry);
 
                 Request {
                     request_flags: flags.bits(),
                     owner_slot,
                     fee_tier: fee_tier.into(),
                     self_trade_behavior: self_trade_behavior.into(),
+                    expiry_kind,
                     padding: Zeroable::zeroed(),
                     order_id: *order_id,
                     owner: *owner,
                     max_coin_qty_or_cancel_id: max_coin_qty.get(),
                     native_pc_qty_locked: native_pc_qty_locked.map_or(0, NonZeroU64::get),
                     client_order_id: client_order_id.map_or(0, NonZeroU64::get),
+                    expiry,
+                    display_qty: display_qty.map_or(0, NonZeroU64::get),
                 }
             }
             RequestView::CancelOrder {
@@ -774,8 +1423,11 @@ impl Request {
                     self_trade_behavior: 0,
                     owner: *expected_owner,
                     native_pc_qty_locked: 0,
+                    expiry_kind: 0,
                     padding: Zeroable::zeroed(),
                     client_order_id: client_order_id.map_or(0, NonZeroU64::get),
+                    expiry: 0,
+                    display_qty: 0,
                 }
             }
         }
@@ -792,21 +1444,27 @@ impl Request {
         if flags.contains(RequestFlag::NewOrder) {
             let allowed_flags = {
                 use RequestFlag::*;
-                NewOrder | Bid | PostOnly | ImmediateOrCancel
+                NewOrder | Bid | PostOnly | ImmediateOrCancel | FillOrKill | PostOnlySlide
             };
             check_assert!(allowed_flags.contains(flags))?;
             let post_only = flags.contains(RequestFlag::PostOnly);
             let ioc = flags.contains(RequestFlag::ImmediateOrCancel);
-            let order_type = match (post_only, ioc) {
-                (true, false) => OrderType::PostOnly,
-                (false, true) => OrderType::ImmediateOrCancel,
-                (false, false) => OrderType::Limit,
-                (true, true) => unreachable!(),
+            let fok = flags.contains(RequestFlag::FillOrKill);
+            let slide = flags.contains(RequestFlag::PostOnlySlide);
+            let order_type = match (post_only, ioc, fok, slide) {
+                (true, false, false, false) => OrderType::PostOnly,
+                (false, true, false, false) => OrderType::ImmediateOrCancel,
+                (false, false, true, false) => OrderType::FillOrKill,
+                (false, false, false, true) => OrderType::PostOnlySlide,
+                (false, false, false, false) => OrderType::Limit,
+                _ => check_unreachable!()?,
             };
             let fee_tier = FeeTier::try_from_primitive(self.fee_tier).or(check_unreachable!())?;
             let self_trade_behavior =
                 SelfTradeBehavior::try_from_primitive(self.self_trade_behavior)
                     .or(check_unreachable!())?;
+            let expiry =
+                OrderExpiry::from_parts(self.expiry_kind, self.expiry).ok_or(assertion_error!())?;
             Ok(RequestView::NewOrder {
                 side,
                 order_type,
@@ -818,6 +1476,8 @@ impl Request {
                 max_coin_qty: NonZeroU64::new(self.max_coin_qty_or_cancel_id).unwrap(),
                 native_pc_qty_locked: NonZeroU64::new(self.native_pc_qty_locked),
                 client_order_id: NonZeroU64::new(self.client_order_id),
+                expiry,
+                display_qty: NonZeroU64::new(self.display_qty),
             })
         } else {
             check_assert!(flags.contains(RequestFlag::CancelOrder))?;
@@ -841,10 +1501,10 @@ impl Request {
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
 pub struct EventQueueHeader {
-    account_flags: u64, // Initialized, EventQueue
+    pub(crate) account_flags: u64, // Initialized, EventQueue
     head: u64,
     count: u64,
-    seq_num: u64,
+    pub(crate) seq_num: u64,
 }
 unsafe impl Zeroable for EventQueueHeader {}
 unsafe impl Pod for EventQueueHeader {}
@@ -854,6 +1514,7 @@ unsafe impl TriviallyTransmutable for RequestQueueHeader {}
 
 impl QueueHeader for EventQueueHeader {
     type Item = Event;
+    const ORIGINAL_ITEM_LEN: usize = ORIGINAL_EVENT_LEN;
 
     fn head(&self) -> u64 {
         self.head
@@ -873,10 +1534,29 @@ impl QueueHeader for EventQueueHeader {
     fn decr_event_id(&mut self, n: u64) {
         self.seq_num -= n;
     }
+    fn stamp_item(&self, event: &mut Event) {
+        event.seq_num = self.seq_num;
+    }
+    fn to_original_item(event: &mut Event) {
+        event.version = 0;
+    }
 }
 
 pub type EventQueue<'a> = Queue<'a, EventQueueHeader>;
 
+impl EventQueue<'_> {
+    /// The layout version of the events the queue holds: 0 for the queues of
+    /// markets of the original size.
+    #[inline]
+    pub fn event_version(&self) -> u8 {
+        if self.item_len == size_of::<Event>() {
+            EVENT_VERSION
+        } else {
+            0
+        }
+    }
+}
+
 #[derive(Copy, Clone, BitFlags, Debug)]
 #[repr(u8)]
 enum EventFlag {
@@ -884,6 +1564,7 @@ enum EventFlag {
     Out = 0x2,
     Bid = 0x4,
     Maker = 0x8,
+    Repost = 0x10,
 }
 
 impl EventFlag {
@@ -905,6 +1586,15 @@ impl EventFlag {
     }
 }
 
+/// The layout version written into every event. Version 0 events had none
+/// of the fields from `price` on and could not be reposts. The queues of
+/// markets of the original size still hold them.
+pub const EVENT_VERSION: u8 = 1;
+
+/// The length of events in queues of markets of the original size, which
+/// hold version 0 events.
+pub const ORIGINAL_EVENT_LEN: usize = 88;
+
 #[derive(Copy, Clone, Debug)]
 #[repr(C)]
 pub struct Event {
@@ -912,8 +1602,9 @@ pub struct Event {
     owner_slot: u8,
 
     fee_tier: u8,
+    version: u8,
 
-    _padding: [u8; 5],
+    _padding: [u8; 4],
 
     native_qty_released: u64,
     native_qty_paid: u64,
@@ -922,6 +1613,12 @@ pub struct Event {
     order_id: u128,
     pub owner: [u64; 4],
     client_order_id: u64,
+
+    price: u64, // only set for fills
+    seq_num: u64,
+    slot: u64,
+
+    new_order_id: u128, // only set for reposts
 }
 unsafe impl Zeroable for Event {}
 unsafe impl Pod for Event {}
@@ -930,8 +1627,10 @@ unsafe impl TriviallyTransmutable for Event {}
 unsafe impl TriviallyTransmutable for Request {}
 
 impl Event {
+    /// Creates an event that happened in `slot`. Its sequence number is
+    /// assigned when it is pushed onto the event queue.
     #[inline(always)]
-    pub fn new(view: EventView) -> Self {
+    pub fn new(view: EventView, slot: u64) -> Self {
         match view {
             EventView::Fill {
                 side,
@@ -944,6 +1643,7 @@ impl Event {
                 owner_slot,
                 fee_tier,
                 client_order_id,
+                price,
             } => {
                 let maker_flag = if maker {
                     BitFlags::from_flag(EventFlag::Maker).bits()
@@ -956,6 +1656,7 @@ impl Event {
                     event_flags,
                     owner_slot,
                     fee_tier: fee_tier.into(),
+                    version: EVENT_VERSION,
 
                     _padding: Zeroable::zeroed(),
 
@@ -967,6 +1668,12 @@ impl Event {
                     owner: *owner,
 
                     client_order_id: client_order_id.map_or(0, NonZeroU64::get),
+
+                    price,
+                    seq_num: 0,
+                    slot,
+
+                    new_order_id: 0,
                 }
             }
 
@@ -984,6 +1691,7 @@ impl Event {
                     event_flags,
                     owner_slot,
                     fee_tier: 0,
+                    version: EVENT_VERSION,
 
                     _padding: Zeroable::zeroed(),
 
@@ -994,13 +1702,56 @@ impl Event {
                     order_id: *order_id,
                     owner: *owner,
                     client_order_id: client_order_id.map_or(0, NonZeroU64::get),
+
+                    price: 0,
+                    seq_num: 0,
+                    slot,
+
+                    new_order_id: 0,
+                }
+            }
+
+            EventView::Repost {
+                side,
+                order_id,
+                new_order_id,
+                owner,
+                owner_slot,
+                client_order_id,
+            } => {
+                let event_flags = (EventFlag::from_side(side) | EventFlag::Repost).bits();
+                Event {
+                    event_flags,
+                    owner_slot,
+                    fee_tier: 0,
+                    version: EVENT_VERSION,
+
+                    _padding: Zeroable::zeroed(),
+
+                    native_qty_released: 0,
+                    native_qty_paid: 0,
+                    native_fee_or_rebate: 0,
+
+                    order_id: *order_id,
+                    owner: *owner,
+                    client_order_id: client_order_id.map_or(0, NonZeroU64::get),
+
+                    price: 0,
+                    seq_num: 0,
+                    slot,
+
+                    new_order_id,
                 }
             }
         }
     }
 
+    /// Reads an event of either layout version.
     #[inline(always)]
     pub fn as_view(&self) -> DexResult<EventView> {
+        if self.version != EVENT_VERSION && self.version != 0 {
+            Err(DexErrorCode::UnsupportedEventVersion)?
+        }
         let flags = BitFlags::from_bits(self.event_flags).unwrap();
         let side = EventFlag::flags_to_side(flags);
         let client_order_id = NonZeroU64::new(self.client_order_id);
@@ -1024,6 +1775,23 @@ impl Event {
                 owner_slot: self.owner_slot,
                 fee_tier: self.fee_tier.try_into().or(check_unreachable!())?,
                 client_order_id,
+                price: self.price,
+            });
+        }
+        if flags.contains(EventFlag::Repost) && self.version == EVENT_VERSION {
+            let allowed_flags = {
+                use EventFlag::*;
+                Repost | Bid
+            };
+            check_assert!(allowed_flags.contains(flags))?;
+
+            return Ok(EventView::Repost {
+                side,
+                order_id: &self.order_id,
+                new_order_id: self.new_order_id,
+                owner: &self.owner,
+                owner_slot: self.owner_slot,
+                client_order_id,
             });
         }
         let allowed_flags = {
@@ -1043,6 +1811,25 @@ impl Event {
             client_order_id,
         })
     }
+
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// The position of the event in the sequence of all events the market's
+    /// event queue has held.
+    #[inline]
+    pub fn seq_num(&self) -> u64 {
+        self.seq_num
+    }
+
+    /// The slot the event happened in, or 0 if it came from a `MatchOrders`
+    /// that was not given the clock.
+    #[inline]
+    pub fn slot(&self) -> u64 {
+        self.slot
+    }
 }
 
 #[derive(Debug)]
@@ -1058,6 +1845,9 @@ pub enum EventView<'a> {
         owner_slot: u8,
         fee_tier: FeeTier,
         client_order_id: Option<NonZeroU64>,
+        /// In pc lots per coin lot. Each fill is against a single maker
+        /// order, and both sides report that order's price.
+        price: u64,
     },
     Out {
         side: Side,
@@ -1068,20 +1858,33 @@ pub enum EventView<'a> {
         owner_slot: u8,
         client_order_id: Option<NonZeroU64>,
     },
+    /// A resting order moved to `new_order_id`: either the next slice of an
+    /// iceberg order's reserve was posted, or a post-only order slid to a
+    /// price that does not cross.
+    Repost {
+        side: Side,
+        order_id: &'a u128,
+        new_order_id: u128,
+        owner: &'a [u64; 4],
+        owner_slot: u8,
+        client_order_id: Option<NonZeroU64>,
+    },
 }
 
 impl<'a> EventView<'a> {
     fn side(&self) -> Side {
         match self {
-            &EventView::Fill { side, .. } | &EventView::Out { side, .. } => side,
+            &EventView::Fill { side, .. }
+            | &EventView::Out { side, .. }
+            | &EventView::Repost { side, .. } => side,
         }
     }
 }
 
 #[derive(Copy, Clone)]
 #[repr(C)]
-struct OrderBookStateHeader {
-    account_flags: u64, // Initialized, (Bids or Asks)
+pub(crate) struct OrderBookStateHeader {
+    pub(crate) account_flags: u64, // Initialized, (Bids or Asks)
 }
 unsafe impl Zeroable for OrderBookStateHeader {}
 unsafe impl Pod for OrderBookStateHeader {}
@@ -1113,6 +1916,66 @@ pub fn gen_vault_signer_key(
     Ok(Pubkey::default())
 }
 
+fn gen_open_orders_seeds<'a>(
+    market: &'a Pubkey,
+    owner: &'a Pubkey,
+    index: &'a u64,
+    nonce: &'a u64,
+) -> [&'a [u8]; 4] {
+    [
+        market.as_ref(),
+        owner.as_ref(),
+        bytes_of(index),
+        bytes_of(nonce),
+    ]
+}
+
+#[cfg(not(any(test, feature = "fuzz")))]
+#[inline]
+pub fn gen_open_orders_key(
+    nonce: u64,
+    market: &Pubkey,
+    owner: &Pubkey,
+    index: u64,
+    program_id: &Pubkey,
+) -> Result<Pubkey, ProgramError> {
+    let seeds = gen_open_orders_seeds(market, owner, &index, &nonce);
+    Ok(Pubkey::create_program_address(&seeds, program_id)?)
+}
+
+#[cfg(any(test, feature = "fuzz"))]
+pub fn gen_open_orders_key(
+    nonce: u64,
+    market: &Pubkey,
+    owner: &Pubkey,
+    index: u64,
+    _program_id: &Pubkey,
+) -> Result<Pubkey, ProgramError> {
+    gen_open_orders_seeds(market, owner, &index, &nonce);
+    let mut key = market.to_aligned_bytes();
+    for (word, owner_word) in key.iter_mut().zip(owner.to_aligned_bytes().iter()) {
+        *word ^= owner_word;
+    }
+    key[0] ^= index;
+    Ok(Pubkey::new(transmute_to_bytes(&key)))
+}
+
+/// Finds the address of a user's `index`th OpenOrders account on a market,
+/// as created by `InitOpenOrders`, along with the nonce used to derive it.
+pub fn find_open_orders_key(
+    market: &Pubkey,
+    owner: &Pubkey,
+    index: u64,
+    program_id: &Pubkey,
+) -> Result<(Pubkey, u64), ProgramError> {
+    for nonce in 0..=std::u8::MAX as u64 {
+        if let Ok(key) = gen_open_orders_key(nonce, market, owner, index, program_id) {
+            return Ok((key, nonce));
+        }
+    }
+    Err(ProgramError::InvalidSeeds)
+}
+
 #[cfg(not(feature = "fuzz"))]
 #[cfg(feature = "program")]
 fn invoke_spl_token(
@@ -1123,6 +1986,45 @@ fn invoke_spl_token(
     solana_sdk::program::invoke_signed(instruction, account_infos, signers_seeds)
 }
 
+#[cfg(not(feature = "fuzz"))]
+#[cfg(feature = "program")]
+fn invoke_system_program(
+    instruction: &solana_sdk::instruction::Instruction,
+    account_infos: &[AccountInfo],
+    signers_seeds: &[&[&[u8]]],
+) -> solana_sdk::entrypoint::ProgramResult {
+    solana_sdk::program::invoke_signed(instruction, account_infos, signers_seeds)
+}
+
+// accounts can't be resized outside the runtime, so they have to be
+// allocated up front; only transfers are carried out
+#[cfg(feature = "fuzz")]
+fn invoke_system_program(
+    instruction: &solana_sdk::instruction::Instruction,
+    account_infos: &[AccountInfo],
+    _signers_seeds: &[&[&[u8]]],
+) -> solana_sdk::entrypoint::ProgramResult {
+    assert_eq!(instruction.program_id, system_program::ID);
+    if let Ok(system_instruction::SystemInstruction::Transfer { lamports }) =
+        bincode::deserialize(&instruction.data)
+    {
+        let find_account = |meta: &solana_sdk::instruction::AccountMeta| {
+            account_infos
+                .iter()
+                .find(|info| *info.key == meta.pubkey)
+                .unwrap()
+        };
+        let from = find_account(&instruction.accounts[0]);
+        let to = find_account(&instruction.accounts[1]);
+        let mut from_lamports = from.lamports.borrow_mut();
+        **from_lamports = from_lamports
+            .checked_sub(lamports)
+            .ok_or(ProgramError::InsufficientFunds)?;
+        **to.lamports.borrow_mut() += lamports;
+    }
+    Ok(())
+}
+
 #[cfg(feature = "fuzz")]
 fn invoke_spl_token(
     instruction: &solana_sdk::instruction::Instruction,
@@ -1179,20 +2081,148 @@ fn send_from_vault<'a, 'b: 'a>(
     Ok(())
 }
 
-pub mod account_parser {
-    use super::*;
-
-    macro_rules! declare_validated_account_wrapper {
-        ($WrapperT:ident, $validate:expr $(, $a:ident : $t:ty)*) => {
-            #[derive(Copy, Clone)]
-            pub struct $WrapperT<'a, 'b: 'a>(&'a AccountInfo<'b>);
-            impl<'a, 'b: 'a> $WrapperT<'a, 'b> {
-                fn new(account: &'a AccountInfo<'b> $(,$a: $t)*) -> DexResult<Self> {
-                    let validate_result: DexResult = $validate(account $(,$a)*);
-                    validate_result?;
-                    Ok($WrapperT(account))
-                }
-
+/// Pulls `native_amount` from `payer` into `vault`, signed by the payer's owner.
+fn deposit_to_vault<'a, 'b: 'a>(
+    native_amount: u64,
+    payer: account_parser::TokenAccount<'a, 'b>,
+    vault: account_parser::TokenAccount<'a, 'b>,
+    owner: account_parser::SignerAccount<'a, 'b>,
+    spl_token_program: account_parser::SplTokenProgram<'a, 'b>,
+) -> DexResult {
+    let deposit_instruction = spl_token::instruction::transfer(
+        &spl_token::ID,
+        payer.inner().key,
+        vault.inner().key,
+        owner.inner().key,
+        &[],
+        native_amount,
+    )
+    .unwrap();
+    assert_eq!(*spl_token_program.inner().key, spl_token::ID);
+
+    invoke_spl_token(
+        &deposit_instruction,
+        &[
+            payer.inner().clone(),
+            vault.inner().clone(),
+            owner.inner().clone(),
+            spl_token_program.inner().clone(),
+        ],
+        &[],
+    )
+    .map_err(|err| match err {
+        ProgramError::Custom(i) => match TokenError::from_u32(i) {
+            Some(TokenError::InsufficientFunds) => DexErrorCode::InsufficientFunds,
+            _ => DexErrorCode::TransferFailed,
+        },
+        _ => DexErrorCode::TransferFailed,
+    })?;
+    Ok(())
+}
+
+/// Moves the market's crank fee into its reward pools, out of the lamports the
+/// OpenOrders account holds beyond its rent exempt balance. Half of the fee
+/// goes to matching the order's request and half to consuming its events.
+fn collect_crank_fee(
+    market: &mut MarketStateV2,
+    market_acc: &AccountInfo,
+    open_orders_acc: &AccountInfo,
+    open_orders_data_len: usize,
+    rent: &Rent,
+) -> DexResult {
+    let crank_fee = market.crank_fee_lamports;
+    if crank_fee == 0 {
+        return Ok(());
+    }
+    let mut open_orders_lamports = open_orders_acc.lamports.borrow_mut();
+    let spendable_lamports =
+        open_orders_lamports.saturating_sub(rent.minimum_balance(open_orders_data_len));
+    if spendable_lamports < crank_fee {
+        Err(DexErrorCode::InsufficientCrankFee)?
+    }
+    let mut market_lamports = market_acc.lamports.borrow_mut();
+    **open_orders_lamports -= crank_fee;
+    **market_lamports = market_lamports
+        .checked_add(crank_fee)
+        .ok_or(ProgramError::InvalidArgument)?;
+    let match_reward = crank_fee / 2;
+    market.match_rewards_accrued += match_reward;
+    market.consume_rewards_accrued += crank_fee - match_reward;
+    Ok(())
+}
+
+/// Pays the cranker its share of a reward pool: the fraction of the work
+/// outstanding before the call that it has just done. Once no work is left
+/// pending, the cranker collects whatever is left in the pool, including the
+/// shares of cranks that didn't claim theirs.
+fn pay_crank_reward(
+    rewards_accrued: &mut u64,
+    market_acc: &AccountInfo,
+    recipient: Option<&AccountInfo>,
+    processed: u64,
+    pending: u64,
+) -> DexResult {
+    let recipient = match recipient {
+        Some(recipient) => recipient,
+        None => return Ok(()),
+    };
+    let reward = if pending == 0 {
+        *rewards_accrued
+    } else {
+        (*rewards_accrued as u128 * processed as u128 / (processed as u128 + pending as u128))
+            as u64
+    };
+    if reward == 0 {
+        return Ok(());
+    }
+    let mut market_lamports = market_acc.lamports.borrow_mut();
+    let mut recipient_lamports = recipient.lamports.borrow_mut();
+    **market_lamports -= reward;
+    **recipient_lamports = recipient_lamports
+        .checked_add(reward)
+        .ok_or(ProgramError::InvalidArgument)?;
+    *rewards_accrued -= reward;
+    Ok(())
+}
+
+/// Pays the cranker its share of the matching pool. Once the request queue
+/// is drained, what nobody claimed of the pool moves to the event pool, as
+/// consuming the events is all the work left for those requests.
+fn pay_match_reward(
+    market: &mut MarketStateV2,
+    market_acc: &AccountInfo,
+    recipient: Option<&AccountInfo>,
+    processed: u64,
+    pending: u64,
+) -> DexResult {
+    pay_crank_reward(
+        &mut market.match_rewards_accrued,
+        market_acc,
+        recipient,
+        processed,
+        pending,
+    )?;
+    if pending == 0 {
+        market.consume_rewards_accrued += market.match_rewards_accrued;
+        market.match_rewards_accrued = 0;
+    }
+    Ok(())
+}
+
+pub mod account_parser {
+    use super::*;
+
+    macro_rules! declare_validated_account_wrapper {
+        ($WrapperT:ident, $validate:expr $(, $a:ident : $t:ty)*) => {
+            #[derive(Copy, Clone)]
+            pub struct $WrapperT<'a, 'b: 'a>(&'a AccountInfo<'b>);
+            impl<'a, 'b: 'a> $WrapperT<'a, 'b> {
+                fn new(account: &'a AccountInfo<'b> $(,$a: $t)*) -> DexResult<Self> {
+                    let validate_result: DexResult = $validate(account $(,$a)*);
+                    validate_result?;
+                    Ok($WrapperT(account))
+                }
+
                 #[inline(always)]
                 pub fn inner(self) -> &'a AccountInfo<'b> {
                     self.0
@@ -1206,6 +2236,11 @@ pub mod account_parser {
         Ok(())
     });
 
+    declare_validated_account_wrapper!(SystemProgram, |account: &AccountInfo| {
+        check_assert_eq!(*account.key, system_program::ID)?;
+        Ok(())
+    });
+
     declare_validated_account_wrapper!(TokenMint, |mint: &AccountInfo| {
         check_assert_eq!(*mint.owner, spl_token::ID)?;
         let data = mint.try_borrow_data()?;
@@ -1226,6 +2261,16 @@ pub mod account_parser {
         Ok(())
     });
 
+    impl<'a, 'b: 'a> TokenAccount<'a, 'b> {
+        /// The key allowed to transfer out of this token account.
+        fn owner(self) -> DexResult<[u64; 4]> {
+            let data = self.inner().try_borrow_data()?;
+            let mut owner: [u64; 4] = Zeroable::zeroed();
+            bytes_of_mut(&mut owner).copy_from_slice(&data[32..64]);
+            Ok(owner)
+        }
+    }
+
     macro_rules! declare_validated_token_account_wrapper {
         ($WrapperT:ident, $validate:expr $(, $a:ident : $t:ty)*) => {
             #[derive(Copy, Clone)]
@@ -1260,6 +2305,11 @@ pub mod account_parser {
         Ok(())
     });
 
+    declare_validated_account_wrapper!(ClockSysvarAccount, |account: &AccountInfo| {
+        check_assert!(Clock::check_id(account.key))?;
+        Ok(())
+    });
+
     declare_validated_account_wrapper!(SignerAccount, |account: &AccountInfo| {
         check_assert!(account.is_signer)?;
         Ok(())
@@ -1351,19 +2401,21 @@ pub mod account_parser {
 
     pub struct InitializeMarketArgs<'a, 'b: 'a> {
         pub program_id: &'a Pubkey,
-        pub instruction: &'a InitializeMarketInstruction,
+        pub instruction: &'a InitializeMarketInstructionV2,
         serum_dex_accounts: &'a [AccountInfo<'b>; 5],
         pub coin_vault_and_mint: TokenAccountAndMint<'a, 'b>,
         pub pc_vault_and_mint: TokenAccountAndMint<'a, 'b>,
+        pub authority: Option<&'a AccountInfo<'b>>,
     }
 
     impl<'a, 'b: 'a> InitializeMarketArgs<'a, 'b> {
         pub fn new(
             program_id: &'a Pubkey,
-            instruction: &'a InitializeMarketInstruction,
+            instruction: &'a InitializeMarketInstructionV2,
             accounts: &'a [AccountInfo<'b>],
         ) -> DexResult<Self> {
-            check_assert_eq!(accounts.len(), 9)?;
+            check_assert!(accounts.len() == 9 || accounts.len() == 10)?;
+            let authority = accounts.get(9);
             let accounts = array_ref![accounts, 0, 9];
             let (unchecked_serum_dex_accounts, unchecked_vaults, unchecked_mints) =
                 array_refs![accounts, 5, 2, 2];
@@ -1411,6 +2463,7 @@ pub mod account_parser {
                 serum_dex_accounts,
                 coin_vault_and_mint,
                 pc_vault_and_mint,
+                authority,
             })
         }
 
@@ -1440,9 +2493,11 @@ pub mod account_parser {
     }
 
     pub struct NewOrderArgs<'a, 'b: 'a> {
-        pub instruction: &'a NewOrderInstructionV2,
-        pub market: &'a mut MarketState,
-        pub open_orders: &'a mut OpenOrders,
+        pub instruction: &'a NewOrderInstructionV6,
+        pub market: &'a mut MarketStateV2,
+        pub market_acc: &'a AccountInfo<'b>,
+        pub open_orders: OpenOrdersMut<'a>,
+        pub open_orders_acc: &'a AccountInfo<'b>,
         pub open_orders_address: &'a [u64; 4],
         pub owner: SignerAccount<'a, 'b>,
         pub req_q: RequestQueue<'a>,
@@ -1451,11 +2506,12 @@ pub mod account_parser {
         pub pc_vault: PcVault<'a, 'b>,
         pub spl_token_program: SplTokenProgram<'a, 'b>,
         pub fee_tier: FeeTier,
+        pub rent: Rent,
     }
     impl<'a, 'b: 'a> NewOrderArgs<'a, 'b> {
         pub fn with_parsed_args<T>(
             program_id: &'a Pubkey,
-            instruction: &'a NewOrderInstructionV2,
+            instruction: &'a NewOrderInstructionV6,
             accounts: &'a [AccountInfo<'b>],
             f: impl FnOnce(NewOrderArgs) -> DexResult<T>,
         ) -> DexResult<T> {
@@ -1481,7 +2537,8 @@ pub mod account_parser {
                 _ => check_unreachable!()?,
             };
 
-            let mut market: RefMut<'a, MarketState> = MarketState::load(market_acc, program_id)?;
+            let mut market: RefMut<'a, MarketStateV2> =
+                MarketStateV2::load(market_acc, program_id)?;
             let rent = {
                 let rent_sysvar = RentSysvarAccount::new(rent_sysvar_acc)?;
                 Rent::from_account_info(rent_sysvar.inner()).or(check_unreachable!())?
@@ -1489,9 +2546,9 @@ pub mod account_parser {
             let owner = SignerAccount::new(owner_acc)?;
             let fee_tier =
                 market.load_fee_tier(&owner.inner().key.to_aligned_bytes(), srm_or_msrm_account)?;
-            let mut open_orders = market.load_orders_mut(
+            let open_orders = market.load_orders_mut_for_trader(
                 open_orders_acc,
-                Some(owner.inner()),
+                owner.inner(),
                 program_id,
                 Some(rent),
             )?;
@@ -1510,7 +2567,9 @@ pub mod account_parser {
             let args = NewOrderArgs {
                 instruction,
                 market: market.deref_mut(),
-                open_orders: open_orders.deref_mut(),
+                market_acc,
+                open_orders,
+                open_orders_acc,
                 open_orders_address,
                 owner,
                 req_q,
@@ -1519,34 +2578,82 @@ pub mod account_parser {
                 pc_vault,
                 spl_token_program,
                 fee_tier,
+                rent,
             };
             f(args)
         }
     }
 
-    pub struct MatchOrdersArgs<'a> {
+    pub struct MatchOrdersArgs<'a, 'b: 'a, X = LeafTail> {
         pub limit: u16,
-        pub order_book_state: OrderBookState<'a>,
+        pub order_book_state: OrderBookState<'a, X>,
         pub req_q: RequestQueue<'a>,
         pub event_q: EventQueue<'a>,
+        pub market_acc: &'a AccountInfo<'b>,
+        pub crank_reward_recipient: Option<&'a AccountInfo<'b>>,
     }
-    impl<'a> MatchOrdersArgs<'a> {
-        pub fn with_parsed_args<'b, T>(
+    impl<'a, 'b: 'a> MatchOrdersArgs<'a, 'b> {
+        pub fn with_parsed_args<T>(
             program_id: &'a Pubkey,
             accounts: &'a [AccountInfo<'b>],
             limit: u16,
             f: impl FnOnce(MatchOrdersArgs) -> DexResult<T>,
+            f_original: impl FnOnce(MatchOrdersArgs<'_, '_, ()>) -> DexResult<T>,
         ) -> DexResult<T> {
-            check_assert!(accounts.len() >= 5)?;
+            check_assert!(accounts.len() >= 7 && accounts.len() <= 9)?;
             #[rustfmt::skip]
             let &[
                 ref market_acc,
                 ref req_q_acc,
                 ref event_q_acc,
                 ref bids_acc,
-                ref asks_acc
-            ] = array_ref![accounts, 0, 5];
-            let mut market = MarketState::load(market_acc, program_id).or(check_unreachable!())?;
+                ref asks_acc,
+                _,
+                _,
+            ] = array_ref![accounts, 0, 7];
+            let clock_sysvar_acc = accounts.get(7);
+            let crank_reward_recipient = match accounts.get(8) {
+                Some(recipient_acc) => Some(SignerAccount::new(recipient_acc)?.inner()),
+                None => None,
+            };
+            if MarketState::is_original(market_acc) {
+                return MatchOrdersArgs::with_original_accounts(
+                    program_id,
+                    array_ref![accounts, 0, 7],
+                    clock_sysvar_acc,
+                    crank_reward_recipient,
+                    limit,
+                    f_original,
+                );
+            }
+            Self::with_accounts(
+                program_id,
+                market_acc,
+                req_q_acc,
+                event_q_acc,
+                bids_acc,
+                asks_acc,
+                clock_sysvar_acc,
+                crank_reward_recipient,
+                limit,
+                f,
+            )
+        }
+
+        pub fn with_accounts<T>(
+            program_id: &'a Pubkey,
+            market_acc: &'a AccountInfo<'b>,
+            req_q_acc: &'a AccountInfo<'b>,
+            event_q_acc: &'a AccountInfo<'b>,
+            bids_acc: &'a AccountInfo<'b>,
+            asks_acc: &'a AccountInfo<'b>,
+            clock_sysvar_acc: Option<&'a AccountInfo<'b>>,
+            crank_reward_recipient: Option<&'a AccountInfo<'b>>,
+            limit: u16,
+            f: impl FnOnce(MatchOrdersArgs) -> DexResult<T>,
+        ) -> DexResult<T> {
+            let clock = load_optional_clock(clock_sysvar_acc)?;
+            let mut market = MarketStateV2::load(market_acc, program_id)?;
             let req_q = market
                 .load_request_queue_mut(req_q_acc)
                 .or(check_unreachable!())?;
@@ -1560,6 +2667,53 @@ pub mod account_parser {
                 bids: bids.deref_mut(),
                 asks: asks.deref_mut(),
                 market_state: market.deref_mut(),
+                clock,
+            };
+
+            let args = MatchOrdersArgs {
+                limit,
+                order_book_state,
+                req_q,
+                event_q,
+                market_acc,
+                crank_reward_recipient,
+            };
+            f(args)
+        }
+    }
+    impl<'a, 'b: 'a> MatchOrdersArgs<'a, 'b, ()> {
+        /// Matches a market of the original size as
+        /// `MarketStateV2::from_original`, over its original book and queues.
+        fn with_original_accounts<T>(
+            program_id: &'a Pubkey,
+            accounts: &'a [AccountInfo<'b>; 7],
+            clock_sysvar_acc: Option<&'a AccountInfo<'b>>,
+            crank_reward_recipient: Option<&'a AccountInfo<'b>>,
+            limit: u16,
+            f: impl FnOnce(MatchOrdersArgs<'_, '_, ()>) -> DexResult<T>,
+        ) -> DexResult<T> {
+            let [market_acc, req_q_acc, event_q_acc, bids_acc, asks_acc, _, _] = accounts;
+            let clock = load_optional_clock(clock_sysvar_acc)?;
+            let mut market = MarketState::load(market_acc, program_id)?;
+            let mut market_v2 = MarketStateV2::from_original(*market);
+            let req_q = market
+                .load_original_request_queue_mut(req_q_acc)
+                .or(check_unreachable!())?;
+            let event_q = market
+                .load_original_event_queue_mut(event_q_acc)
+                .or(check_unreachable!())?;
+            let mut bids = market
+                .load_original_bids_mut(bids_acc)
+                .or(check_unreachable!())?;
+            let mut asks = market
+                .load_original_asks_mut(asks_acc)
+                .or(check_unreachable!())?;
+
+            let order_book_state = OrderBookState {
+                bids: bids.deref_mut(),
+                asks: asks.deref_mut(),
+                market_state: &mut market_v2,
+                clock,
             };
 
             let args = MatchOrdersArgs {
@@ -1567,6 +2721,162 @@ pub mod account_parser {
                 order_book_state,
                 req_q,
                 event_q,
+                market_acc,
+                crank_reward_recipient,
+            };
+            let result = f(args)?;
+            *market = market_v2.inner;
+            Ok(result)
+        }
+    }
+
+    fn load_optional_clock(clock_sysvar_acc: Option<&AccountInfo>) -> DexResult<Option<Clock>> {
+        Ok(match clock_sysvar_acc {
+            Some(clock_sysvar_acc) => {
+                let clock_sysvar = ClockSysvarAccount::new(clock_sysvar_acc)?;
+                Some(Clock::from_account_info(clock_sysvar.inner()).or(check_unreachable!())?)
+            }
+            None => None,
+        })
+    }
+
+    pub struct SendTakeArgs<'a, 'b: 'a> {
+        pub instruction: &'a SendTakeInstruction,
+        pub market_acc: &'a AccountInfo<'b>,
+        pub order_book_state: OrderBookState<'a>,
+        pub req_q: RequestQueue<'a>,
+        pub event_q: EventQueue<'a>,
+        pub coin_wallet: CoinWallet<'a, 'b>,
+        pub pc_wallet: PcWallet<'a, 'b>,
+        pub owner: SignerAccount<'a, 'b>,
+        pub coin_vault: CoinVault<'a, 'b>,
+        pub pc_vault: PcVault<'a, 'b>,
+        pub vault_signer: VaultSigner<'a, 'b>,
+        pub spl_token_program: SplTokenProgram<'a, 'b>,
+        pub system_program: SystemProgram<'a, 'b>,
+        pub fee_tier: FeeTier,
+    }
+    impl<'a, 'b: 'a> SendTakeArgs<'a, 'b> {
+        pub fn with_parsed_args<T>(
+            program_id: &'a Pubkey,
+            instruction: &'a SendTakeInstruction,
+            accounts: &'a [AccountInfo<'b>],
+            f: impl FnOnce(SendTakeArgs) -> DexResult<T>,
+        ) -> DexResult<T> {
+            check_assert!(accounts.len() == 14 || accounts.len() == 15)?;
+            #[rustfmt::skip]
+            let (&[
+                ref market_acc,
+                ref req_q_acc,
+                ref event_q_acc,
+                ref bids_acc,
+                ref asks_acc,
+                ref coin_wallet_acc,
+                ref pc_wallet_acc,
+                ref owner_acc,
+                ref coin_vault_acc,
+                ref pc_vault_acc,
+                ref vault_signer_acc,
+                ref spl_token_program_acc,
+                ref clock_sysvar_acc,
+                ref system_program_acc,
+            ], fee_discount_account) = array_refs![accounts, 14; .. ;];
+            let srm_or_msrm_account = match fee_discount_account {
+                &[] => None,
+                &[ref account] => Some(TokenAccount::new(account)?),
+                _ => check_unreachable!()?,
+            };
+
+            let clock = {
+                let clock_sysvar = ClockSysvarAccount::new(clock_sysvar_acc)?;
+                Clock::from_account_info(clock_sysvar.inner()).or(check_unreachable!())?
+            };
+            let mut market = MarketStateV2::load(market_acc, program_id)?;
+            market.check_enabled()?;
+            let owner = SignerAccount::new(owner_acc)?;
+            let fee_tier =
+                market.load_fee_tier(&owner.inner().key.to_aligned_bytes(), srm_or_msrm_account)?;
+
+            let req_q = market.load_request_queue_mut(req_q_acc)?;
+            let event_q = market.load_event_queue_mut(event_q_acc)?;
+            let mut bids = market.load_bids_mut(bids_acc)?;
+            let mut asks = market.load_asks_mut(asks_acc)?;
+
+            let coin_wallet = CoinWallet::from_account(coin_wallet_acc, &market)?;
+            let pc_wallet = PcWallet::from_account(pc_wallet_acc, &market)?;
+            let coin_vault = CoinVault::from_account(coin_vault_acc, &market)?;
+            let pc_vault = PcVault::from_account(pc_vault_acc, &market)?;
+            let vault_signer = VaultSigner::new(vault_signer_acc, &market, program_id)?;
+            let spl_token_program = SplTokenProgram::new(spl_token_program_acc)?;
+            let system_program = SystemProgram::new(system_program_acc)?;
+
+            let order_book_state = OrderBookState {
+                bids: bids.deref_mut(),
+                asks: asks.deref_mut(),
+                market_state: market.deref_mut(),
+                clock: Some(clock),
+            };
+            let args = SendTakeArgs {
+                instruction,
+                market_acc,
+                order_book_state,
+                req_q,
+                event_q,
+                coin_wallet,
+                pc_wallet,
+                owner,
+                coin_vault,
+                pc_vault,
+                vault_signer,
+                spl_token_program,
+                system_program,
+                fee_tier,
+            };
+            f(args)
+        }
+    }
+
+    pub struct PruneExpiredOrdersArgs<'a> {
+        pub limit: u16,
+        pub order_book_state: OrderBookState<'a>,
+        pub event_q: EventQueue<'a>,
+    }
+    impl<'a> PruneExpiredOrdersArgs<'a> {
+        pub fn with_parsed_args<'b, T>(
+            program_id: &'a Pubkey,
+            accounts: &'a [AccountInfo<'b>],
+            limit: u16,
+            f: impl FnOnce(PruneExpiredOrdersArgs) -> DexResult<T>,
+        ) -> DexResult<T> {
+            check_assert!(accounts.len() == 5)?;
+            #[rustfmt::skip]
+            let &[
+                ref market_acc,
+                ref event_q_acc,
+                ref bids_acc,
+                ref asks_acc,
+                ref clock_sysvar_acc,
+            ] = array_ref![accounts, 0, 5];
+            let clock = {
+                let clock_sysvar = ClockSysvarAccount::new(clock_sysvar_acc)?;
+                Clock::from_account_info(clock_sysvar.inner()).or(check_unreachable!())?
+            };
+            let mut market = MarketStateV2::load(market_acc, program_id)?;
+            let event_q = market.load_event_queue_mut(event_q_acc)?;
+            let mut bids = market.load_bids_mut(bids_acc)?;
+            let mut asks = market.load_asks_mut(asks_acc)?;
+
+            let order_book_state = OrderBookState {
+                bids: bids.deref_mut(),
+                asks: asks.deref_mut(),
+                market_state: market.deref_mut(),
+                clock: Some(clock),
+            };
+
+            let args = PruneExpiredOrdersArgs {
+                limit,
+                order_book_state,
+                event_q,
             };
             f(args)
         }
@@ -1576,8 +2886,10 @@ pub mod account_parser {
         pub limit: u16,
         pub program_id: &'a Pubkey,
         pub open_orders_accounts: &'a [AccountInfo<'b>],
-        pub market: &'a mut MarketState,
+        pub market: &'a mut MarketStateV2,
         pub event_q: EventQueue<'a>,
+        pub market_acc: &'a AccountInfo<'b>,
+        pub crank_reward_recipient: Option<&'a AccountInfo<'b>>,
     }
     impl<'a, 'b: 'a> ConsumeEventsArgs<'a, 'b> {
         pub fn with_parsed_args<T>(
@@ -1585,6 +2897,29 @@ pub mod account_parser {
             accounts: &'a [AccountInfo<'b>],
             limit: u16,
             f: impl FnOnce(ConsumeEventsArgs) -> DexResult<T>,
+        ) -> DexResult<T> {
+            Self::with_accounts(program_id, accounts, None, limit, f)
+        }
+
+        /// Parses the accounts of `ConsumeEventsWithReward`, which end with
+        /// the signer the crank reward is paid to.
+        pub fn with_parsed_args_and_reward<T>(
+            program_id: &'a Pubkey,
+            accounts: &'a [AccountInfo<'b>],
+            limit: u16,
+            f: impl FnOnce(ConsumeEventsArgs) -> DexResult<T>,
+        ) -> DexResult<T> {
+            let (recipient_acc, accounts) = accounts.split_last().ok_or(assertion_error!())?;
+            let crank_reward_recipient = SignerAccount::new(recipient_acc)?.inner();
+            Self::with_accounts(program_id, accounts, Some(crank_reward_recipient), limit, f)
+        }
+
+        fn with_accounts<T>(
+            program_id: &'a Pubkey,
+            accounts: &'a [AccountInfo<'b>],
+            crank_reward_recipient: Option<&'a AccountInfo<'b>>,
+            limit: u16,
+            f: impl FnOnce(ConsumeEventsArgs) -> DexResult<T>,
         ) -> DexResult<T> {
             check_assert!(accounts.len() >= 5)?;
             #[rustfmt::skip]
@@ -1593,9 +2928,26 @@ pub mod account_parser {
                 open_orders_accounts,
                 &[ref market_acc],
                 &[ref event_q_acc],
-                _unused
+                &[_, _],
             ) = array_refs![accounts, 0; .. ; 1, 1, 2];
-            let mut market = MarketState::load(market_acc, program_id)?;
+            if MarketState::is_original(market_acc) {
+                let mut market = MarketState::load_allow_disabled(market_acc, program_id)?;
+                let mut market_v2 = MarketStateV2::from_original(*market);
+                let event_q = market.load_original_event_queue_mut(event_q_acc)?;
+                let args = ConsumeEventsArgs {
+                    limit,
+                    program_id,
+                    open_orders_accounts,
+                    market: &mut market_v2,
+                    event_q,
+                    market_acc,
+                    crank_reward_recipient,
+                };
+                let result = f(args)?;
+                *market = market_v2.inner;
+                return Ok(result);
+            }
+            let mut market = MarketStateV2::load_allow_disabled(market_acc, program_id)?;
             let event_q = market.load_event_queue_mut(event_q_acc)?;
             let args = ConsumeEventsArgs {
                 limit,
@@ -1603,6 +2955,8 @@ pub mod account_parser {
                 open_orders_accounts,
                 market: market.deref_mut(),
                 event_q,
+                market_acc,
+                crank_reward_recipient,
             };
             f(args)
         }
@@ -1630,12 +2984,21 @@ pub mod account_parser {
                 ref req_q_acc,
                 ref owner_acc
             ] = array_ref![accounts, 0, 4];
+            let original = MarketState::is_original(market_acc);
             let market: RefMut<'a, MarketState> = MarketState::load(market_acc, program_id)?;
             let owner = SignerAccount::new(owner_acc)?;
-            let mut open_orders =
-                market.load_orders_mut(open_orders_acc, Some(owner.inner()), program_id, None)?;
+            let mut open_orders = market.load_orders_mut_for_trader(
+                open_orders_acc,
+                owner.inner(),
+                program_id,
+                None,
+            )?;
             let ref open_orders_address = open_orders_acc.key.to_aligned_bytes();
-            let req_q = market.load_request_queue_mut(req_q_acc)?;
+            let req_q = if original {
+                market.load_original_request_queue_mut(req_q_acc)?
+            } else {
+                market.load_request_queue_mut(req_q_acc)?
+            };
             let args = CancelOrderArgs {
                 instruction,
                 open_orders: open_orders.deref_mut(),
@@ -1649,7 +3012,7 @@ pub mod account_parser {
 
     pub struct CancelOrderByClientIdArgs<'a, 'b: 'a> {
         pub client_order_id: NonZeroU64,
-        pub open_orders: &'a mut OpenOrders,
+        pub open_orders: OpenOrdersMut<'a>,
         pub open_orders_address: &'a [u64; 4],
         pub req_q: RequestQueue<'a>,
         pub orders_owner: SignerAccount<'a, 'b>,
@@ -1670,15 +3033,72 @@ pub mod account_parser {
                 ref req_q_acc,
                 ref owner_acc
             ] = array_ref![accounts, 0, 4];
+            let original = MarketState::is_original(market_acc);
             let market: RefMut<'a, MarketState> = MarketState::load(market_acc, program_id)?;
             let owner = SignerAccount::new(owner_acc)?;
-            let mut open_orders =
-                market.load_orders_mut(open_orders_acc, Some(owner.inner()), program_id, None)?;
+            let open_orders = market.load_orders_mut_for_trader(
+                open_orders_acc,
+                owner.inner(),
+                program_id,
+                None,
+            )?;
             let ref open_orders_address = open_orders_acc.key.to_aligned_bytes();
-            let req_q = market.load_request_queue_mut(req_q_acc)?;
+            let req_q = if original {
+                market.load_original_request_queue_mut(req_q_acc)?
+            } else {
+                market.load_request_queue_mut(req_q_acc)?
+            };
             let args = CancelOrderByClientIdArgs {
                 client_order_id,
-                open_orders: open_orders.deref_mut(),
+                open_orders,
+                open_orders_address,
+                req_q,
+                orders_owner: owner,
+            };
+            f(args)
+        }
+    }
+
+    pub struct CancelAllOrdersArgs<'a, 'b: 'a> {
+        pub side: Option<Side>,
+        pub open_orders: OpenOrdersMut<'a>,
+        pub open_orders_address: &'a [u64; 4],
+        pub req_q: RequestQueue<'a>,
+        pub orders_owner: SignerAccount<'a, 'b>,
+    }
+    impl<'a, 'b: 'a> CancelAllOrdersArgs<'a, 'b> {
+        pub fn with_parsed_args<T>(
+            program_id: &'a Pubkey,
+            accounts: &'a [AccountInfo<'b>],
+            side: Option<Side>,
+            f: impl FnOnce(CancelAllOrdersArgs) -> DexResult<T>,
+        ) -> DexResult<T> {
+            check_assert_eq!(accounts.len(), 4)?;
+            #[rustfmt::skip]
+            let &[
+                ref market_acc,
+                ref open_orders_acc,
+                ref req_q_acc,
+                ref owner_acc
+            ] = array_ref![accounts, 0, 4];
+            let original = MarketState::is_original(market_acc);
+            let market: RefMut<'a, MarketState> = MarketState::load(market_acc, program_id)?;
+            let owner = SignerAccount::new(owner_acc)?;
+            let open_orders = market.load_orders_mut_for_trader(
+                open_orders_acc,
+                owner.inner(),
+                program_id,
+                None,
+            )?;
+            let ref open_orders_address = open_orders_acc.key.to_aligned_bytes();
+            let req_q = if original {
+                market.load_original_request_queue_mut(req_q_acc)?
+            } else {
+                market.load_request_queue_mut(req_q_acc)?
+            };
+            let args = CancelAllOrdersArgs {
+                side,
+                open_orders,
                 open_orders_address,
                 req_q,
                 orders_owner: owner,
@@ -1687,6 +3107,53 @@ pub mod account_parser {
         }
     }
 
+    pub struct CloseOpenOrdersArgs<'a, 'b: 'a> {
+        pub open_orders: OpenOrdersMut<'a>,
+        pub open_orders_acc: &'a AccountInfo<'b>,
+        pub dest_acc: &'a AccountInfo<'b>,
+        pub owner: SignerAccount<'a, 'b>,
+    }
+    impl<'a, 'b: 'a> CloseOpenOrdersArgs<'a, 'b> {
+        pub fn with_parsed_args<T>(
+            program_id: &'a Pubkey,
+            accounts: &'a [AccountInfo<'b>],
+            f: impl FnOnce(CloseOpenOrdersArgs) -> DexResult<T>,
+        ) -> DexResult<T> {
+            check_assert_eq!(accounts.len(), 4)?;
+            #[rustfmt::skip]
+            let &[
+                ref open_orders_acc,
+                ref owner_acc,
+                ref dest_acc,
+                ref market_acc,
+            ] = array_ref![accounts, 0, 4];
+            check_assert!(dest_acc.key != open_orders_acc.key)?;
+            let owner = SignerAccount::new(owner_acc)?;
+            // a closed market has had its lamports taken, and once the
+            // runtime has reclaimed it, it's no longer owned by the program
+            let open_orders = if market_acc.lamports() == 0 || market_acc.owner != program_id {
+                load_orders_mut_for_market(
+                    &market_acc.key.to_aligned_bytes(),
+                    open_orders_acc,
+                    Some(owner.inner()),
+                    false,
+                    program_id,
+                    None,
+                )?
+            } else {
+                let market = MarketState::load_allow_disabled(market_acc, program_id)?;
+                market.load_orders_mut(open_orders_acc, Some(owner.inner()), program_id, None)?
+            };
+            let args = CloseOpenOrdersArgs {
+                open_orders,
+                open_orders_acc,
+                dest_acc,
+                owner,
+            };
+            f(args)
+        }
+    }
+
     pub struct SettleFundsArgs<'a, 'b: 'a> {
         pub market: &'a mut MarketState,
         pub open_orders: &'a mut OpenOrders,
@@ -1718,7 +3185,7 @@ pub mod account_parser {
                 ref spl_token_program_acc,
             ], remaining_accounts) = array_refs![accounts, 9; ..;];
             let spl_token_program = SplTokenProgram::new(spl_token_program_acc)?;
-            let mut market = MarketState::load(market_acc, program_id)?;
+            let mut market = MarketState::load_allow_disabled(market_acc, program_id)?;
             let owner = SignerAccount::new(owner_acc).or(check_unreachable!())?;
 
             let coin_vault =
@@ -1739,8 +3206,19 @@ pub mod account_parser {
 
             let vault_signer = VaultSigner::new(vault_signer_acc, &market, program_id)?;
 
-            let mut open_orders =
-                market.load_orders_mut(open_orders_acc, Some(owner.inner()), program_id, None)?;
+            let mut open_orders = market.load_orders_mut_for_trader(
+                open_orders_acc,
+                owner.inner(),
+                program_id,
+                None,
+            )?;
+            // the delegate may settle, but only into the owner's own wallets
+            if open_orders.owner != owner.inner().key.to_aligned_bytes() {
+                for wallet in &[coin_wallet.token_account(), pc_wallet.token_account()] {
+                    check_assert_eq!(&wallet.owner()?, &open_orders.owner)
+                        .map_err(|_| DexErrorCode::WrongSettlementWallet)?;
+                }
+            }
 
             let args = SettleFundsArgs {
                 market: market.deref_mut(),
@@ -1780,6 +3258,176 @@ pub mod account_parser {
         }
     }
 
+    pub struct MarketAuthorityArgs<'a, 'b: 'a> {
+        pub market: &'a mut MarketStateV2,
+        pub authority: SignerAccount<'a, 'b>,
+        pub remaining_accounts: &'a [AccountInfo<'b>],
+    }
+    impl<'a, 'b: 'a> MarketAuthorityArgs<'a, 'b> {
+        pub fn with_parsed_args<T>(
+            program_id: &'a Pubkey,
+            accounts: &'a [AccountInfo<'b>],
+            f: impl FnOnce(MarketAuthorityArgs) -> DexResult<T>,
+        ) -> DexResult<T> {
+            check_assert!(accounts.len() >= 2)?;
+            let (fixed_accounts, remaining_accounts) = accounts.split_at(2);
+            let &[ref market_acc, ref authority_acc] = array_ref![fixed_accounts, 0, 2];
+            let mut market = MarketStateV2::load(market_acc, program_id)?;
+            let authority = SignerAccount::new(authority_acc)?;
+            if market.authority == [0; 4]
+                || market.authority != authority.inner().key.to_aligned_bytes()
+            {
+                Err(DexErrorCode::WrongMarketAuthority)?
+            }
+
+            let args = MarketAuthorityArgs {
+                market: market.deref_mut(),
+                authority,
+                remaining_accounts,
+            };
+            f(args)
+        }
+    }
+
+    pub struct CloseMarketArgs<'a, 'b: 'a> {
+        pub market: &'a mut MarketStateV2,
+        pub req_q: RequestQueue<'a>,
+        pub event_q: EventQueue<'a>,
+        pub bids: &'a Slab,
+        pub asks: &'a Slab,
+        pub authority: SignerAccount<'a, 'b>,
+        pub market_accounts: &'a [AccountInfo<'b>; 5],
+        pub dest_acc: &'a AccountInfo<'b>,
+    }
+    impl<'a, 'b: 'a> CloseMarketArgs<'a, 'b> {
+        pub fn with_parsed_args<T>(
+            program_id: &'a Pubkey,
+            accounts: &'a [AccountInfo<'b>],
+            f: impl FnOnce(CloseMarketArgs) -> DexResult<T>,
+        ) -> DexResult<T> {
+            check_assert_eq!(accounts.len(), 7)?;
+            let market_accounts = array_ref![accounts, 0, 5];
+            let &[ref authority_acc, ref dest_acc] = array_ref![accounts, 5, 2];
+            #[rustfmt::skip]
+            let &[
+                ref market_acc,
+                ref req_q_acc,
+                ref event_q_acc,
+                ref bids_acc,
+                ref asks_acc,
+            ] = market_accounts;
+            for account in market_accounts.iter() {
+                check_assert!(account.key != dest_acc.key)?;
+            }
+
+            let mut market = MarketStateV2::load_disabled(market_acc, program_id)?;
+            let authority = SignerAccount::new(authority_acc)?;
+            // a market created without an authority is closed by the
+            // authority that disabled it
+            let is_authority = if market.authority == [0; 4] {
+                authority.inner().key == &disable_authority::ID
+            } else {
+                market.authority == authority.inner().key.to_aligned_bytes()
+            };
+            if !is_authority {
+                Err(DexErrorCode::WrongMarketAuthority)?
+            }
+            let req_q = market.load_request_queue_mut(req_q_acc)?;
+            let event_q = market.load_event_queue_mut(event_q_acc)?;
+            let bids = market.load_bids_mut(bids_acc)?;
+            let asks = market.load_asks_mut(asks_acc)?;
+
+            let args = CloseMarketArgs {
+                market: market.deref_mut(),
+                req_q,
+                event_q,
+                bids: &bids,
+                asks: &asks,
+                authority,
+                market_accounts,
+                dest_acc,
+            };
+            f(args)
+        }
+    }
+
+    pub struct InitOpenOrdersArgs<'a, 'b: 'a> {
+        pub instruction: &'a InitOpenOrdersInstruction,
+        pub program_id: &'a Pubkey,
+        pub market: &'a MarketState,
+        pub open_orders_acc: &'a AccountInfo<'b>,
+        pub owner: SignerAccount<'a, 'b>,
+        pub system_program: SystemProgram<'a, 'b>,
+        pub rent: Rent,
+    }
+    impl<'a, 'b: 'a> InitOpenOrdersArgs<'a, 'b> {
+        pub fn with_parsed_args<T>(
+            program_id: &'a Pubkey,
+            accounts: &'a [AccountInfo<'b>],
+            instruction: &'a InitOpenOrdersInstruction,
+            f: impl FnOnce(InitOpenOrdersArgs) -> DexResult<T>,
+        ) -> DexResult<T> {
+            check_assert_eq!(accounts.len(), 5)?;
+            #[rustfmt::skip]
+            let &[
+                ref market_acc,
+                ref open_orders_acc,
+                ref owner_acc,
+                ref system_program_acc,
+                ref rent_sysvar_acc,
+            ] = array_ref![accounts, 0, 5];
+            let market = MarketState::load(market_acc, program_id)?;
+            let owner = SignerAccount::new(owner_acc)?;
+            let system_program = SystemProgram::new(system_program_acc)?;
+            let rent = {
+                let rent_sysvar = RentSysvarAccount::new(rent_sysvar_acc)?;
+                Rent::from_account_info(rent_sysvar.inner()).or(check_unreachable!())?
+            };
+            let args = InitOpenOrdersArgs {
+                instruction,
+                program_id,
+                market: &market,
+                open_orders_acc,
+                owner,
+                system_program,
+                rent,
+            };
+            f(args)
+        }
+    }
+
+    pub struct OpenOrdersOwnerArgs<'a, 'b: 'a> {
+        pub open_orders: OpenOrdersMut<'a>,
+        pub owner: SignerAccount<'a, 'b>,
+        pub remaining_accounts: &'a [AccountInfo<'b>],
+    }
+    impl<'a, 'b: 'a> OpenOrdersOwnerArgs<'a, 'b> {
+        pub fn with_parsed_args<T>(
+            program_id: &'a Pubkey,
+            accounts: &'a [AccountInfo<'b>],
+            f: impl FnOnce(OpenOrdersOwnerArgs) -> DexResult<T>,
+        ) -> DexResult<T> {
+            check_assert!(accounts.len() >= 3)?;
+            let (fixed_accounts, remaining_accounts) = accounts.split_at(3);
+            #[rustfmt::skip]
+            let &[
+                ref market_acc,
+                ref open_orders_acc,
+                ref owner_acc,
+            ] = array_ref![fixed_accounts, 0, 3];
+            let market: RefMut<'a, MarketState> = MarketState::load(market_acc, program_id)?;
+            let owner = SignerAccount::new(owner_acc)?;
+            let open_orders =
+                market.load_orders_mut(open_orders_acc, Some(owner.inner()), program_id, None)?;
+            let args = OpenOrdersOwnerArgs {
+                open_orders,
+                owner,
+                remaining_accounts,
+            };
+            f(args)
+        }
+    }
+
     pub struct SweepFeesArgs<'a, 'b: 'a> {
         pub market: &'a mut MarketState,
         pub pc_vault: PcVault<'a, 'b>,
@@ -1805,7 +3453,7 @@ pub mod account_parser {
                 ref spl_token_program
             ] = array_ref![accounts, 0, 6];
 
-            let mut market = MarketState::load(market_acc, program_id)?;
+            let mut market = MarketState::load_allow_disabled(market_acc, program_id)?;
             let pc_vault = PcVault::from_account(pc_vault_acc, &market)?;
             let fee_receiver = PcWallet::from_account(pc_wallet_acc, &market)?;
             let vault_signer = VaultSigner::new(vault_signer_acc, &market, program_id)?;
@@ -1845,22 +3493,38 @@ impl State {
     pub fn process(program_id: &Pubkey, accounts: &[AccountInfo], input: &[u8]) -> DexResult {
         let instruction = MarketInstruction::unpack(input).ok_or(ProgramError::InvalidArgument)?;
         match instruction {
-            MarketInstruction::InitializeMarket(ref inner) => Self::process_initialize_market(
+            MarketInstruction::InitializeMarket(inner) => {
+                let initialize_market_v2 = inner.add_fee_schedule(FeeSchedule::default());
+                Self::process_initialize_market(account_parser::InitializeMarketArgs::new(
+                    program_id,
+                    &initialize_market_v2,
+                    accounts,
+                )?)?
+            }
+            MarketInstruction::InitializeMarketV2(ref inner) => Self::process_initialize_market(
                 account_parser::InitializeMarketArgs::new(program_id, inner, accounts)?,
             )?,
             MarketInstruction::NewOrder(inner) => {
-                let new_order_v2 = inner.add_self_trade_behavior(SelfTradeBehavior::DecrementTake);
+                let new_order_v6 = inner
+                    .add_self_trade_behavior(SelfTradeBehavior::DecrementTake)
+                    .add_expiry(None)
+                    .without_max_native_pc_qty()
+                    .add_display_qty(None);
                 account_parser::NewOrderArgs::with_parsed_args(
                     program_id,
-                    &new_order_v2,
+                    &new_order_v6,
                     accounts,
                     Self::process_new_order,
                 )?
             }
-            MarketInstruction::NewOrderV2(ref inner) => {
+            MarketInstruction::NewOrderV2(inner) => {
+                let new_order_v6 = inner
+                    .add_expiry(None)
+                    .without_max_native_pc_qty()
+                    .add_display_qty(None);
                 account_parser::NewOrderArgs::with_parsed_args(
                     program_id,
-                    inner,
+                    &new_order_v6,
                     accounts,
                     Self::process_new_order,
                 )?
@@ -1871,6 +3535,7 @@ impl State {
                     accounts,
                     limit,
                     Self::process_match_orders,
+                    Self::process_match_orders,
                 )?
             }
             MarketInstruction::ConsumeEvents(limit) => {
@@ -1881,6 +3546,14 @@ impl State {
                     Self::process_consume_events,
                 )?
             }
+            MarketInstruction::ConsumeEventsWithReward(limit) => {
+                account_parser::ConsumeEventsArgs::with_parsed_args_and_reward(
+                    program_id,
+                    accounts,
+                    limit,
+                    Self::process_consume_events,
+                )?
+            }
             MarketInstruction::CancelOrder(ref inner) => {
                 account_parser::CancelOrderArgs::with_parsed_args(
                     program_id,
@@ -1889,31 +3562,165 @@ impl State {
                     Self::process_cancel_order,
                 )?
             }
-            MarketInstruction::SettleFunds => account_parser::SettleFundsArgs::with_parsed_args(
+            MarketInstruction::SettleFunds => account_parser::SettleFundsArgs::with_parsed_args(
+                program_id,
+                accounts,
+                Self::process_settle_funds,
+            )?,
+            MarketInstruction::CancelOrderByClientId(client_id) => {
+                account_parser::CancelOrderByClientIdArgs::with_parsed_args(
+                    program_id,
+                    accounts,
+                    client_id,
+                    Self::process_cancel_order_by_client_id,
+                )?
+            }
+            MarketInstruction::DisableMarket => {
+                account_parser::DisableMarketArgs::with_parsed_args(
+                    program_id,
+                    accounts,
+                    Self::process_disable_market,
+                )?
+            }
+            MarketInstruction::SweepFees => account_parser::SweepFeesArgs::with_parsed_args(
+                program_id,
+                accounts,
+                Self::process_sweep_fees,
+            )?,
+            MarketInstruction::CancelAllOrders(side) => {
+                account_parser::CancelAllOrdersArgs::with_parsed_args(
+                    program_id,
+                    accounts,
+                    side,
+                    Self::process_cancel_all_orders,
+                )?
+            }
+            MarketInstruction::ReplaceOrderByClientId(inner) => {
+                let new_order_v6 = inner
+                    .add_expiry(None)
+                    .without_max_native_pc_qty()
+                    .add_display_qty(None);
+                account_parser::NewOrderArgs::with_parsed_args(
+                    program_id,
+                    &new_order_v6,
+                    accounts,
+                    Self::process_replace_order_by_client_id,
+                )?
+            }
+            MarketInstruction::NewOrderV3(inner) => {
+                let new_order_v6 = inner.without_max_native_pc_qty().add_display_qty(None);
+                account_parser::NewOrderArgs::with_parsed_args(
+                    program_id,
+                    &new_order_v6,
+                    accounts,
+                    Self::process_new_order,
+                )?
+            }
+            MarketInstruction::NewOrderV5(inner) => {
+                let new_order_v6 = inner.add_display_qty(None);
+                account_parser::NewOrderArgs::with_parsed_args(
+                    program_id,
+                    &new_order_v6,
+                    accounts,
+                    Self::process_new_order,
+                )?
+            }
+            MarketInstruction::NewOrderV6(ref inner) => {
+                account_parser::NewOrderArgs::with_parsed_args(
+                    program_id,
+                    inner,
+                    accounts,
+                    Self::process_new_order,
+                )?
+            }
+            MarketInstruction::NewOrderV4(inner) => {
+                Self::process_new_order_and_match(program_id, inner, accounts)?
+            }
+            MarketInstruction::PauseMarket => {
+                account_parser::MarketAuthorityArgs::with_parsed_args(
+                    program_id,
+                    accounts,
+                    Self::process_pause_market,
+                )?
+            }
+            MarketInstruction::ResumeMarket => {
+                account_parser::MarketAuthorityArgs::with_parsed_args(
+                    program_id,
+                    accounts,
+                    Self::process_resume_market,
+                )?
+            }
+            MarketInstruction::SetMarketAuthority => {
+                account_parser::MarketAuthorityArgs::with_parsed_args(
+                    program_id,
+                    accounts,
+                    Self::process_set_market_authority,
+                )?
+            }
+            MarketInstruction::SetPriceBand(ref inner) => {
+                account_parser::MarketAuthorityArgs::with_parsed_args(
+                    program_id,
+                    accounts,
+                    |args| Self::process_set_price_band(inner, args),
+                )?
+            }
+            MarketInstruction::SetCrankFee(crank_fee_lamports) => {
+                account_parser::MarketAuthorityArgs::with_parsed_args(
+                    program_id,
+                    accounts,
+                    |args| Self::process_set_crank_fee(crank_fee_lamports, args),
+                )?
+            }
+            MarketInstruction::InitOpenOrders(ref inner) => {
+                account_parser::InitOpenOrdersArgs::with_parsed_args(
+                    program_id,
+                    accounts,
+                    inner,
+                    Self::process_init_open_orders,
+                )?
+            }
+            MarketInstruction::SetDelegate => {
+                account_parser::OpenOrdersOwnerArgs::with_parsed_args(
+                    program_id,
+                    accounts,
+                    Self::process_set_delegate,
+                )?
+            }
+            MarketInstruction::RevokeDelegate => {
+                account_parser::OpenOrdersOwnerArgs::with_parsed_args(
+                    program_id,
+                    accounts,
+                    Self::process_revoke_delegate,
+                )?
+            }
+            MarketInstruction::CloseMarket => account_parser::CloseMarketArgs::with_parsed_args(
                 program_id,
                 accounts,
-                Self::process_settle_funds,
+                Self::process_close_market,
             )?,
-            MarketInstruction::CancelOrderByClientId(client_id) => {
-                account_parser::CancelOrderByClientIdArgs::with_parsed_args(
+            MarketInstruction::SendTake(ref inner) => {
+                account_parser::SendTakeArgs::with_parsed_args(
                     program_id,
+                    inner,
                     accounts,
-                    client_id,
-                    Self::process_cancel_order_by_client_id,
+                    Self::process_send_take,
                 )?
             }
-            MarketInstruction::DisableMarket => {
-                account_parser::DisableMarketArgs::with_parsed_args(
+            MarketInstruction::CloseOpenOrders => {
+                account_parser::CloseOpenOrdersArgs::with_parsed_args(
                     program_id,
                     accounts,
-                    Self::process_disable_market,
+                    Self::process_close_open_orders,
+                )?
+            }
+            MarketInstruction::PruneExpiredOrders(limit) => {
+                account_parser::PruneExpiredOrdersArgs::with_parsed_args(
+                    program_id,
+                    accounts,
+                    limit,
+                    Self::process_prune_expired_orders,
                 )?
             }
-            MarketInstruction::SweepFees => account_parser::SweepFeesArgs::with_parsed_args(
-                program_id,
-                accounts,
-                Self::process_sweep_fees,
-            )?,
         };
         Ok(())
     }
@@ -2012,18 +3819,14 @@ impl State {
             mut req_q,
             orders_owner: _,
         } = args;
-        let mut slot = None;
-        for (i, ith_client_order_id) in open_orders.client_order_ids.iter().enumerate() {
-            if *ith_client_order_id == client_order_id.get() && !open_orders.slot_is_free(i as u8) {
-                slot = Some(i);
-                break;
-            }
-        }
-        let expected_open_orders_slot = slot.ok_or(DexErrorCode::ClientIdNotFound)? as u8;
+        let expected_open_orders_slot = open_orders
+            .iter_filled_slots()
+            .find(|&slot| open_orders.client_order_id(slot) == client_order_id.get())
+            .ok_or(DexErrorCode::ClientIdNotFound)?;
         let side = open_orders
             .slot_side(expected_open_orders_slot)
             .ok_or(DexErrorCode::ClientIdNotFound)?;
-        let ref order_id = open_orders.orders[expected_open_orders_slot as usize];
+        let ref order_id = open_orders.order_id(expected_open_orders_slot);
         let request = Request::new(RequestView::CancelOrder {
             cancel_id: req_q.gen_seq_num(),
             expected_owner: open_orders_address,
@@ -2061,6 +3864,294 @@ impl State {
         Ok(())
     }
 
+    fn push_cancel_request(
+        req_q: &mut RequestQueue,
+        open_orders: &OpenOrdersMut,
+        open_orders_address: &[u64; 4],
+        slot: u8,
+    ) -> DexResult {
+        let side = open_orders.slot_side(slot).ok_or(assertion_error!())?;
+        let request = Request::new(RequestView::CancelOrder {
+            cancel_id: req_q.gen_seq_num(),
+            expected_owner: open_orders_address,
+            expected_owner_slot: slot,
+            order_id: &open_orders.order_id(slot),
+            side,
+            client_order_id: NonZeroU64::new(open_orders.client_order_id(slot)),
+        });
+        req_q
+            .push_back(request)
+            .map_err(|_| DexErrorCode::RequestQueueFull)?;
+        Ok(())
+    }
+
+    fn process_cancel_all_orders(args: account_parser::CancelAllOrdersArgs) -> DexResult {
+        let account_parser::CancelAllOrdersArgs {
+            side,
+            open_orders,
+            open_orders_address,
+            mut req_q,
+            orders_owner: _,
+        } = args;
+
+        for slot in open_orders.iter_filled_slots() {
+            if side.map_or(true, |side| open_orders.slot_side(slot) == Some(side)) {
+                Self::push_cancel_request(&mut req_q, &open_orders, open_orders_address, slot)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "program")]
+    fn process_replace_order_by_client_id(args: account_parser::NewOrderArgs) -> DexResult {
+        let mut args = args;
+        let client_order_id =
+            NonZeroU64::new(args.instruction.client_id).ok_or(DexErrorCode::ClientOrderIdIsZero)?;
+        if args.instruction.self_trade_behavior == SelfTradeBehavior::AbortTransaction {
+            Err(DexErrorCode::AbortOrderNotMatched)?
+        }
+
+        let slot = args
+            .open_orders
+            .iter_filled_slots()
+            .find(|&slot| args.open_orders.client_order_id(slot) == client_order_id.get())
+            .ok_or(DexErrorCode::ClientIdNotFound)?;
+        Self::push_cancel_request(
+            &mut args.req_q,
+            &args.open_orders,
+            args.open_orders_address,
+            slot,
+        )?;
+
+        // The replacement takes over the slot right away, so it neither needs
+        // a free one nor leaves the client id on two orders. The cancel is
+        // queued first, so the replaced order's last event is consumed before
+        // any of the replacement's.
+        Self::queue_new_order_in_slot(args, Some(slot))
+    }
+
+    #[cfg(feature = "program")]
+    fn process_new_order_and_match(
+        program_id: &Pubkey,
+        instruction: NewOrderInstructionV4,
+        accounts: &[AccountInfo],
+    ) -> DexResult {
+        check_assert!(accounts.len() == 13 || accounts.len() == 14)?;
+        let (new_order_accounts, match_accounts, fee_discount_account) =
+            array_refs![accounts, 9, 4; .. ;];
+        let &[ref market_acc, ref open_orders_acc, ref req_q_acc, _, _, _, _, _, _] =
+            new_order_accounts;
+        let &[ref event_q_acc, ref bids_acc, ref asks_acc, ref clock_sysvar_acc] = match_accounts;
+        let new_order_accounts: Vec<AccountInfo> = new_order_accounts
+            .iter()
+            .chain(fee_discount_account)
+            .cloned()
+            .collect();
+
+        let (new_order_v3, limit) = instruction.remove_match_limit();
+        let new_order_v6 = new_order_v3
+            .without_max_native_pc_qty()
+            .add_display_qty(None);
+        account_parser::NewOrderArgs::with_parsed_args(
+            program_id,
+            &new_order_v6,
+            &new_order_accounts,
+            Self::queue_new_order,
+        )?;
+        account_parser::MatchOrdersArgs::with_accounts(
+            program_id,
+            market_acc,
+            req_q_acc,
+            event_q_acc,
+            bids_acc,
+            asks_acc,
+            Some(clock_sysvar_acc),
+            None,
+            limit,
+            |args| {
+                let account_parser::MatchOrdersArgs {
+                    mut order_book_state,
+                    mut req_q,
+                    mut event_q,
+                    limit,
+                    ..
+                } = args;
+                let requests_pending = req_q.len();
+                order_book_state.process_requests(&mut req_q, &mut event_q, limit)?;
+                // the new order is the last request, so anything left over
+                // in the queue would include it
+                if !req_q.empty() {
+                    match new_order_v6.self_trade_behavior {
+                        SelfTradeBehavior::AbortTransaction => {
+                            Err(DexErrorCode::AbortOrderNotMatched)?
+                        }
+                        _ => Err(DexErrorCode::NewOrderNotMatched)?,
+                    }
+                }
+                // the matching reward goes back to the OpenOrders account the
+                // crank fee was taken from
+                pay_match_reward(
+                    order_book_state.market_state,
+                    market_acc,
+                    Some(open_orders_acc),
+                    requests_pending,
+                    0,
+                )
+            },
+        )
+    }
+
+    #[cfg(feature = "program")]
+    fn process_send_take(args: account_parser::SendTakeArgs) -> DexResult {
+        let account_parser::SendTakeArgs {
+            instruction,
+            market_acc,
+            mut order_book_state,
+            mut req_q,
+            mut event_q,
+            coin_wallet,
+            pc_wallet,
+            owner,
+            coin_vault,
+            pc_vault,
+            vault_signer,
+            spl_token_program,
+            system_program,
+            fee_tier,
+        } = args;
+        let &SendTakeInstruction {
+            side,
+            limit_price,
+            max_qty,
+            limit,
+        } = instruction;
+
+        let market = &mut *order_book_state.market_state;
+        // The owner pays the crank fee in lamports, since there's no
+        // OpenOrders account to take it out of. All of it goes to consuming
+        // the maker events, as the take itself never enters the request queue.
+        let crank_fee = market.crank_fee_lamports;
+        if crank_fee > 0 {
+            let transfer_ix =
+                system_instruction::transfer(owner.inner().key, market_acc.key, crank_fee);
+            invoke_system_program(
+                &transfer_ix,
+                &[
+                    owner.inner().clone(),
+                    market_acc.clone(),
+                    system_program.inner().clone(),
+                ],
+                &[],
+            )?;
+            market.consume_rewards_accrued += crank_fee;
+        }
+
+        let native_qty_locked;
+        let native_pc_qty_locked;
+        // The whole amount is counted as deposited while matching so that
+        // the taker fees can be taken out of it; the unused part is released
+        // again below.
+        match side {
+            Side::Bid => {
+                let native_lock_qty_before_fee = max_qty
+                    .get()
+                    .checked_mul(limit_price.get())
+                    .and_then(|lots| lots.checked_mul(market.pc_lot_size))
+                    .ok_or(DexErrorCode::InsufficientFunds)?;
+                native_qty_locked = native_lock_qty_before_fee
+                    .checked_add(
+                        market
+                            .fee_schedule
+                            .taker_fee(fee_tier, native_lock_qty_before_fee),
+                    )
+                    .ok_or(DexErrorCode::InsufficientFunds)?;
+                native_pc_qty_locked = NonZeroU64::new(native_qty_locked);
+                market.pc_deposits_total = market
+                    .pc_deposits_total
+                    .checked_add(native_qty_locked)
+                    .unwrap();
+            }
+            Side::Ask => {
+                native_qty_locked = max_qty
+                    .get()
+                    .checked_mul(market.coin_lot_size)
+                    .ok_or(DexErrorCode::InsufficientFunds)?;
+                native_pc_qty_locked = None;
+                market.coin_deposits_total = market
+                    .coin_deposits_total
+                    .checked_add(native_qty_locked)
+                    .unwrap();
+            }
+        };
+
+        let order_id = req_q.gen_order_id(limit_price.get(), side);
+        let owner_key = owner.inner().key.to_aligned_bytes();
+        let requests_pending = req_q.len();
+        let result = order_book_state.send_take(
+            SendTakeParams {
+                side,
+                order_id: &order_id,
+                owner: &owner_key,
+                fee_tier,
+                max_coin_qty: max_qty,
+                native_pc_qty_locked,
+            },
+            &mut req_q,
+            &mut event_q,
+            limit,
+        )?;
+
+        let market = order_book_state.market_state;
+        // The queue is drained by now, so the taker has done all the
+        // matching that was outstanding.
+        pay_match_reward(market, market_acc, Some(owner.inner()), requests_pending, 0)?;
+        let native_qty_unused = native_qty_locked - result.native_qty_paid;
+        let (payer, deposit_vault, recipient, withdrawal_vault) = match side {
+            Side::Bid => {
+                market.pc_deposits_total -= native_qty_unused;
+                market.coin_deposits_total -= result.native_qty_received;
+                (
+                    pc_wallet.token_account(),
+                    pc_vault.token_account(),
+                    coin_wallet.token_account(),
+                    coin_vault.token_account(),
+                )
+            }
+            Side::Ask => {
+                market.coin_deposits_total -= native_qty_unused;
+                market.pc_deposits_total -= result.native_qty_received;
+                (
+                    coin_wallet.token_account(),
+                    coin_vault.token_account(),
+                    pc_wallet.token_account(),
+                    pc_vault.token_account(),
+                )
+            }
+        };
+        // Without an OpenOrders account there is nowhere to credit a
+        // referrer, so the rebate is kept as a fee.
+        market.referrer_rebates_accrued -= result.native_referrer_rebate;
+        market.pc_fees_accrued += result.native_referrer_rebate;
+
+        deposit_to_vault(
+            result.native_qty_paid,
+            payer,
+            deposit_vault,
+            owner,
+            spl_token_program,
+        )?;
+        let market_pubkey = market.pubkey();
+        let vault_signer_seeds = gen_vault_signer_seeds(&market.vault_signer_nonce, &market_pubkey);
+        send_from_vault(
+            result.native_qty_received,
+            recipient,
+            withdrawal_vault,
+            spl_token_program,
+            vault_signer,
+            &vault_signer_seeds,
+        )
+    }
+
     fn process_consume_events(args: account_parser::ConsumeEventsArgs) -> DexResult {
         let account_parser::ConsumeEventsArgs {
             limit,
@@ -2068,31 +4159,42 @@ impl State {
             open_orders_accounts,
             market,
             mut event_q,
+            market_acc,
+            crank_reward_recipient,
         } = args;
 
+        let mut events_processed = 0;
         for _i in 0u16..limit {
             let event = match event_q.peek_front() {
                 None => break,
                 Some(e) => e,
             };
+            if event.version() != event_q.event_version() {
+                Err(DexErrorCode::UnsupportedEventVersion)?
+            }
 
             let view = event.as_view()?;
             let owner: [u64; 4] = event.owner;
             let owner_index: Result<usize, usize> = open_orders_accounts
                 .binary_search_by_key(&owner, |account_info| account_info.key.to_aligned_bytes());
-            let mut open_orders: RefMut<OpenOrders> = match owner_index {
+            let mut open_orders: OpenOrdersMut = match owner_index {
                 Err(_) => break,
                 Ok(i) => {
                     market.load_orders_mut(&open_orders_accounts[i], None, program_id, None)?
                 }
             };
 
-            check_assert!(event.owner_slot < 128)?;
-            check_assert_eq!(&open_orders.slot_side(event.owner_slot), &Some(view.side()))?;
-            check_assert_eq!(
-                &open_orders.orders[event.owner_slot as usize],
-                &event.order_id
-            )?;
+            check_assert!((event.owner_slot as usize) < open_orders.capacity())?;
+            // An order replaced by client id has handed its slot over to the
+            // replacement by the time its last events are consumed. Those
+            // only settle funds and leave the slot alone.
+            let replaced = open_orders.order_id(event.owner_slot) != event.order_id
+                && event.client_order_id != 0
+                && open_orders.client_order_id(event.owner_slot) == event.client_order_id;
+            if !replaced {
+                check_assert_eq!(&open_orders.slot_side(event.owner_slot), &Some(view.side()))?;
+                check_assert_eq!(&open_orders.order_id(event.owner_slot), &event.order_id)?;
+            }
 
             match event.as_view()? {
                 EventView::Fill {
@@ -2106,6 +4208,7 @@ impl State {
                     owner: _,
                     owner_slot,
                     client_order_id,
+                    price: _,
                 } => {
                     match side {
                         Side::Bid => {
@@ -2124,14 +4227,12 @@ impl State {
                         }
                     };
                     if !maker {
-                        let referrer_rebate = fees::referrer_rebate(native_fee_or_rebate);
+                        let referrer_rebate =
+                            market.fee_schedule.referrer_rebate(native_fee_or_rebate);
                         open_orders.referrer_rebates_accrued += referrer_rebate;
                     }
                     if let Some(client_id) = client_order_id {
-                        debug_assert_eq!(
-                            client_id.get(),
-                            open_orders.client_order_ids[owner_slot as usize]
-                        );
+                        debug_assert_eq!(client_id.get(), open_orders.client_order_id(owner_slot));
                     }
                 }
                 EventView::Out {
@@ -2160,40 +4261,99 @@ impl State {
                         }
                     };
                     if let Some(client_id) = client_order_id {
-                        debug_assert_eq!(
-                            client_id.get(),
-                            open_orders.client_order_ids[owner_slot as usize]
-                        );
+                        debug_assert_eq!(client_id.get(), open_orders.client_order_id(owner_slot));
                     }
-                    if fully_out {
+                    if fully_out && !replaced {
                         open_orders.remove_order(owner_slot)?;
                     }
                 }
+                EventView::Repost {
+                    side: _,
+                    order_id: _,
+                    new_order_id,
+                    owner: _,
+                    owner_slot,
+                    client_order_id: _,
+                } => {
+                    if !replaced {
+                        open_orders.set_order_id(owner_slot, new_order_id);
+                    }
+                }
             };
 
             event_q
                 .pop_front()
                 .map_err(|()| DexErrorCode::ConsumeEventsQueueFailure)?;
+            events_processed += 1;
         }
-        Ok(())
+        pay_crank_reward(
+            &mut market.consume_rewards_accrued,
+            market_acc,
+            crank_reward_recipient,
+            events_processed,
+            event_q.len(),
+        )
     }
 
-    fn process_match_orders(args: account_parser::MatchOrdersArgs) -> DexResult {
+    fn process_match_orders<X: NodeTail>(
+        args: account_parser::MatchOrdersArgs<'_, '_, X>,
+    ) -> DexResult {
         let account_parser::MatchOrdersArgs {
             mut order_book_state,
             mut req_q,
             mut event_q,
             limit,
+            market_acc,
+            crank_reward_recipient,
+        } = args;
+        let requests_pending = req_q.len();
+        order_book_state.process_requests(&mut req_q, &mut event_q, limit)?;
+        pay_match_reward(
+            order_book_state.market_state,
+            market_acc,
+            crank_reward_recipient,
+            requests_pending - req_q.len(),
+            req_q.len(),
+        )
+    }
+
+    fn process_prune_expired_orders(args: account_parser::PruneExpiredOrdersArgs) -> DexResult {
+        let account_parser::PruneExpiredOrdersArgs {
+            mut order_book_state,
+            mut event_q,
+            limit,
         } = args;
-        order_book_state.process_requests(&mut req_q, &mut event_q, limit)
+        order_book_state.prune_expired_orders(&mut event_q, limit)
     }
 
     #[cfg(feature = "program")]
     fn process_new_order(args: account_parser::NewOrderArgs) -> DexResult {
+        // An order queued here is matched by a later crank, which an order
+        // that aborts on a self trade would make fail.
+        if args.instruction.self_trade_behavior == SelfTradeBehavior::AbortTransaction {
+            Err(DexErrorCode::AbortOrderNotMatched)?
+        }
+        Self::queue_new_order(args)
+    }
+
+    #[cfg(feature = "program")]
+    fn queue_new_order(args: account_parser::NewOrderArgs) -> DexResult {
+        Self::queue_new_order_in_slot(args, None)
+    }
+
+    /// Queues a new order, recording it in `replaced_slot` if given, or else
+    /// in a free slot.
+    #[cfg(feature = "program")]
+    fn queue_new_order_in_slot(
+        args: account_parser::NewOrderArgs,
+        replaced_slot: Option<u8>,
+    ) -> DexResult {
         let account_parser::NewOrderArgs {
             instruction,
             market,
-            open_orders,
+            market_acc,
+            mut open_orders,
+            open_orders_acc,
             open_orders_address,
             mut req_q,
             payer,
@@ -2202,25 +4362,41 @@ impl State {
             pc_vault,
             spl_token_program,
             fee_tier,
+            rent,
         } = args;
 
+        collect_crank_fee(
+            market,
+            market_acc,
+            open_orders_acc,
+            open_orders.data_len(),
+            &rent,
+        )?;
+
         let deposit_amount;
         let deposit_vault;
 
         let native_pc_qty_locked;
         match instruction.side {
             Side::Bid => {
-                let lock_qty_lots = instruction
+                // An order too large to price in a u64 is still bounded by
+                // its price currency budget.
+                let max_native_pc_qty = instruction.max_native_pc_qty_including_fees.get();
+                let lock_qty_native = instruction
                     .max_qty
                     .get()
                     .checked_mul(instruction.limit_price.get())
-                    .ok_or(DexErrorCode::InsufficientFunds)?;
-                let native_lock_qty_before_fee = lock_qty_lots
-                    .checked_mul(market.pc_lot_size)
-                    .ok_or(DexErrorCode::InsufficientFunds)?;
-                let lock_qty_native = native_lock_qty_before_fee
-                    .checked_add(fee_tier.taker_fee(native_lock_qty_before_fee))
-                    .ok_or(DexErrorCode::InsufficientFunds)?;
+                    .and_then(|lock_qty_lots| lock_qty_lots.checked_mul(market.pc_lot_size))
+                    .and_then(|native_lock_qty_before_fee| {
+                        native_lock_qty_before_fee.checked_add(
+                            market
+                                .fee_schedule
+                                .taker_fee(fee_tier, native_lock_qty_before_fee),
+                        )
+                    })
+                    .map_or(max_native_pc_qty, |lock_qty_native| {
+                        lock_qty_native.min(max_native_pc_qty)
+                    });
                 native_pc_qty_locked = Some(NonZeroU64::new(lock_qty_native).unwrap());
                 let free_qty_to_lock = lock_qty_native.min(open_orders.native_pc_free);
                 deposit_amount = lock_qty_native - free_qty_to_lock;
@@ -2251,41 +4427,24 @@ impl State {
             }
         };
 
-        // pull balances from payer, signed by owner
-        let deposit_instruction = spl_token::instruction::transfer(
-            &spl_token::ID,
-            payer.inner().key,
-            deposit_vault.inner().key,
-            owner.inner().key,
-            &[],
+        deposit_to_vault(
             deposit_amount,
-        )
-        .unwrap();
-        assert_eq!(*spl_token_program.inner().key, spl_token::ID);
-
-        // pull the deposited funds
-        invoke_spl_token(
-            &deposit_instruction,
-            &[
-                payer.inner().clone(),
-                deposit_vault.inner().clone(),
-                owner.inner().clone(),
-                spl_token_program.inner().clone(),
-            ],
-            &[],
-        )
-        .map_err(|err| match err {
-            ProgramError::Custom(i) => match TokenError::from_u32(i) {
-                Some(TokenError::InsufficientFunds) => DexErrorCode::InsufficientFunds,
-                _ => DexErrorCode::TransferFailed,
-            },
-            _ => DexErrorCode::TransferFailed,
-        })?;
+            payer,
+            deposit_vault,
+            owner,
+            spl_token_program,
+        )?;
 
         // record the open order in the user account
         let order_id = req_q.gen_order_id(instruction.limit_price.get(), instruction.side);
-        let owner_slot = open_orders.add_order(order_id, instruction.side)?;
-        open_orders.client_order_ids[owner_slot as usize] = instruction.client_id;
+        let owner_slot = match replaced_slot {
+            Some(slot) => {
+                open_orders.replace_order(slot, order_id, instruction.side)?;
+                slot
+            }
+            None => open_orders.add_order(order_id, instruction.side)?,
+        };
+        open_orders.set_client_order_id(owner_slot, instruction.client_id);
 
         // add the request to the queue
         let request = Request::new(RequestView::NewOrder {
@@ -2299,6 +4458,8 @@ impl State {
             max_coin_qty: instruction.max_qty,
             native_pc_qty_locked,
             client_order_id: NonZeroU64::new(instruction.client_id),
+            expiry: instruction.expiry,
+            display_qty: instruction.display_qty,
         });
 
         req_q
@@ -2307,6 +4468,71 @@ impl State {
         Ok(())
     }
 
+    fn process_close_open_orders(args: account_parser::CloseOpenOrdersArgs) -> DexResult {
+        let account_parser::CloseOpenOrdersArgs {
+            mut open_orders,
+            open_orders_acc,
+            dest_acc,
+            owner: _,
+        } = args;
+
+        if open_orders.iter_filled_slots().next().is_some()
+            || open_orders.native_coin_total != 0
+            || open_orders.native_pc_total != 0
+            || open_orders.referrer_rebates_accrued != 0
+        {
+            Err(DexErrorCode::OpenOrdersNotEmpty)?
+        }
+        open_orders.account_flags = 0;
+
+        let mut dest_lamports = dest_acc.lamports.borrow_mut();
+        let mut open_orders_lamports = open_orders_acc.lamports.borrow_mut();
+        **dest_lamports = dest_lamports
+            .checked_add(**open_orders_lamports)
+            .ok_or(ProgramError::InvalidArgument)?;
+        **open_orders_lamports = 0;
+        Ok(())
+    }
+
+    fn process_close_market(args: account_parser::CloseMarketArgs) -> DexResult {
+        let account_parser::CloseMarketArgs {
+            market,
+            req_q,
+            event_q,
+            bids,
+            asks,
+            authority: _,
+            market_accounts,
+            dest_acc,
+        } = args;
+
+        if market.coin_deposits_total != 0
+            || market.pc_deposits_total != 0
+            || market.coin_fees_accrued != 0
+            || market.pc_fees_accrued != 0
+            || market.referrer_rebates_accrued != 0
+            || market.match_rewards_accrued != 0
+            || market.consume_rewards_accrued != 0
+            || !req_q.empty()
+            || !event_q.empty()
+            || bids.find_min().is_some()
+            || asks.find_min().is_some()
+        {
+            Err(DexErrorCode::MarketNotEmpty)?
+        }
+        market.account_flags = 0;
+
+        let mut dest_lamports = dest_acc.lamports.borrow_mut();
+        for account in market_accounts.iter() {
+            let mut account_lamports = account.lamports.borrow_mut();
+            **dest_lamports = dest_lamports
+                .checked_add(**account_lamports)
+                .ok_or(ProgramError::InvalidArgument)?;
+            **account_lamports = 0;
+        }
+        Ok(())
+    }
+
     fn process_disable_market(args: account_parser::DisableMarketArgs) -> DexResult {
         let account_parser::DisableMarketArgs {
             market,
@@ -2316,6 +4542,157 @@ impl State {
         Ok(())
     }
 
+    fn process_pause_market(args: account_parser::MarketAuthorityArgs) -> DexResult {
+        let account_parser::MarketAuthorityArgs {
+            market,
+            authority: _,
+            remaining_accounts,
+        } = args;
+        check_assert!(remaining_accounts.is_empty())?;
+        market.account_flags = market.account_flags | (AccountFlag::Paused as u64);
+        Ok(())
+    }
+
+    fn process_resume_market(args: account_parser::MarketAuthorityArgs) -> DexResult {
+        let account_parser::MarketAuthorityArgs {
+            market,
+            authority: _,
+            remaining_accounts,
+        } = args;
+        check_assert!(remaining_accounts.is_empty())?;
+        market.account_flags = market.account_flags & !(AccountFlag::Paused as u64);
+        Ok(())
+    }
+
+    fn process_set_market_authority(args: account_parser::MarketAuthorityArgs) -> DexResult {
+        let account_parser::MarketAuthorityArgs {
+            market,
+            authority: _,
+            remaining_accounts,
+        } = args;
+        check_assert_eq!(remaining_accounts.len(), 1)?;
+        market.authority = remaining_accounts[0].key.to_aligned_bytes();
+        Ok(())
+    }
+
+    fn process_set_price_band(
+        instruction: &PriceBandInstruction,
+        args: account_parser::MarketAuthorityArgs,
+    ) -> DexResult {
+        let account_parser::MarketAuthorityArgs {
+            market,
+            authority: _,
+            remaining_accounts,
+        } = args;
+        check_assert!(remaining_accounts.is_empty())?;
+        // a zero band with halting would halt on every trade, and from
+        // 100% the lower bound drops to zero
+        if (instruction.max_deviation_bps == 0 && instruction.halt_slots != 0)
+            || instruction.max_deviation_bps >= 10_000
+        {
+            Err(DexErrorCode::InvalidPriceBand)?
+        }
+        market.price_band_bps = instruction.max_deviation_bps;
+        market.price_band_halt_slots = instruction.halt_slots;
+        Ok(())
+    }
+
+    #[cfg(feature = "program")]
+    fn process_init_open_orders(args: account_parser::InitOpenOrdersArgs) -> DexResult {
+        let account_parser::InitOpenOrdersArgs {
+            instruction,
+            program_id,
+            market,
+            open_orders_acc,
+            owner,
+            system_program,
+            rent,
+        } = args;
+
+        let market_pubkey = market.pubkey();
+        let owner_pubkey = owner.inner().key;
+        let index = instruction.index;
+        let (open_orders_key, nonce) =
+            find_open_orders_key(&market_pubkey, owner_pubkey, index, program_id)?;
+        check_assert_eq!(open_orders_acc.key, &open_orders_key)
+            .map_err(|_| DexErrorCode::WrongOrdersAccount)?;
+
+        let space = OpenOrders::account_size(instruction.capacity as usize)
+            .ok_or(DexErrorCode::InvalidOpenOrdersCapacity)?;
+        let open_orders_seeds = gen_open_orders_seeds(&market_pubkey, owner_pubkey, &index, &nonce);
+        let cpi_accounts = [
+            owner.inner().clone(),
+            open_orders_acc.clone(),
+            system_program.inner().clone(),
+        ];
+        let required_lamports = rent.minimum_balance(space);
+        let current_lamports = open_orders_acc.lamports();
+        if current_lamports == 0 {
+            let create_instruction = system_instruction::create_account(
+                owner_pubkey,
+                open_orders_acc.key,
+                required_lamports,
+                space as u64,
+                program_id,
+            );
+            invoke_system_program(&create_instruction, &cpi_accounts, &[&open_orders_seeds])?;
+        } else {
+            // anyone can send lamports to the address before it is created,
+            // which makes create_account fail, so top up, allocate and
+            // assign it instead
+            if current_lamports < required_lamports {
+                let transfer_instruction = system_instruction::transfer(
+                    owner_pubkey,
+                    open_orders_acc.key,
+                    required_lamports - current_lamports,
+                );
+                invoke_system_program(&transfer_instruction, &cpi_accounts, &[])?;
+            }
+            let allocate_instruction =
+                system_instruction::allocate(open_orders_acc.key, space as u64);
+            invoke_system_program(&allocate_instruction, &cpi_accounts, &[&open_orders_seeds])?;
+            let assign_instruction = system_instruction::assign(open_orders_acc.key, program_id);
+            invoke_system_program(&assign_instruction, &cpi_accounts, &[&open_orders_seeds])?;
+        }
+
+        market.load_orders_mut(open_orders_acc, Some(owner.inner()), program_id, Some(rent))?;
+        Ok(())
+    }
+
+    fn process_set_delegate(args: account_parser::OpenOrdersOwnerArgs) -> DexResult {
+        let account_parser::OpenOrdersOwnerArgs {
+            mut open_orders,
+            owner: _,
+            remaining_accounts,
+        } = args;
+        check_assert_eq!(remaining_accounts.len(), 1)?;
+        open_orders.set_delegate(&remaining_accounts[0].key.to_aligned_bytes())
+    }
+
+    fn process_revoke_delegate(args: account_parser::OpenOrdersOwnerArgs) -> DexResult {
+        let account_parser::OpenOrdersOwnerArgs {
+            mut open_orders,
+            owner: _,
+            remaining_accounts,
+        } = args;
+        check_assert!(remaining_accounts.is_empty())?;
+        open_orders.set_delegate(&[0; 4])
+    }
+
+    fn process_set_crank_fee(
+        crank_fee_lamports: u64,
+        args: account_parser::MarketAuthorityArgs,
+    ) -> DexResult {
+        let account_parser::MarketAuthorityArgs {
+            market,
+            authority: _,
+            remaining_accounts,
+        } = args;
+        check_assert!(remaining_accounts.is_empty())?;
+        market.crank_fee_lamports = crank_fee_lamports;
+        Ok(())
+    }
+
     #[cfg(feature = "program")]
     fn process_sweep_fees(args: account_parser::SweepFeesArgs) -> DexResult {
         let account_parser::SweepFeesArgs {
@@ -2342,13 +4719,15 @@ impl State {
     }
 
     fn process_initialize_market(args: account_parser::InitializeMarketArgs) -> DexResult {
-        let &InitializeMarketInstruction {
+        let &InitializeMarketInstructionV2 {
             coin_lot_size,
             pc_lot_size,
             fee_rate_bps,
             vault_signer_nonce,
             pc_dust_threshold,
+            fee_schedule,
         } = args.instruction;
+        fee_schedule.validate()?;
 
         let market = args.get_market();
         let req_q = args.get_req_q();
@@ -2406,16 +4785,16 @@ impl State {
             *ob_hdr = OrderBookStateHeader {
                 account_flags: (AccountFlag::Initialized | *flag).bits(),
             };
-            let slab = Slab::new(cast_slice_mut(slab_words));
+            let slab: &mut Slab = Slab::new(cast_slice_mut(slab_words));
             slab.assert_minimum_capacity(100)?;
         }
         info!("Initializing market...");
         // initialize market
         let mut market_data = market.try_borrow_mut_data()?;
         let market_view = init_account_padding(&mut market_data)?;
-        let market_hdr: &mut MarketState =
+        let market_hdr: &mut MarketStateV2 =
             try_from_bytes_mut(cast_slice_mut(market_view)).or(check_unreachable!())?;
-        *market_hdr = MarketState {
+        let inner = MarketState {
             coin_lot_size,
             pc_lot_size,
             own_address: market.key.to_aligned_bytes(),
@@ -2441,6 +4820,25 @@ impl State {
             fee_rate_bps: fee_rate_bps as u64,
             referrer_rebates_accrued: 0,
         };
+        *market_hdr = MarketStateV2 {
+            inner,
+            fee_schedule,
+            fee_schedule_padding: [0; 2],
+            authority: args
+                .authority
+                .map(|authority| authority.key.to_aligned_bytes())
+                .unwrap_or([0; 4]),
+            last_trade_price: 0,
+            price_band_bps: 0,
+            price_band_halt_slots: 0,
+            halted_until_slot: 0,
+            coin_volume_total: 0,
+            pc_volume_total: 0,
+            fill_count: 0,
+            crank_fee_lamports: 0,
+            match_rewards_accrued: 0,
+            consume_rewards_accrued: 0,
+        };
         Ok(())
     }
 }
@@ -5,7 +5,7 @@ use bumpalo::{collections::Vec as BumpVec, vec as bump_vec, Bump};
 use rand::prelude::*;
 use safe_transmute::to_bytes::{transmute_to_bytes, transmute_to_bytes_mut};
 use solana_sdk::bpf_loader;
-use solana_sdk::clock::Epoch;
+use solana_sdk::clock::{Clock, Epoch};
 use solana_sdk::rent::Rent;
 use solana_sdk::system_program;
 use solana_sdk::sysvar;
@@ -13,10 +13,19 @@ use solana_sdk::sysvar::Sysvar;
 use spl_token::pack::Pack;
 use spl_token::state::{Account, AccountState, Mint};
 
-use instruction::{initialize_market, MarketInstruction, NewOrderInstructionV1};
+use error::{DexErrorCode, DexResult};
+use fees::FeeSchedule;
+use instruction::{
+    disable_authority, fee_sweeper, initialize_market, CancelOrderInstruction, MarketInstruction,
+    NewOrderInstructionV1, NewOrderInstructionV2, NewOrderInstructionV3, NewOrderInstructionV4,
+    NewOrderInstructionV5, NewOrderInstructionV6, OrderExpiry, PriceBandInstruction,
+    SelfTradeBehavior,
+};
 use matching::{OrderType, Side};
 use state::gen_vault_signer_key;
-use state::{MarketState, OpenOrders, State, ToAlignedBytes};
+use state::{
+    ExtraOrderSlots, MarketState, MarketStateV2, OpenOrders, OpenOrdersV2, State, ToAlignedBytes,
+};
 
 use super::*;
 
@@ -35,6 +44,8 @@ struct MarketAccounts<'bump> {
     coin_mint: AccountInfo<'bump>,
     pc_mint: AccountInfo<'bump>,
     rent_sysvar: AccountInfo<'bump>,
+    clock_sysvar: AccountInfo<'bump>,
+    authority: AccountInfo<'bump>,
 }
 
 fn allocate_dex_owned_account(unpadded_size: usize, bump: &Bump) -> &mut [u8] {
@@ -69,6 +80,22 @@ fn new_rent_sysvar_account<'bump>(
     account_info
 }
 
+fn new_clock_sysvar_account<'bump>(clock: Clock, bump: &'bump Bump) -> AccountInfo<'bump> {
+    let data = bump_vec![in bump; 0u8; size_of::<Clock>()].into_bump_slice_mut();
+    let mut account_info = AccountInfo::new(
+        &sysvar::clock::ID,
+        false,
+        false,
+        bump.alloc(0),
+        data,
+        &sysvar::ID,
+        false,
+        Epoch::default(),
+    );
+    clock.to_account_info(&mut account_info).unwrap();
+    account_info
+}
+
 fn new_sol_account<'bump, Gen: Rng>(
     rng: &mut Gen,
     lamports: u64,
@@ -145,6 +172,18 @@ fn new_token_account<'bump, Gen: Rng>(
     )
 }
 
+// a wallet holding enough to cover every order a test places
+fn new_funded_token_account<'bump, Gen: Rng>(
+    rng: &mut Gen,
+    mint_pubkey: &'bump Pubkey,
+    owner_pubkey: &'bump Pubkey,
+    bump: &'bump Bump,
+) -> AccountInfo<'bump> {
+    let account = new_token_account(rng, mint_pubkey, owner_pubkey, bump);
+    set_token_balance(&account, 1_000_000_000_000);
+    account
+}
+
 fn new_spl_token_program<'bump>(bump: &'bump Bump) -> AccountInfo<'bump> {
     AccountInfo::new(
         &spl_token::ID,
@@ -158,9 +197,30 @@ fn new_spl_token_program<'bump>(bump: &'bump Bump) -> AccountInfo<'bump> {
     )
 }
 
+fn new_system_program(bump: &Bump) -> AccountInfo<'_> {
+    AccountInfo::new(
+        &system_program::ID,
+        false,
+        false,
+        bump.alloc(0),
+        &mut [],
+        &bpf_loader::ID,
+        false,
+        Epoch::default(),
+    )
+}
+
 fn setup_market<'bump, R: Rng>(rng: &mut R, bump: &'bump Bump) -> MarketAccounts<'bump> {
+    setup_market_with_fee_schedule(rng, bump, FeeSchedule::default()).unwrap()
+}
+
+fn setup_market_with_fee_schedule<'bump, R: Rng>(
+    rng: &mut R,
+    bump: &'bump Bump,
+    fee_schedule: FeeSchedule,
+) -> DexResult<MarketAccounts<'bump>> {
     let program_id = random_pubkey(rng, bump);
-    let market = new_dex_owned_account(rng, size_of::<MarketState>(), program_id, bump);
+    let market = new_dex_owned_account(rng, size_of::<MarketStateV2>(), program_id, bump);
     let bids = new_dex_owned_account(rng, 1 << 23, program_id, bump);
     let asks = new_dex_owned_account(rng, 1 << 23, program_id, bump);
     let req_q = new_dex_owned_account(rng, 640, program_id, bump);
@@ -170,6 +230,8 @@ fn setup_market<'bump, R: Rng>(rng: &mut R, bump: &'bump Bump) -> MarketAccounts
     let pc_mint = new_token_mint(rng, bump);
 
     let rent_sysvar = new_rent_sysvar_account(100000, Rent::default(), bump);
+    let clock_sysvar = new_clock_sysvar_account(Clock::default(), bump);
+    let authority = new_sol_account(rng, 0, bump);
 
     let mut i = 0;
     let (vault_signer_nonce, vault_signer_pk) = loop {
@@ -203,6 +265,8 @@ fn setup_market<'bump, R: Rng>(rng: &mut R, bump: &'bump Bump) -> MarketAccounts
         pc_lot_size,
         vault_signer_nonce,
         pc_dust_threshold,
+        fee_schedule,
+        Some(authority.key),
     )
     .unwrap();
 
@@ -217,12 +281,13 @@ fn setup_market<'bump, R: Rng>(rng: &mut R, bump: &'bump Bump) -> MarketAccounts
             pc_vault.clone(),
             coin_mint.clone(),
             pc_mint.clone(),
+            authority.clone(),
         ]
         .into_bump_slice_mut();
-        State::process(&program_id, accounts, &init_instruction.data).unwrap();
+        State::process(&program_id, accounts, &init_instruction.data)?;
     }
 
-    MarketAccounts {
+    Ok(MarketAccounts {
         market,
         req_q,
         event_q,
@@ -233,7 +298,112 @@ fn setup_market<'bump, R: Rng>(rng: &mut R, bump: &'bump Bump) -> MarketAccounts
         coin_mint,
         pc_mint,
         rent_sysvar,
-    }
+        clock_sysvar,
+        authority,
+    })
+}
+
+#[cfg(feature = "fuzz")]
+fn token_balance(account: &AccountInfo) -> u64 {
+    Account::unpack(&account.try_borrow_data().unwrap())
+        .unwrap()
+        .amount
+}
+
+fn set_token_balance(account: &AccountInfo, amount: u64) {
+    let mut data = account.try_borrow_mut_data().unwrap();
+    let mut token_account = Account::unpack(&data).unwrap();
+    token_account.amount = amount;
+    Account::pack(token_account, &mut data).unwrap();
+}
+
+fn set_clock(clock_sysvar: &AccountInfo, clock: Clock) {
+    clock.to_account_info(&mut clock_sysvar.clone()).unwrap();
+}
+
+fn new_vault_signer_account<'bump>(
+    accounts: &MarketAccounts<'bump>,
+    bump: &'bump Bump,
+) -> AccountInfo<'bump> {
+    let dex_program_id = accounts.market.owner;
+    let vault_signer_nonce = MarketState::load(&accounts.market, dex_program_id)
+        .unwrap()
+        .vault_signer_nonce;
+    // marked as a signer, since the token program is invoked directly
+    // rather than through `invoke_signed` outside the runtime
+    AccountInfo::new(
+        bump.alloc(
+            gen_vault_signer_key(vault_signer_nonce, accounts.market.key, dex_program_id).unwrap(),
+        ),
+        true,
+        false,
+        bump.alloc(0),
+        &mut [],
+        &system_program::ID,
+        false,
+        Epoch::default(),
+    )
+}
+
+fn new_order_accounts<'bump>(
+    accounts: &MarketAccounts<'bump>,
+    orders_account: &AccountInfo<'bump>,
+    payer: &AccountInfo<'bump>,
+    owner: &AccountInfo<'bump>,
+    spl_token_program: &AccountInfo<'bump>,
+    bump: &'bump Bump,
+) -> &'bump [AccountInfo<'bump>] {
+    bump_vec![in bump;
+        accounts.market.clone(),
+        orders_account.clone(),
+        accounts.req_q.clone(),
+        payer.clone(),
+        owner.clone(),
+        accounts.coin_vault.clone(),
+        accounts.pc_vault.clone(),
+        spl_token_program.clone(),
+        accounts.rent_sysvar.clone(),
+    ]
+    .into_bump_slice()
+}
+
+fn match_orders_accounts<'bump>(
+    accounts: &MarketAccounts<'bump>,
+    coin_fee_receivable: &AccountInfo<'bump>,
+    pc_fee_receivable: &AccountInfo<'bump>,
+    bump: &'bump Bump,
+) -> &'bump [AccountInfo<'bump>] {
+    bump_vec![in bump;
+        accounts.market.clone(),
+        accounts.req_q.clone(),
+        accounts.event_q.clone(),
+        accounts.bids.clone(),
+        accounts.asks.clone(),
+        coin_fee_receivable.clone(),
+        pc_fee_receivable.clone(),
+        accounts.clock_sysvar.clone(),
+    ]
+    .into_bump_slice()
+}
+
+// ConsumeEvents expects the open orders accounts sorted by key
+fn consume_events_accounts<'bump>(
+    accounts: &MarketAccounts<'bump>,
+    orders_accounts: &[&AccountInfo<'bump>],
+    coin_fee_receivable: &AccountInfo<'bump>,
+    pc_fee_receivable: &AccountInfo<'bump>,
+    bump: &'bump Bump,
+) -> &'bump [AccountInfo<'bump>] {
+    let mut crank_accounts = BumpVec::with_capacity_in(orders_accounts.len() + 4, bump);
+    crank_accounts.extend(orders_accounts.iter().map(|&account| account.clone()));
+    crank_accounts.sort_by_key(|account_info| account_info.key.to_aligned_bytes());
+    crank_accounts.extend_from_slice(&[
+        accounts.market.clone(),
+        accounts.event_q.clone(),
+        coin_fee_receivable.clone(),
+        pc_fee_receivable.clone(),
+    ]);
+    crank_accounts.into_bump_slice()
 }
 
 #[test]
@@ -244,6 +414,9 @@ fn test_initialize_market() {
     setup_market(&mut rng, &bump);
 }
 
+// the wallets start out empty, which only works while token transfers are
+// stubbed out
+#[cfg(not(feature = "fuzz"))]
 #[test]
 fn test_new_order() {
     let mut rng = StdRng::seed_from_u64(1);
@@ -411,3 +584,3242 @@ fn test_new_order() {
         assert_eq!(open_orders_seller.native_pc_total, 399_120);
     }
 }
+
+#[test]
+fn test_cancel_all_orders() {
+    let mut rng = StdRng::seed_from_u64(2);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    let orders = [
+        (Side::Bid, 100_000, 5, &pc_account),
+        (Side::Bid, 99_000, 1, &pc_account),
+        (Side::Ask, 110_000, 3, &coin_account),
+    ];
+    for &(side, limit_price, max_qty, payer) in orders.iter() {
+        let instruction_data = MarketInstruction::NewOrder(NewOrderInstructionV1 {
+            side,
+            limit_price: NonZeroU64::new(limit_price).unwrap(),
+            max_qty: NonZeroU64::new(max_qty).unwrap(),
+            order_type: OrderType::Limit,
+            client_id: limit_price,
+        })
+        .pack();
+        let instruction_accounts = new_order_accounts(
+            &accounts,
+            &orders_account,
+            payer,
+            &owner,
+            &spl_token_program,
+            &bump,
+        );
+        State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+    }
+
+    let match_accounts = match_orders_accounts(&accounts, &coin_account, &pc_account, &bump);
+    let instruction_data = MarketInstruction::MatchOrders(10).pack();
+    State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+
+    let instruction_data = MarketInstruction::CancelAllOrders(Some(Side::Bid)).pack();
+    let instruction_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account.clone(),
+        accounts.req_q.clone(),
+        owner.clone(),
+    ]
+    .into_bump_slice();
+    State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+
+    let instruction_data = MarketInstruction::MatchOrders(10).pack();
+    State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+
+    let crank_accounts = bump_vec![in &bump;
+        orders_account.clone(),
+        accounts.market.clone(),
+        accounts.event_q.clone(),
+        coin_account.clone(),
+        pc_account.clone(),
+    ]
+    .into_bump_slice();
+    let instruction_data = MarketInstruction::ConsumeEvents(200).pack();
+    State::process(dex_program_id, crank_accounts, &instruction_data).unwrap();
+
+    let open_orders = MarketState::load(&accounts.market, &dex_program_id)
+        .unwrap()
+        .load_orders_mut(&orders_account, None, &dex_program_id, None)
+        .unwrap();
+    assert_eq!(open_orders.slot_side(0), None);
+    assert_eq!(open_orders.slot_side(1), None);
+    assert_eq!(open_orders.slot_side(2), Some(Side::Ask));
+    assert_eq!(open_orders.free_slot_bits, !(1u128 << 2));
+    assert_eq!(open_orders.native_pc_free, open_orders.native_pc_total);
+    assert_eq!(open_orders.native_coin_free, 0);
+    assert_eq!(open_orders.native_coin_total, 3_000);
+}
+
+#[test]
+fn test_replace_order_by_client_id() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    let order_accounts = new_order_accounts(
+        &accounts,
+        &orders_account,
+        &pc_account,
+        &owner,
+        &spl_token_program,
+        &bump,
+    );
+    let match_accounts = match_orders_accounts(&accounts, &coin_account, &pc_account, &bump);
+    let crank_accounts = consume_events_accounts(
+        &accounts,
+        &[&orders_account],
+        &coin_account,
+        &pc_account,
+        &bump,
+    );
+
+    let instruction_data = MarketInstruction::NewOrderV2(NewOrderInstructionV2 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(5).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 7,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+    })
+    .pack();
+    State::process(dex_program_id, order_accounts, &instruction_data).unwrap();
+    let instruction_data = MarketInstruction::MatchOrders(10).pack();
+    State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+
+    let replacement = NewOrderInstructionV2 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(101_000).unwrap(),
+        max_qty: NonZeroU64::new(2).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 8,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+    };
+    let instruction_data = MarketInstruction::ReplaceOrderByClientId(replacement.clone()).pack();
+    assert_eq!(
+        State::process(dex_program_id, order_accounts, &instruction_data).unwrap_err(),
+        DexErrorCode::ClientIdNotFound.into()
+    );
+
+    let instruction_data = MarketInstruction::ReplaceOrderByClientId(NewOrderInstructionV2 {
+        client_id: 7,
+        ..replacement
+    })
+    .pack();
+    State::process(dex_program_id, order_accounts, &instruction_data).unwrap();
+    // the replacement takes over the slot before the cancel is matched
+    let load_slot = || {
+        let market = MarketState::load(&accounts.market, &dex_program_id).unwrap();
+        let open_orders = market
+            .load_orders_mut(&orders_account, None, &dex_program_id, None)
+            .unwrap();
+        (
+            open_orders.free_slot_bits,
+            open_orders.client_order_ids[0],
+            (open_orders.orders[0] >> 64) as u64,
+        )
+    };
+    assert_eq!(load_slot(), (!1, 7, 101_000));
+
+    let instruction_data = MarketInstruction::MatchOrders(10).pack();
+    State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+    let instruction_data = MarketInstruction::ConsumeEvents(200).pack();
+    State::process(dex_program_id, crank_accounts, &instruction_data).unwrap();
+
+    // the replaced order's out event leaves the slot to the replacement
+    assert_eq!(load_slot(), (!1, 7, 101_000));
+    let open_orders = MarketState::load(&accounts.market, &dex_program_id)
+        .unwrap()
+        .load_orders_mut(&orders_account, None, &dex_program_id, None)
+        .unwrap();
+    assert_eq!(open_orders.slot_side(0), Some(Side::Bid));
+    assert_eq!(
+        open_orders.native_pc_total - open_orders.native_pc_free,
+        2 * 101_000
+    );
+}
+
+#[test]
+fn test_fill_or_kill() {
+    let mut rng = StdRng::seed_from_u64(4);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account_buyer =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let orders_account_seller =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    let match_accounts = match_orders_accounts(&accounts, &coin_account, &pc_account, &bump);
+    let crank_accounts = consume_events_accounts(
+        &accounts,
+        &[&orders_account_buyer, &orders_account_seller],
+        &coin_account,
+        &pc_account,
+        &bump,
+    );
+
+    let place_and_match = |side: Side, max_qty: u64, order_type: OrderType, match_limit: u16| {
+        let (orders_account, payer) = match side {
+            Side::Bid => (&orders_account_buyer, &pc_account),
+            Side::Ask => (&orders_account_seller, &coin_account),
+        };
+        let instruction_data = MarketInstruction::NewOrderV2(NewOrderInstructionV2 {
+            side,
+            limit_price: NonZeroU64::new(100_000).unwrap(),
+            max_qty: NonZeroU64::new(max_qty).unwrap(),
+            order_type,
+            client_id: 0,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        })
+        .pack();
+        let instruction_accounts = new_order_accounts(
+            &accounts,
+            orders_account,
+            payer,
+            &owner,
+            &spl_token_program,
+            &bump,
+        );
+        State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+        let instruction_data = MarketInstruction::MatchOrders(match_limit).pack();
+        State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+        let instruction_data = MarketInstruction::ConsumeEvents(200).pack();
+        State::process(dex_program_id, crank_accounts, &instruction_data).unwrap();
+    };
+
+    place_and_match(Side::Ask, 3, OrderType::Limit, 10);
+
+    // Only 3 lots are offered, so the order is killed without any fills.
+    place_and_match(Side::Bid, 5, OrderType::FillOrKill, 10);
+    {
+        let open_orders_buyer = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_buyer.free_slot_bits, std::u128::MAX);
+        assert_eq!(open_orders_buyer.native_coin_total, 0);
+        assert_eq!(
+            open_orders_buyer.native_pc_free,
+            open_orders_buyer.native_pc_total
+        );
+        let open_orders_seller = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_seller, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_seller.native_coin_total, 3_000);
+        assert_eq!(open_orders_seller.native_pc_total, 0);
+    }
+
+    place_and_match(Side::Bid, 3, OrderType::FillOrKill, 10);
+    {
+        let open_orders_buyer = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_buyer.free_slot_bits, std::u128::MAX);
+        assert_eq!(open_orders_buyer.native_coin_free, 3_000);
+        let open_orders_seller = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_seller, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_seller.native_coin_total, 0);
+        assert_eq!(open_orders_seller.native_pc_free, 300_090);
+    }
+
+    for _ in 0..3 {
+        place_and_match(Side::Ask, 1, OrderType::Limit, 10);
+    }
+
+    // Filling 3 lots takes 3 matches, so with a limit of 2 the order is killed
+    // rather than partially filled.
+    place_and_match(Side::Bid, 3, OrderType::FillOrKill, 2);
+    {
+        let open_orders_buyer = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_buyer.free_slot_bits, std::u128::MAX);
+        assert_eq!(open_orders_buyer.native_coin_free, 3_000);
+        let open_orders_seller = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_seller, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_seller.native_coin_total, 3_000);
+    }
+
+    place_and_match(Side::Bid, 3, OrderType::FillOrKill, 3);
+    {
+        let open_orders_buyer = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_buyer.native_coin_free, 6_000);
+        let open_orders_seller = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_seller, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_seller.native_coin_total, 0);
+    }
+}
+
+#[test]
+fn test_order_expiry() {
+    let mut rng = StdRng::seed_from_u64(5);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account_buyer =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let orders_account_seller =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    let latest_clock = new_clock_sysvar_account(
+        Clock {
+            slot: 20,
+            unix_timestamp: 100,
+            ..Clock::default()
+        },
+        &bump,
+    );
+    let crank_accounts = consume_events_accounts(
+        &accounts,
+        &[&orders_account_buyer, &orders_account_seller],
+        &coin_account,
+        &pc_account,
+        &bump,
+    );
+
+    let place_and_match = |side: Side, expiry: OrderExpiry, clock: Clock| {
+        let (orders_account, payer) = match side {
+            Side::Bid => (&orders_account_buyer, &pc_account),
+            Side::Ask => (&orders_account_seller, &coin_account),
+        };
+        let instruction_data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+            side,
+            limit_price: NonZeroU64::new(100_000).unwrap(),
+            max_qty: NonZeroU64::new(3).unwrap(),
+            order_type: OrderType::Limit,
+            client_id: 0,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            expiry: Some(expiry),
+        })
+        .pack();
+        let instruction_accounts = new_order_accounts(
+            &accounts,
+            orders_account,
+            payer,
+            &owner,
+            &spl_token_program,
+            &bump,
+        );
+        State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+        set_clock(&accounts.clock_sysvar, clock);
+        let match_accounts = match_orders_accounts(&accounts, &coin_account, &pc_account, &bump);
+        let instruction_data = MarketInstruction::MatchOrders(10).pack();
+        State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+        let instruction_data = MarketInstruction::ConsumeEvents(200).pack();
+        State::process(dex_program_id, crank_accounts, &instruction_data).unwrap();
+    };
+
+    place_and_match(Side::Ask, OrderExpiry::Slot(10), Clock::default());
+
+    // The ask has expired by the time the bid is matched, so it is cancelled
+    // instead of filled and the bid rests on the book.
+    place_and_match(
+        Side::Bid,
+        OrderExpiry::UnixTimestamp(100),
+        Clock {
+            slot: 10,
+            unix_timestamp: 50,
+            ..Clock::default()
+        },
+    );
+    {
+        let open_orders_seller = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_seller, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_seller.free_slot_bits, std::u128::MAX);
+        assert_eq!(open_orders_seller.native_coin_free, 3_000);
+        assert_eq!(open_orders_seller.native_pc_total, 0);
+        let open_orders_buyer = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_buyer.slot_side(0), Some(Side::Bid));
+        assert_eq!(open_orders_buyer.native_coin_total, 0);
+    }
+
+    let instruction_data = MarketInstruction::PruneExpiredOrders(5).pack();
+    let prune_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        accounts.event_q.clone(),
+        accounts.bids.clone(),
+        accounts.asks.clone(),
+        latest_clock.clone(),
+    ]
+    .into_bump_slice();
+    State::process(dex_program_id, prune_accounts, &instruction_data).unwrap();
+    let instruction_data = MarketInstruction::ConsumeEvents(200).pack();
+    State::process(dex_program_id, crank_accounts, &instruction_data).unwrap();
+    {
+        let open_orders_buyer = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_buyer.free_slot_bits, std::u128::MAX);
+        assert_eq!(
+            open_orders_buyer.native_pc_free,
+            open_orders_buyer.native_pc_total
+        );
+    }
+
+    // without the clock, matching works until it meets an order with an expiry
+    let match_accounts = match_orders_accounts(&accounts, &coin_account, &pc_account, &bump);
+    let match_accounts_without_clock = &match_accounts[..7];
+    let match_orders = MarketInstruction::MatchOrders(10).pack();
+    for &expiry in &[None, Some(OrderExpiry::Slot(100))] {
+        let instruction_data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+            side: Side::Ask,
+            limit_price: NonZeroU64::new(100_000).unwrap(),
+            max_qty: NonZeroU64::new(3).unwrap(),
+            order_type: OrderType::Limit,
+            client_id: 0,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            expiry,
+        })
+        .pack();
+        let instruction_accounts = new_order_accounts(
+            &accounts,
+            &orders_account_seller,
+            &coin_account,
+            &owner,
+            &spl_token_program,
+            &bump,
+        );
+        State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+        if expiry.is_none() {
+            State::process(dex_program_id, match_accounts_without_clock, &match_orders).unwrap();
+        } else {
+            assert_eq!(
+                State::process(dex_program_id, match_accounts_without_clock, &match_orders),
+                Err(DexErrorCode::ClockNotProvided.into())
+            );
+        }
+    }
+    State::process(dex_program_id, match_accounts, &match_orders).unwrap();
+}
+
+#[test]
+fn test_new_order_and_match() {
+    let mut rng = StdRng::seed_from_u64(6);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account_buyer =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let orders_account_seller =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    let place_and_match = |side: Side, order_type: OrderType, limit: u16| {
+        let (orders_account, payer) = match side {
+            Side::Bid => (&orders_account_buyer, &pc_account),
+            Side::Ask => (&orders_account_seller, &coin_account),
+        };
+        let instruction_data = MarketInstruction::NewOrderV4(NewOrderInstructionV4 {
+            side,
+            limit_price: NonZeroU64::new(100_000).unwrap(),
+            max_qty: NonZeroU64::new(3).unwrap(),
+            order_type,
+            client_id: 0,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            expiry: None,
+            limit,
+        })
+        .pack();
+        let instruction_accounts = bump_vec![in &bump;
+            accounts.market.clone(),
+            orders_account.clone(),
+            accounts.req_q.clone(),
+            payer.clone(),
+            owner.clone(),
+            accounts.coin_vault.clone(),
+            accounts.pc_vault.clone(),
+            spl_token_program.clone(),
+            accounts.rent_sysvar.clone(),
+            accounts.event_q.clone(),
+            accounts.bids.clone(),
+            accounts.asks.clone(),
+            accounts.clock_sysvar.clone(),
+        ]
+        .into_bump_slice();
+        State::process(dex_program_id, instruction_accounts, &instruction_data)
+    };
+
+    place_and_match(Side::Ask, OrderType::Limit, 10).unwrap();
+    // Both orders are matched without a separate MatchOrders instruction.
+    place_and_match(Side::Bid, OrderType::ImmediateOrCancel, 10).unwrap();
+
+    let crank_accounts = consume_events_accounts(
+        &accounts,
+        &[&orders_account_buyer, &orders_account_seller],
+        &coin_account,
+        &pc_account,
+        &bump,
+    );
+    let instruction_data = MarketInstruction::ConsumeEvents(200).pack();
+    State::process(dex_program_id, crank_accounts, &instruction_data).unwrap();
+    {
+        let open_orders_buyer = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_buyer.free_slot_bits, std::u128::MAX);
+        assert_eq!(open_orders_buyer.native_coin_free, 3_000);
+        let open_orders_seller = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_seller, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_seller.native_coin_total, 0);
+        assert_eq!(open_orders_seller.native_pc_free, 300_090);
+    }
+
+    // The queued ask uses up the limit, leaving the new bid unmatched.
+    let instruction_data = MarketInstruction::NewOrderV2(NewOrderInstructionV2 {
+        side: Side::Ask,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(3).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 0,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+    })
+    .pack();
+    let instruction_accounts = new_order_accounts(
+        &accounts,
+        &orders_account_seller,
+        &coin_account,
+        &owner,
+        &spl_token_program,
+        &bump,
+    );
+    State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+    assert_eq!(
+        place_and_match(Side::Bid, OrderType::ImmediateOrCancel, 1),
+        Err(DexErrorCode::NewOrderNotMatched.into())
+    );
+    place_and_match(Side::Bid, OrderType::ImmediateOrCancel, 2).unwrap();
+}
+
+#[test]
+fn test_close_open_orders() {
+    let mut rng = StdRng::seed_from_u64(7);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let destination = new_sol_account(&mut rng, 0, &bump);
+    let orders_account =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+    let vault_signer = new_vault_signer_account(&accounts, &bump);
+
+    let instruction_data = MarketInstruction::NewOrderV2(NewOrderInstructionV2 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(2).unwrap(),
+        order_type: OrderType::ImmediateOrCancel,
+        client_id: 0,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+    })
+    .pack();
+    let instruction_accounts = new_order_accounts(
+        &accounts,
+        &orders_account,
+        &pc_account,
+        &owner,
+        &spl_token_program,
+        &bump,
+    );
+    State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+
+    let close_accounts = bump_vec![in &bump;
+        orders_account.clone(),
+        owner.clone(),
+        destination.clone(),
+        accounts.market.clone(),
+    ]
+    .into_bump_slice();
+    let instruction_data = MarketInstruction::CloseOpenOrders.pack();
+    assert_eq!(
+        State::process(dex_program_id, close_accounts, &instruction_data).unwrap_err(),
+        DexErrorCode::OpenOrdersNotEmpty.into()
+    );
+
+    let match_accounts = match_orders_accounts(&accounts, &coin_account, &pc_account, &bump);
+    let instruction_data = MarketInstruction::MatchOrders(10).pack();
+    State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+    let crank_accounts = bump_vec![in &bump;
+        orders_account.clone(),
+        accounts.market.clone(),
+        accounts.event_q.clone(),
+        coin_account.clone(),
+        pc_account.clone(),
+    ]
+    .into_bump_slice();
+    let instruction_data = MarketInstruction::ConsumeEvents(200).pack();
+    State::process(dex_program_id, crank_accounts, &instruction_data).unwrap();
+    let settle_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account.clone(),
+        owner.clone(),
+        accounts.coin_vault.clone(),
+        accounts.pc_vault.clone(),
+        coin_account.clone(),
+        pc_account.clone(),
+        vault_signer.clone(),
+        spl_token_program.clone(),
+    ]
+    .into_bump_slice();
+    let instruction_data = MarketInstruction::SettleFunds.pack();
+    State::process(dex_program_id, settle_accounts, &instruction_data).unwrap();
+
+    // open orders accounts can still be closed once the market is disabled
+    let disabler = AccountInfo::new(
+        &disable_authority::ID,
+        true,
+        false,
+        bump.alloc(0),
+        &mut [],
+        &system_program::ID,
+        false,
+        Epoch::default(),
+    );
+    let disable_accounts =
+        bump_vec![in &bump; accounts.market.clone(), disabler.clone()].into_bump_slice();
+    State::process(
+        dex_program_id,
+        disable_accounts,
+        &MarketInstruction::DisableMarket.pack(),
+    )
+    .unwrap();
+
+    let rent_lamports = orders_account.lamports();
+    let instruction_data = MarketInstruction::CloseOpenOrders.pack();
+    State::process(dex_program_id, close_accounts, &instruction_data).unwrap();
+    assert_eq!(orders_account.lamports(), 0);
+    assert_eq!(destination.lamports(), rent_lamports);
+}
+
+#[test]
+fn test_custom_fee_schedule() {
+    let mut rng = StdRng::seed_from_u64(8);
+    let bump = Bump::new();
+
+    let invalid_schedule = FeeSchedule {
+        maker_rebate_bps: [30; 7],
+        ..FeeSchedule::default()
+    };
+    assert_eq!(
+        setup_market_with_fee_schedule(&mut rng, &bump, invalid_schedule).err(),
+        Some(DexErrorCode::InvalidFeeSchedule.into())
+    );
+
+    let fee_schedule = FeeSchedule {
+        taker_fee_bps: [50; 7],
+        maker_rebate_bps: [10; 7],
+        referrer_rebate_bps: 0,
+    };
+    let accounts = setup_market_with_fee_schedule(&mut rng, &bump, fee_schedule).unwrap();
+    let dex_program_id = accounts.market.owner;
+    {
+        let market = MarketStateV2::load(&accounts.market, &dex_program_id).unwrap();
+        assert_eq!(market.fee_schedule, fee_schedule);
+    }
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account_buyer =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let orders_account_seller =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    let match_accounts = match_orders_accounts(&accounts, &coin_account, &pc_account, &bump);
+
+    let instruction_data = MarketInstruction::NewOrder(NewOrderInstructionV1 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(5).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 0,
+    })
+    .pack();
+    let instruction_accounts = new_order_accounts(
+        &accounts,
+        &orders_account_buyer,
+        &pc_account,
+        &owner,
+        &spl_token_program,
+        &bump,
+    );
+    State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+    {
+        let market = MarketState::load(&accounts.market, &dex_program_id).unwrap();
+        assert_eq!(market.pc_deposits_total, 502_500);
+    }
+
+    let instruction_data = MarketInstruction::NewOrder(NewOrderInstructionV1 {
+        side: Side::Ask,
+        limit_price: NonZeroU64::new(99_000).unwrap(),
+        max_qty: NonZeroU64::new(4).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 0,
+    })
+    .pack();
+    let instruction_accounts = new_order_accounts(
+        &accounts,
+        &orders_account_seller,
+        &coin_account,
+        &owner,
+        &spl_token_program,
+        &bump,
+    );
+    State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+
+    let instruction_data = MarketInstruction::MatchOrders(5).pack();
+    State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+    {
+        let market = MarketState::load(&accounts.market, &dex_program_id).unwrap();
+        assert_eq!(market.referrer_rebates_accrued, 0);
+        assert_eq!(market.pc_fees_accrued, 1_600);
+        assert_eq!(market.pc_deposits_total, 500_900);
+    }
+}
+
+#[test]
+fn test_market_authority() {
+    let mut rng = StdRng::seed_from_u64(9);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let new_authority = new_sol_account(&mut rng, 0, &bump);
+    let orders_account =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    let new_order_accounts = new_order_accounts(
+        &accounts,
+        &orders_account,
+        &pc_account,
+        &owner,
+        &spl_token_program,
+        &bump,
+    );
+    let new_order = |client_id| {
+        MarketInstruction::NewOrder(NewOrderInstructionV1 {
+            side: Side::Bid,
+            limit_price: NonZeroU64::new(100_000).unwrap(),
+            max_qty: NonZeroU64::new(1).unwrap(),
+            order_type: OrderType::Limit,
+            client_id,
+        })
+        .pack()
+    };
+    let owner_accounts =
+        bump_vec![in &bump; accounts.market.clone(), owner.clone()].into_bump_slice();
+    let authority_accounts =
+        bump_vec![in &bump; accounts.market.clone(), accounts.authority.clone()].into_bump_slice();
+    let new_authority_accounts =
+        bump_vec![in &bump; accounts.market.clone(), new_authority.clone()].into_bump_slice();
+
+    State::process(dex_program_id, new_order_accounts, &new_order(1)).unwrap();
+
+    let pause = MarketInstruction::PauseMarket.pack();
+    assert_eq!(
+        State::process(dex_program_id, owner_accounts, &pause),
+        Err(DexErrorCode::WrongMarketAuthority.into())
+    );
+    State::process(dex_program_id, authority_accounts, &pause).unwrap();
+    assert!(MarketState::load(&accounts.market, &dex_program_id)
+        .unwrap()
+        .is_paused());
+
+    assert_eq!(
+        State::process(dex_program_id, new_order_accounts, &new_order(2)),
+        Err(DexErrorCode::MarketIsPaused.into())
+    );
+    let cancel_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account.clone(),
+        accounts.req_q.clone(),
+        owner.clone(),
+    ]
+    .into_bump_slice();
+    State::process(
+        dex_program_id,
+        cancel_accounts,
+        &MarketInstruction::CancelOrderByClientId(1).pack(),
+    )
+    .unwrap();
+
+    let resume = MarketInstruction::ResumeMarket.pack();
+    State::process(dex_program_id, authority_accounts, &resume).unwrap();
+    State::process(dex_program_id, new_order_accounts, &new_order(2)).unwrap();
+
+    let set_authority_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        accounts.authority.clone(),
+        new_authority.clone(),
+    ]
+    .into_bump_slice();
+    State::process(
+        dex_program_id,
+        set_authority_accounts,
+        &MarketInstruction::SetMarketAuthority.pack(),
+    )
+    .unwrap();
+    assert_eq!(
+        State::process(dex_program_id, authority_accounts, &pause),
+        Err(DexErrorCode::WrongMarketAuthority.into())
+    );
+    State::process(dex_program_id, new_authority_accounts, &pause).unwrap();
+    assert!(MarketState::load(&accounts.market, &dex_program_id)
+        .unwrap()
+        .is_paused());
+}
+
+// SendTake settles straight from the wallets, so the token program has to
+// run for real
+#[cfg(feature = "fuzz")]
+#[test]
+fn test_send_take() {
+    use instruction::SendTakeInstruction;
+    use std::convert::TryInto;
+
+    let mut rng = StdRng::seed_from_u64(11);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let maker = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let taker = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account_maker =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let maker_coin_account = new_token_account(&mut rng, accounts.coin_mint.key, maker.key, &bump);
+    let taker_coin_account = new_token_account(&mut rng, accounts.coin_mint.key, taker.key, &bump);
+    let taker_pc_account = new_token_account(&mut rng, accounts.pc_mint.key, taker.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+    let system_program = new_system_program(&bump);
+    let vault_signer = new_vault_signer_account(&accounts, &bump);
+
+    set_token_balance(&maker_coin_account, 3_000);
+    set_token_balance(&taker_pc_account, 1_000_000);
+
+    let authority_accounts =
+        bump_vec![in &bump; accounts.market.clone(), accounts.authority.clone()].into_bump_slice();
+    let instruction_data = MarketInstruction::SetCrankFee(1_000_000).pack();
+    State::process(dex_program_id, authority_accounts, &instruction_data).unwrap();
+
+    let instruction_data = MarketInstruction::NewOrderV4(NewOrderInstructionV4 {
+        side: Side::Ask,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(3).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 0,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        expiry: None,
+        limit: 10,
+    })
+    .pack();
+    let instruction_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account_maker.clone(),
+        accounts.req_q.clone(),
+        maker_coin_account.clone(),
+        maker.clone(),
+        accounts.coin_vault.clone(),
+        accounts.pc_vault.clone(),
+        spl_token_program.clone(),
+        accounts.rent_sysvar.clone(),
+        accounts.event_q.clone(),
+        accounts.bids.clone(),
+        accounts.asks.clone(),
+        accounts.clock_sysvar.clone(),
+    ]
+    .into_bump_slice();
+    State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+
+    let instruction_data = MarketInstruction::SendTake(SendTakeInstruction {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(5).unwrap(),
+        limit: 10,
+    })
+    .pack();
+    let send_take_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        accounts.req_q.clone(),
+        accounts.event_q.clone(),
+        accounts.bids.clone(),
+        accounts.asks.clone(),
+        taker_coin_account.clone(),
+        taker_pc_account.clone(),
+        taker.clone(),
+        accounts.coin_vault.clone(),
+        accounts.pc_vault.clone(),
+        vault_signer.clone(),
+        spl_token_program.clone(),
+        accounts.clock_sysvar.clone(),
+        system_program.clone(),
+    ]
+    .into_bump_slice();
+    State::process(dex_program_id, send_take_accounts, &instruction_data).unwrap();
+
+    // only the taker fee is charged on top of the 3 lots that were filled
+    assert_eq!(token_balance(&taker_coin_account), 3_000);
+    assert_eq!(token_balance(&taker_pc_account), 1_000_000 - 300_660);
+    {
+        let market = MarketStateV2::load(&accounts.market, dex_program_id).unwrap();
+        assert_eq!(market.coin_deposits_total, 0);
+        assert_eq!(market.pc_deposits_total, 300_090);
+        assert_eq!(market.pc_fees_accrued, 570);
+        assert_eq!(market.referrer_rebates_accrued, 0);
+        // the taker's crank fee joins what is left of the maker's for
+        // consuming the events
+        assert_eq!(market.match_rewards_accrued, 0);
+        assert_eq!(market.consume_rewards_accrued, 1_500_000);
+    }
+    assert_eq!(taker.lamports(), 999_000_000);
+    // the maker's fill and out events are the only ones in the queue; the
+    // event queue count sits after the 5 byte padding, the flags and the head
+    let event_count = {
+        let data = accounts.event_q.try_borrow_data().unwrap();
+        u64::from_le_bytes(data[21..29].try_into().unwrap())
+    };
+    assert_eq!(event_count, 2);
+
+    let crank_accounts = bump_vec![in &bump;
+        orders_account_maker.clone(),
+        accounts.market.clone(),
+        accounts.event_q.clone(),
+        maker_coin_account.clone(),
+        taker_pc_account.clone(),
+    ]
+    .into_bump_slice();
+    let instruction_data = MarketInstruction::ConsumeEvents(200).pack();
+    State::process(dex_program_id, crank_accounts, &instruction_data).unwrap();
+    let open_orders_maker = MarketState::load(&accounts.market, &dex_program_id)
+        .unwrap()
+        .load_orders_mut(&orders_account_maker, None, &dex_program_id, None)
+        .unwrap();
+    assert_eq!(open_orders_maker.free_slot_bits, std::u128::MAX);
+    assert_eq!(open_orders_maker.native_coin_total, 0);
+    assert_eq!(open_orders_maker.native_pc_free, 300_090);
+}
+
+#[cfg(feature = "fuzz")]
+#[test]
+fn test_send_take_after_queued_cancel() {
+    use instruction::SendTakeInstruction;
+
+    let mut rng = StdRng::seed_from_u64(25);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let maker = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let taker = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account_maker =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let maker_coin_account = new_token_account(&mut rng, accounts.coin_mint.key, maker.key, &bump);
+    let taker_coin_account = new_token_account(&mut rng, accounts.coin_mint.key, taker.key, &bump);
+    let taker_pc_account = new_token_account(&mut rng, accounts.pc_mint.key, taker.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+    let system_program = new_system_program(&bump);
+    let vault_signer = new_vault_signer_account(&accounts, &bump);
+
+    set_token_balance(&maker_coin_account, 6_000);
+    set_token_balance(&taker_pc_account, 1_000_000);
+
+    let new_order_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account_maker.clone(),
+        accounts.req_q.clone(),
+        maker_coin_account.clone(),
+        maker.clone(),
+        accounts.coin_vault.clone(),
+        accounts.pc_vault.clone(),
+        spl_token_program.clone(),
+        accounts.rent_sysvar.clone(),
+        accounts.event_q.clone(),
+        accounts.bids.clone(),
+        accounts.asks.clone(),
+        accounts.clock_sysvar.clone(),
+    ]
+    .into_bump_slice();
+    for client_id in 1..=2 {
+        let instruction_data = MarketInstruction::NewOrderV4(NewOrderInstructionV4 {
+            side: Side::Ask,
+            limit_price: NonZeroU64::new(100_000).unwrap(),
+            max_qty: NonZeroU64::new(3).unwrap(),
+            order_type: OrderType::Limit,
+            client_id,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            expiry: None,
+            limit: 10,
+        })
+        .pack();
+        State::process(dex_program_id, new_order_accounts, &instruction_data).unwrap();
+    }
+
+    // the maker asks to cancel both asks, but nobody has matched the
+    // cancels yet
+    let cancel_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account_maker.clone(),
+        accounts.req_q.clone(),
+        maker.clone(),
+    ]
+    .into_bump_slice();
+    for client_id in 1..=2 {
+        State::process(
+            dex_program_id,
+            cancel_accounts,
+            &MarketInstruction::CancelOrderByClientId(client_id).pack(),
+        )
+        .unwrap();
+    }
+
+    let send_take_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        accounts.req_q.clone(),
+        accounts.event_q.clone(),
+        accounts.bids.clone(),
+        accounts.asks.clone(),
+        taker_coin_account.clone(),
+        taker_pc_account.clone(),
+        taker.clone(),
+        accounts.coin_vault.clone(),
+        accounts.pc_vault.clone(),
+        vault_signer.clone(),
+        spl_token_program.clone(),
+        accounts.clock_sysvar.clone(),
+        system_program.clone(),
+    ]
+    .into_bump_slice();
+    let send_take = |limit| {
+        State::process(
+            dex_program_id,
+            send_take_accounts,
+            &MarketInstruction::SendTake(SendTakeInstruction {
+                side: Side::Bid,
+                limit_price: NonZeroU64::new(100_000).unwrap(),
+                max_qty: NonZeroU64::new(5).unwrap(),
+                limit,
+            })
+            .pack(),
+        )
+    };
+
+    // both cancels have to fit within the limit
+    assert_eq!(send_take(1), Err(DexErrorCode::RequestQueueNotEmpty.into()));
+    // the cancels go through first, leaving nothing to take
+    send_take(10).unwrap();
+    assert_eq!(token_balance(&taker_coin_account), 0);
+    assert_eq!(token_balance(&taker_pc_account), 1_000_000);
+    let open_orders_maker = MarketState::load(&accounts.market, dex_program_id)
+        .unwrap()
+        .load_orders_mut(&orders_account_maker, None, dex_program_id, None)
+        .unwrap();
+    assert_eq!(open_orders_maker.native_coin_total, 6_000);
+    assert_eq!(open_orders_maker.native_coin_free, 0);
+}
+
+#[test]
+fn test_new_order_with_max_native_pc_qty() {
+    let mut rng = StdRng::seed_from_u64(12);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account_buyer =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let orders_account_seller =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+    set_token_balance(&coin_account, 3_000);
+    set_token_balance(&pc_account, 1_000_000);
+
+    let place_order = |side: Side, instruction_data: &[u8]| {
+        let (orders_account, payer) = match side {
+            Side::Bid => (&orders_account_buyer, &pc_account),
+            Side::Ask => (&orders_account_seller, &coin_account),
+        };
+        let instruction_accounts = new_order_accounts(
+            &accounts,
+            orders_account,
+            payer,
+            &owner,
+            &spl_token_program,
+            &bump,
+        );
+        State::process(dex_program_id, instruction_accounts, instruction_data).unwrap();
+    };
+
+    let instruction_data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+        side: Side::Ask,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(3).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 0,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        expiry: None,
+    })
+    .pack();
+    place_order(Side::Ask, &instruction_data);
+
+    // a market bid: only the price currency budget limits the order
+    let instruction_data = MarketInstruction::NewOrderV5(NewOrderInstructionV5 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(std::u64::MAX).unwrap(),
+        max_qty: NonZeroU64::new(std::u64::MAX).unwrap(),
+        order_type: OrderType::ImmediateOrCancel,
+        client_id: 0,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        expiry: None,
+        max_native_pc_qty_including_fees: NonZeroU64::new(200_500).unwrap(),
+    })
+    .pack();
+    place_order(Side::Bid, &instruction_data);
+    {
+        let market = MarketState::load(&accounts.market, dex_program_id).unwrap();
+        assert_eq!(market.pc_deposits_total, 200_500);
+    }
+
+    let match_accounts = match_orders_accounts(&accounts, &coin_account, &pc_account, &bump);
+    let instruction_data = MarketInstruction::MatchOrders(10).pack();
+    State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+
+    let crank_accounts = consume_events_accounts(
+        &accounts,
+        &[&orders_account_buyer, &orders_account_seller],
+        &coin_account,
+        &pc_account,
+        &bump,
+    );
+    let instruction_data = MarketInstruction::ConsumeEvents(200).pack();
+    State::process(dex_program_id, crank_accounts, &instruction_data).unwrap();
+
+    // 2 lots cost 200_000 plus a 440 taker fee; the third lot is out of budget
+    let open_orders_buyer = MarketState::load(&accounts.market, &dex_program_id)
+        .unwrap()
+        .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+        .unwrap();
+    assert_eq!(open_orders_buyer.free_slot_bits, std::u128::MAX);
+    assert_eq!(open_orders_buyer.native_coin_free, 2_000);
+    assert_eq!(open_orders_buyer.native_pc_free, 60);
+    assert_eq!(open_orders_buyer.native_pc_total, 60);
+}
+
+#[test]
+fn test_iceberg_order() {
+    let mut rng = StdRng::seed_from_u64(13);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account_buyer =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let orders_account_seller =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+    set_token_balance(&coin_account, 5_000);
+    set_token_balance(&pc_account, 1_000_000);
+
+    let place_order = |side: Side, instruction_data: &[u8]| {
+        let (orders_account, payer) = match side {
+            Side::Bid => (&orders_account_buyer, &pc_account),
+            Side::Ask => (&orders_account_seller, &coin_account),
+        };
+        let instruction_accounts = new_order_accounts(
+            &accounts,
+            orders_account,
+            payer,
+            &owner,
+            &spl_token_program,
+            &bump,
+        );
+        State::process(dex_program_id, instruction_accounts, instruction_data).unwrap();
+    };
+    let match_accounts = match_orders_accounts(&accounts, &coin_account, &pc_account, &bump);
+    let crank_accounts = consume_events_accounts(
+        &accounts,
+        &[&orders_account_buyer, &orders_account_seller],
+        &coin_account,
+        &pc_account,
+        &bump,
+    );
+    let match_and_crank = || {
+        let instruction_data = MarketInstruction::MatchOrders(10).pack();
+        State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+        let instruction_data = MarketInstruction::ConsumeEvents(200).pack();
+        State::process(dex_program_id, crank_accounts, &instruction_data).unwrap();
+    };
+    let load_seller_order_id = || {
+        MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_seller, None, &dex_program_id, None)
+            .unwrap()
+            .orders[0]
+    };
+
+    // 5 lots, shown 2 at a time
+    let instruction_data = MarketInstruction::NewOrderV6(NewOrderInstructionV6 {
+        side: Side::Ask,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(5).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 7,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        expiry: None,
+        max_native_pc_qty_including_fees: NonZeroU64::new(std::u64::MAX).unwrap(),
+        display_qty: NonZeroU64::new(2),
+    })
+    .pack();
+    place_order(Side::Ask, &instruction_data);
+    match_and_crank();
+    let posted_order_id = load_seller_order_id();
+
+    // takes the first slice and one lot of the second
+    let instruction_data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(3).unwrap(),
+        order_type: OrderType::ImmediateOrCancel,
+        client_id: 0,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        expiry: None,
+    })
+    .pack();
+    place_order(Side::Bid, &instruction_data);
+    match_and_crank();
+    {
+        let market = MarketState::load(&accounts.market, &dex_program_id).unwrap();
+        let open_orders_buyer = market
+            .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_buyer.native_coin_free, 3_000);
+        let open_orders_seller = market
+            .load_orders_mut(&orders_account_seller, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_seller.native_coin_free, 0);
+        assert_eq!(open_orders_seller.native_coin_total, 2_000);
+        assert_ne!(open_orders_seller.free_slot_bits, std::u128::MAX);
+    }
+    let replenished_order_id = load_seller_order_id();
+    assert_ne!(replenished_order_id, posted_order_id);
+    assert_eq!(replenished_order_id >> 64, posted_order_id >> 64);
+
+    // cancelling under the new id releases the shown lot and the reserve
+    let cancel_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account_seller.clone(),
+        accounts.req_q.clone(),
+        owner.clone(),
+    ]
+    .into_bump_slice();
+    State::process(
+        dex_program_id,
+        cancel_accounts,
+        &MarketInstruction::CancelOrderByClientId(7).pack(),
+    )
+    .unwrap();
+    match_and_crank();
+    let open_orders_seller = MarketState::load(&accounts.market, &dex_program_id)
+        .unwrap()
+        .load_orders_mut(&orders_account_seller, None, &dex_program_id, None)
+        .unwrap();
+    assert_eq!(open_orders_seller.free_slot_bits, std::u128::MAX);
+    assert_eq!(open_orders_seller.native_coin_free, 2_000);
+    drop(open_orders_seller);
+
+    // a cancel sent before the repost is consumed still finds the next slice
+    let instruction_data = MarketInstruction::NewOrderV6(NewOrderInstructionV6 {
+        side: Side::Ask,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(2).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 8,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        expiry: None,
+        max_native_pc_qty_including_fees: NonZeroU64::new(std::u64::MAX).unwrap(),
+        display_qty: NonZeroU64::new(1),
+    })
+    .pack();
+    place_order(Side::Ask, &instruction_data);
+    match_and_crank();
+    let instruction_data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(1).unwrap(),
+        order_type: OrderType::ImmediateOrCancel,
+        client_id: 0,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        expiry: None,
+    })
+    .pack();
+    place_order(Side::Bid, &instruction_data);
+    let instruction_data = MarketInstruction::MatchOrders(10).pack();
+    State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+    State::process(
+        dex_program_id,
+        cancel_accounts,
+        &MarketInstruction::CancelOrderByClientId(8).pack(),
+    )
+    .unwrap();
+    match_and_crank();
+    let open_orders_seller = MarketState::load(&accounts.market, &dex_program_id)
+        .unwrap()
+        .load_orders_mut(&orders_account_seller, None, &dex_program_id, None)
+        .unwrap();
+    assert_eq!(open_orders_seller.free_slot_bits, std::u128::MAX);
+    assert_eq!(open_orders_seller.native_coin_free, 1_000);
+    assert_eq!(open_orders_seller.native_coin_total, 1_000);
+}
+
+#[test]
+fn test_post_only_slide() {
+    let mut rng = StdRng::seed_from_u64(14);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account_buyer =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let orders_account_seller =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    let match_accounts = match_orders_accounts(&accounts, &coin_account, &pc_account, &bump);
+    let crank_accounts = consume_events_accounts(
+        &accounts,
+        &[&orders_account_buyer, &orders_account_seller],
+        &coin_account,
+        &pc_account,
+        &bump,
+    );
+
+    let place_and_match = |side: Side, limit_price: u64, order_type: OrderType| {
+        let (orders_account, payer) = match side {
+            Side::Bid => (&orders_account_buyer, &pc_account),
+            Side::Ask => (&orders_account_seller, &coin_account),
+        };
+        let instruction_data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+            side,
+            limit_price: NonZeroU64::new(limit_price).unwrap(),
+            max_qty: NonZeroU64::new(2).unwrap(),
+            order_type,
+            client_id: 0,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            expiry: None,
+        })
+        .pack();
+        let instruction_accounts = new_order_accounts(
+            &accounts,
+            orders_account,
+            payer,
+            &owner,
+            &spl_token_program,
+            &bump,
+        );
+        State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+        let instruction_data = MarketInstruction::MatchOrders(10).pack();
+        State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+        let instruction_data = MarketInstruction::ConsumeEvents(200).pack();
+        State::process(dex_program_id, crank_accounts, &instruction_data).unwrap();
+    };
+
+    place_and_match(Side::Ask, 100_000, OrderType::Limit);
+
+    // a plain post-only bid that would cross is dropped
+    place_and_match(Side::Bid, 101_000, OrderType::PostOnly);
+    {
+        let open_orders_buyer = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_buyer.free_slot_bits, std::u128::MAX);
+        assert_eq!(
+            open_orders_buyer.native_pc_free,
+            open_orders_buyer.native_pc_total
+        );
+    }
+
+    // a sliding one rests a tick below the best ask, with only that locked
+    place_and_match(Side::Bid, 101_000, OrderType::PostOnlySlide);
+    {
+        let open_orders_buyer = MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders_buyer.slot_side(0), Some(Side::Bid));
+        assert_eq!((open_orders_buyer.orders[0] >> 64) as u64, 99_999);
+        assert_eq!(
+            open_orders_buyer.native_pc_total - open_orders_buyer.native_pc_free,
+            2 * 99_999
+        );
+        assert_eq!(open_orders_buyer.native_coin_total, 0);
+    }
+
+    // and a sliding ask rests a tick above the best bid
+    place_and_match(Side::Ask, 99_000, OrderType::PostOnlySlide);
+    let open_orders_seller = MarketState::load(&accounts.market, &dex_program_id)
+        .unwrap()
+        .load_orders_mut(&orders_account_seller, None, &dex_program_id, None)
+        .unwrap();
+    assert_eq!(open_orders_seller.slot_side(1), Some(Side::Ask));
+    assert_eq!((open_orders_seller.orders[1] >> 64) as u64, 100_000);
+    assert_eq!(open_orders_seller.native_coin_free, 0);
+    assert_eq!(open_orders_seller.native_pc_total, 0);
+    drop(open_orders_seller);
+
+    // a cancel under the id it was placed with still finds the slid order
+    let instruction_data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(101_000).unwrap(),
+        max_qty: NonZeroU64::new(2).unwrap(),
+        order_type: OrderType::PostOnlySlide,
+        client_id: 0,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        expiry: None,
+    })
+    .pack();
+    let instruction_accounts = new_order_accounts(
+        &accounts,
+        &orders_account_buyer,
+        &pc_account,
+        &owner,
+        &spl_token_program,
+        &bump,
+    );
+    State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+    let placed_order_id = MarketState::load(&accounts.market, &dex_program_id)
+        .unwrap()
+        .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+        .unwrap()
+        .orders[1];
+    let instruction_data = MarketInstruction::MatchOrders(10).pack();
+    State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+    let cancel_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account_buyer.clone(),
+        accounts.req_q.clone(),
+        owner.clone(),
+    ]
+    .into_bump_slice();
+    let instruction_data = MarketInstruction::CancelOrder(CancelOrderInstruction {
+        side: Side::Bid,
+        order_id: placed_order_id,
+        owner: [0; 4],
+        owner_slot: 1,
+    })
+    .pack();
+    State::process(dex_program_id, cancel_accounts, &instruction_data).unwrap();
+    State::process(
+        dex_program_id,
+        match_accounts,
+        &MarketInstruction::MatchOrders(10).pack(),
+    )
+    .unwrap();
+    State::process(
+        dex_program_id,
+        crank_accounts,
+        &MarketInstruction::ConsumeEvents(200).pack(),
+    )
+    .unwrap();
+    let open_orders_buyer = MarketState::load(&accounts.market, &dex_program_id)
+        .unwrap()
+        .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+        .unwrap();
+    assert_eq!(open_orders_buyer.slot_side(1), None);
+    assert_eq!(open_orders_buyer.slot_side(0), Some(Side::Bid));
+    drop(open_orders_buyer);
+
+    // a stale cancel leaves alone a newer order in the same slot
+    let instruction_data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(98_000).unwrap(),
+        max_qty: NonZeroU64::new(1).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 0,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        expiry: None,
+    })
+    .pack();
+    State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+    let instruction_data = MarketInstruction::CancelOrder(CancelOrderInstruction {
+        side: Side::Bid,
+        order_id: placed_order_id,
+        owner: [0; 4],
+        owner_slot: 1,
+    })
+    .pack();
+    State::process(dex_program_id, cancel_accounts, &instruction_data).unwrap();
+    State::process(
+        dex_program_id,
+        match_accounts,
+        &MarketInstruction::MatchOrders(10).pack(),
+    )
+    .unwrap();
+    State::process(
+        dex_program_id,
+        crank_accounts,
+        &MarketInstruction::ConsumeEvents(200).pack(),
+    )
+    .unwrap();
+    let open_orders_buyer = MarketState::load(&accounts.market, dex_program_id)
+        .unwrap()
+        .load_orders_mut(&orders_account_buyer, None, dex_program_id, None)
+        .unwrap();
+    assert_eq!(open_orders_buyer.slot_side(1), Some(Side::Bid));
+    assert_eq!((open_orders_buyer.orders[1] >> 64) as u64, 98_000);
+}
+
+#[test]
+fn test_abort_on_self_trade() {
+    let mut rng = StdRng::seed_from_u64(15);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let orders_account_other =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    let new_order =
+        |side: Side, max_qty: u64, self_trade_behavior: SelfTradeBehavior| NewOrderInstructionV4 {
+            side,
+            limit_price: NonZeroU64::new(100_000).unwrap(),
+            max_qty: NonZeroU64::new(max_qty).unwrap(),
+            order_type: OrderType::Limit,
+            client_id: 0,
+            self_trade_behavior,
+            expiry: None,
+            limit: 10,
+        };
+    let place_and_match = |other_account: bool, instruction: NewOrderInstructionV4| {
+        let orders_account = if other_account {
+            &orders_account_other
+        } else {
+            &orders_account
+        };
+        let payer = match instruction.side {
+            Side::Bid => &pc_account,
+            Side::Ask => &coin_account,
+        };
+        let instruction_accounts = bump_vec![in &bump;
+            accounts.market.clone(),
+            orders_account.clone(),
+            accounts.req_q.clone(),
+            payer.clone(),
+            owner.clone(),
+            accounts.coin_vault.clone(),
+            accounts.pc_vault.clone(),
+            spl_token_program.clone(),
+            accounts.rent_sysvar.clone(),
+            accounts.event_q.clone(),
+            accounts.bids.clone(),
+            accounts.asks.clone(),
+            accounts.clock_sysvar.clone(),
+        ]
+        .into_bump_slice();
+        State::process(
+            dex_program_id,
+            instruction_accounts,
+            &MarketInstruction::NewOrderV4(instruction).pack(),
+        )
+    };
+
+    place_and_match(
+        false,
+        new_order(Side::Ask, 3, SelfTradeBehavior::DecrementTake),
+    )
+    .unwrap();
+
+    // an order that is only queued could abort a later crank instead
+    let (new_order_v3, _) =
+        new_order(Side::Bid, 1, SelfTradeBehavior::AbortTransaction).remove_match_limit();
+    let new_order_accounts = new_order_accounts(
+        &accounts,
+        &orders_account_other,
+        &pc_account,
+        &owner,
+        &spl_token_program,
+        &bump,
+    );
+    assert_eq!(
+        State::process(
+            dex_program_id,
+            new_order_accounts,
+            &MarketInstruction::NewOrderV3(new_order_v3).pack(),
+        ),
+        Err(DexErrorCode::AbortOrderNotMatched.into())
+    );
+
+    // matching another owner's order goes through
+    place_and_match(
+        true,
+        new_order(Side::Bid, 1, SelfTradeBehavior::AbortTransaction),
+    )
+    .unwrap();
+    assert_eq!(
+        place_and_match(
+            false,
+            new_order(Side::Bid, 1, SelfTradeBehavior::AbortTransaction),
+        ),
+        Err(DexErrorCode::WouldSelfTrade.into())
+    );
+}
+
+#[test]
+fn test_price_band() {
+    let mut rng = StdRng::seed_from_u64(16);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account_buyer =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let orders_account_seller =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    let crank_accounts = consume_events_accounts(
+        &accounts,
+        &[&orders_account_buyer, &orders_account_seller],
+        &coin_account,
+        &pc_account,
+        &bump,
+    );
+
+    let place_and_match = |side: Side, limit_price: u64, max_qty: u64, slot: u64| {
+        let (orders_account, payer) = match side {
+            Side::Bid => (&orders_account_buyer, &pc_account),
+            Side::Ask => (&orders_account_seller, &coin_account),
+        };
+        let instruction_data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+            side,
+            limit_price: NonZeroU64::new(limit_price).unwrap(),
+            max_qty: NonZeroU64::new(max_qty).unwrap(),
+            order_type: OrderType::Limit,
+            client_id: 0,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            expiry: None,
+        })
+        .pack();
+        let instruction_accounts = new_order_accounts(
+            &accounts,
+            orders_account,
+            payer,
+            &owner,
+            &spl_token_program,
+            &bump,
+        );
+        State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+        set_clock(
+            &accounts.clock_sysvar,
+            Clock {
+                slot,
+                ..Clock::default()
+            },
+        );
+        let match_accounts = match_orders_accounts(&accounts, &coin_account, &pc_account, &bump);
+        let instruction_data = MarketInstruction::MatchOrders(10).pack();
+        State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+        let instruction_data = MarketInstruction::ConsumeEvents(200).pack();
+        State::process(dex_program_id, crank_accounts, &instruction_data).unwrap();
+    };
+    let buyer_coin_total = || {
+        MarketState::load(&accounts.market, &dex_program_id)
+            .unwrap()
+            .load_orders_mut(&orders_account_buyer, None, &dex_program_id, None)
+            .unwrap()
+            .native_coin_total
+    };
+
+    let authority_accounts =
+        bump_vec![in &bump; accounts.market.clone(), accounts.authority.clone()].into_bump_slice();
+    let set_price_band = |max_deviation_bps, halt_slots| {
+        let instruction_data = MarketInstruction::SetPriceBand(PriceBandInstruction {
+            max_deviation_bps,
+            halt_slots,
+        })
+        .pack();
+        State::process(dex_program_id, authority_accounts, &instruction_data)
+    };
+    assert_eq!(
+        set_price_band(0, 10),
+        Err(DexErrorCode::InvalidPriceBand.into())
+    );
+    assert_eq!(
+        set_price_band(10_000, 10),
+        Err(DexErrorCode::InvalidPriceBand.into())
+    );
+    set_price_band(0, 0).unwrap();
+    set_price_band(1_000, 10).unwrap();
+
+    // the first trade is unbounded and sets the reference price
+    place_and_match(Side::Ask, 100_000, 1, 0);
+    place_and_match(Side::Bid, 100_000, 1, 0);
+    assert_eq!(buyer_coin_total(), 1_000);
+
+    // a sweeping bid stops at the first ask more than 10% away
+    place_and_match(Side::Ask, 105_000, 1, 0);
+    place_and_match(Side::Ask, 120_000, 1, 0);
+    place_and_match(Side::Bid, 130_000, 2, 0);
+    assert_eq!(buyer_coin_total(), 2_000);
+    {
+        let market = MarketStateV2::load(&accounts.market, &dex_program_id).unwrap();
+        assert_eq!(market.last_trade_price, 105_000);
+        assert_eq!(market.halted_until_slot, 10);
+    }
+
+    // nothing matches while halted, even within the band
+    place_and_match(Side::Ask, 110_000, 1, 0);
+    place_and_match(Side::Bid, 110_000, 1, 5);
+    assert_eq!(buyer_coin_total(), 2_000);
+
+    place_and_match(Side::Bid, 110_000, 1, 10);
+    assert_eq!(buyer_coin_total(), 3_000);
+}
+
+#[test]
+fn test_market_stats() {
+    let mut rng = StdRng::seed_from_u64(17);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account_buyer =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let orders_account_seller =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    let crank_accounts = consume_events_accounts(
+        &accounts,
+        &[&orders_account_buyer, &orders_account_seller],
+        &coin_account,
+        &pc_account,
+        &bump,
+    );
+
+    let place_and_match = |side: Side, limit_price: u64, max_qty: u64| {
+        let (orders_account, payer) = match side {
+            Side::Bid => (&orders_account_buyer, &pc_account),
+            Side::Ask => (&orders_account_seller, &coin_account),
+        };
+        let instruction_data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+            side,
+            limit_price: NonZeroU64::new(limit_price).unwrap(),
+            max_qty: NonZeroU64::new(max_qty).unwrap(),
+            order_type: OrderType::Limit,
+            client_id: 0,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            expiry: None,
+        })
+        .pack();
+        let instruction_accounts = new_order_accounts(
+            &accounts,
+            orders_account,
+            payer,
+            &owner,
+            &spl_token_program,
+            &bump,
+        );
+        State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+        let match_accounts = match_orders_accounts(&accounts, &coin_account, &pc_account, &bump);
+        let instruction_data = MarketInstruction::MatchOrders(10).pack();
+        State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+        let instruction_data = MarketInstruction::ConsumeEvents(200).pack();
+        State::process(dex_program_id, crank_accounts, &instruction_data).unwrap();
+    };
+    let stats = || {
+        let market = MarketStateV2::load(&accounts.market, &dex_program_id).unwrap();
+        (
+            market.last_trade_price,
+            market.coin_volume_total,
+            market.pc_volume_total,
+            market.fill_count,
+        )
+    };
+
+    place_and_match(Side::Ask, 100, 1);
+    place_and_match(Side::Ask, 101, 2);
+    assert_eq!(stats(), (0, 0, 0, 0));
+
+    // one fill per maker order crossed
+    place_and_match(Side::Bid, 105, 3);
+    assert_eq!(stats(), (101, 3_000, 302, 2));
+
+    place_and_match(Side::Bid, 99, 2);
+    place_and_match(Side::Ask, 98, 1);
+    assert_eq!(stats(), (99, 4_000, 401, 3));
+}
+
+#[test]
+fn test_crank_rewards() {
+    let mut rng = StdRng::seed_from_u64(18);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let cranker = new_sol_account(&mut rng, 0, &bump);
+    let orders_account_buyer =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let orders_account_seller =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    let place_order = |side: Side| {
+        let (orders_account, payer) = match side {
+            Side::Bid => (&orders_account_buyer, &pc_account),
+            Side::Ask => (&orders_account_seller, &coin_account),
+        };
+        let instruction_data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+            side,
+            limit_price: NonZeroU64::new(100).unwrap(),
+            max_qty: NonZeroU64::new(1).unwrap(),
+            order_type: OrderType::Limit,
+            client_id: 0,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            expiry: None,
+        })
+        .pack();
+        let instruction_accounts = new_order_accounts(
+            &accounts,
+            orders_account,
+            payer,
+            &owner,
+            &spl_token_program,
+            &bump,
+        );
+        State::process(dex_program_id, instruction_accounts, &instruction_data)
+    };
+    // `Some(signed)` adds a reward recipient: the cranker, or else an account
+    // that doesn't sign
+    let recipient_account = |signed: bool| if signed { &cranker } else { &pc_account };
+    let match_orders = |limit: u16, recipient: Option<bool>| {
+        let mut match_accounts = BumpVec::new_in(&bump);
+        match_accounts.extend_from_slice(match_orders_accounts(
+            &accounts,
+            &coin_account,
+            &pc_account,
+            &bump,
+        ));
+        match_accounts.extend(recipient.map(|signed| recipient_account(signed).clone()));
+        let instruction_data = MarketInstruction::MatchOrders(limit).pack();
+        State::process(
+            dex_program_id,
+            match_accounts.into_bump_slice(),
+            &instruction_data,
+        )
+    };
+    let consume_events = |limit: u16, recipient: Option<bool>| {
+        let mut crank_accounts = BumpVec::new_in(&bump);
+        crank_accounts.extend_from_slice(consume_events_accounts(
+            &accounts,
+            &[&orders_account_buyer, &orders_account_seller],
+            &coin_account,
+            &pc_account,
+            &bump,
+        ));
+        let instruction_data = match recipient {
+            Some(signed) => {
+                crank_accounts.push(recipient_account(signed).clone());
+                MarketInstruction::ConsumeEventsWithReward(limit).pack()
+            }
+            None => MarketInstruction::ConsumeEvents(limit).pack(),
+        };
+        State::process(
+            dex_program_id,
+            crank_accounts.into_bump_slice(),
+            &instruction_data,
+        )
+    };
+    let rewards_accrued = || {
+        let market = MarketStateV2::load(&accounts.market, &dex_program_id).unwrap();
+        (market.match_rewards_accrued, market.consume_rewards_accrued)
+    };
+
+    let authority_accounts =
+        bump_vec![in &bump; accounts.market.clone(), accounts.authority.clone()].into_bump_slice();
+    let instruction_data = MarketInstruction::SetCrankFee(1_000_000).pack();
+    State::process(dex_program_id, authority_accounts, &instruction_data).unwrap();
+
+    place_order(Side::Ask).unwrap();
+    place_order(Side::Bid).unwrap();
+    assert_eq!(orders_account_seller.lamports(), 99_000_000);
+    assert_eq!(orders_account_buyer.lamports(), 99_000_000);
+    assert_eq!(accounts.market.lamports(), 102_000_000);
+    assert_eq!(rewards_accrued(), (1_000_000, 1_000_000));
+
+    // the recipient has to sign
+    assert!(match_orders(1, Some(false)).is_err());
+
+    // half of the queued requests earns half of the matching pool
+    match_orders(1, Some(true)).unwrap();
+    assert_eq!(cranker.lamports(), 500_000);
+    assert_eq!(rewards_accrued(), (500_000, 1_000_000));
+
+    // draining the request queue without claiming the reward leaves the
+    // rest of the matching pool to whoever consumes the events
+    match_orders(1, None).unwrap();
+    assert_eq!(rewards_accrued(), (0, 1_500_000));
+
+    // the event pool is only paid out by ConsumeEventsWithReward
+    consume_events(1, None).unwrap();
+    assert_eq!(rewards_accrued(), (0, 1_500_000));
+
+    // draining the event queue earns the rest of the event pool
+    consume_events(200, Some(true)).unwrap();
+    assert_eq!(cranker.lamports(), 2_000_000);
+    assert_eq!(accounts.market.lamports(), 100_000_000);
+    assert_eq!(rewards_accrued(), (0, 0));
+
+    // an order matched as it is placed gets the matching half of its fee back
+    let instruction_data = MarketInstruction::NewOrderV4(NewOrderInstructionV4 {
+        side: Side::Ask,
+        limit_price: NonZeroU64::new(100).unwrap(),
+        max_qty: NonZeroU64::new(1).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 0,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        expiry: None,
+        limit: 10,
+    })
+    .pack();
+    let instruction_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account_seller.clone(),
+        accounts.req_q.clone(),
+        coin_account.clone(),
+        owner.clone(),
+        accounts.coin_vault.clone(),
+        accounts.pc_vault.clone(),
+        spl_token_program.clone(),
+        accounts.rent_sysvar.clone(),
+        accounts.event_q.clone(),
+        accounts.bids.clone(),
+        accounts.asks.clone(),
+        accounts.clock_sysvar.clone(),
+    ]
+    .into_bump_slice();
+    State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+    assert_eq!(orders_account_seller.lamports(), 98_500_000);
+    assert_eq!(accounts.market.lamports(), 100_500_000);
+    assert_eq!(rewards_accrued(), (0, 500_000));
+
+    // the fee can't dip into an OpenOrders account's rent exempt balance
+    let rent_exempt_lamports = Rent::default().minimum_balance(orders_account_buyer.data_len());
+    **orders_account_buyer.lamports.borrow_mut() = rent_exempt_lamports + 999_999;
+    assert_eq!(
+        place_order(Side::Bid),
+        Err(DexErrorCode::InsufficientCrankFee.into())
+    );
+    assert_eq!(
+        orders_account_buyer.lamports(),
+        rent_exempt_lamports + 999_999
+    );
+}
+
+#[test]
+fn test_delegate() {
+    let mut rng = StdRng::seed_from_u64(19);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let delegate = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrdersV2>(), dex_program_id, &bump);
+    let original_orders_account =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let delegate_pc_account =
+        new_funded_token_account(&mut rng, accounts.pc_mint.key, delegate.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+    let vault_signer = new_vault_signer_account(&accounts, &bump);
+
+    // the owner or the delegate signs, selected by `as_delegate`
+    let signer = |as_delegate: bool| if as_delegate { &delegate } else { &owner };
+    let place_order = |as_delegate: bool, client_id: u64| {
+        let instruction_data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+            side: Side::Bid,
+            limit_price: NonZeroU64::new(100).unwrap(),
+            max_qty: NonZeroU64::new(1).unwrap(),
+            order_type: OrderType::Limit,
+            client_id,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            expiry: None,
+        })
+        .pack();
+        let payer = if as_delegate {
+            &delegate_pc_account
+        } else {
+            &pc_account
+        };
+        let instruction_accounts = new_order_accounts(
+            &accounts,
+            &orders_account,
+            payer,
+            signer(as_delegate),
+            &spl_token_program,
+            &bump,
+        );
+        State::process(dex_program_id, instruction_accounts, &instruction_data)
+    };
+    let owner_instruction = |as_delegate: bool, instruction: MarketInstruction| {
+        let mut instruction_accounts = bump_vec![in &bump;
+            accounts.market.clone(),
+            orders_account.clone(),
+            signer(as_delegate).clone(),
+        ];
+        if instruction == MarketInstruction::SetDelegate {
+            instruction_accounts.push(delegate.clone());
+        }
+        State::process(
+            dex_program_id,
+            instruction_accounts.into_bump_slice(),
+            &instruction.pack(),
+        )
+    };
+    let settle = |as_delegate: bool, into_delegate_wallet: bool| {
+        let pc_wallet = if into_delegate_wallet {
+            &delegate_pc_account
+        } else {
+            &pc_account
+        };
+        let settle_accounts = bump_vec![in &bump;
+            accounts.market.clone(),
+            orders_account.clone(),
+            signer(as_delegate).clone(),
+            accounts.coin_vault.clone(),
+            accounts.pc_vault.clone(),
+            coin_account.clone(),
+            pc_wallet.clone(),
+            vault_signer.clone(),
+            spl_token_program.clone(),
+        ]
+        .into_bump_slice();
+        State::process(
+            dex_program_id,
+            settle_accounts,
+            &MarketInstruction::SettleFunds.pack(),
+        )
+    };
+
+    place_order(false, 1).unwrap();
+    assert_eq!(
+        place_order(true, 2),
+        Err(DexErrorCode::WrongOrdersAccount.into())
+    );
+
+    owner_instruction(false, MarketInstruction::SetDelegate).unwrap();
+    place_order(true, 2).unwrap();
+    let cancel_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account.clone(),
+        accounts.req_q.clone(),
+        delegate.clone(),
+    ]
+    .into_bump_slice();
+    State::process(
+        dex_program_id,
+        cancel_accounts,
+        &MarketInstruction::CancelOrderByClientId(2).pack(),
+    )
+    .unwrap();
+
+    // the delegate can settle, but only into the owner's wallets
+    assert_eq!(
+        settle(true, true),
+        Err(DexErrorCode::WrongSettlementWallet.into())
+    );
+    settle(true, false).unwrap();
+
+    // only the owner manages the delegate
+    assert_eq!(
+        owner_instruction(true, MarketInstruction::RevokeDelegate),
+        Err(DexErrorCode::WrongOrdersAccount.into())
+    );
+    owner_instruction(false, MarketInstruction::RevokeDelegate).unwrap();
+    assert_eq!(
+        place_order(true, 3),
+        Err(DexErrorCode::WrongOrdersAccount.into())
+    );
+
+    // accounts of the original size have no room for a delegate
+    let instruction_accounts = new_order_accounts(
+        &accounts,
+        &original_orders_account,
+        &pc_account,
+        &owner,
+        &spl_token_program,
+        &bump,
+    );
+    let instruction_data = MarketInstruction::NewOrderV3(NewOrderInstructionV3 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(100).unwrap(),
+        max_qty: NonZeroU64::new(1).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 4,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+        expiry: None,
+    })
+    .pack();
+    State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+    let set_delegate_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        original_orders_account.clone(),
+        owner.clone(),
+        delegate.clone(),
+    ]
+    .into_bump_slice();
+    assert_eq!(
+        State::process(
+            dex_program_id,
+            set_delegate_accounts,
+            &MarketInstruction::SetDelegate.pack(),
+        ),
+        Err(DexErrorCode::UnsupportedOpenOrdersVersion.into())
+    );
+}
+
+// the system program only runs through the fuzzing shims
+#[cfg(feature = "fuzz")]
+#[test]
+fn test_init_open_orders() {
+    use instruction::InitOpenOrdersInstruction;
+    use state::find_open_orders_key;
+
+    let mut rng = StdRng::seed_from_u64(20);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let system_program = new_system_program(&bump);
+    let (open_orders_key, _nonce) =
+        find_open_orders_key(accounts.market.key, owner.key, 3, dex_program_id).unwrap();
+    // creating the account is a no-op outside the runtime, so it's
+    // allocated up front, and already holding lamports it takes the
+    // pre-funded path
+    let orders_account = AccountInfo::new(
+        bump.alloc(open_orders_key),
+        false,
+        true,
+        bump.alloc(100_000_000),
+        allocate_dex_owned_account(
+            size_of::<OpenOrdersV2>() + 2 * size_of::<ExtraOrderSlots>(),
+            &bump,
+        ),
+        dex_program_id,
+        false,
+        Epoch::default(),
+    );
+    let init_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account.clone(),
+        owner.clone(),
+        system_program.clone(),
+        accounts.rent_sysvar.clone(),
+    ]
+    .into_bump_slice();
+
+    let init_open_orders = |index, capacity| {
+        let instruction_data =
+            MarketInstruction::InitOpenOrders(InitOpenOrdersInstruction { index, capacity }).pack();
+        State::process(dex_program_id, init_accounts, &instruction_data)
+    };
+    assert_eq!(
+        init_open_orders(2, 256),
+        Err(DexErrorCode::WrongOrdersAccount.into())
+    );
+    assert_eq!(
+        init_open_orders(3, 200),
+        Err(DexErrorCode::InvalidOpenOrdersCapacity.into())
+    );
+    init_open_orders(3, 256).unwrap();
+
+    let market = MarketState::load(&accounts.market, dex_program_id).unwrap();
+    let open_orders = market
+        .load_orders_mut(&orders_account, Some(&owner), dex_program_id, None)
+        .unwrap();
+    assert_eq!(open_orders.market, accounts.market.key.to_aligned_bytes());
+    assert_eq!(open_orders.owner, owner.key.to_aligned_bytes());
+    assert_eq!(open_orders.capacity(), 256);
+    assert_eq!(open_orders.iter_filled_slots().next(), None);
+}
+
+#[test]
+fn test_open_orders_capacity() {
+    let mut rng = StdRng::seed_from_u64(21);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account = new_dex_owned_account(
+        &mut rng,
+        size_of::<OpenOrdersV2>() + size_of::<ExtraOrderSlots>(),
+        dex_program_id,
+        &bump,
+    );
+    let bad_orders_account =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>() + 8, dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    let place_bid = |use_bad_account: bool, client_id: u64| {
+        let instruction_data = MarketInstruction::NewOrder(NewOrderInstructionV1 {
+            side: Side::Bid,
+            limit_price: NonZeroU64::new(client_id).unwrap(),
+            max_qty: NonZeroU64::new(1).unwrap(),
+            order_type: OrderType::Limit,
+            client_id,
+        })
+        .pack();
+        let orders_account = if use_bad_account {
+            &bad_orders_account
+        } else {
+            &orders_account
+        };
+        let instruction_accounts = new_order_accounts(
+            &accounts,
+            orders_account,
+            &pc_account,
+            &owner,
+            &spl_token_program,
+            &bump,
+        );
+        State::process(dex_program_id, instruction_accounts, &instruction_data)
+    };
+    let match_accounts = match_orders_accounts(&accounts, &coin_account, &pc_account, &bump);
+    let match_orders = || {
+        let instruction_data = MarketInstruction::MatchOrders(10).pack();
+        State::process(dex_program_id, match_accounts, &instruction_data).unwrap();
+    };
+
+    assert_eq!(
+        place_bid(true, 1),
+        Err(DexErrorCode::InvalidOpenOrdersCapacity.into())
+    );
+
+    for client_id in 1..=192 {
+        place_bid(false, client_id).unwrap();
+        match_orders();
+    }
+    assert_eq!(
+        place_bid(false, 193),
+        Err(DexErrorCode::TooManyOpenOrders.into())
+    );
+    {
+        let market = MarketState::load(&accounts.market, dex_program_id).unwrap();
+        let open_orders = market
+            .load_orders_mut(&orders_account, None, dex_program_id, None)
+            .unwrap();
+        assert_eq!(open_orders.capacity(), 192);
+        assert_eq!(open_orders.free_slot_bits, 0);
+        assert_eq!(open_orders.iter_filled_slots().count(), 192);
+        assert_eq!(open_orders.slot_side(191), Some(Side::Bid));
+        assert_eq!(open_orders.client_order_id(191), 192);
+        assert_eq!(open_orders.slot_side(192), None);
+    }
+
+    let cancel_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account.clone(),
+        accounts.req_q.clone(),
+        owner.clone(),
+    ]
+    .into_bump_slice();
+    State::process(
+        dex_program_id,
+        cancel_accounts,
+        &MarketInstruction::CancelOrderByClientId(150).pack(),
+    )
+    .unwrap();
+    match_orders();
+    // every posted bid queued an out event for its unused fee reserve ahead
+    // of the cancel's
+    let crank_accounts = consume_events_accounts(
+        &accounts,
+        &[&orders_account],
+        &coin_account,
+        &pc_account,
+        &bump,
+    );
+    State::process(
+        dex_program_id,
+        crank_accounts,
+        &MarketInstruction::ConsumeEvents(200).pack(),
+    )
+    .unwrap();
+
+    // the freed slot past the first 128 is reused
+    place_bid(false, 193).unwrap();
+    let market = MarketState::load(&accounts.market, dex_program_id).unwrap();
+    let open_orders = market
+        .load_orders_mut(&orders_account, None, dex_program_id, None)
+        .unwrap();
+    assert_eq!(open_orders.slot_side(149), Some(Side::Bid));
+    assert_eq!(open_orders.client_order_id(149), 193);
+}
+
+fn close_market_accounts<'bump>(
+    accounts: &MarketAccounts<'bump>,
+    signer: &AccountInfo<'bump>,
+    dest: &AccountInfo<'bump>,
+    bump: &'bump Bump,
+) -> &'bump [AccountInfo<'bump>] {
+    bump_vec![in bump;
+        accounts.market.clone(),
+        accounts.req_q.clone(),
+        accounts.event_q.clone(),
+        accounts.bids.clone(),
+        accounts.asks.clone(),
+        signer.clone(),
+        dest.clone(),
+    ]
+    .into_bump_slice()
+}
+
+#[test]
+fn test_close_market() {
+    let mut rng = StdRng::seed_from_u64(22);
+    let bump = Bump::new();
+
+    let disabler = AccountInfo::new(
+        &disable_authority::ID,
+        true,
+        false,
+        bump.alloc(0),
+        &mut [],
+        &system_program::ID,
+        false,
+        Epoch::default(),
+    );
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let dest = new_sol_account(&mut rng, 0, &bump);
+
+    let close_market = MarketInstruction::CloseMarket.pack();
+    let disable_market = MarketInstruction::DisableMarket.pack();
+
+    // a market with an order resting on the book can't be closed
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+    let orders_account =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+    let vault_signer = new_vault_signer_account(&accounts, &bump);
+    let order_accounts = new_order_accounts(
+        &accounts,
+        &orders_account,
+        &pc_account,
+        &owner,
+        &spl_token_program,
+        &bump,
+    );
+    let new_order = MarketInstruction::NewOrder(NewOrderInstructionV1 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(1).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 1,
+    })
+    .pack();
+    State::process(dex_program_id, order_accounts, &new_order).unwrap();
+    let match_accounts = match_orders_accounts(&accounts, &pc_account, &pc_account, &bump);
+    State::process(
+        dex_program_id,
+        match_accounts,
+        &MarketInstruction::MatchOrders(10).pack(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        State::process(
+            dex_program_id,
+            close_market_accounts(&accounts, &accounts.authority, &dest, &bump),
+            &close_market
+        ),
+        Err(DexErrorCode::MarketNotDisabled.into())
+    );
+    let disable_accounts =
+        bump_vec![in &bump; accounts.market.clone(), disabler.clone()].into_bump_slice();
+    State::process(dex_program_id, disable_accounts, &disable_market).unwrap();
+    assert_eq!(
+        State::process(
+            dex_program_id,
+            close_market_accounts(&accounts, &owner, &dest, &bump),
+            &close_market
+        ),
+        Err(DexErrorCode::WrongMarketAuthority.into())
+    );
+    assert_eq!(
+        State::process(
+            dex_program_id,
+            close_market_accounts(&accounts, &accounts.authority, &dest, &bump),
+            &close_market
+        ),
+        Err(DexErrorCode::MarketNotEmpty.into())
+    );
+    // funds can still be settled out of a disabled market
+    let settle_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account.clone(),
+        owner.clone(),
+        accounts.coin_vault.clone(),
+        accounts.pc_vault.clone(),
+        coin_account.clone(),
+        pc_account.clone(),
+        vault_signer.clone(),
+        spl_token_program.clone(),
+    ]
+    .into_bump_slice();
+    State::process(
+        dex_program_id,
+        settle_accounts,
+        &MarketInstruction::SettleFunds.pack(),
+    )
+    .unwrap();
+
+    // an unused market gives back the lamports of all five accounts
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+    let market_lamports: u64 = [
+        &accounts.market,
+        &accounts.req_q,
+        &accounts.event_q,
+        &accounts.bids,
+        &accounts.asks,
+    ]
+    .iter()
+    .map(|account| account.lamports())
+    .sum();
+    let disable_accounts =
+        bump_vec![in &bump; accounts.market.clone(), disabler.clone()].into_bump_slice();
+    State::process(dex_program_id, disable_accounts, &disable_market).unwrap();
+    State::process(
+        dex_program_id,
+        close_market_accounts(&accounts, &accounts.authority, &dest, &bump),
+        &close_market,
+    )
+    .unwrap();
+
+    assert_eq!(dest.lamports(), market_lamports);
+    assert_eq!(accounts.market.lamports(), 0);
+    assert_eq!(accounts.asks.lamports(), 0);
+    assert!(MarketState::load(&accounts.market, dex_program_id).is_err());
+
+    // unswept fees keep a market open, but can be swept once it's disabled
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+    let vault_signer = new_vault_signer_account(&accounts, &bump);
+    MarketState::load(&accounts.market, dex_program_id)
+        .unwrap()
+        .pc_fees_accrued = 100;
+    set_token_balance(&accounts.pc_vault, 100);
+    let disable_accounts =
+        bump_vec![in &bump; accounts.market.clone(), disabler.clone()].into_bump_slice();
+    State::process(dex_program_id, disable_accounts, &disable_market).unwrap();
+    assert_eq!(
+        State::process(
+            dex_program_id,
+            close_market_accounts(&accounts, &accounts.authority, &dest, &bump),
+            &close_market
+        ),
+        Err(DexErrorCode::MarketNotEmpty.into())
+    );
+    let sweep_authority = AccountInfo::new(
+        &fee_sweeper::ID,
+        true,
+        false,
+        bump.alloc(0),
+        &mut [],
+        &system_program::ID,
+        false,
+        Epoch::default(),
+    );
+    let fee_receiver = new_token_account(&mut rng, accounts.pc_mint.key, &fee_sweeper::ID, &bump);
+    let sweep_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        accounts.pc_vault.clone(),
+        sweep_authority.clone(),
+        fee_receiver.clone(),
+        vault_signer.clone(),
+        spl_token_program.clone(),
+    ]
+    .into_bump_slice();
+    State::process(
+        dex_program_id,
+        sweep_accounts,
+        &MarketInstruction::SweepFees.pack(),
+    )
+    .unwrap();
+    State::process(
+        dex_program_id,
+        close_market_accounts(&accounts, &accounts.authority, &dest, &bump),
+        &close_market,
+    )
+    .unwrap();
+
+    // unpaid crank rewards keep a market open, but whoever drains its event
+    // queue once it's disabled collects them
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+    let orders_account =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let cranker = new_sol_account(&mut rng, 0, &bump);
+    MarketStateV2::load(&accounts.market, dex_program_id)
+        .unwrap()
+        .consume_rewards_accrued = 1_000_000;
+    **accounts.market.lamports.borrow_mut() += 1_000_000;
+    let disable_accounts =
+        bump_vec![in &bump; accounts.market.clone(), disabler.clone()].into_bump_slice();
+    State::process(dex_program_id, disable_accounts, &disable_market).unwrap();
+    assert_eq!(
+        State::process(
+            dex_program_id,
+            close_market_accounts(&accounts, &accounts.authority, &dest, &bump),
+            &close_market
+        ),
+        Err(DexErrorCode::MarketNotEmpty.into())
+    );
+    let mut crank_accounts = BumpVec::new_in(&bump);
+    crank_accounts.extend_from_slice(consume_events_accounts(
+        &accounts,
+        &[&orders_account],
+        &dest,
+        &dest,
+        &bump,
+    ));
+    crank_accounts.push(cranker.clone());
+    State::process(
+        dex_program_id,
+        crank_accounts.into_bump_slice(),
+        &MarketInstruction::ConsumeEventsWithReward(10).pack(),
+    )
+    .unwrap();
+    assert_eq!(cranker.lamports(), 1_000_000);
+    State::process(
+        dex_program_id,
+        close_market_accounts(&accounts, &accounts.authority, &dest, &bump),
+        &close_market,
+    )
+    .unwrap();
+
+    // a market without an authority is closed by the disable authority
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+    MarketStateV2::load(&accounts.market, dex_program_id)
+        .unwrap()
+        .authority = [0; 4];
+    let disable_accounts =
+        bump_vec![in &bump; accounts.market.clone(), disabler.clone()].into_bump_slice();
+    State::process(dex_program_id, disable_accounts, &disable_market).unwrap();
+    assert_eq!(
+        State::process(
+            dex_program_id,
+            close_market_accounts(&accounts, &accounts.authority, &dest, &bump),
+            &close_market
+        ),
+        Err(DexErrorCode::WrongMarketAuthority.into())
+    );
+    State::process(
+        dex_program_id,
+        close_market_accounts(&accounts, &disabler, &dest, &bump),
+        &close_market,
+    )
+    .unwrap();
+    assert_eq!(accounts.market.lamports(), 0);
+
+    // OpenOrders accounts can still be closed after their market is
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+    let orders_account =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let vault_signer = new_vault_signer_account(&accounts, &bump);
+    let new_order = MarketInstruction::NewOrder(NewOrderInstructionV1 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(1).unwrap(),
+        order_type: OrderType::ImmediateOrCancel,
+        client_id: 1,
+    })
+    .pack();
+    State::process(
+        dex_program_id,
+        new_order_accounts(
+            &accounts,
+            &orders_account,
+            &pc_account,
+            &owner,
+            &spl_token_program,
+            &bump,
+        ),
+        &new_order,
+    )
+    .unwrap();
+    State::process(
+        dex_program_id,
+        match_orders_accounts(&accounts, &coin_account, &pc_account, &bump),
+        &MarketInstruction::MatchOrders(10).pack(),
+    )
+    .unwrap();
+    State::process(
+        dex_program_id,
+        consume_events_accounts(
+            &accounts,
+            &[&orders_account],
+            &coin_account,
+            &pc_account,
+            &bump,
+        ),
+        &MarketInstruction::ConsumeEvents(10).pack(),
+    )
+    .unwrap();
+    let settle_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        orders_account.clone(),
+        owner.clone(),
+        accounts.coin_vault.clone(),
+        accounts.pc_vault.clone(),
+        coin_account.clone(),
+        pc_account.clone(),
+        vault_signer.clone(),
+        spl_token_program.clone(),
+    ]
+    .into_bump_slice();
+    State::process(
+        dex_program_id,
+        settle_accounts,
+        &MarketInstruction::SettleFunds.pack(),
+    )
+    .unwrap();
+    let disable_accounts =
+        bump_vec![in &bump; accounts.market.clone(), disabler.clone()].into_bump_slice();
+    State::process(dex_program_id, disable_accounts, &disable_market).unwrap();
+    State::process(
+        dex_program_id,
+        close_market_accounts(&accounts, &accounts.authority, &dest, &bump),
+        &close_market,
+    )
+    .unwrap();
+    let orders_lamports = orders_account.lamports();
+    let dest_lamports = dest.lamports();
+    let close_accounts = bump_vec![in &bump;
+        orders_account.clone(),
+        owner.clone(),
+        dest.clone(),
+        accounts.market.clone(),
+    ]
+    .into_bump_slice();
+    State::process(
+        dex_program_id,
+        close_accounts,
+        &MarketInstruction::CloseOpenOrders.pack(),
+    )
+    .unwrap();
+    assert_eq!(orders_account.lamports(), 0);
+    assert_eq!(dest.lamports(), dest_lamports + orders_lamports);
+}
+
+#[test]
+fn test_fill_events() {
+    let mut rng = StdRng::seed_from_u64(23);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+
+    let dex_program_id = accounts.market.owner;
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account_buyer =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let orders_account_seller =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let coin_account = new_funded_token_account(&mut rng, accounts.coin_mint.key, owner.key, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+    let clock = new_clock_sysvar_account(
+        Clock {
+            slot: 7,
+            ..Clock::default()
+        },
+        &bump,
+    );
+
+    let place_and_match = |side: Side, limit_price: u64, max_qty: u64| {
+        let (orders_account, payer) = match side {
+            Side::Bid => (&orders_account_buyer, &pc_account),
+            Side::Ask => (&orders_account_seller, &coin_account),
+        };
+        let instruction_data = MarketInstruction::NewOrderV4(NewOrderInstructionV4 {
+            side,
+            limit_price: NonZeroU64::new(limit_price).unwrap(),
+            max_qty: NonZeroU64::new(max_qty).unwrap(),
+            order_type: OrderType::Limit,
+            client_id: 0,
+            self_trade_behavior: SelfTradeBehavior::DecrementTake,
+            expiry: None,
+            limit: 10,
+        })
+        .pack();
+        let instruction_accounts = bump_vec![in &bump;
+            accounts.market.clone(),
+            orders_account.clone(),
+            accounts.req_q.clone(),
+            payer.clone(),
+            owner.clone(),
+            accounts.coin_vault.clone(),
+            accounts.pc_vault.clone(),
+            spl_token_program.clone(),
+            accounts.rent_sysvar.clone(),
+            accounts.event_q.clone(),
+            accounts.bids.clone(),
+            accounts.asks.clone(),
+            clock.clone(),
+        ]
+        .into_bump_slice();
+        State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+    };
+
+    place_and_match(Side::Ask, 100_000, 3);
+    place_and_match(Side::Ask, 100_004, 3);
+    place_and_match(Side::Bid, 100_004, 4);
+
+    let event_q = client::EventQueueView::decode(&accounts.event_q.data.borrow()).unwrap();
+    let seq_nums: Vec<u64> = event_q.events().iter().map(|e| e.seq_num()).collect();
+    let expected: Vec<u64> = (0..event_q.next_seq_num()).collect();
+    assert_eq!(seq_nums, expected);
+    assert!(event_q.events().iter().all(|e| e.slot() == 7));
+
+    let fills: Vec<(bool, u64, u64)> = event_q
+        .fills()
+        .iter()
+        .map(|f| (f.maker, f.price_lots, f.native_qty_paid))
+        .collect();
+    // the taker gets a fill at each ask's price
+    assert_eq!(
+        fills
+            .iter()
+            .map(|&(maker, price, _)| (maker, price))
+            .collect::<Vec<_>>(),
+        vec![
+            (true, 100_000),
+            (false, 100_000),
+            (true, 100_004),
+            (false, 100_004)
+        ]
+    );
+    assert_eq!(fills[0].2, 3_000);
+    assert_eq!(fills[2].2, 1_000);
+
+    // the program refuses to consume an event of another layout version
+    let version_offset =
+        state::ACCOUNT_HEAD_PADDING.len() + size_of::<state::EventQueueHeader>() + 3;
+    accounts.event_q.data.borrow_mut()[version_offset] = 0;
+    let crank_accounts = consume_events_accounts(
+        &accounts,
+        &[&orders_account_buyer, &orders_account_seller],
+        &coin_account,
+        &pc_account,
+        &bump,
+    );
+    assert_eq!(
+        State::process(
+            dex_program_id,
+            crank_accounts,
+            &MarketInstruction::ConsumeEvents(200).pack(),
+        ),
+        Err(DexErrorCode::UnsupportedEventVersion.into())
+    );
+}
+
+#[test]
+fn test_original_request_queue() {
+    use bytemuck::{bytes_of, Zeroable};
+    use state::{RequestQueue, RequestQueueHeader, RequestView, ORIGINAL_REQUEST_LEN};
+    use std::cell::{RefCell, RefMut};
+
+    let cancel = |order_id: u128, cancel_id: u64| {
+        state::Request::new(RequestView::CancelOrder {
+            side: Side::Bid,
+            order_id: &order_id,
+            cancel_id,
+            expected_owner_slot: 3,
+            expected_owner: &[9; 4],
+            client_order_id: NonZeroU64::new(cancel_id),
+        })
+    };
+    let header = RefCell::new(RequestQueueHeader::zeroed());
+    // room for three requests of the original length, and some slop
+    let bytes = RefCell::new(vec![0u8; 3 * ORIGINAL_REQUEST_LEN + 7]);
+    {
+        let mut req_q = RequestQueue::original(
+            header.borrow_mut(),
+            RefMut::map(bytes.borrow_mut(), Vec::as_mut_slice),
+        );
+        for i in 0..3 {
+            req_q.push_back(cancel(i, i as u64 + 1)).unwrap();
+        }
+        assert!(req_q.full());
+        assert!(req_q.push_back(cancel(3, 4)).is_err());
+
+        let front = req_q.pop_front().unwrap();
+        assert_eq!(bytes_of(&front), bytes_of(&cancel(0, 1)));
+        // wraps around into the slot just freed
+        req_q.push_back(cancel(3, 4)).unwrap();
+        let ids: Vec<u128> = req_q
+            .iter()
+            .map(|request| match request.as_view().unwrap() {
+                RequestView::CancelOrder { order_id, .. } => *order_id,
+                _ => panic!(),
+            })
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3]);
+    }
+
+    // each request only takes up its original length, and the slop is untouched
+    let bytes = bytes.borrow();
+    assert_eq!(
+        &bytes[..ORIGINAL_REQUEST_LEN],
+        &bytes_of(&cancel(3, 4))[..ORIGINAL_REQUEST_LEN]
+    );
+    assert_eq!(
+        &bytes[2 * ORIGINAL_REQUEST_LEN..3 * ORIGINAL_REQUEST_LEN],
+        &bytes_of(&cancel(2, 3))[..ORIGINAL_REQUEST_LEN]
+    );
+    assert!(bytes[3 * ORIGINAL_REQUEST_LEN..].iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_original_market_version() {
+    let mut rng = StdRng::seed_from_u64(24);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    // a market of the original size, holding only the fields it had
+    let original_market = resized_account_copy(
+        &accounts.market,
+        size_of::<MarketState>(),
+        size_of::<MarketState>(),
+        &bump,
+    );
+    let accounts = MarketAccounts {
+        market: original_market,
+        ..accounts
+    };
+
+    let owner = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let orders_account =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let pc_account = new_funded_token_account(&mut rng, accounts.pc_mint.key, owner.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    // such a market takes no new orders
+    let new_order = MarketInstruction::NewOrderV2(NewOrderInstructionV2 {
+        side: Side::Bid,
+        limit_price: NonZeroU64::new(100_000).unwrap(),
+        max_qty: NonZeroU64::new(1).unwrap(),
+        order_type: OrderType::Limit,
+        client_id: 0,
+        self_trade_behavior: SelfTradeBehavior::DecrementTake,
+    })
+    .pack();
+    let new_order_accounts = new_order_accounts(
+        &accounts,
+        &orders_account,
+        &pc_account,
+        &owner,
+        &spl_token_program,
+        &bump,
+    );
+    assert_eq!(
+        State::process(dex_program_id, new_order_accounts, &new_order),
+        Err(DexErrorCode::UnsupportedMarketVersion.into())
+    );
+
+    // but it can still be disabled
+    let disabler = AccountInfo::new(
+        &disable_authority::ID,
+        true,
+        false,
+        bump.alloc(0),
+        &mut [],
+        &system_program::ID,
+        false,
+        Epoch::default(),
+    );
+    let disable_accounts =
+        bump_vec![in &bump; accounts.market.clone(), disabler.clone()].into_bump_slice();
+    State::process(
+        dex_program_id,
+        disable_accounts,
+        &MarketInstruction::DisableMarket.pack(),
+    )
+    .unwrap();
+    assert!(MarketState::load(&accounts.market, dex_program_id).is_err());
+}
+
+// A copy of `account` with `unpadded_size` bytes of data, of which only the
+// first `copied_len` are taken from `account`
+fn resized_account_copy<'bump>(
+    account: &AccountInfo<'bump>,
+    unpadded_size: usize,
+    copied_len: usize,
+    bump: &'bump Bump,
+) -> AccountInfo<'bump> {
+    let data = allocate_dex_owned_account(unpadded_size, bump);
+    let copied_end = state::ACCOUNT_HEAD_PADDING.len() + copied_len;
+    data[..copied_end].copy_from_slice(&account.try_borrow_data().unwrap()[..copied_end]);
+    let tail_start = data.len() - state::ACCOUNT_TAIL_PADDING.len();
+    data[tail_start..].copy_from_slice(state::ACCOUNT_TAIL_PADDING);
+    AccountInfo::new(
+        account.key,
+        false,
+        true,
+        bump.alloc(account.lamports()),
+        data,
+        account.owner,
+        false,
+        Epoch::default(),
+    )
+}
+
+// Moves a market with an empty request queue to the account sizes and the
+// book and queue layouts of a market created before the upgrade
+fn to_original_layouts<'bump>(
+    accounts: MarketAccounts<'bump>,
+    bump: &'bump Bump,
+) -> MarketAccounts<'bump> {
+    use critbit::{LeafNode, Slab};
+    use state::{
+        strip_header, Event, EventQueue, EventQueueHeader, OrderBookStateHeader, Request,
+        RequestQueue, RequestQueueHeader,
+    };
+
+    // every header starts with the account flags, which are all that is kept
+    let flags_len = size_of::<u64>();
+    let market = resized_account_copy(
+        &accounts.market,
+        size_of::<MarketState>(),
+        size_of::<MarketState>(),
+        bump,
+    );
+
+    let (header, buf) =
+        strip_header::<RequestQueueHeader, Request>(&accounts.req_q, false).unwrap();
+    assert!(RequestQueue::new(header, buf).empty());
+    let req_q_len = size_of::<RequestQueueHeader>() + 8 * state::ORIGINAL_REQUEST_LEN;
+    let req_q = resized_account_copy(&accounts.req_q, req_q_len, flags_len, bump);
+
+    let event_q_len = size_of::<EventQueueHeader>() + 64 * state::ORIGINAL_EVENT_LEN;
+    let event_q = resized_account_copy(&accounts.event_q, event_q_len, flags_len, bump);
+    {
+        let (header, buf) =
+            strip_header::<EventQueueHeader, Event>(&accounts.event_q, false).unwrap();
+        let events = EventQueue::new(header, buf);
+        let (header, buf) = strip_header::<EventQueueHeader, u8>(&event_q, false).unwrap();
+        let mut original_events = EventQueue::original(header, buf);
+        for event in events.iter() {
+            original_events.push_back(event).unwrap();
+        }
+    }
+
+    let original_book = |book: &AccountInfo<'bump>| {
+        let original_book = resized_account_copy(book, 1 << 16, flags_len, bump);
+        let (_, mut buf) = strip_header::<OrderBookStateHeader, u8>(book, false).unwrap();
+        let slab: &mut Slab = Slab::new(&mut buf);
+        let (_, mut original_buf) =
+            strip_header::<OrderBookStateHeader, u8>(&original_book, false).unwrap();
+        let original_slab: &mut Slab<()> = Slab::new(&mut original_buf);
+        for leaf in slab.leaves(false) {
+            let original_leaf = LeafNode::new(
+                leaf.owner_slot(),
+                leaf.order_id(),
+                leaf.owner(),
+                leaf.quantity(),
+                leaf.fee_tier(),
+                leaf.client_order_id(),
+                None,
+            );
+            original_slab.insert_leaf(&original_leaf).unwrap();
+        }
+        drop(original_buf);
+        original_book
+    };
+    let bids = original_book(&accounts.bids);
+    let asks = original_book(&accounts.asks);
+
+    MarketAccounts {
+        market,
+        req_q,
+        event_q,
+        bids,
+        asks,
+        ..accounts
+    }
+}
+
+#[test]
+fn test_original_market_orders() {
+    let mut rng = StdRng::seed_from_u64(25);
+    let bump = Bump::new();
+
+    let accounts = setup_market(&mut rng, &bump);
+    let dex_program_id = accounts.market.owner;
+
+    let buyer = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let seller = new_sol_account(&mut rng, 1_000_000_000, &bump);
+    let buyer_orders =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let seller_orders =
+        new_dex_owned_account(&mut rng, size_of::<OpenOrders>(), dex_program_id, &bump);
+    let buyer_coin = new_funded_token_account(&mut rng, accounts.coin_mint.key, buyer.key, &bump);
+    let buyer_pc = new_funded_token_account(&mut rng, accounts.pc_mint.key, buyer.key, &bump);
+    let seller_coin = new_funded_token_account(&mut rng, accounts.coin_mint.key, seller.key, &bump);
+    let spl_token_program = new_spl_token_program(&bump);
+
+    // a bid rests on the book, partly filled by an ask, and the market is
+    // upgraded before the events are consumed
+    let orders = [
+        (Side::Bid, 5, &buyer_orders, &buyer_pc, &buyer),
+        (Side::Ask, 2, &seller_orders, &seller_coin, &seller),
+    ];
+    for &(side, max_qty, orders_account, payer, owner) in orders.iter() {
+        let instruction_data = MarketInstruction::NewOrder(NewOrderInstructionV1 {
+            side,
+            limit_price: NonZeroU64::new(100_000).unwrap(),
+            max_qty: NonZeroU64::new(max_qty).unwrap(),
+            order_type: OrderType::Limit,
+            client_id: 1,
+        })
+        .pack();
+        let instruction_accounts = new_order_accounts(
+            &accounts,
+            orders_account,
+            payer,
+            owner,
+            &spl_token_program,
+            &bump,
+        );
+        State::process(dex_program_id, instruction_accounts, &instruction_data).unwrap();
+    }
+    let match_accounts = match_orders_accounts(&accounts, &buyer_coin, &buyer_pc, &bump);
+    State::process(
+        dex_program_id,
+        match_accounts,
+        &MarketInstruction::MatchOrders(10).pack(),
+    )
+    .unwrap();
+    let accounts = to_original_layouts(accounts, &bump);
+
+    let match_accounts = match_orders_accounts(&accounts, &buyer_coin, &buyer_pc, &bump);
+    let crank_accounts = consume_events_accounts(
+        &accounts,
+        &[&buyer_orders, &seller_orders],
+        &buyer_coin,
+        &buyer_pc,
+        &bump,
+    );
+    State::process(
+        dex_program_id,
+        crank_accounts,
+        &MarketInstruction::ConsumeEvents(10).pack(),
+    )
+    .unwrap();
+    {
+        let market = MarketState::load(&accounts.market, dex_program_id).unwrap();
+        let buyer_open_orders = market
+            .load_orders_mut(&buyer_orders, None, dex_program_id, None)
+            .unwrap();
+        assert_eq!(buyer_open_orders.slot_side(0), Some(Side::Bid));
+        assert_eq!(buyer_open_orders.native_coin_free, 2_000);
+        assert_eq!(buyer_open_orders.native_pc_free, 1_160);
+        let seller_open_orders = market
+            .load_orders_mut(&seller_orders, None, dex_program_id, None)
+            .unwrap();
+        assert_eq!(seller_open_orders.slot_side(0), None);
+        assert_eq!(seller_open_orders.native_coin_total, 0);
+        assert_eq!(seller_open_orders.native_pc_free, 199_560);
+    }
+
+    // the rest of the bid can still be cancelled and its funds settled
+    let cancel_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        buyer_orders.clone(),
+        accounts.req_q.clone(),
+        buyer.clone(),
+    ]
+    .into_bump_slice();
+    State::process(
+        dex_program_id,
+        cancel_accounts,
+        &MarketInstruction::CancelOrderByClientId(1).pack(),
+    )
+    .unwrap();
+    State::process(
+        dex_program_id,
+        match_accounts,
+        &MarketInstruction::MatchOrders(10).pack(),
+    )
+    .unwrap();
+    State::process(
+        dex_program_id,
+        crank_accounts,
+        &MarketInstruction::ConsumeEvents(10).pack(),
+    )
+    .unwrap();
+    {
+        let market = MarketState::load(&accounts.market, dex_program_id).unwrap();
+        let buyer_open_orders = market
+            .load_orders_mut(&buyer_orders, None, dex_program_id, None)
+            .unwrap();
+        assert_eq!(buyer_open_orders.slot_side(0), None);
+        assert_eq!(
+            buyer_open_orders.native_pc_free,
+            buyer_open_orders.native_pc_total
+        );
+    }
+
+    let vault_signer = new_vault_signer_account(&accounts, &bump);
+    let settle_accounts = bump_vec![in &bump;
+        accounts.market.clone(),
+        buyer_orders.clone(),
+        buyer.clone(),
+        accounts.coin_vault.clone(),
+        accounts.pc_vault.clone(),
+        buyer_coin.clone(),
+        buyer_pc.clone(),
+        vault_signer.clone(),
+        spl_token_program.clone(),
+    ]
+    .into_bump_slice();
+    State::process(
+        dex_program_id,
+        settle_accounts,
+        &MarketInstruction::SettleFunds.pack(),
+    )
+    .unwrap();
+    let market = MarketState::load(&accounts.market, dex_program_id).unwrap();
+    let buyer_open_orders = market
+        .load_orders_mut(&buyer_orders, None, dex_program_id, None)
+        .unwrap();
+    assert_eq!(buyer_open_orders.native_coin_total, 0);
+    assert_eq!(buyer_open_orders.native_pc_total, 0);
+}